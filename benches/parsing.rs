@@ -0,0 +1,91 @@
+// Baselines for the parsers, on synthetic data so no game files or runtime
+// libraries are needed. Run with `cargo bench --features fixtures`.
+
+use anyhow::Result;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use soulframe_language_downloader::manifest::SoulframeManifest;
+use soulframe_language_downloader::extract::{languages_read_header, languages_unpack, BundledZstd, UnpackOptions};
+use soulframe_language_downloader::fixtures::{LanguagesBuilder, ManifestBuilder};
+use soulframe_language_downloader::{shcc_decompress_chunk_oodle, BlockDecompressor, ManifestFormat};
+
+/// Copies each block's compressed bytes into its output, so only the framing and
+/// copying around the decompressor is measured
+struct CopyDecompressor;
+
+impl BlockDecompressor for CopyDecompressor {
+    fn decompress_into(&self, compressed: &[u8], output: &mut [u8]) -> Result<()> {
+        let len = compressed.len().min(output.len());
+        output[..len].copy_from_slice(&compressed[..len]);
+        Ok(())
+    }
+}
+
+/// An Oodle chunk of `blocks` blocks of `block_size` bytes each
+fn oodle_chunk(blocks: usize, block_size: u32) -> Vec<u8> {
+    let mut chunk = Vec::new();
+    for _ in 0..blocks {
+        chunk.extend((0x8000_0000u32 | (block_size << 2)).to_be_bytes());
+        chunk.extend(((block_size << 5) | 0x01).to_be_bytes());
+        chunk.push(0x8C);
+        chunk.extend(std::iter::repeat_n(0x5A, block_size as usize - 1));
+    }
+    chunk
+}
+
+fn bench_oodle_chunk(c: &mut Criterion) {
+    const BLOCKS: usize = 64;
+    const BLOCK_SIZE: u32 = 0x4000;
+    let chunk = oodle_chunk(BLOCKS, BLOCK_SIZE);
+    let decompressed_size = BLOCKS * BLOCK_SIZE as usize;
+    
+    let mut group = c.benchmark_group("shcc");
+    group.throughput(Throughput::Bytes(decompressed_size as u64));
+    group.bench_function("decompress_chunk_oodle_64_blocks", |b| {
+        b.iter(|| shcc_decompress_chunk_oodle(black_box(&chunk), 0, decompressed_size, &CopyDecompressor).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_languages_unpack(c: &mut Criterion) {
+    const LABELS: usize = 100_000;
+    let mut builder = LanguagesBuilder::new().with_suffix(b"_en");
+    for i in 0..LABELS {
+        builder = builder.with_label(&format!("/Lotus/Language/Path{}/Label{}", i / 100, i), "Some translated text");
+    }
+    let bin = builder.build().unwrap();
+    let (_, dict) = languages_read_header(&bin).unwrap();
+    let decompressor = BundledZstd::new(&dict).unwrap();
+    let options = UnpackOptions::default();
+    
+    let mut group = c.benchmark_group("languages");
+    group.throughput(Throughput::Elements(LABELS as u64));
+    group.sample_size(20);
+    group.bench_function("unpack_100k_labels", |b| {
+        b.iter(|| languages_unpack(black_box(&bin), &decompressor, &options).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_manifest_seek(c: &mut Criterion) {
+    const ENTRIES: usize = 50_000;
+    let mut builder = ManifestBuilder::new();
+    for i in 0..ENTRIES {
+        builder = builder.with_entry(&format!("/Lotus/Some/Path/File{}.bin", i), [i as u8; 16]);
+    }
+    let bin = builder.build().unwrap();
+    
+    let mut group = c.benchmark_group("manifest");
+    group.throughput(Throughput::Elements(ENTRIES as u64));
+    group.sample_size(20);
+    group.bench_function("seek_50k_entries", |b| {
+        b.iter(|| {
+            let mut manifest = SoulframeManifest::from_bytes(bin.clone(), ManifestFormat::Current).unwrap();
+            manifest.seek(None);
+            manifest
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_oodle_chunk, bench_languages_unpack, bench_manifest_seek);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use soulframe_language_downloader::fuzzing::fuzz_languages;
+
+fuzz_target!(|data: &[u8]| {
+    fuzz_languages(data);
+});
@@ -0,0 +1,114 @@
+use clap::Parser;
+use anyhow::{anyhow, Context, Result};
+use soulframe_language_downloader::extract::{
+    duplicate_values, languages_read_header, languages_unpack, BundledZstd, UnpackOptions, ValueNormalization,
+};
+use soulframe_language_downloader::{get_download_path, locale_suffix, logging, DEFAULT_PLATFORM};
+use std::fs;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Parser)]
+#[command(name = "analyze")]
+#[command(about = "Report strings duplicated across the keys of a locale")]
+struct Args {
+    /// Locale whose downloaded Languages.bin is analyzed
+    #[arg(short, long, default_value = "en")]
+    locale: String,
+
+    /// Platform whose Languages.bin is analyzed
+    #[arg(long, default_value = DEFAULT_PLATFORM)]
+    platform: String,
+
+    /// Analyze this Languages.bin_H instead of the downloaded one
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// Number of most duplicated strings to list
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    top: usize,
+
+    /// Ignore leading and trailing whitespace when comparing strings
+    #[arg(long)]
+    trim: bool,
+
+    /// Ignore case when comparing strings
+    #[arg(long)]
+    case_fold: bool,
+
+    /// Also write every group of duplicated strings, with the totals, as JSON
+    #[arg(long, value_name = "PATH")]
+    json: Option<PathBuf>,
+
+    /// Log more: -v adds each URL tried and other details, -vv parse offsets.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.verbose, args.quiet);
+
+    let h_file_path = match &args.input {
+        Some(input) => input.clone(),
+        None => {
+            let h_path = get_download_path("/Languages.bin", Some(&locale_suffix(&args.locale, &args.platform)))?;
+            PathBuf::from(format!("{}_H", h_path.to_string_lossy()))
+        }
+    };
+    let bin = fs::read(&h_file_path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", h_file_path.display(), e))?;
+
+    let (_, dict) = languages_read_header(&bin)
+        .with_context(|| format!("parsing {}", h_file_path.display()))?;
+    let entries = languages_unpack(&bin, &BundledZstd::new(&dict)?, &UnpackOptions::default())
+        .with_context(|| format!("parsing {}", h_file_path.display()))?
+        .entries;
+
+    let normalization = ValueNormalization { trim: args.trim, case_fold: args.case_fold };
+    let report = duplicate_values(&entries, normalization);
+
+    let duplicated_keys: usize = report.groups.iter().map(|group| group.keys.len()).sum();
+    let percent = |part: usize, whole: usize| if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 };
+    println!("=== Duplicate strings in {} ===", h_file_path.display());
+    println!("Strings:          {}", report.strings);
+    println!("Unique values:    {}", report.unique_values);
+    println!(
+        "Duplicated:       {} values held by {} keys ({:.1}% of keys)",
+        report.groups.len(),
+        duplicated_keys,
+        percent(duplicated_keys, report.strings)
+    );
+    println!(
+        "Redundant bytes:  {} of {} ({:.1}%)",
+        report.redundant_bytes,
+        report.total_bytes,
+        percent(report.redundant_bytes, report.total_bytes)
+    );
+    println!("Dedup ratio:      {:.3}", report.dedup_ratio);
+
+    if args.top > 0 && !report.groups.is_empty() {
+        println!("\nTop {} duplicated values:", args.top.min(report.groups.len()));
+        for group in report.groups.iter().take(args.top) {
+            println!("\n{:>6} keys {:>8} bytes  {:?}", group.keys.len(), group.redundant_bytes, group.value);
+            for key in &group.keys {
+                println!("    {}", key);
+            }
+        }
+    }
+
+    if let Some(json) = &args.json {
+        if let Some(parent) = json.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(json, serde_json::to_string_pretty(&report)?)?;
+        info!("\nGroups written: {}", json.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use clap::Parser;
+use anyhow::{anyhow, Context, Result};
+use soulframe_language_downloader::codegen::{generate, CodegenTarget};
+use soulframe_language_downloader::extract::read_language_json;
+use soulframe_language_downloader::{get_extract_path, locale_suffix, logging, DEFAULT_PLATFORM};
+use std::fs;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Parser)]
+#[command(name = "codegen")]
+#[command(about = "Generate TypeScript or Rust constants for the keys of an extracted locale")]
+struct Args {
+    /// Language of the generated module
+    #[arg(long, value_enum)]
+    target: Target,
+
+    /// Locale whose extracted JSON provides the keys
+    #[arg(short, long, default_value = "en")]
+    locale: String,
+
+    /// Platform the locale was extracted for
+    #[arg(long, default_value = DEFAULT_PLATFORM)]
+    platform: String,
+
+    /// Read the keys from this flat JSON file instead of the extracted locale
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// Write the module to this file instead of standard output
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Log more: -v adds each URL tried and other details, -vv parse offsets.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Target {
+    /// `export const Keys = { ... } as const;` with a `Key` union type
+    #[value(name = "typescript", alias = "ts")]
+    TypeScript,
+    /// A `pub const` per key and an `ALL` array
+    Rust,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.verbose, args.quiet);
+
+    let input = match &args.input {
+        Some(input) => input.clone(),
+        None => {
+            let stem = &locale_suffix(&args.locale, &args.platform)[1..];
+            get_extract_path(&format!("/Languages/{}.json", stem), None)?
+        }
+    };
+    let json = fs::read_to_string(&input)
+        .map_err(|e| anyhow!("Failed to read {} (run extract first): {}", input.display(), e))?;
+    let entries = read_language_json(&json)
+        .with_context(|| format!("parsing {} (codegen needs the flat layout)", input.display()))?;
+
+    let target = match args.target {
+        Target::TypeScript => CodegenTarget::TypeScript,
+        Target::Rust => CodegenTarget::Rust,
+    };
+    let source = input.file_name().map_or_else(|| input.to_string_lossy(), |name| name.to_string_lossy());
+    let code = generate(entries.keys().map(String::as_str), target, &source)?;
+
+    match &args.output {
+        Some(output) => {
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(output, code)?;
+            info!("{} keys written: {}", entries.len(), output.display());
+        }
+        None => print!("{}", code),
+    }
+    Ok(())
+}
@@ -1,500 +1,1240 @@
-use clap::Parser;
-use anyhow::{anyhow, Result};
-use rand::Rng;
-use soulframe_language_downloader::{find_runtime_lib, TYPE_BIN, TYPE_MANIFEST};
-use std::collections::HashMap;
+use clap::{Parser, Subcommand};
+use anyhow::{anyhow, Context, Result};
+use soulframe_language_downloader::bundle::{unbundle, write_bundle, BundleFormat};
+use soulframe_language_downloader::cache::{known_manifest_hashes, ContentCache};
+use soulframe_language_downloader::clean::{clean, CleanOptions};
+use soulframe_language_downloader::download::{
+    build_http_client_with_timeouts, DownloadClient, DownloadMeta, RateLimiter, SoulframeManifest, DEFAULT_MAX_DOWNLOAD_SIZE,
+};
+use soulframe_language_downloader::extract::Zstd;
+use soulframe_language_downloader::locales::{known_locales, parse_locales, LocaleAliases};
+use soulframe_language_downloader::lock::{RunLock, LOCK_ENV};
+use soulframe_language_downloader::logging;
+use soulframe_language_downloader::output::{self, Status, Table};
+use soulframe_language_downloader::snapshot::{list_snapshots, set_latest, snapshot_id};
+use soulframe_language_downloader::status::{data_status, FileReport, ManifestReport};
+use soulframe_language_downloader::storage::LocalFs;
+use soulframe_language_downloader::{
+    b64m_decode, b64m_encode, download_key, locale_suffix, localized_manifest_path, manifest_platforms, safe_join, Oodle, PhaseTimings,
+    SoulframeError, DEFAULT_DECOMPRESS_MULTIPLIER, DEFAULT_PLATFORM, MANIFEST_HASH_LEN, ManifestFormat, TYPE_BIN, TYPE_MANIFEST,
+};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
-use std::ffi::{c_char, c_int, c_void};
-use libloading::{Library, Symbol};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tracing::{info, warn};
 
 #[derive(Parser)]
 #[command(name = "download")]
 #[command(about = "Download Soulframe language files from CDN")]
 struct Args {
-    /// Locales to download (comma-separated)
+    /// Locales to download (comma-separated). "all" selects every locale in the primary
+    /// manifest and "-<locale>" removes one, e.g. "all,-ru". Standard codes such as
+    /// zh-Hans are translated to the game's tokens (see locale-aliases.json)
     #[arg(short, long, default_value = "en,fr,de,es,it,pt,ru,pl,tr,ja,ko,zh")]
     locales: String,
-}
 
-fn get_download_path(path: &str, suffix: Option<&str>) -> PathBuf {
-    let suffix = suffix.unwrap_or("");
-    let root = std::env::current_dir().unwrap();
-    root.join("downloaded-data").join(format!("0{}{}", suffix, path))
-}
+    /// Write the computed SHCC hash of each downloaded file to this JSON file
+    #[arg(long, value_name = "PATH")]
+    emit_hashes: Option<PathBuf>,
 
-fn b64m_encode(data: &[u8]) -> String {
-    use base64::prelude::*;
-    BASE64_STANDARD_NO_PAD.encode(data).replace('/', "-")
-}
+    /// Download the primary manifest and each locale's localized manifest, but no
+    /// Languages.bin, e.g. to index the file tree
+    #[arg(long)]
+    manifests_only: bool,
+
+    /// Abort on the first locale that fails instead of continuing with the rest
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Threads used to decompress Oodle blocks (defaults to one per CPU; 1 disables parallelism)
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Platform token in localized manifest names (`/B.Cache.<platform>_<lang>.bin`)
+    #[arg(long, default_value = DEFAULT_PLATFORM)]
+    platform: String,
+
+    /// Game version the CDN content belongs to; versions before 40.0.0 use the old
+    /// manifest layout and type IDs
+    #[arg(long, value_name = "VERSION")]
+    game_version: Option<String>,
+
+    /// Keep this game version's files in downloaded-data/<manifest hash>/ instead of
+    /// overwriting the previous ones, and point downloaded-data/latest at it on success
+    #[arg(long)]
+    snapshot: bool,
+
+    /// Wait at least this many milliseconds between consecutive CDN requests
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    delay_ms: u64,
+
+    /// Cap the average CDN request rate, in requests per second
+    #[arg(long, value_name = "N")]
+    max_rps: Option<f64>,
+
+    /// Reject responses, and payloads once decompressed, larger than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_DOWNLOAD_SIZE)]
+    max_download_size: u64,
+
+    /// Take an Oodle-compressed payload to decompress to at most this many times its
+    /// size. Raise it if such a payload fails to decompress
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_DECOMPRESS_MULTIPLIER,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    decompress_multiplier: usize,
+
+    /// Don't check downloaded files against their manifest hash, for dev builds known
+    /// to serve mismatching files
+    #[arg(long)]
+    no_verify: bool,
+
+    /// Give up on a request that hasn't completed in this long, e.g. 30s or 2m,
+    /// counting from the start of the connection to the end of the body
+    #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = humantime::parse_duration)]
+    timeout: Duration,
+
+    /// Give up on connecting to a host after this long (default: only --timeout applies)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    connect_timeout: Option<Duration>,
+
+    /// Log more: -v adds each URL tried and other details, -vv parse offsets.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Don't color status markers (also off when NO_COLOR is set or stdout isn't a
+    /// terminal)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// If another run holds the downloaded-data lock, wait for it to finish instead
+    /// of failing
+    #[arg(long, global = true)]
+    wait: bool,
+
+    /// Share downloaded files between runs and snapshots through a cache folder
+    /// keyed by content hash
+    #[arg(long, value_name = "DIR", global = true)]
+    cache_dir: Option<PathBuf>,
+
+    /// Download this one file instead of the locale files; the same as `fetch <PATH>`
+    #[arg(long, value_name = "PATH")]
+    file: Option<String>,
 
-/// Oodle compression library interface
-struct Oodle {
-    #[allow(dead_code)]
-    lib: Library,
-    decompress_fn: Symbol<'static, unsafe extern "C" fn(
-        *const c_char, usize, *mut c_void, usize,
-        c_int, c_int, c_int, usize, usize, usize, usize, usize, usize, c_int
-    ) -> c_int>,
+    /// With --file, the hash to request (b64m, 16 bytes), bypassing the primary manifest
+    #[arg(long, value_name = "B64M", requires = "file")]
+    hash: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl Oodle {
-    fn new() -> Result<Self> {
-        let lib_name = if cfg!(windows) {
-            "oo2core_9.dll"
-        } else {
-            "oo2core_9.so"
-        };
+#[derive(Subcommand)]
+enum Command {
+    /// Download a single file by path instead of the locale files
+    Fetch {
+        /// Path as listed in the manifest, e.g. /B.Cache.Windows_de.bin
+        path: String,
 
-        let lib_path = find_runtime_lib(lib_name)?;
+        /// Type byte: "bin", "manifest", or a number such as 0x2C
+        #[arg(long = "type", value_name = "TYPE", default_value = "bin", value_parser = parse_file_type)]
+        file_type: u8,
+
+        /// Hash to request (b64m, 16 bytes), bypassing the primary manifest
+        #[arg(long, value_name = "B64M")]
+        hash: Option<String>,
+    },
+    
+    /// Manage the snapshots written by --snapshot
+    Snapshots {
+        #[command(subcommand)]
+        command: SnapshotsCommand,
+    },
+    
+    /// Manage the --cache-dir content cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    
+    /// Poll the primary manifest and run download + extract whenever the selected
+    /// locales' entries change, until Ctrl-C
+    Watch {
+        /// Time between polls, e.g. "30m", "1h", "90s"
+        #[arg(long, default_value = "30m", value_parser = humantime::parse_duration)]
+        interval: Duration,
         
-        unsafe {
-            let lib = Library::new(&lib_path)
-                .map_err(|e| anyhow!("Failed to load Oodle library from {:?}: {}", lib_path, e))?;
-            
-            let decompress_fn: Symbol<unsafe extern "C" fn(
-                *const c_char, usize, *mut c_void, usize,
-                c_int, c_int, c_int, usize, usize, usize, usize, usize, usize, c_int
-            ) -> c_int> = lib.get(b"OodleLZ_Decompress\0")
-                .map_err(|e| anyhow!("Failed to get OodleLZ_Decompress function: {}", e))?;
-            
-            // Extend the lifetime to 'static - this is safe because we keep the library alive
-            let decompress_fn: Symbol<'static, _> = std::mem::transmute(decompress_fn);
-            
-            Ok(Self { lib, decompress_fn })
-        }
-    }
+        /// Shell command run after each update. SOULFRAME_CHANGED_PATHS,
+        /// SOULFRAME_CHANGED_LOCALES and SOULFRAME_SNAPSHOT describe what changed.
+        #[arg(long, value_name = "COMMAND")]
+        on_update: Option<String>,
+    },
+    
+    /// Pack the manifests and locale files in downloaded-data into one .tar.zst or
+    /// .zip for copying to another machine
+    Bundle {
+        /// Output file; the extension picks the format
+        output: PathBuf,
+    },
+    
+    /// Check that the runtime libraries load, the CDN answers and the data folders
+    /// are writable, without downloading anything
+    Doctor,
     
-    fn decompress(&self, compressed: &[u8], decompressed_size: usize) -> Result<Vec<u8>> {
-        let mut output = vec![0u8; decompressed_size];
+    /// List each locale's downloaded and extracted files, without network access
+    Status {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Remove files no manifest on disk lists, files whose hash no longer matches,
+    /// and the folders of locales not in --locales
+    Clean {
+        /// List what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
         
-        unsafe {
-            let result = (self.decompress_fn)(
-                compressed.as_ptr() as *const c_char,
-                compressed.len(),
-                output.as_mut_ptr() as *mut c_void,
-                decompressed_size,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 3
-            );
-            
-            if result as usize != decompressed_size {
-                return Err(anyhow!("Oodle decompression failed"));
-            }
-        }
+        /// Also remove folders left empty
+        #[arg(long)]
+        prune_empty_dirs: bool,
+    },
+    
+    /// Verify a bundle and unpack it into downloaded-data
+    Unbundle {
+        /// Bundle written by `download bundle`
+        input: PathBuf,
         
-        Ok(output)
-    }
+        /// Replace files from a different primary manifest already in downloaded-data
+        #[arg(long)]
+        overwrite: bool,
+    },
 }
 
-#[derive(Debug, Clone)]
-struct ShccData {
-    h: Vec<u8>,
-    b: Option<Vec<u8>>,
+#[derive(Subcommand)]
+enum SnapshotsCommand {
+    /// List the snapshots on disk with their dates and file counts
+    List,
 }
 
-fn shcc_decompress_chunk_oodle(bin: &[u8], start: usize, decompressed_size: usize, oodle: &Oodle) -> Result<(Vec<u8>, usize)> {
-    let mut decompressed = Vec::new();
-    let mut i = start;
-    
-    while decompressed.len() < decompressed_size {
-        if i + 8 > bin.len() {
-            return Err(anyhow!("Unexpected end of data in SHCC Oodle chunk"));
-        }
-        
-        let block_info = &bin[i..i + 8];
-        i += 8;
-        
-        if block_info[0] != 0x80 {
-            return Err(anyhow!("Invalid block header"));
-        }
-        
-        if (block_info[7] & 0x0F) != 0x01 {
-            return Err(anyhow!("Invalid block footer"));
-        }
-        
-        let num1 = ((block_info[0] as u32) << 24) | 
-                   ((block_info[1] as u32) << 16) | 
-                   ((block_info[2] as u32) << 8) | 
-                   (block_info[3] as u32);
-        let num2 = ((block_info[4] as u32) << 24) | 
-                   ((block_info[5] as u32) << 16) | 
-                   ((block_info[6] as u32) << 8) | 
-                   (block_info[7] as u32);
-        
-        let block_compressed_size = ((num1 >> 2) & 0xFFFFFF) as usize;
-        let block_decompressed_size = ((num2 >> 5) & 0xFFFFFF) as usize;
-        
-        if i >= bin.len() || bin[i] != 0x8C {
-            return Err(anyhow!("Invalid Oodle block marker"));
-        }
-        
-        if i + block_compressed_size > bin.len() {
-            return Err(anyhow!("Block compressed size exceeds available data"));
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove cached files no manifest in downloaded-data or its snapshots lists
+    Gc {
+        /// List what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+fn parse_file_type(value: &str) -> std::result::Result<u8, String> {
+    match value {
+        "bin" => Ok(TYPE_BIN),
+        "manifest" => Ok(TYPE_MANIFEST),
+        _ => {
+            let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+                Some(hex) => u8::from_str_radix(hex, 16),
+                None => value.parse(),
+            };
+            parsed.map_err(|_| format!("expected \"bin\", \"manifest\" or a byte value, got {:?}", value))
         }
-        
-        let block_data = oodle.decompress(&bin[i..i + block_compressed_size], block_decompressed_size)?;
-        decompressed.extend_from_slice(&block_data);
-        i += block_compressed_size;
     }
-    
-    Ok((decompressed, i))
 }
 
-fn shcc_decompress_chunk(bin: &[u8], start: usize, oodle: &Oodle) -> Result<(Vec<u8>, usize)> {
-    if start + 9 > bin.len() {
-        return Err(anyhow!("Not enough data for SHCC chunk header"));
+/// Collects `path -> hex SHCC hash` for `--emit-hashes`, rewriting the file after each download
+struct HashLog {
+    path: Option<PathBuf>,
+    hashes: BTreeMap<String, String>,
+}
+
+impl HashLog {
+    fn new(path: Option<PathBuf>) -> Self {
+        Self { path, hashes: BTreeMap::new() }
     }
-    
-    let chunk_type = bin[start];
-    let decompressed_size = u32::from_le_bytes([
-        bin[start + 1], bin[start + 2], bin[start + 3], bin[start + 4]
-    ]) as usize;
-    let compressed_size = u32::from_le_bytes([
-        bin[start + 5], bin[start + 6], bin[start + 7], bin[start + 8]
-    ]) as usize;
-    
-    let mut i = start + 9;
-    
-    match chunk_type {
-        0 => {
-            // Uncompressed
-            if compressed_size != decompressed_size {
-                return Err(anyhow!("Compressed size mismatch for uncompressed chunk"));
-            }
-            
-            if i + compressed_size > bin.len() {
-                return Err(anyhow!("Not enough data for uncompressed chunk"));
-            }
-            
-            let data = bin[i..i + compressed_size].to_vec();
-            i += decompressed_size;
-            Ok((data, i))
-        }
-        2 => {
-            // Oodle compressed
-            shcc_decompress_chunk_oodle(bin, i, decompressed_size, oodle)
+
+    fn record(&mut self, file_path: String, hash: String) -> Result<()> {
+        let Some(ref out_path) = self.path else {
+            return Ok(());
+        };
+
+        self.hashes.insert(file_path, hash);
+
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
         }
-        _ => Err(anyhow!("Unknown chunk type: {}", chunk_type))
+        fs::write(out_path, serde_json::to_string_pretty(&self.hashes)?)?;
+        Ok(())
     }
 }
 
-fn shcc_unpack(bin: &[u8], oodle: &Oodle) -> Result<ShccData> {
-    if bin.len() < 8 {
-        return Err(anyhow!("SHCC data too short"));
+/// Manifest hash of every file from the last successful runs, kept in
+/// `downloaded-data/state.json` so unchanged files are skipped without reading them
+struct RunState {
+    path: PathBuf,
+    hashes: BTreeMap<String, String>,
+    changed: usize,
+    unchanged: usize,
+}
+
+impl RunState {
+    fn load(path: PathBuf) -> Self {
+        let hashes = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self { path, hashes, changed: 0, unchanged: 0 }
     }
-    
-    let mut i = 8; // Skip initial 8 bytes
-    
-    // Decompress H chunk
-    let (h_data, new_i) = shcc_decompress_chunk(bin, i, oodle)?;
-    i = new_i;
-    
-    // Try to decompress B chunk (optional)
-    let b_data = if i < bin.len() {
-        match shcc_decompress_chunk(bin, i, oodle) {
-            Ok((b, _)) => Some(b),
-            Err(_) => None, // B chunk is optional
+
+    /// True if `file_path` was last downloaded with `manifest_hash`
+    fn is_unchanged(&self, file_path: &str, manifest_hash: &[u8]) -> bool {
+        self.hashes.get(file_path) == Some(&hex(manifest_hash))
+    }
+
+    fn record(&mut self, file_path: String, manifest_hash: &[u8]) -> Result<()> {
+        self.hashes.insert(file_path, hex(manifest_hash));
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
         }
-    } else {
-        None
-    };
-    
-    Ok(ShccData {
-        h: h_data,
-        b: b_data,
-    })
+        fs::write(&self.path, serde_json::to_string_pretty(&self.hashes)?)?;
+        Ok(())
+    }
 }
 
-struct SoulframeManifest {
-    bin: Vec<u8>,
-    i: usize,
-    entry_i: usize,
-    remaining_entries: u32,
-    paths: Vec<String>,
-    hashes: HashMap<String, Vec<u8>>,
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
-impl SoulframeManifest {
-    fn new(path: &str) -> Result<Self> {
-        let file_path = get_download_path(path, None);
-        let h_path = format!("{}_H", file_path.to_string_lossy());
-        
-        let bin = fs::read(&h_path)
-            .map_err(|_| anyhow!("{} was not found on disk.", path))?;
-        
-        Ok(Self {
-            bin,
-            i: 20, // Skip initial 20 bytes
-            entry_i: 0,
-            remaining_entries: 0,
-            paths: Vec::new(),
-            hashes: HashMap::new(),
-        })
+/// Reads the manifest `path` downloaded to `downloads`, which is `downloaded-data`
+/// or one of its snapshots
+fn load_manifest(downloads: &Path, path: &str, format: ManifestFormat) -> Result<SoulframeManifest> {
+    Ok(SoulframeManifest::from_storage(&LocalFs::new(downloads), path, format)?)
+}
+
+/// Client for the files under `downloads`, paced by `limiter`
+fn download_client(args: &Args, http: &reqwest::blocking::Client, limiter: &Arc<RateLimiter>, downloads: &Path) -> DownloadClient {
+    DownloadClient::with_client(http.clone())
+        .with_rate_limiter(Arc::clone(limiter))
+        .with_max_download_size(args.max_download_size)
+        .with_decompress_multiplier(args.decompress_multiplier)
+        .with_verify(!args.no_verify)
+        .with_storage(Arc::new(LocalFs::new(downloads)))
+}
+
+/// The download client plus the per-run bookkeeping every download feeds into
+struct Downloader {
+    client: DownloadClient,
+    /// Root that local paths are joined onto
+    downloads: PathBuf,
+    http: reqwest::blocking::Client,
+    limiter: Arc<RateLimiter>,
+    cache: Option<ContentCache>,
+    hash_log: HashLog,
+    state: RunState,
+    timings: PhaseTimings,
+    bytes_downloaded: u64,
+    /// Requests sent by clients `set_downloads` replaced
+    earlier_requests: u64,
+}
+
+impl Downloader {
+    fn new(args: &Args, http: reqwest::blocking::Client, downloads: &Path) -> Self {
+        let limiter = Arc::new(RateLimiter::new(Duration::from_millis(args.delay_ms), args.max_rps));
+        Self {
+            client: download_client(args, &http, &limiter, downloads),
+            downloads: downloads.to_path_buf(),
+            http,
+            limiter,
+            cache: args.cache_dir.as_ref().map(ContentCache::new),
+            hash_log: HashLog::new(args.emit_hashes.clone()),
+            state: RunState::load(downloads.join("state.json")),
+            timings: PhaseTimings::default(),
+            bytes_downloaded: 0,
+            earlier_requests: 0,
+        }
     }
     
-    fn seek(&mut self, opt_stop_at_path: Option<&str>) -> Option<Vec<u8>> {
-        while self.i < self.bin.len() {
-            while self.remaining_entries == 0 {
-                if self.i + 4 > self.bin.len() {
-                    return None;
-                }
-                
-                self.remaining_entries = u32::from_le_bytes([
-                    self.bin[self.i],
-                    self.bin[self.i + 1],
-                    self.bin[self.i + 2],
-                    self.bin[self.i + 3],
-                ]);
-                self.i += 4;
-            }
-            
-            self.entry_i += 1;
-            self.remaining_entries -= 1;
-            
-            // Read path (4-byte length prefix + string)
-            if self.i + 4 > self.bin.len() {
-                break;
-            }
-            
-            let path_len = u32::from_le_bytes([
-                self.bin[self.i],
-                self.bin[self.i + 1],
-                self.bin[self.i + 2],
-                self.bin[self.i + 3],
-            ]) as usize;
-            self.i += 4;
-            
-            if self.i + path_len + 20 > self.bin.len() {
-                break;
-            }
-            
-            let path = String::from_utf8_lossy(&self.bin[self.i..self.i + path_len]).to_string();
-            self.i += path_len;
-            
-            // Read hash (16 bytes) and skip unk (4 bytes)
-            let hash = self.bin[self.i..self.i + 16].to_vec();
-            self.i += 20; // 16 bytes hash + 4 bytes unk
-            
-            self.paths.push(path.clone());
-            self.hashes.insert(path.clone(), hash.clone());
-            
-            if let Some(target_path) = opt_stop_at_path {
-                if path == target_path {
-                    return Some(hash);
-                }
-            }
-        }
-        
-        None
+    /// Sends later downloads to `downloads`, e.g. a snapshot folder, with its own state
+    fn set_downloads(&mut self, args: &Args, downloads: PathBuf) {
+        self.earlier_requests += self.client.requests_sent();
+        self.client = download_client(args, &self.http, &self.limiter, &downloads);
+        self.state = RunState::load(downloads.join("state.json"));
+        self.downloads = downloads;
     }
     
-    fn get_hash(&mut self, path: &str) -> Option<Vec<u8>> {
-        if let Some(hash) = self.hashes.get(path) {
-            return Some(hash.clone());
-        }
-        
-        self.seek(Some(path))
+    /// HTTP requests sent so far, all through the one pooled client
+    fn requests(&self) -> u64 {
+        self.earlier_requests + self.client.requests_sent()
+    }
+    
+    /// Runs `f`, recording its duration and the bytes downloaded meanwhile under `phase`
+    fn timed<T>(&mut self, phase: &str, f: impl FnOnce(&mut Self) -> T) -> T {
+        let started = Instant::now();
+        let bytes_before = self.bytes_downloaded;
+        let result = f(self);
+        let bytes = self.bytes_downloaded - bytes_before;
+        self.timings.record(phase, started.elapsed(), bytes);
+        result
     }
     
-    fn download_file(&mut self, path: &str, file_type: u8, suffix: Option<&str>, client: &reqwest::blocking::Client) -> Result<bool> {
-        let manifest_hash = self.get_hash(path);
+    /// Downloads `path` as listed in `manifest` unless it is unchanged, already on
+    /// disk or in the cache. `suffix` selects the CDN folder; `local_suffix` the
+    /// folder under `downloads`.
+    fn download_file(
+        &mut self,
+        manifest: &mut SoulframeManifest,
+        path: &str,
+        file_type: u8,
+        suffix: Option<&str>,
+        local_suffix: Option<&str>,
+    ) -> Result<bool> {
+        let manifest_hash = manifest.get_hash(path).ok_or_else(|| anyhow!("file not in manifest"))?;
+        let state_key = format!("/0{}{}", local_suffix.unwrap_or(""), path);
         
-        if manifest_hash.is_none() {
-            return Err(anyhow!("file not in manifest"));
-        }
+        let local_path = safe_join(&self.downloads, &download_key(path, local_suffix))?;
+        let h_path = format!("{}_H", local_path.to_string_lossy());
         
-        let manifest_hash = manifest_hash.unwrap();
+        // Unchanged since the last run and still on disk
+        if self.state.is_unchanged(&state_key, &manifest_hash) && fs::metadata(&h_path).is_ok() {
+            info!("  {} {} unchanged since last run", Status::Skip, path);
+            self.state.unchanged += 1;
+            return Ok(true);
+        }
+        self.state.changed += 1;
         
         // Check if file already exists with correct hash
-        let local_path = get_download_path(path, suffix);
-        let h_path = format!("{}_H", local_path.to_string_lossy());
-        
         if let Ok(existing_content) = fs::read(&h_path) {
-            if existing_content.len() >= 16 {
-                let header_hash = &existing_content[0..16];
-                if header_hash == manifest_hash {
-                    println!("  File {} already exists with correct hash, skipping download", path);
-                    return Ok(true);
+            if existing_content.get(0..MANIFEST_HASH_LEN) == Some(manifest_hash.as_slice()) {
+                match DownloadMeta::verify(&local_path) {
+                    Ok(()) => {
+                        info!("  {} {} already on disk with the manifest's hash", Status::Skip, path);
+                        self.state.record(state_key, &manifest_hash)?;
+                        return Ok(true);
+                    }
+                    Err(err) => warn!("  {}, downloading again", err),
                 }
             }
         }
         
+        if let Some(cache) = &self.cache {
+            if cache.restore(&manifest_hash, &local_path)? {
+                info!("  {} {} restored from the cache", Status::Skip, path);
+                self.state.record(state_key, &manifest_hash)?;
+                return Ok(true);
+            }
+        }
+        
         let hash_b64 = b64m_encode(&manifest_hash);
-        download_soulframe_file(client, path, file_type, Some(&hash_b64), suffix)
+        let downloaded = self.download_soulframe_file(path, file_type, Some(&hash_b64), suffix, local_suffix)?;
+        if downloaded {
+            self.state.record(state_key, &manifest_hash)?;
+            // Only files whose _H header matches the manifest hash are stored
+            if let Some(cache) = &self.cache {
+                cache.store(&manifest_hash, &local_path)?;
+            }
+        }
+        Ok(downloaded)
+    }
+    
+    /// Downloads one file through `client`, adding it to the hash log and timings.
+    /// Returns `Ok(false)` if every URL failed.
+    fn download_soulframe_file(
+        &mut self,
+        path: &str,
+        file_type: u8,
+        b64m_hash: Option<&str>,
+        suffix: Option<&str>,
+        local_suffix: Option<&str>,
+    ) -> Result<bool> {
+        let stored = match self.client.download_to(path, file_type, b64m_hash, suffix, local_suffix) {
+            Ok(stored) => stored,
+            Err(SoulframeError::Http { .. } | SoulframeError::DownloadFailed { .. }) => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        
+        let meta = stored.meta;
+        self.bytes_downloaded += meta.payload_size as u64;
+        let unpacked_bytes = meta.h_size + meta.b_size.unwrap_or(0);
+        self.timings.record("Oodle decompress", stored.decode_time, unpacked_bytes as u64);
+        
+        let slash = if path.starts_with('/') { "" } else { "/" };
+        self.hash_log.record(format!("/0{}{}{}", local_suffix.unwrap_or(""), slash, path), meta.shcc_hash)?;
+        Ok(true)
     }
 }
 
-fn download_soulframe_file(
-    client: &reqwest::blocking::Client,
-    path: &str,
-    file_type: u8,
-    b64m_hash: Option<&str>,
-    suffix: Option<&str>,
-) -> Result<bool> {
-    let b64m_hash = b64m_hash.unwrap_or("---------------------w");
-    let suffix = suffix.unwrap_or("");
-    
-    let normalized_path = if path.starts_with('/') {
+/// Downloads one file, with its hash from the primary manifest unless `hash` is given,
+/// and reports where its `_H`/`_B` files were written
+fn fetch(downloader: &mut Downloader, path: &str, file_type: u8, hash: Option<&str>, format: ManifestFormat) -> Result<()> {
+    let path = if path.starts_with('/') {
         path.to_string()
     } else {
         format!("/{}", path)
     };
     
-    let req_path = format!("/0{}{}!{:X}_{}", suffix, normalized_path, file_type, b64m_hash);
+    let hash_b64 = match hash {
+        Some(hash) => {
+            let decoded = b64m_decode(hash)?;
+            if decoded.len() != MANIFEST_HASH_LEN {
+                return Err(anyhow!(
+                    "--hash must decode to {} bytes, got {}",
+                    MANIFEST_HASH_LEN,
+                    decoded.len()
+                ));
+            }
+            hash.to_string()
+        }
+        None => {
+            info!("Downloading primary manifest /H.Cache.bin ...");
+            if !downloader.download_soulframe_file("/H.Cache.bin", format.type_manifest(), None, None, None)? {
+                return Err(anyhow!("Failed to download /H.Cache.bin"));
+            }
+            
+            let mut meta = load_manifest(&downloader.downloads, "/H.Cache.bin", format)?;
+            let manifest_hash = meta.get_hash(&path)
+                .ok_or_else(|| anyhow!("{} is not in the primary manifest", path))?;
+            b64m_encode(&manifest_hash)
+        }
+    };
+    
+    info!("Fetching {} (type 0x{:X}, hash {})", path, file_type, hash_b64);
+    if !downloader.download_soulframe_file(&path, file_type, Some(&hash_b64), None, None)? {
+        return Err(anyhow!("Failed to download {}", path));
+    }
     
-    let mut urls = Vec::new();
+    let local_path = safe_join(&downloader.downloads, &download_key(&path, None))?;
+    for part in ["_H", "_B"] {
+        let part_path = format!("{}{}", local_path.to_string_lossy(), part);
+        if let Ok(metadata) = fs::metadata(&part_path) {
+            info!("  {} {} ({} bytes)", Status::Ok, part_path, metadata.len());
+        }
+    }
     
-    // Prefer the CDN, but include origin endpoints and a cache-busting origin URL as fallbacks.
-    urls.push(format!("https://content.soulframe.com{}", req_path));
-    urls.push(format!("https://origin.soulframe.com{}", req_path));
+    Ok(())
+}
 
-    let random_id: u32 = rand::thread_rng().gen();
-    urls.push(format!("https://origin.soulframe.com/origin/{:08X}{}", random_id, req_path));
-    urls.push(format!("https://origin.soulframe.com/origin/0{}", req_path));
+/// Prints the snapshots under `downloads`, oldest first
+fn list_snapshots_command(downloads: &Path) -> Result<()> {
+    let snapshots = list_snapshots(downloads)?;
+    if snapshots.is_empty() {
+        println!("No snapshots in {}", downloads.to_string_lossy());
+        return Ok(());
+    }
     
-    for url in urls {
-        println!("Attempting download from {}", url);
-        
-        match client.get(&url).send() {
-            Ok(response) if response.status().is_success() => {
-                println!("Successfully downloaded from {}", url);
-                
-                let bin = response.bytes()?.to_vec();
-                let local_path = get_download_path(&normalized_path, Some(suffix));
-                
-                // Create parent directories
-                if let Some(parent) = local_path.parent() {
-                    fs::create_dir_all(parent)?;
-                }
-                
-                let shcc_itself_compressed = !bin.starts_with(b"SHCC");
-                
-                let final_bin = if shcc_itself_compressed {
-                    let oodle = Oodle::new()?;
-                    // Estimate decompressed size (the original uses bin size * 10)
-                    oodle.decompress(&bin, bin.len() * 10)?
-                } else {
-                    bin
-                };
-                
-                let oodle = Oodle::new()?;
-                let data = shcc_unpack(&final_bin, &oodle)?;
-                
-                // Write H data (the decompressed content)
-                let h_path = format!("{}_H", local_path.to_string_lossy());
-                fs::write(&h_path, &data.h)?;
-                
-                // Write B data if present
-                if let Some(ref b_data) = data.b {
-                    let b_path = format!("{}_B", local_path.to_string_lossy());
-                    fs::write(&b_path, b_data)?;
-                }
-                
-                return Ok(true);
-            }
-            Ok(response) => {
-                println!(
-                    "Download failed from {} (HTTP {})",
-                    url,
-                    response.status().as_u16()
-                );
-            }
-            Err(e) => {
-                println!("Download failed from {}: {}", url, e);
-            }
-        }
+    println!("{:<10} {:<20} {:>6}", "Snapshot", "Created (UTC)", "Files");
+    for snapshot in &snapshots {
+        let created = snapshot.created
+            .and_then(|created| created.duration_since(UNIX_EPOCH).ok())
+            .map_or("unknown".to_string(), |since| utc_timestamp(since.as_secs()));
+        let latest = if snapshot.latest { "  (latest)" } else { "" };
+        println!("{:<10} {:<20} {:>6}{}", snapshot.id, created, snapshot.files, latest);
+    }
+    Ok(())
+}
+
+/// Removes cache entries that no manifest on disk refers to
+fn cache_gc(args: &Args, downloads: &Path, format: ManifestFormat, dry_run: bool) -> Result<()> {
+    let cache_dir = args.cache_dir.as_ref().ok_or_else(|| anyhow!("cache gc needs --cache-dir"))?;
+    let referenced = known_manifest_hashes(downloads, format)?;
+    if referenced.is_empty() {
+        return Err(anyhow!("No manifests in {}; refusing to empty the cache", downloads.to_string_lossy()));
     }
     
-    println!("All download attempts failed for {}", normalized_path);
-    Ok(false)
+    let removed = ContentCache::new(cache_dir).gc(&referenced, dry_run)?;
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    for (path, size) in &removed {
+        info!("{} {} ({} bytes)", verb, path.to_string_lossy(), size);
+    }
+    info!(
+        "{} {} cached files ({} bytes), keeping entries for {} manifest hashes",
+        verb,
+        removed.len(),
+        removed.iter().map(|(_, size)| size).sum::<u64>(),
+        referenced.len()
+    );
+    Ok(())
+}
+
+/// Prints `data_status` as a table, or as JSON with `json`
+fn status(downloads: &Path, platform: &str, json: bool) -> Result<()> {
+    let extracted = std::env::current_dir()?.join("extracted-data");
+    let reports = data_status(downloads, &extracted, platform)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+    if reports.is_empty() {
+        println!("Nothing downloaded or extracted in {}", downloads.to_string_lossy());
+        return Ok(());
+    }
+    
+    let mut table = Table::new(&["Locale", "_H", "Hash", "_B", "Manifest", "Strings", "Extracted"])
+        .right_align(1)
+        .right_align(3)
+        .right_align(5);
+    for report in &reports {
+        let size = |file: &Option<FileReport>| file.as_ref().map_or("-".to_string(), |f| f.size.to_string());
+        let hash = report.languages_h.as_ref().and_then(|h| h.hash.as_deref()).map_or("-", |hash| &hash[..12]);
+        let manifest = match &report.manifest {
+            None => "not on disk",
+            Some(ManifestReport { matches: None, .. }) => "listed",
+            Some(ManifestReport { matches: Some(true), .. }) => "match",
+            Some(ManifestReport { matches: Some(false), .. }) => "MISMATCH",
+        };
+        let (strings, extracted) = match &report.extracted {
+            None => ("-".to_string(), "no".to_string()),
+            Some(extracted) => (
+                extracted.strings.map_or("invalid".to_string(), |count| count.to_string()),
+                extracted.older_than_download_secs.map_or("current".to_string(), |secs| {
+                    format!("{} older than download", humantime::format_duration(Duration::from_secs(secs)))
+                }),
+            ),
+        };
+        let state = if report.is_current() {
+            Status::Ok
+        } else if report.languages_h.is_none() || report.manifest.as_ref().is_some_and(|m| m.matches == Some(false)) {
+            Status::Fail
+        } else {
+            Status::Warn
+        };
+        table.row(state, vec![
+            report.locale.clone(),
+            size(&report.languages_h),
+            hash.to_string(),
+            size(&report.languages_b),
+            manifest.to_string(),
+            strings,
+            extracted,
+        ]);
+    }
+    for line in table.lines() {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// `YYYY-MM-DD HH:MM` for `secs` seconds since the Unix epoch
+fn utc_timestamp(secs: u64) -> String {
+    // Days to civil date, after Howard Hinnant's `civil_from_days`
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, secs % 86_400 / 3600, secs % 3600 / 60)
+}
+
+/// Locales that have a `/B.Cache.<platform>_<lang>.bin` entry in the manifest
+fn manifest_locales(paths: &[String], platform: &str) -> Vec<String> {
+    let prefix = format!("/B.Cache.{}_", platform);
+    let mut locales: Vec<String> = paths
+        .iter()
+        .filter_map(|p| p.strip_prefix(prefix.as_str())?.strip_suffix(".bin"))
+        .filter(|lang| !lang.is_empty() && lang.chars().all(|c| c.is_alphanumeric() || c == '_'))
+        .map(|lang| lang.to_string())
+        .collect();
+    locales.sort();
+    locales.dedup();
+    locales
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    logging::init(args.verbose, args.quiet);
+    output::init(args.no_color);
+    
+    // `--file <PATH> --hash <B64M>` is shorthand for `fetch <PATH> --hash <B64M>`
+    if let Some(path) = args.file.take() {
+        if args.command.is_some() {
+            return Err(anyhow!("--file downloads a single file and can't be combined with a subcommand"));
+        }
+        args.command = Some(Command::Fetch { path, file_type: TYPE_BIN, hash: args.hash.take() });
+    }
     
-    println!("=== Soulframe Language Downloader ===");
+    // Keep `status --json` output parseable
+    if !matches!(args.command, Some(Command::Status { json: true })) {
+        info!("=== Soulframe Language Downloader ===");
+    }
     
-    // Parse locales
-    let locales: Vec<String> = args.locales
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .collect();
+    let format = match &args.game_version {
+        Some(version) => ManifestFormat::from_game_version(version)?,
+        None => ManifestFormat::Current,
+    };
+    
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.max(1))
+            .build_global()?;
+    }
+    
+    // One client for the whole run, so its pooled connections are reused across files
+    let client = build_http_client_with_timeouts(args.timeout, args.connect_timeout)?;
+    if args.decompress_multiplier != DEFAULT_DECOMPRESS_MULTIPLIER {
+        info!("Assuming Oodle payloads decompress to at most {}x their size", args.decompress_multiplier);
+    }
+    if args.no_verify {
+        warn!("--no-verify: downloaded files are not checked against their manifest hash");
+    }
     
-    // Create download client - use HTTP/1.1 only and disable automatic decompression
-    let client = reqwest::blocking::Client::builder()
-        .http1_only()
-        .no_gzip()
-        .no_brotli()
-        .no_deflate()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
+    let base_downloads = std::env::current_dir()?.join("downloaded-data");
+    let mut downloader = Downloader::new(&args, client.clone(), &base_downloads);
     
     // Ensure base folders exist
-    let marker_path = get_download_path("/marker", None);
+    let marker_path = safe_join(&base_downloads, &download_key("/marker", None))?;
     if let Some(parent) = marker_path.parent() {
         fs::create_dir_all(parent)?;
     }
     
-    // Download primary manifest
-    println!("Downloading primary manifest /H.Cache.bin ...");
-    if !download_soulframe_file(&client, "/H.Cache.bin", TYPE_MANIFEST, None, None)? {
-        println!("x Failed to download /H.Cache.bin");
-        return Ok(());
+    // Commands that write downloaded-data hold the run lock until they return;
+    // watch takes it for each poll instead
+    let lock_command = match &args.command {
+        None => Some("download"),
+        Some(Command::Fetch { .. }) => Some("download fetch"),
+        Some(Command::Unbundle { .. }) => Some("download unbundle"),
+        Some(Command::Clean { .. }) => Some("download clean"),
+        Some(Command::Cache { .. }) => Some("download cache gc"),
+        _ => None,
+    };
+    let _lock = lock_command
+        .map(|command| RunLock::acquire(&base_downloads, command, args.wait))
+        .transpose()?;
+    
+    match &args.command {
+        Some(Command::Fetch { path, file_type, hash }) => {
+            return fetch(&mut downloader, path, *file_type, hash.as_deref(), format);
+        }
+        Some(Command::Snapshots { command: SnapshotsCommand::List }) => {
+            return list_snapshots_command(&base_downloads);
+        }
+        Some(Command::Cache { command: CacheCommand::Gc { dry_run } }) => {
+            return cache_gc(&args, &base_downloads, format, *dry_run);
+        }
+        Some(Command::Watch { interval, on_update }) => {
+            return watch(&args, &client, &base_downloads, format, *interval, on_update.as_deref());
+        }
+        Some(Command::Doctor) => {
+            return doctor(&client, &base_downloads);
+        }
+        Some(Command::Status { json }) => {
+            return status(&base_downloads, &args.platform, *json);
+        }
+        Some(Command::Clean { dry_run, prune_empty_dirs }) => {
+            return clean_command(&args, &base_downloads, format, *dry_run, *prune_empty_dirs);
+        }
+        Some(Command::Bundle { output }) => {
+            let manifest = write_bundle(&base_downloads, output, BundleFormat::from_path(output)?)?;
+            info!(
+                "Bundled {} files (primary manifest {}) into {}",
+                manifest.files.len(),
+                manifest.manifest_hash,
+                output.to_string_lossy()
+            );
+            return Ok(());
+        }
+        Some(Command::Unbundle { input, overwrite }) => {
+            let manifest = unbundle(input, &base_downloads, *overwrite)?;
+            if manifest.tool_version != env!("CARGO_PKG_VERSION") {
+                info!(
+                    "Note: bundle was written by version {}, this is {}",
+                    manifest.tool_version,
+                    env!("CARGO_PKG_VERSION")
+                );
+            }
+            info!(
+                "Unpacked {} verified files (primary manifest {}) into {}",
+                manifest.files.len(),
+                manifest.manifest_hash,
+                base_downloads.to_string_lossy()
+            );
+            return Ok(());
+        }
+        None => {}
+    }
+    
+    let meta = fetch_primary_manifest(&mut downloader, &base_downloads, format)?;
+    run_download(&args, &mut downloader, &base_downloads, format, meta)?;
+    Ok(())
+}
+
+/// Removes stale and orphaned files for the selected locales and reports the space
+/// reclaimed
+fn clean_command(args: &Args, base_downloads: &Path, format: ManifestFormat, dry_run: bool, prune_empty_dirs: bool) -> Result<()> {
+    let mut meta = load_manifest(base_downloads, "/H.Cache.bin", format)
+        .context("clean needs the primary manifest; run download first")?;
+    let locales = select_locales(args, &meta.get_paths())?;
+    
+    let options = CleanOptions {
+        platform: args.platform.clone(),
+        locales,
+        format,
+        dry_run,
+        prune_empty_dirs,
+    };
+    let report = clean(base_downloads, &options)?;
+    
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    for removal in &report.files {
+        info!("{} {} ({}, {} bytes)", verb, removal.path.to_string_lossy(), removal.reason, removal.size);
+    }
+    for dir in &report.dirs {
+        info!("{} empty folder {}", verb, dir.to_string_lossy());
+    }
+    info!(
+        "{} {} files ({} bytes){}",
+        verb,
+        report.files.len(),
+        report.bytes(),
+        if report.dirs.is_empty() { String::new() } else { format!(" and {} empty folders", report.dirs.len()) }
+    );
+    Ok(())
+}
+
+/// Downloads `/H.Cache.bin` to `base_downloads` and reads all of its entries
+fn fetch_primary_manifest(downloader: &mut Downloader, base_downloads: &Path, format: ManifestFormat) -> Result<SoulframeManifest> {
+    info!("Downloading primary manifest /H.Cache.bin ...");
+    let downloaded = downloader.timed("Primary manifest download", |d| {
+        d.download_soulframe_file("/H.Cache.bin", format.type_manifest(), None, None, None)
+    })?;
+    if !downloaded {
+        return Err(anyhow!("Failed to download /H.Cache.bin"));
+    }
+    
+    let mut meta = load_manifest(base_downloads, "/H.Cache.bin", format)?;
+    info!("Primary manifest loaded with {} files", meta.get_paths().len());
+    Ok(meta)
+}
+
+/// Checks `--platform` against the primary manifest and expands `--locales`
+fn select_locales(args: &Args, paths: &[String]) -> Result<Vec<String>> {
+    // Other platforms must actually have localized manifests; the default one may
+    // fall back to the primary manifest as before
+    if args.platform != DEFAULT_PLATFORM {
+        let platforms = manifest_platforms(paths);
+        if !platforms.contains(&args.platform) {
+            return Err(anyhow!(
+                "No /B.Cache.{}_<lang>.bin entries in the primary manifest; available platforms: {}",
+                args.platform,
+                platforms.join(", ")
+            ));
+        }
     }
     
-    // Load primary manifest
-    let mut meta = SoulframeManifest::new("/H.Cache.bin")?;
+    // Parse locales against the ones the manifest has, or the built-in list if it names none
+    let mut known = manifest_locales(paths, &args.platform);
+    if known.is_empty() {
+        known = known_locales();
+    } else {
+        info!("Discovered {} locales: {}", known.len(), known.join(", "));
+    }
+    let aliases = LocaleAliases::load_default()?;
+    Ok(parse_locales(&aliases.translate_spec(&args.locales), &known)?)
+}
+
+/// Downloads the selected locales' files listed by the primary manifest `meta`,
+/// returning the snapshot ID with `--snapshot`
+fn run_download(
+    args: &Args,
+    downloader: &mut Downloader,
+    base_downloads: &Path,
+    format: ManifestFormat,
+    mut meta: SoulframeManifest,
+) -> Result<Option<String>> {
+    // With --snapshot, everything after the primary manifest goes to a folder named
+    // after its hash, which gets its own copy of the manifest and its own state
+    let snapshot = if args.snapshot {
+        let id = snapshot_id(meta.as_bytes()).ok_or_else(|| anyhow!("/H.Cache.bin is too short to name a snapshot"))?;
+        let snapshot_root = safe_join(base_downloads, &id)?;
+        let manifest_path = safe_join(base_downloads, &download_key("/H.Cache.bin", None))?;
+        let snapshot_manifest_path = safe_join(&snapshot_root, &download_key("/H.Cache.bin", None))?;
+        if let Some(parent) = snapshot_manifest_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        for part in ["_H", "_B", ".meta.json"] {
+            let source = format!("{}{}", manifest_path.to_string_lossy(), part);
+            if fs::metadata(&source).is_ok() {
+                fs::copy(&source, format!("{}{}", snapshot_manifest_path.to_string_lossy(), part))?;
+            }
+        }
+        info!("Snapshot {} -> {}", id, snapshot_root.to_string_lossy());
+        downloader.set_downloads(args, snapshot_root);
+        Some(id)
+    } else {
+        None
+    };
     
-    // Parse all manifest entries
-    meta.seek(None);
-    println!("Primary manifest loaded with {} files", meta.paths.len());
+    let locales = select_locales(args, &meta.get_paths())?;
     
     // Process each locale
+    let locale_count = locales.len();
+    let mut failed_locales: Vec<String> = Vec::new();
+    let mut table = Table::new(&["Locale", if args.manifests_only { "Manifest" } else { "Languages.bin" }]);
     for lang in locales {
-        println!("\n--- Locale: {} ---", lang);
+        info!("\n--- Locale: {} ---", lang);
         
-        // Try to download localized main manifest; fall back to global if missing
-        let localized_manifest = format!("/B.Cache.Windows_{}.bin", lang);
+        // Try to download localized main manifest; fall back to the primary one if missing
+        let localized_manifest = localized_manifest_path(&args.platform, &lang);
         let mut have_localized_manifest = false;
-        match meta.download_file(&localized_manifest, TYPE_MANIFEST, None, &client) {
+        let manifest_result = downloader.timed("Locale manifest fetch", |d| {
+            d.download_file(&mut meta, &localized_manifest, format.type_manifest(), None, None)
+        });
+        match manifest_result {
             Ok(true) => {
-                println!("  Localized manifest ready for {}", lang);
+                info!("  Localized manifest ready for {}", lang);
                 have_localized_manifest = true;
             }
             Ok(false) => {
-                println!("  x Failed to obtain localized manifest for {}", lang);
+                warn!("  {} localized manifest for {}", Status::Fail, lang);
             }
             Err(_) => {
-                println!("  (no localized manifest entry in primary manifest)");
+                info!("  (no localized manifest entry in primary manifest)");
+            }
+        }
+        
+        if args.manifests_only {
+            match manifest_result {
+                Ok(true) => table.row(Status::Ok, vec![lang.clone(), "ready".to_string()]),
+                Ok(false) => {
+                    table.row(Status::Fail, vec![lang.clone(), "all download attempts failed".to_string()]);
+                    failed_locales.push(lang.clone());
+                }
+                Err(_) => table.row(Status::Skip, vec![lang.clone(), "not in the primary manifest".to_string()]),
+            }
+            if args.fail_fast && failed_locales.last() == Some(&lang) {
+                downloader.timings.print();
+                return Err(anyhow!("Aborting after locale {} failed (--fail-fast)", lang));
             }
+            continue;
         }
 
-    // Try to use the localized manifest (either just downloaded or already existing on disk)
-    let localized_manifest_h = format!("{}_H", get_download_path(&localized_manifest, None).to_string_lossy());
-    match if have_localized_manifest || fs::metadata(&localized_manifest_h).is_ok() { SoulframeManifest::new(&localized_manifest) } else { Err(anyhow!("{} was not found on disk.", &localized_manifest)) } {
-            Ok(mut localized_man) => {
-                println!("  Using localized manifest for {}", lang);
-                let suffix = format!("_{}", lang);
-                match localized_man.download_file("/Languages.bin", TYPE_BIN, Some(&suffix), &client) {
-                    Ok(true) => {
-                        println!("  ✓ Languages.bin downloaded for {}", lang);
-                    }
-                    Ok(false) => {
-                        println!("  x Languages.bin failed for {}", lang);
-                    }
-                    Err(err) => {
-                        println!("  x Languages.bin failed for {}: {}", lang, err);
-                    }
+        // Try to use the localized manifest (either just downloaded or already existing on disk);
+        // some locales ship Languages.bin in the primary manifest instead
+        let localized_manifest_h = format!("{}_H", safe_join(&downloader.downloads, &download_key(&localized_manifest, None))?.to_string_lossy());
+        let mut localized_man;
+        let manifest = if have_localized_manifest || fs::metadata(&localized_manifest_h).is_ok() {
+            match load_manifest(&downloader.downloads, &localized_manifest, format) {
+                Ok(man) => {
+                    info!("  Using localized manifest for {}", lang);
+                    localized_man = man;
+                    Some(&mut localized_man)
                 }
+                Err(err) => {
+                    warn!("  {} cannot load manifest for {}: {}", Status::Fail, lang, err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let manifest = manifest.unwrap_or_else(|| {
+            info!("  Falling back to the primary manifest for {}", lang);
+            &mut meta
+        });
+        
+        let suffix = format!("_{}", lang);
+        let local_suffix = locale_suffix(&lang, &args.platform);
+        let languages_result = downloader.timed("Languages.bin download", |d| {
+            d.download_file(manifest, "/Languages.bin", format.type_bin(), Some(&suffix), Some(&local_suffix))
+        });
+        match languages_result {
+            Ok(true) => {
+                info!("  {} Languages.bin ready for {}", Status::Ok, lang);
+                table.row(Status::Ok, vec![lang.clone(), "ready".to_string()]);
+            }
+            Ok(false) => {
+                warn!("  {} Languages.bin for {}", Status::Fail, lang);
+                table.row(Status::Fail, vec![lang.clone(), "all download attempts failed".to_string()]);
+                failed_locales.push(lang.clone());
             }
             Err(err) => {
-                println!("  x Cannot load manifest for {}: {}", lang, err);
+                warn!("  {} Languages.bin for {}: {}", Status::Fail, lang, err);
+                table.row(Status::Fail, vec![lang.clone(), err.to_string()]);
+                failed_locales.push(lang.clone());
             }
         }
+        
+        if args.fail_fast && failed_locales.last() == Some(&lang) {
+            downloader.timings.print();
+            return Err(anyhow!("Aborting after locale {} failed (--fail-fast)", lang));
+        }
+    }
+    
+    info!("");
+    for line in table.lines() {
+        info!("{}", line);
+    }
+    
+    downloader.timings.print();
+    info!("  {} HTTP requests, all through one pooled client", downloader.requests());
+    
+    info!(
+        "\n{} files changed since last run, {} unchanged",
+        downloader.state.changed,
+        downloader.state.unchanged
+    );
+    
+    if !failed_locales.is_empty() {
+        return Err(anyhow!(
+            "{} of {} locales failed: {}",
+            failed_locales.len(),
+            locale_count,
+            failed_locales.join(", ")
+        ));
+    }
+    
+    if let Some(id) = &snapshot {
+        set_latest(base_downloads, id)?;
+        info!("\nDownload complete! Files saved to ./downloaded-data/{}/ (now latest)", id);
+        return Ok(snapshot);
+    }
+    
+    info!("\nDownload complete! Files saved to ./downloaded-data/");
+    if !args.manifests_only {
+        info!("Run 'extract' to convert Languages.bin files to JSON.");
+    }
+    
+    Ok(None)
+}
+
+/// Manifest hashes of the entries `watch` cares about as of its last completed
+/// update, kept in `downloaded-data/watch.json` across restarts
+struct WatchState {
+    path: PathBuf,
+    hashes: BTreeMap<String, String>,
+}
+
+impl WatchState {
+    fn load(path: PathBuf) -> Self {
+        let hashes = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self { path, hashes }
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(&self.hashes)?)?;
+        Ok(())
+    }
+}
+
+/// Polls every `interval` until Ctrl-C. A poll interrupted by Ctrl-C finishes first,
+/// so files and state are never left half-written.
+fn watch(
+    args: &Args,
+    client: &reqwest::blocking::Client,
+    base_downloads: &Path,
+    format: ManifestFormat,
+    interval: Duration,
+    on_update: Option<&str>,
+) -> Result<()> {
+    if args.manifests_only {
+        return Err(anyhow!("--manifests-only can't be used with watch, which extracts after each update"));
+    }
+    
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))?;
+    
+    let mut state = WatchState::load(base_downloads.join("watch.json"));
+    info!("watch: polling every {}", humantime::format_duration(interval));
+    while !stop.load(Ordering::SeqCst) {
+        if let Err(err) = watch_poll(args, client, base_downloads, format, on_update, &mut state) {
+            warn!("watch: poll failed: {:#}", err);
+        }
+        
+        let next_poll = Instant::now() + interval;
+        while !stop.load(Ordering::SeqCst) && Instant::now() < next_poll {
+            std::thread::sleep(Duration::from_millis(250));
+        }
     }
     
-    println!("\n✓ Download complete! Files saved to ./downloaded-data/");
-    println!("Run 'extract' to convert Languages.bin files to JSON.");
+    info!("watch: stopping");
+    Ok(())
+}
+
+/// One `watch` poll: runs the pipeline if the primary manifest's `/Languages.bin`
+/// or selected localized manifest hashes differ from the last completed update
+fn watch_poll(
+    args: &Args,
+    client: &reqwest::blocking::Client,
+    base_downloads: &Path,
+    format: ManifestFormat,
+    on_update: Option<&str>,
+    state: &mut WatchState,
+) -> Result<()> {
+    let manifest_path = safe_join(base_downloads, &download_key("/H.Cache.bin", None))?;
+    if !state.hashes.is_empty() && manifest_not_modified(client, &manifest_path) {
+        info!("watch: /H.Cache.bin not modified");
+        return Ok(());
+    }
     
+    let lock = RunLock::acquire(base_downloads, "download watch", args.wait)?;
+    let mut downloader = Downloader::new(args, client.clone(), base_downloads);
+    let mut meta = fetch_primary_manifest(&mut downloader, base_downloads, format)?;
+    let locales = select_locales(args, &meta.get_paths())?;
+    
+    let watched = std::iter::once("/Languages.bin".to_string())
+        .chain(locales.iter().map(|lang| localized_manifest_path(&args.platform, lang)));
+    let hashes: BTreeMap<String, String> = watched
+        .filter_map(|path| Some((path.clone(), hex(&meta.get_hash(&path)?))))
+        .collect();
+    let changed: Vec<String> = hashes
+        .iter()
+        .filter(|(path, hash)| state.hashes.get(*path) != Some(*hash))
+        .map(|(path, _)| path.clone())
+        .collect();
+    if changed.is_empty() {
+        info!("watch: no language changes");
+        return Ok(());
+    }
+    
+    // A locale changed if its localized manifest did, or if it has none and the
+    // primary manifest's /Languages.bin did
+    let languages_changed = changed.iter().any(|path| path == "/Languages.bin");
+    let changed_locales: Vec<String> = locales
+        .iter()
+        .filter(|lang| {
+            let localized = localized_manifest_path(&args.platform, lang);
+            if hashes.contains_key(&localized) {
+                changed.contains(&localized)
+            } else {
+                languages_changed
+            }
+        })
+        .cloned()
+        .collect();
+    info!("watch: changed: {}", changed.join(", "));
+    
+    let snapshot = run_download(args, &mut downloader, base_downloads, format, meta)?;
+    run_extract(args, &locales, &lock)?;
+    if let Some(command) = on_update {
+        run_on_update(command, &changed, &changed_locales, snapshot.as_deref(), &lock)?;
+    }
+    
+    state.hashes = hashes;
+    state.save()?;
+    info!("watch: update complete");
+    Ok(())
+}
+
+/// True if the URL that last served `manifest_path` answers `304 Not Modified` to its
+/// recorded ETag. Any other outcome, including no sidecar, means "maybe modified".
+fn manifest_not_modified(client: &reqwest::blocking::Client, manifest_path: &Path) -> bool {
+    let Ok(Some(meta)) = DownloadMeta::read(manifest_path) else {
+        return false;
+    };
+    let Some(etag) = meta.etag else {
+        return false;
+    };
+    
+    client
+        .get(&meta.url)
+        .header(reqwest::header::IF_NONE_MATCH, etag)
+        .send()
+        .is_ok_and(|response| response.status() == reqwest::StatusCode::NOT_MODIFIED)
+}
+
+/// Host probed by `doctor`; the first of the download URLs
+const CDN_HOST: &str = "https://content.soulframe.com/";
+
+/// Prints a pass/fail line for each environment check and fails if any did
+fn doctor(client: &reqwest::blocking::Client, downloads: &Path) -> Result<()> {
+    println!("\nChecking the environment...");
+    let mut failed = 0;
+    let mut report = |name: &str, result: Result<String>| {
+        match result {
+            Ok(detail) => println!("  {} {}: {}", Status::Ok, name, detail),
+            Err(err) => {
+                println!("  {} {}: {:#}", Status::Fail, name, err);
+                failed += 1;
+            }
+        }
+    };
+    
+    report("Oodle library", Oodle::new().map(|_| "loaded".to_string()).map_err(Into::into));
+    report("ZSTD library", Zstd::new().map(|_| "loaded".to_string()).map_err(Into::into));
+    report("CDN reachable", probe_cdn(client));
+    report("downloaded-data writable", check_writable(downloads));
+    report("extracted-data writable", check_writable(&std::env::current_dir()?.join("extracted-data")));
+    
+    if failed > 0 {
+        return Err(anyhow!("{} of 5 checks failed", failed));
+    }
+    println!("\nAll checks passed.");
+    Ok(())
+}
+
+/// Sends a HEAD request to `CDN_HOST`. Any response but 403 means it is reachable.
+fn probe_cdn(client: &reqwest::blocking::Client) -> Result<String> {
+    let response = client.head(CDN_HOST).send().with_context(|| format!("no response from {}", CDN_HOST))?;
+    let status = response.status();
+    if status == reqwest::StatusCode::FORBIDDEN {
+        return Err(SoulframeError::Forbidden { url: CDN_HOST.to_string() }.into());
+    }
+    Ok(format!("{} answered HTTP {}", CDN_HOST, status.as_u16()))
+}
+
+/// Creates `dir` if needed, then writes and removes a probe file in it
+fn check_writable(dir: &Path) -> Result<String> {
+    fs::create_dir_all(dir).with_context(|| format!("cannot create {}", dir.to_string_lossy()))?;
+    let probe = dir.join(".doctor-probe");
+    fs::write(&probe, b"ok").with_context(|| format!("cannot write to {}", dir.to_string_lossy()))?;
+    fs::remove_file(&probe)?;
+    Ok(dir.to_string_lossy().to_string())
+}
+
+/// Runs the `extract` binary next to this one for `locales`, handing it `lock`
+fn run_extract(args: &Args, locales: &[String], lock: &RunLock) -> Result<()> {
+    let extract = std::env::current_exe()?.with_file_name(format!("extract{}", std::env::consts::EXE_SUFFIX));
+    let mut command = std::process::Command::new(&extract);
+    command.args(["--locales", &locales.join(","), "--platform", &args.platform]);
+    command.env(LOCK_ENV, lock.path());
+    if args.snapshot {
+        command.args(["--snapshot", "latest"]);
+    }
+    
+    let status = command.status().with_context(|| format!("running {}", extract.to_string_lossy()))?;
+    if !status.success() {
+        return Err(anyhow!("extract exited with {}", status));
+    }
+    Ok(())
+}
+
+/// Runs the `--on-update` command through the shell. It inherits `lock`, so it can
+/// run `extract` or `download fetch` itself.
+fn run_on_update(command: &str, changed: &[String], changed_locales: &[String], snapshot: Option<&str>, lock: &RunLock) -> Result<()> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    shell
+        .env("SOULFRAME_CHANGED_PATHS", changed.join(","))
+        .env("SOULFRAME_CHANGED_LOCALES", changed_locales.join(","))
+        .env("SOULFRAME_SNAPSHOT", snapshot.unwrap_or(""))
+        .env(LOCK_ENV, lock.path());
+    
+    info!("watch: running {}", command);
+    let status = shell.status()?;
+    if !status.success() {
+        warn!("watch: --on-update command exited with {}", status);
+    }
     Ok(())
 }
@@ -1,276 +1,1100 @@
 use clap::Parser;
-use anyhow::{anyhow, Result};
-use libloading::{Library, Symbol};
-use soulframe_language_downloader::find_runtime_lib;
+use rayon::prelude::*;
+use anyhow::{anyhow, Context, Result};
+use soulframe_language_downloader::extract::{
+    diff_entries, languages_read_header, languages_unpack, read_language_json, write_language_json_ordered,
+    write_debug_language_json, write_nested_language_json, BundledZstd, DictDecompressor, ExtractionStats, JsonStyle, KeyFilter, KeyOrder, LocaleDelta, UnpackOptions, UnpackedLanguages, Zstd,
+    write_language_json_in_order,
+    markdown_changelog, split_file_names, DEFAULT_MARKDOWN_VALUE_LEN, DEFAULT_MAX_LABELS, DEFAULT_MAX_PATHS,
+};
+use soulframe_language_downloader::locales::{available_platform_locales, known_locales, parse_locales, LocaleAliases};
+use soulframe_language_downloader::lock::RunLock;
+use soulframe_language_downloader::logging;
+use soulframe_language_downloader::output::{self, Status, Table};
+use soulframe_language_downloader::snapshot::resolve_snapshot;
+use soulframe_language_downloader::storage::{LocalFs, Storage};
+use soulframe_language_downloader::transform::{ascii_escape_json, describe_transforms, ValueTransform};
+#[cfg(feature = "sqlite")]
+use soulframe_language_downloader::sqlite::SqliteWriter;
+#[cfg(feature = "xlsx")]
+use soulframe_language_downloader::xlsx::write_workbook;
+use soulframe_language_downloader::{locale_suffix, safe_join, PhaseTimings, DEFAULT_PLATFORM};
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::ffi::c_void;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{debug, info, warn};
 
 #[derive(Parser)]
 #[command(name = "extract")]
 #[command(about = "Extract downloaded Languages.bin files to JSON per locale")]
 struct Args {
-    /// Locales to extract (comma-separated)
+    /// Locales to extract (comma-separated). "all" selects every known or downloaded
+    /// locale and "-<locale>" removes one, e.g. "all,-ru". Standard codes such as
+    /// zh-Hans are translated to the game's tokens (see locale-aliases.json)
     #[arg(short, long, default_value = "en,fr,de,es,it,pt,ru,pl,tr,ja,ko,zh")]
     locales: String,
+
+    /// Also write each locale's embedded ZSTD dictionary to <locale>.dict
+    #[arg(long)]
+    write_dict: bool,
+
+    /// Also write each label's offset, size and flags to <locale>.meta.json
+    #[arg(long)]
+    with_metadata: bool,
+
+    /// Write each locale under extracted-data/0_<locale>/Languages/, mirroring the download layout
+    #[arg(long)]
+    mirror_paths: bool,
+
+    /// Stop at the first locale that fails instead of extracting the rest
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Locales extracted at once, each on its own thread (default 1, in order).
+    /// Their log lines interleave when above 1
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Write each locale as a tree of objects split on `/` instead of a flat key map
+    #[arg(long)]
+    nested: bool,
+
+    /// Write each string as an object with its label table offset, size and flags,
+    /// e.g. {"value": "...", "offset": 12, "size": 5, "flags": "0x0000"}
+    #[arg(long, conflicts_with = "nested")]
+    debug_json: bool,
+
+    /// Write each locale as one JSON file per manifest path under <locale>/, listed
+    /// in <locale>.index.json, instead of a single file. No Languages.json alias is
+    /// written in this layout
+    #[arg(long, conflicts_with_all = ["nested", "debug_json"])]
+    split_by_path: bool,
+
+    /// Sort keys with numbers compared by value (Item2 before Item10) instead of byte
+    /// order; applies to the flat layout and its __order array
+    #[arg(long)]
+    natural_sort: bool,
+
+    /// Keep keys in the order their labels appear in Languages.bin, grouped by path,
+    /// instead of sorting them; __order follows the same order
+    #[arg(long, conflicts_with_all = ["natural_sort", "nested", "debug_json"])]
+    original_order: bool,
+
+    /// Write minified JSON (locale files and --with-metadata) instead of indented
+    #[arg(long, overrides_with = "pretty")]
+    compact: bool,
+
+    /// Write indented JSON, the default; overrides an earlier --compact
+    #[arg(long, overrides_with = "compact")]
+    pretty: bool,
+
+    /// Leave the __order array out, writing only the strings, for consumers that
+    /// would take it for a string key
+    #[arg(long, overrides_with = "include_order")]
+    no_order: bool,
+
+    /// Write the __order array, the default; overrides an earlier --no-order
+    #[arg(long, overrides_with = "no_order")]
+    include_order: bool,
+
+    /// Remove angle-bracket markup tags such as <DT_FIRE> from values
+    #[arg(long)]
+    strip_tags: bool,
+
+    /// Collapse each run of whitespace in values to one space (or line break) and
+    /// trim them
+    #[arg(long)]
+    normalize_whitespace: bool,
+
+    /// Normalize values to Unicode NFC
+    #[arg(long)]
+    nfc: bool,
+
+    /// Write non-ASCII characters in the JSON files as \uXXXX escapes
+    #[arg(long)]
+    ascii_escape: bool,
+
+    /// Name each locale's output files with the game's token (zh.json) or its
+    /// standard code (zh-Hans.json)
+    #[arg(long, value_enum, default_value_t = LocaleNaming::Game)]
+    output_locale_naming: LocaleNaming,
+
+    /// Platform the locales were downloaded for (must match the download's --platform)
+    #[arg(long, default_value = DEFAULT_PLATFORM)]
+    platform: String,
+
+    /// Reject Languages.bin files claiming more paths than this
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_PATHS)]
+    max_paths: usize,
+
+    /// Reject Languages.bin files claiming more labels than this in a single path
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_LABELS)]
+    max_labels: usize,
+
+    /// Only extract keys starting with one of these prefixes (comma-separated or
+    /// repeated), e.g. /Lotus/Language/Menu/
+    #[arg(long, value_name = "PREFIX", value_delimiter = ',')]
+    include_keys: Vec<String>,
+
+    /// Leave out keys starting with one of these prefixes (comma-separated or repeated)
+    #[arg(long, value_name = "PREFIX", value_delimiter = ',')]
+    exclude_keys: Vec<String>,
+
+    /// Extract this Languages.bin_H file, e.g. from a game install, instead of looking
+    /// in downloaded-data; --locales names the one locale it holds
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["snapshot", "since"])]
+    input: Option<PathBuf>,
+
+    /// Read from the snapshot with this ID (or unique prefix), or "latest", instead of
+    /// the files directly under downloaded-data
+    #[arg(long, value_name = "ID")]
+    snapshot: Option<String>,
+
+    /// Extract every locale even if its Languages.bin and options are unchanged since
+    /// the last run
+    #[arg(long)]
+    force: bool,
+
+    /// Run this command after extracting, with the path of the JSON delta against the
+    /// previous extraction as its argument
+    #[arg(long, value_name = "COMMAND")]
+    hook: Option<String>,
+
+    /// Don't compare against the previous extraction (no delta file or hook)
+    #[arg(long)]
+    no_delta: bool,
+
+    /// In the delta and --since, also count a removed and an added key as a move when
+    /// their strings share this fraction of their words (0.8 if no value is given),
+    /// not only when the strings are identical
+    #[arg(
+        long,
+        value_name = "SIMILARITY",
+        num_args = 0..=1,
+        default_missing_value = "0.8",
+        value_parser = parse_similarity
+    )]
+    fuzzy: Option<f64>,
+
+    /// Also write the delta as Markdown to extracted-data/delta.md, and print --since
+    /// as Markdown
+    #[arg(long, value_enum, default_value_t = DeltaFormat::Json)]
+    delta_format: DeltaFormat,
+
+    /// Cut values in Markdown deltas to this many characters (0 for no limit)
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MARKDOWN_VALUE_LEN)]
+    max_value_length: usize,
+
+    /// After extracting, print the strings added, modified and removed since this
+    /// snapshot (ID, unique prefix or "latest"), as a changelog
+    #[arg(long, value_name = "SNAPSHOT")]
+    since: Option<String>,
+
+    /// Also pack the extracted JSON files into this .zip or .tar.gz
+    #[arg(long, value_name = "PATH")]
+    archive: Option<PathBuf>,
+
+    /// Also write every decoded string to this SQLite database, in a
+    /// strings(locale, key, value) table; a locale's earlier rows are replaced
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "DB")]
+    sqlite: Option<PathBuf>,
+
+    /// Also write an Excel workbook for translators: each key with its English source
+    /// and a column per locale, and a sheet of extraction metadata
+    #[cfg(feature = "xlsx")]
+    #[arg(long, value_name = "PATH")]
+    xlsx: Option<PathBuf>,
+
+    /// ZSTD implementation used to decompress strings
+    #[arg(long, value_enum, default_value_t = ZstdBackend::Shared)]
+    zstd_backend: ZstdBackend,
+
+    /// Log more: -v adds each URL tried and other details, -vv parse offsets.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Don't color status markers (also off when NO_COLOR is set or stdout isn't a
+    /// terminal)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// If another run holds the downloaded-data lock, wait for it to finish instead
+    /// of failing
+    #[arg(long, global = true)]
+    wait: bool,
 }
 
-fn get_download_path(path: &str, suffix: Option<&str>) -> PathBuf {
-    let suffix = suffix.unwrap_or("");
-    let root = std::env::current_dir().unwrap();
-    root.join("downloaded-data").join(format!("0{}{}", suffix, path))
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ZstdBackend {
+    /// libzstd loaded from the lib/ directory
+    Shared,
+    /// The pure-Rust ZSTD decoder built into this binary
+    Bundled,
 }
 
-fn get_extract_path(path: &str, suffix: Option<&str>) -> PathBuf {
-    let suffix = suffix.unwrap_or("");
-    let root = std::env::current_dir().unwrap();
-    root.join("extracted-data").join(format!("0{}{}", suffix, path))
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum DeltaFormat {
+    /// delta.json, and --since as plain text
+    Json,
+    /// delta.json and delta.md, and --since as Markdown
+    Markdown,
 }
 
-fn read_u32_le(bin: &[u8], i: &mut usize) -> Result<u32> {
-    if *i + 4 > bin.len() { return Err(anyhow!("Unexpected EOF reading u32")); }
-    let v = u32::from_le_bytes([bin[*i], bin[*i + 1], bin[*i + 2], bin[*i + 3]]);
-    *i += 4;
-    Ok(v)
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum LocaleNaming {
+    /// The token the game uses in its manifests, e.g. zh
+    Game,
+    /// The standard code from locale-aliases.json or the built-in table, e.g. zh-Hans
+    Standard,
 }
 
-fn read_u16_le(bin: &[u8], i: &mut usize) -> Result<u16> {
-    if *i + 2 > bin.len() { return Err(anyhow!("Unexpected EOF reading u16")); }
-    let v = u16::from_le_bytes([bin[*i], bin[*i + 1]]);
-    *i += 2;
-    Ok(v)
+/// `downloads` is `downloaded-data` or one of its snapshots
+fn get_download_path(downloads: &Path, path: &str, suffix: Option<&str>) -> Result<PathBuf> {
+    let suffix = suffix.unwrap_or("");
+    safe_join(downloads, &format!("0{}{}", suffix, path))
 }
 
-fn read_s4(bin: &[u8], i: &mut usize) -> Result<Vec<u8>> {
-    let len = read_u32_le(bin, i)? as usize;
-    if *i + len > bin.len() { return Err(anyhow!("Unexpected EOF reading s4")); }
-    let v = bin[*i..*i + len].to_vec();
-    *i += len;
-    Ok(v)
+/// Built-in locales plus any others found under `downloads` for `platform`
+fn known_or_downloaded_locales(downloads: &Path, platform: &str) -> Result<Vec<String>> {
+    let mut known = known_locales();
+    let Ok(dir) = fs::read_dir(downloads) else {
+        return Ok(known);
+    };
+    let platform_suffix = format!("_{}", platform);
+    let mut downloaded: Vec<String> = dir
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| {
+            let locale = name.strip_prefix("0_")?;
+            let locale = if platform == DEFAULT_PLATFORM { locale } else { locale.strip_suffix(&platform_suffix)? };
+            Some(locale.to_string())
+        })
+        .filter(|locale| !locale.is_empty() && locale.chars().all(|c| c.is_alphanumeric()))
+        .filter(|locale| !known.contains(locale))
+        .collect();
+    downloaded.sort();
+    known.append(&mut downloaded);
+    Ok(known)
 }
 
-fn unpack_u32_dyn_le(bin: &[u8], i: &mut usize) -> Result<u32> {
-    let mut value: u32 = 0;
-    let mut shift: u32 = 0;
-    while shift < 28 {
-        if *i >= bin.len() { return Err(anyhow!("Unexpected EOF in dyn u32")); }
-        let byte = bin[*i];
-        *i += 1;
-        value |= ((byte & 0x7f) as u32) << shift;
-        if (byte & 0x80) == 0 { return Ok(value); }
-        shift += 7;
-    }
-    if *i >= bin.len() { return Err(anyhow!("Unexpected EOF in dyn u32 final")); }
-    let byte = bin[*i];
-    *i += 1;
-    if byte > 0x0F { return Err(anyhow!("Invalid final dyn u32 byte: {}", byte)); }
-    value |= (byte as u32) << shift;
-    Ok(value)
+/// Storage path in `extracted-data` of one of a locale's files, e.g. `<locale>.json`
+fn locale_output_key(locale: &str, platform: &str, file_name: &str, mirror_paths: bool) -> String {
+    let suffix = if mirror_paths { locale_suffix(locale, platform) } else { String::new() };
+    format!("0{}/Languages/{}", suffix, file_name)
 }
 
-// Minimal Zstd FFI wrapper to match Pluto behavior
-struct Zstd {
-    lib: Library,
-    create_ddict: Symbol<'static, unsafe extern "C" fn(*const u8, usize) -> usize>,
-    create_dctx: Symbol<'static, unsafe extern "C" fn() -> usize>,
-    dctx_set_param: Symbol<'static, unsafe extern "C" fn(usize, i32, i32) -> usize>,
-    decompress_using_ddict: Symbol<'static, unsafe extern "C" fn(usize, *mut c_void, usize, *const u8, usize, usize) -> usize>,
-    free_dctx: Symbol<'static, unsafe extern "C" fn(usize) -> usize>,
-    free_ddict: Symbol<'static, unsafe extern "C" fn(usize) -> usize>,
+/// Where `key` in `extracted` is on disk, for messages and the archive
+fn disk_path(extracted: &dyn Storage, key: &str) -> PathBuf {
+    extracted.local_path(key).unwrap_or_else(|| PathBuf::from(key))
 }
 
-impl Zstd {
-    fn new() -> Result<Self> {
-        let lib_name = if cfg!(windows) { "libzstd.dll" } else { "libzstd.so" };
-        let lib_path = find_runtime_lib(lib_name)?;
-        
-        unsafe {
-            let lib = Library::new(&lib_path)
-                .map_err(|e| anyhow!("Failed to load Zstd library from {:?}: {}", lib_path, e))?;
-            let create_ddict: Symbol<unsafe extern "C" fn(*const u8, usize) -> usize> = lib.get(b"ZSTD_createDDict\0")?;
-            let create_dctx: Symbol<unsafe extern "C" fn() -> usize> = lib.get(b"ZSTD_createDCtx\0")?;
-            let dctx_set_param: Symbol<unsafe extern "C" fn(usize, i32, i32) -> usize> = lib.get(b"ZSTD_DCtx_setParameter\0")?;
-            let decompress_using_ddict: Symbol<unsafe extern "C" fn(usize, *mut c_void, usize, *const u8, usize, usize) -> usize> = lib.get(b"ZSTD_decompress_usingDDict\0")?;
-            let free_dctx: Symbol<unsafe extern "C" fn(usize) -> usize> = lib.get(b"ZSTD_freeDCtx\0")?;
-            let free_ddict: Symbol<unsafe extern "C" fn(usize) -> usize> = lib.get(b"ZSTD_freeDDict\0")?;
-            // Extend lifetimes
-            let create_ddict = std::mem::transmute(create_ddict);
-            let create_dctx = std::mem::transmute(create_dctx);
-            let dctx_set_param = std::mem::transmute(dctx_set_param);
-            let decompress_using_ddict = std::mem::transmute(decompress_using_ddict);
-            let free_dctx = std::mem::transmute(free_dctx);
-            let free_ddict = std::mem::transmute(free_ddict);
-            Ok(Self { lib, create_ddict, create_dctx, dctx_set_param, decompress_using_ddict, free_dctx, free_ddict })
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.verbose, args.quiet);
+    output::init(args.no_color);
+    
+    info!("=== Extract downloaded Languages.bin -> JSON ===");
+    
+    let base_downloads = std::env::current_dir()?.join("downloaded-data");
+    // Keeps a download from replacing files mid-read and other extracts from
+    // writing the same JSON. An --input run without downloaded-data has nothing to share.
+    let _lock = if base_downloads.is_dir() {
+        Some(RunLock::acquire(&base_downloads, "extract", args.wait)?)
+    } else {
+        None
+    };
+    let mut downloads = base_downloads.clone();
+    if let Some(snapshot) = &args.snapshot {
+        downloads = resolve_snapshot(&base_downloads, snapshot)?;
+        info!("Using snapshot {}", downloads.to_string_lossy());
+    }
+    let since = args.since.as_ref().map(|since| resolve_snapshot(&base_downloads, since)).transpose()?;
+    
+    // Parse locales
+    let aliases = LocaleAliases::load_default()?;
+    let locales = parse_locales(
+        &aliases.translate_spec(&args.locales),
+        &known_or_downloaded_locales(&downloads, &args.platform)?,
+    )?;
+    
+    // Ensure extract base folder exists; every output is written through `extracted`
+    let extracted = LocalFs::extracted();
+    extracted.create_dir_all("0")?;
+    
+    // Check which locales are present
+    let present = match &args.input {
+        Some(input) => {
+            if locales.len() != 1 {
+                return Err(anyhow!(
+                    "--input reads a single locale; name it with --locales, e.g. --locales en (got {})",
+                    locales.join(",")
+                ));
+            }
+            if !input.is_file() {
+                return Err(anyhow!("--input {} is not a file", input.to_string_lossy()));
+            }
+            locales.clone()
         }
+        None => available_platform_locales(&downloads, &locales, &args.platform),
+    };
+    
+    if present.is_empty() {
+        return Err(anyhow!("No downloaded Languages.bin found. Run download command first."));
     }
-}
-
-fn languages_unpack(bin: &[u8]) -> Result<(BTreeMap<String, String>, Vec<u8>)> {
-    let mut i = 0usize;
-    if bin.len() < 16 + 12 { return Err(anyhow!("Languages.bin too short")); }
-    // skip 16-byte hash and 3 u32 constants
-    i += 16; // hash
-    i += 4; // 0x14
-    i += 4; // 0x2B
-    i += 4; // 0x01
-
-    let num_suffixes = read_u32_le(bin, &mut i)? as usize;
-    for _ in 0..num_suffixes { let _ = read_s4(bin, &mut i)?; }
-
-    let dict_bin = read_s4(bin, &mut i)?;
-    let num_paths = read_u32_le(bin, &mut i)? as usize;
-
-    let zstd = Zstd::new()?;
-    let dict_handle;
-    let dctx_handle;
-    unsafe {
-        dict_handle = (zstd.create_ddict)(dict_bin.as_ptr(), dict_bin.len());
-        dctx_handle = (zstd.create_dctx)();
-        // Mirrors Pluto: set parameter 1000 to 1
-        let _ = (zstd.dctx_set_param)(dctx_handle, 1000, 1);
+    
+    let missing: Vec<&String> = locales.iter().filter(|l| !present.contains(l)).collect();
+    if !missing.is_empty() {
+        warn!(
+            "Warning: {} requested locales have no downloaded Languages.bin: {}",
+            missing.len(),
+            missing.iter().map(|l| l.as_str()).collect::<Vec<_>>().join(", ")
+        );
     }
+    
+    info!("Found {} locales to extract: {}", present.len(), present.join(", "));
 
-    let mut entries: BTreeMap<String, String> = BTreeMap::new();
-
-    for _ in 0..num_paths {
-        let path_bytes = read_s4(bin, &mut i)?;
-        let path = String::from_utf8_lossy(&path_bytes).to_string();
-        let chunk = read_s4(bin, &mut i)?;
-        let num_labels = read_u32_le(bin, &mut i)? as usize;
-
-        for _ in 0..num_labels {
-            let name_bytes = read_s4(bin, &mut i)?;
-            let name = String::from_utf8_lossy(&name_bytes).to_string();
-            let offset = read_u32_le(bin, &mut i)? as usize;
-            let size = read_u16_le(bin, &mut i)? as usize;
-            let flags = read_u16_le(bin, &mut i)? as u32;
-
-            if offset + size > chunk.len() { return Err(anyhow!("Label slice out of bounds")); }
-            let mut data = &chunk[offset..offset + size];
-
-            let value_bytes: Vec<u8> = if (flags & 0x200) != 0 { // compressed with zstd + dict
-                let mut di = 0usize;
-                let decompressed_size = unpack_u32_dyn_le(data, &mut di)? as usize;
-                if di > data.len() { return Err(anyhow!("Invalid dyn len offset")); }
-                let src = &data[di..];
-                let mut out = vec![0u8; decompressed_size];
-                let wrote;
-                unsafe {
-                    wrote = (zstd.decompress_using_ddict)(
-                        dctx_handle,
-                        out.as_mut_ptr() as *mut c_void,
-                        decompressed_size,
-                        src.as_ptr(),
-                        src.len(),
-                        dict_handle,
-                    );
+    // Perform real extraction
+    let zstd = match args.zstd_backend {
+        ZstdBackend::Shared => Some(Zstd::new()?),
+        ZstdBackend::Bundled => None,
+    };
+    let options = UnpackOptions {
+        collect_metadata: args.with_metadata || args.debug_json,
+        collect_paths: args.split_by_path,
+        collect_order: args.original_order,
+        max_paths: args.max_paths,
+        max_labels: args.max_labels,
+        keys: KeyFilter { include: args.include_keys.clone(), exclude: args.exclude_keys.clone() },
+        transforms: value_transforms(&args),
+        ..Default::default()
+    };
+    let extractor = Extractor {
+        args: &args,
+        zstd: zstd.as_ref(),
+        options: &options,
+        extracted: &extracted,
+        outputs: Mutex::new(Outputs::open(&args, aliases)?),
+        timings: Mutex::new(PhaseTimings::default()),
+    };
+    // Locales only write their own files, so they can be extracted side by side. Each
+    // worker decodes through its own ZSTD context; the shared `Zstd` only caches DDicts.
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(args.jobs.unwrap_or(1).max(1)).build()?;
+    let aborted = AtomicBool::new(false);
+    let results: Vec<(String, Result<Extracted>)> = pool.install(|| {
+        present
+            .par_iter()
+            .filter_map(|locale| {
+                // With --fail-fast, locales not started yet are left out after a failure
+                if aborted.load(Ordering::Relaxed) {
+                    return None;
                 }
-                if wrote != decompressed_size { return Err(anyhow!("ZSTD decompression size mismatch: {} != {}", wrote, decompressed_size)); }
-                out
-            } else {
-                data.to_vec()
-            };
-
-            let key = format!("{}{}", path, name);
-            let value = String::from_utf8_lossy(&value_bytes).to_string();
-            entries.insert(key, value);
+                let h_file_path = match &args.input {
+                    Some(input) => Ok(input.to_string_lossy().into_owned()),
+                    None => languages_h_path(&downloads, locale, &args.platform),
+                };
+                let result = h_file_path.and_then(|h_file_path| extractor.extract(locale, &h_file_path));
+                if let Err(ref err) = result {
+                    warn!("  {} {}: {:#}", Status::Fail, locale, err);
+                    if args.fail_fast {
+                        aborted.store(true, Ordering::Relaxed);
+                    }
+                }
+                Some((locale.clone(), result))
+            })
+            .collect()
+    });
+    if args.fail_fast {
+        if let Some((locale, Err(err))) = results.iter().find(|(_, result)| result.is_err()) {
+            return Err(anyhow!("Aborting after locale {} failed (--fail-fast): {:#}", locale, err));
         }
     }
-
-    unsafe {
-        let _ = (zstd.free_dctx)(dctx_handle);
-        let _ = (zstd.free_ddict)(dict_handle);
+    let Extractor { outputs, timings, .. } = extractor;
+    let mut outputs = outputs.into_inner().unwrap();
+    let timings = timings.into_inner().unwrap();
+    
+    let succeeded: Vec<&String> = results
+        .iter()
+        .filter(|(_, result)| result.is_ok())
+        .map(|(locale, _)| locale)
+        .collect();
+    
+    // Create alias Languages.json to en if it succeeded, else the first that did. The
+    // alias only makes sense in the flat layout, where all locales share one folder
+    // and each has a single file.
+    let alias_name = if args.platform == DEFAULT_PLATFORM {
+        "Languages.json".to_string()
+    } else {
+        format!("Languages_{}.json", args.platform)
+    };
+    let alias_key = format!("0/Languages/{}", alias_name);
+    let alias_path = disk_path(&extracted, &alias_key);
+    let alias_locale = succeeded
+        .iter()
+        .find(|locale| locale.as_str() == "en")
+        .or_else(|| succeeded.first());
+    
+    let mut alias_written = false;
+    match alias_locale {
+        Some(alias_locale) if !args.mirror_paths && !args.split_by_path => {
+            let stem = outputs.stem(alias_locale, &args.platform);
+            if let Ok(content) = extracted.read(&format!("0/Languages/{}.json", stem)) {
+                extracted.write(&alias_key, &content)?;
+                alias_written = true;
+                info!("Alias written: {} -> {}.json", alias_name, stem);
+            }
+        }
+        _ => {}
     }
-
-    Ok((entries, dict_bin))
-}
-
-fn main() -> Result<()> {
-    let args = Args::parse();
     
-    println!("=== Extract downloaded Languages.bin -> JSON ===");
+    let mut table = Table::new(&["Locale", "Strings", "Output"]).right_align(1);
+    for (locale, result) in &results {
+        match result {
+            Ok(extracted) => {
+                let status = if extracted.skipped { Status::Skip } else { Status::Ok };
+                table.row(status, vec![locale.clone(), extracted.count.to_string(), extracted.output.to_string_lossy().into_owned()]);
+            }
+            Err(err) => {
+                table.row(Status::Fail, vec![locale.clone(), "-".to_string(), format!("{:#}", err)]);
+            }
+        }
+    }
+    info!("");
+    for line in table.lines() {
+        info!("{}", line);
+    }
     
-    // Parse locales
-    let locales: Vec<String> = args.locales
-        .split(',')
-        .map(|s| s.trim().to_string())
+    timings.print();
+    
+    // Everything below covers the locales that succeeded; a failed one only makes
+    // the run exit non-zero at the end
+    let stats: BTreeMap<&str, &ExtractionStats> = results
+        .iter()
+        .filter_map(|(locale, result)| Some((locale.as_str(), &result.as_ref().ok()?.stats)))
         .collect();
+    extracted.write("stats.json", serde_json::to_string_pretty(&stats)?.as_bytes())?;
+    debug!("Stats written: {}", disk_path(&extracted, "stats.json").to_string_lossy());
+    outputs.finish(&stats, &present, || read_locale_entries("en", &downloads, &args.platform, zstd.as_ref(), &options))?;
     
-    // Ensure extract base folder exists
-    let marker_path = get_extract_path("/marker", None);
-    if let Some(parent) = marker_path.parent() {
-        fs::create_dir_all(parent)?;
+    let deltas: BTreeMap<&str, &LocaleDelta> = results
+        .iter()
+        .filter_map(|(locale, result)| Some((locale.as_str(), result.as_ref().ok()?.delta.as_ref()?)))
+        .collect();
+    if !deltas.is_empty() {
+        extracted.write("delta.json", serde_json::to_string_pretty(&deltas)?.as_bytes())?;
+        let delta_path = disk_path(&extracted, "delta.json");
+        info!("\nDelta written: {}", delta_path.to_string_lossy());
+        if args.delta_format == DeltaFormat::Markdown {
+            let markdown = markdown_changelog("Changes since the last extraction", &deltas, args.max_value_length);
+            extracted.write("delta.md", markdown.as_bytes())?;
+            info!("Delta written: {}", disk_path(&extracted, "delta.md").to_string_lossy());
+        }
+        if let Some(hook) = &args.hook {
+            run_hook(hook, &delta_path, &deltas)?;
+        }
     }
     
-    // Check which locales are present
-    let mut present = Vec::new();
-    for locale in &locales {
-        let suffix = format!("_{}", locale);
-        let h_path = get_download_path("/Languages.bin", Some(&suffix));
-        let h_file_path = format!("{}_H", h_path.to_string_lossy());
-        
-        if fs::metadata(&h_file_path).is_ok() {
-            present.push(locale.clone());
+    if let Some(since) = &since {
+        print_changelog(since, &downloads, &succeeded, &args, zstd.as_ref(), &options)?;
+    }
+    
+    if let Some(archive) = &args.archive {
+        let mut files: Vec<&Path> = results
+            .iter()
+            .filter_map(|(_, result)| Some(result.as_ref().ok()?.output.as_path()))
+            .collect();
+        if alias_written {
+            files.push(&alias_path);
         }
+        let split_files = if args.split_by_path {
+            let indexes = files.iter().map(|index| read_split_index(index)).collect::<Result<Vec<_>>>()?;
+            indexes.into_iter().flatten().map(|(_, file)| file).collect()
+        } else {
+            Vec::new()
+        };
+        files.extend(split_files.iter().map(PathBuf::as_path));
+        write_archive(archive, &disk_path(&extracted, ""), &files)?;
+        info!("\nArchive written: {} ({} files)", archive.to_string_lossy(), files.len());
     }
     
-    if present.is_empty() {
-        println!("No downloaded Languages.bin found. Run download command first.");
-        return Ok(());
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|(_, result)| result.is_err())
+        .map(|(locale, _)| locale.as_str())
+        .collect();
+    if !failed.is_empty() {
+        return Err(anyhow!(
+            "{} of {} locales failed to extract: {}",
+            failed.len(),
+            results.len(),
+            failed.join(", ")
+        ));
     }
     
-    println!("Found {} locales to extract: {}", present.len(), present.join(", "));
+    if args.mirror_paths {
+        info!("\nDone. Output under ./extracted-data/0{}/Languages/", locale_suffix("<locale>", &args.platform));
+    } else {
+        info!("\nDone. Output under ./extracted-data/0/Languages/");
+    }
+    
+    Ok(())
+}
 
-    // Perform real extraction
-    for locale in &present {
-        let suffix = format!("_{}", locale);
-        let h_path = get_download_path("/Languages.bin", Some(&suffix));
-        let h_file_path = format!("{}_H", h_path.to_string_lossy());
-
-        println!("[{}] Reading {}", locale, h_file_path);
-        let bin = fs::read(&h_file_path)?;
-        let (entries, _dict) = languages_unpack(&bin)?;
-
-        // Order keys for deterministic output
-        let mut keys: Vec<String> = entries.keys().cloned().collect();
-        keys.sort();
-
-        // Build JSON object with __order and all keys
-        let mut ordered: BTreeMap<String, serde_json::Value> = BTreeMap::new();
-        ordered.insert("__order".to_string(), serde_json::Value::Array(keys.iter().map(|k| serde_json::Value::String(k.clone())).collect()));
-        for k in &keys {
-            if let Some(v) = entries.get(k) {
-                ordered.insert(k.clone(), serde_json::Value::String(v.clone()));
+/// The value transforms selected on the command line, in the order they run: tags
+/// go before whitespace is collapsed, so removing one leaves no double space
+fn value_transforms(args: &Args) -> Vec<ValueTransform> {
+    [
+        (args.strip_tags, ValueTransform::StripTags),
+        (args.normalize_whitespace, ValueTransform::NormalizeWhitespace),
+        (args.nfc, ValueTransform::Nfc),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, transform)| enabled.then_some(transform))
+    .collect()
+}
+
+/// Parses `--fuzzy`: a fraction of shared words, above 0 and at most 1
+fn parse_similarity(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(similarity) if similarity > 0.0 && similarity <= 1.0 => Ok(similarity),
+        _ => Err(format!("expected a number above 0 and at most 1, got {}", value)),
+    }
+}
+
+/// Result of extracting one locale
+struct Extracted {
+    /// Strings written
+    count: usize,
+    /// The locale's JSON file
+    output: PathBuf,
+    /// Changes against the previous output, if one was compared
+    delta: Option<LocaleDelta>,
+    /// Written with the outputs, or left from the extraction a skip reuses
+    stats: ExtractionStats,
+    /// Whether the outputs were up to date and left alone
+    skipped: bool,
+}
+
+/// How each locale's outputs are named, and the destinations its strings are
+/// written to besides the JSON files (each behind its own feature)
+struct Outputs {
+    naming: LocaleNaming,
+    aliases: LocaleAliases,
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<(SqliteWriter, PathBuf)>,
+    #[cfg(feature = "xlsx")]
+    xlsx: Option<WorkbookSink>,
+}
+
+/// `--xlsx`: the workbook's path and the locales collected for it
+#[cfg(feature = "xlsx")]
+struct WorkbookSink {
+    path: PathBuf,
+    locales: Vec<(String, BTreeMap<String, String>)>,
+}
+
+impl Outputs {
+    fn open(args: &Args, aliases: LocaleAliases) -> Result<Self> {
+        Ok(Self {
+            naming: args.output_locale_naming,
+            aliases,
+            #[cfg(feature = "sqlite")]
+            sqlite: match &args.sqlite {
+                Some(path) => Some((SqliteWriter::open(path)?, path.clone())),
+                None => None,
+            },
+            #[cfg(feature = "xlsx")]
+            xlsx: args.xlsx.clone().map(|path| WorkbookSink { path, locales: Vec::new() }),
+        })
+    }
+
+    /// File stem for a locale's outputs: `<locale>`, or `<locale>_<platform>` off the
+    /// default platform so several platforms can share the flat layout. `<locale>` is
+    /// the standard code with `--output-locale-naming standard`.
+    fn stem(&self, locale: &str, platform: &str) -> String {
+        let name = match self.naming {
+            LocaleNaming::Game => locale.to_string(),
+            LocaleNaming::Standard => self.aliases.to_standard(locale),
+        };
+        locale_suffix(&name, platform)[1..].to_string()
+    }
+
+    /// Whether any destination besides the JSON files was requested
+    fn has_sinks(&self) -> bool {
+        [
+            #[cfg(feature = "sqlite")]
+            self.sqlite.is_some(),
+            #[cfg(feature = "xlsx")]
+            self.xlsx.is_some(),
+        ]
+        .contains(&true)
+    }
+
+    #[cfg_attr(not(any(feature = "sqlite", feature = "xlsx")), allow(unused_variables))]
+    fn write(&mut self, locale: &str, entries: &BTreeMap<String, String>) -> Result<()> {
+        #[cfg(feature = "sqlite")]
+        if let Some((sqlite, path)) = &mut self.sqlite {
+            let rows = sqlite.write_locale(locale, entries)?;
+            info!("  {} {} rows -> {}", Status::Ok, rows, path.to_string_lossy());
+        }
+        #[cfg(feature = "xlsx")]
+        if let Some(workbook) = &mut self.xlsx {
+            workbook.locales.push((locale.to_string(), entries.clone()));
+        }
+        Ok(())
+    }
+
+    /// Writes the sinks that need every locale, once all are extracted, with the
+    /// locales in `order`. `english` reads the English source for the workbook when
+    /// en wasn't extracted.
+    #[cfg_attr(not(feature = "xlsx"), allow(unused_variables))]
+    fn finish(
+        &mut self,
+        stats: &BTreeMap<&str, &ExtractionStats>,
+        order: &[String],
+        english: impl FnOnce() -> Result<BTreeMap<String, String>>,
+    ) -> Result<()> {
+        #[cfg(feature = "xlsx")]
+        if let Some(WorkbookSink { path, locales }) = &mut self.xlsx {
+            // Parallel extraction adds the locales in whatever order they finish
+            locales.sort_by_key(|(locale, _)| order.iter().position(|l| l == locale));
+            let source = match locales.iter().find(|(locale, _)| locale == "en") {
+                Some((_, en)) => Some(en.clone()),
+                None => english()
+                    .map_err(|err| warn!("Workbook has no English source column: {:#}", err))
+                    .ok(),
+            };
+            let columns: Vec<(&str, &BTreeMap<String, String>)> = locales
+                .iter()
+                .filter(|(locale, _)| locale != "en")
+                .map(|(locale, entries)| (locale.as_str(), entries))
+                .collect();
+            let stats: Vec<&ExtractionStats> = stats.values().copied().collect();
+            let keys = write_workbook(path, source.as_ref(), &columns, &stats)?;
+            info!("\nWorkbook written: {} ({} keys)", path.to_string_lossy(), keys);
+        }
+        Ok(())
+    }
+}
+
+/// Path of `locale`'s `Languages.bin_H` in the downloads folder `downloads`
+fn languages_h_path(downloads: &Path, locale: &str, platform: &str) -> Result<String> {
+    let h_path = get_download_path(downloads, "/Languages.bin", Some(&locale_suffix(locale, platform)))?;
+    Ok(format!("{}_H", h_path.to_string_lossy()))
+}
+
+/// What every locale is extracted with: the command line, the ZSTD backend and
+/// unpack options, the storage the files go to, and the state the locales share
+struct Extractor<'a> {
+    args: &'a Args,
+    zstd: Option<&'a Zstd>,
+    options: &'a UnpackOptions,
+    extracted: &'a dyn Storage,
+    outputs: Mutex<Outputs>,
+    timings: Mutex<PhaseTimings>,
+}
+
+impl Extractor<'_> {
+    /// Extracts one locale's JSON (and optional dictionary/metadata) from the
+    /// `Languages.bin_H` at `h_file_path`, returning the string count, JSON path and delta
+    fn extract(&self, locale: &str, h_file_path: &str) -> Result<Extracted> {
+        let Self { args, zstd, options, extracted, .. } = *self;
+        let (stem, has_sinks) = {
+            let outputs = self.outputs.lock().unwrap();
+            (outputs.stem(locale, &args.platform), outputs.has_sinks())
+        };
+
+        info!("[{}] Reading {}", locale, h_file_path);
+        let bin = fs::read(h_file_path)?;
+        
+        let output_name = if args.split_by_path { format!("{}.index.json", stem) } else { format!("{}.json", stem) };
+        let output_key = locale_output_key(locale, &args.platform, &output_name, args.mirror_paths);
+        let dict_key = locale_output_key(locale, &args.platform, &format!("{}.dict", stem), args.mirror_paths);
+        let meta_key = locale_output_key(locale, &args.platform, &format!("{}.meta.json", stem), args.mirror_paths);
+        let stats_key = locale_output_key(locale, &args.platform, &format!("{}.stats.json", stem), args.mirror_paths);
+        let output_path = disk_path(extracted, &output_key);
+        let source_md5 = format!("{:x}", md5::compute(&bin));
+        let mut options_desc = format!(
+            "nested={} dict={} metadata={} natural={} compact={} debug={}",
+            args.nested, args.write_dict, args.with_metadata, args.natural_sort, args.compact, args.debug_json
+        );
+        if !options.keys.is_empty() {
+            options_desc.push_str(&format!(" include={:?} exclude={:?}", options.keys.include, options.keys.exclude));
+        }
+        if args.split_by_path {
+            options_desc.push_str(" split=true");
+        }
+        if args.original_order {
+            options_desc.push_str(" original=true");
+        }
+        if args.no_order {
+            options_desc.push_str(" order=false");
+        }
+        if !options.transforms.is_empty() {
+            options_desc.push_str(&format!(" transforms={}", describe_transforms(&options.transforms)));
+        }
+        if args.ascii_escape {
+            options_desc.push_str(" ascii=true");
+        }
+        let outputs_present = extracted.exists(&output_key)
+            && (!args.write_dict || extracted.exists(&dict_key))
+            && (!args.with_metadata || extracted.exists(&meta_key));
+        // The sinks have no record of what they hold, so they always get every locale
+        if !args.force && outputs_present && !has_sinks {
+            let last = ExtractionStats::read(extracted, &stats_key)
+                .filter(|stats| stats.source_md5 == source_md5 && stats.options == options_desc);
+            if let Some(stats) = last {
+                info!("  {} unchanged since last extraction (--force to re-extract)", Status::Skip);
+                return Ok(Extracted { count: stats.strings, output: output_path, delta: None, stats, skipped: true });
+            }
+        }
+        
+        let unpack_started = Instant::now();
+        let unpacked = unpack_languages(&bin, h_file_path, zstd, options)?;
+        let entries = &unpacked.entries;
+        let decoded_bytes: usize = entries.values().map(|v| v.len()).sum();
+        self.timings.lock().unwrap().record("ZSTD decompress", unpack_started.elapsed(), decoded_bytes as u64);
+        info!("  dict_hash {} ({} bytes)", unpacked.dict_hash, unpacked.dict.len());
+
+        // Compare with the previous flat or split output, if any, before it is overwritten
+        let previous = if args.no_delta {
+            None
+        } else if args.split_by_path {
+            read_split_output(extracted, &output_key).ok()
+        } else {
+            extracted
+                .read(&output_key)
+                .ok()
+                .and_then(|previous| read_language_json(std::str::from_utf8(&previous).ok()?).ok())
+        };
+        let delta = previous.map(|previous| {
+            let mut delta = diff_entries(&previous, entries);
+            delta.detect_moves(args.fuzzy);
+            delta
+        });
+        if let Some(delta) = &delta {
+            info!(
+                "  {} added, {} changed, {} removed, {} moved since the last extraction",
+                delta.added.len(),
+                delta.changed.len(),
+                delta.removed.len(),
+                delta.moved.len()
+            );
+            if !delta.unresolved_moves.is_empty() {
+                info!("  {} groups of keys with matching strings could not be paired", delta.unresolved_moves.len());
+            }
+        }
+
+        let write_started = Instant::now();
+        let style = if args.compact { JsonStyle::Compact } else { JsonStyle::Pretty };
+        let mut json = Vec::new();
+        let order = if args.natural_sort { KeyOrder::Natural } else { KeyOrder::Lexicographic };
+        let count = if args.split_by_path {
+            let dir = locale_output_key(locale, &args.platform, &stem, args.mirror_paths);
+            let order = if args.original_order { None } else { Some(order) };
+            self.write_split_output(&unpacked, &dir, order, style, &mut json)?
+        } else if args.nested {
+            write_nested_language_json(entries, style, &mut json)?
+        } else if args.debug_json {
+            write_debug_language_json(&unpacked, order, style, !args.no_order, &mut json)?
+        } else if args.original_order {
+            write_language_json_in_order(entries, &unpacked.order, style, !args.no_order, &mut json)?
+        } else {
+            write_language_json_ordered(entries, order, style, !args.no_order, &mut json)?
+        };
+        if args.ascii_escape {
+            json = ascii_escape_json(&String::from_utf8(json)?).into_bytes();
+        }
+        extracted.write(&output_key, &json)?;
+        self.timings.lock().unwrap().record("JSON write", write_started.elapsed(), json.len() as u64);
+        info!("  {} {} strings -> {}", Status::Ok, count, output_path.to_string_lossy());
+
+        if args.write_dict {
+            extracted.write(&dict_key, &unpacked.dict)?;
+            info!("  {} dictionary -> {}", Status::Ok, disk_path(extracted, &dict_key).to_string_lossy());
+        }
+
+        if args.with_metadata {
+            let mut meta = Vec::new();
+            style.write(&mut meta, &unpacked.metadata)?;
+            extracted.write(&meta_key, &meta)?;
+            info!("  {} label metadata -> {}", Status::Ok, disk_path(extracted, &meta_key).to_string_lossy());
+        }
+        
+        self.outputs.lock().unwrap().write(locale, entries)?;
+        
+        let stats = ExtractionStats::new(locale, &bin, &unpacked, count, &options_desc, unpack_started.elapsed());
+        let mut stats_json = Vec::new();
+        style.write(&mut stats_json, &stats)?;
+        extracted.write(&stats_key, &stats_json)?;
+        debug!("  stats -> {}", disk_path(extracted, &stats_key).to_string_lossy());
+        
+        Ok(Extracted { count, output: output_path, delta, stats, skipped: false })
+    }
+
+    /// Writes one file per path of `unpacked` (unpacked with `collect_paths`) to the
+    /// folder `dir`, keyed by label name, replacing anything left in `dir` from an
+    /// earlier run. The index listing them, with file names relative to the folder
+    /// holding `dir`, goes to `json`. Each file is sorted by `order`, or keeps its
+    /// labels in file order when it is `None`, and has an `__order` array unless
+    /// `--no-order`. Returns the number of strings written.
+    fn write_split_output(
+        &self,
+        unpacked: &UnpackedLanguages,
+        dir: &str,
+        order: Option<KeyOrder>,
+        style: JsonStyle,
+        json: &mut Vec<u8>,
+    ) -> Result<usize> {
+        let include_order = !self.args.no_order;
+        self.extracted.remove_dir_all(dir).with_context(|| format!("removing the old {}", dir))?;
+        self.extracted.create_dir_all(dir)?;
+        let dir_name = dir.rsplit('/').next().unwrap_or_default();
+        
+        let names = split_file_names(unpacked.paths.keys().map(String::as_str));
+        let mut index = Vec::new();
+        let mut count = 0;
+        for (path, labels) in &unpacked.paths {
+            let entries: BTreeMap<String, String> = labels
+                .iter()
+                .filter_map(|name| Some((name.clone(), unpacked.entries.get(&format!("{}{}", path, name))?.clone())))
+                .collect();
+            let file_name = format!("{}.json", names[path.as_str()]);
+            let mut file_json = Vec::new();
+            let written = match order {
+                Some(order) => write_language_json_ordered(&entries, order, style, include_order, &mut file_json)?,
+                None => write_language_json_in_order(&entries, labels, style, include_order, &mut file_json)?,
+            };
+            if self.args.ascii_escape {
+                file_json = ascii_escape_json(&String::from_utf8(file_json)?).into_bytes();
             }
+            self.extracted.write(&format!("{}/{}", dir, file_name), &file_json)?;
+            debug!("  {} strings -> {}", written, file_name);
+            
+            count += written;
+            index.push(SplitFile {
+                path: path.clone(),
+                file: format!("{}/{}", dir_name, file_name),
+                entries: written,
+            });
         }
+        style.write(json, &index)?;
+        info!("  {} {} paths -> {}", Status::Ok, index.len(), disk_path(self.extracted, dir).to_string_lossy());
+        Ok(count)
+    }
+}
+
+/// Unpacks the `_H` contents `bin`, read from `h_file_path`, with the selected ZSTD
+/// backend
+fn unpack_languages(bin: &[u8], h_file_path: &str, zstd: Option<&Zstd>, options: &UnpackOptions) -> Result<UnpackedLanguages> {
+    let (_, dict) = languages_read_header(bin)
+        .with_context(|| format!("parsing {}", h_file_path))?;
+    let decompressor: Box<dyn DictDecompressor> = match zstd {
+        Some(zstd) => Box::new(zstd.with_dict(&dict)?),
+        None => Box::new(BundledZstd::new(&dict)?),
+    };
+    languages_unpack(bin, decompressor.as_ref(), options).with_context(|| format!("parsing {}", h_file_path))
+}
+
+/// Unpacks `locale` from the downloads folder `downloads`
+fn read_locale_entries(
+    locale: &str,
+    downloads: &Path,
+    platform: &str,
+    zstd: Option<&Zstd>,
+    options: &UnpackOptions,
+) -> Result<BTreeMap<String, String>> {
+    let h_file_path = languages_h_path(downloads, locale, platform)?;
+    let bin = fs::read(&h_file_path).with_context(|| format!("reading {}", h_file_path))?;
+    Ok(unpack_languages(&bin, &h_file_path, zstd, options)?.entries)
+}
+
+/// A file of the `--split-by-path` layout, as listed in `<locale>.index.json`
+#[derive(Serialize, Deserialize)]
+struct SplitFile {
+    /// Manifest path the file holds the labels of
+    path: String,
+    /// File name, relative to the index's folder
+    file: String,
+    entries: usize,
+}
+
+/// Manifest path and file of each entry in a `--split-by-path` index
+fn read_split_index(index_path: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let index: Vec<SplitFile> = serde_json::from_str(&fs::read_to_string(index_path)?)
+        .with_context(|| format!("parsing {}", index_path.display()))?;
+    let dir = index_path.parent().unwrap_or(Path::new(""));
+    index.into_iter().map(|file| Ok((file.path, safe_join(dir, &file.file)?))).collect()
+}
 
-        let output_path = get_extract_path(&format!("/Languages/{}.json", locale), None);
-        if let Some(parent) = output_path.parent() { fs::create_dir_all(parent)?; }
-        let json = serde_json::to_string_pretty(&ordered)?;
-        fs::write(&output_path, json)?;
-        println!("  ✓ {} strings -> {}", keys.len(), output_path.to_string_lossy());
+/// Entries of a `--split-by-path` output in `extracted`, read back through its index
+/// at `index_key`
+fn read_split_output(extracted: &dyn Storage, index_key: &str) -> Result<BTreeMap<String, String>> {
+    let index: Vec<SplitFile> = serde_json::from_slice(&extracted.read(index_key)?)
+        .with_context(|| format!("parsing {}", index_key))?;
+    let dir = index_key.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let mut entries = BTreeMap::new();
+    for file in index {
+        let file_key = format!("{}/{}", dir, file.file);
+        let labels = read_language_json(std::str::from_utf8(&extracted.read(&file_key)?)?)
+            .with_context(|| format!("parsing {}", file_key))?;
+        entries.extend(labels.into_iter().map(|(name, value)| (format!("{}{}", file.path, name), value)));
     }
-    
-    // Create alias Languages.json to en if present, else first present
-    let alias_path = get_extract_path("/Languages/Languages.json", None);
-    
-    if present.contains(&"en".to_string()) {
-        let en_path = get_extract_path("/Languages/en.json", None);
-        if let Ok(content) = fs::read_to_string(&en_path) {
-            fs::write(&alias_path, content)?;
-            println!("Alias written: Languages.json -> en.json");
+    Ok(entries)
+}
+
+/// Prints `--since`: for each locale, the strings that differ between the snapshot
+/// `since` and the files just extracted from `downloads`, as text or Markdown
+fn print_changelog(
+    since: &Path,
+    downloads: &Path,
+    locales: &[&String],
+    args: &Args,
+    zstd: Option<&Zstd>,
+    options: &UnpackOptions,
+) -> Result<()> {
+    let since_id = since.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let markdown = args.delta_format == DeltaFormat::Markdown;
+    if !markdown {
+        println!("\n=== Changes since {} ===", since_id);
+    }
+    let mut deltas: Vec<(&str, LocaleDelta)> = Vec::new();
+    for locale in locales {
+        let old = match read_locale_entries(locale, since, &args.platform, zstd, options) {
+            Ok(old) => old,
+            Err(err) if markdown => {
+                warn!("{}: not comparable ({:#})", locale, err);
+                continue;
+            }
+            Err(err) => {
+                println!("\n{}: not comparable ({:#})", locale, err);
+                continue;
+            }
+        };
+        let new = read_locale_entries(locale, downloads, &args.platform, zstd, options)?;
+        let mut delta = diff_entries(&old, &new);
+        delta.detect_moves(args.fuzzy);
+        if markdown {
+            deltas.push((locale.as_str(), delta));
+            continue;
+        }
+        
+        println!(
+            "\n{}: {} added, {} modified, {} removed, {} moved",
+            locale,
+            delta.added.len(),
+            delta.changed.len(),
+            delta.removed.len(),
+            delta.moved.len()
+        );
+        for (key, value) in &delta.added {
+            println!("  + {} = {:?}", key, value);
         }
-    } else if !present.is_empty() {
-        let first = &present[0];
-        let first_path = get_extract_path(&format!("/Languages/{}.json", first), None);
-        if let Ok(content) = fs::read_to_string(&first_path) {
-            fs::write(&alias_path, content)?;
-            println!("Alias written: Languages.json -> {}.json", first);
+        for (key, change) in &delta.changed {
+            println!("  ~ {}: {:?} -> {:?}", key, change.old, change.new);
         }
+        for key in delta.removed.keys() {
+            println!("  - {}", key);
+        }
+        for (key, moved) in &delta.moved {
+            match &moved.old_value {
+                Some(old_value) => println!("  > {} -> {}: {:?} -> {:?}", key, moved.to, old_value, moved.value),
+                None => println!("  > {} -> {}", key, moved.to),
+            }
+        }
+        for unresolved in &delta.unresolved_moves {
+            let keys = |keys: &BTreeMap<String, String>| keys.keys().cloned().collect::<Vec<_>>().join(", ");
+            println!("  ? {} -> {} (ambiguous)", keys(&unresolved.from), keys(&unresolved.to));
+        }
+    }
+    
+    if markdown {
+        let deltas: BTreeMap<&str, &LocaleDelta> = deltas.iter().map(|(locale, delta)| (*locale, delta)).collect();
+        println!();
+        print!("{}", markdown_changelog(&format!("Changes since {}", since_id), &deltas, args.max_value_length));
     }
+    Ok(())
+}
+
+/// Runs `--hook` through the shell with the delta file as its argument and the
+/// total key counts in `SOULFRAME_ADDED`, `SOULFRAME_CHANGED`, `SOULFRAME_REMOVED`
+/// and `SOULFRAME_MOVED`.
+///
+/// The path is never spliced into the command text, so quotes, `$` or backticks in
+/// it can't change what runs: `sh` gets it as `$1`. `cmd` has no positional
+/// arguments, so there it is expanded from `SOULFRAME_DELTA_PATH`, which `cmd` does
+/// not parse again; Windows paths can't contain the `"` around it.
+fn run_hook(hook: &str, delta_path: &Path, deltas: &BTreeMap<&str, &LocaleDelta>) -> Result<()> {
+    #[cfg(windows)]
+    let mut shell = {
+        use std::os::windows::process::CommandExt;
+        // Passed raw, since std's quoting isn't the one cmd parses; /S strips
+        // exactly the outer quotes, leaving any quotes in the hook itself alone
+        let mut shell = std::process::Command::new("cmd");
+        shell.args(["/S", "/C"]).raw_arg(format!("\"{} \"%SOULFRAME_DELTA_PATH%\"\"", hook));
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = std::process::Command::new("sh");
+        shell.args(["-c", &format!("{} \"$1\"", hook), "sh"]).arg(delta_path);
+        shell
+    };
+    shell.env("SOULFRAME_DELTA_PATH", delta_path);
+    let total = |count: fn(&LocaleDelta) -> usize| deltas.values().map(|d| count(d)).sum::<usize>().to_string();
+    shell
+        .env("SOULFRAME_ADDED", total(|d| d.added.len()))
+        .env("SOULFRAME_CHANGED", total(|d| d.changed.len()))
+        .env("SOULFRAME_REMOVED", total(|d| d.removed.len()))
+        .env("SOULFRAME_MOVED", total(|d| d.moved.len()))
+        .env("SOULFRAME_DELTA_LOCALES", deltas.keys().copied().collect::<Vec<_>>().join(","));
     
-    println!("\nDone. Output under ./extracted-data/0/Languages/");
+    info!("Running hook: {} {}", hook, delta_path.display());
+    let status = shell.status()?;
+    if !status.success() {
+        return Err(anyhow!("Hook exited with {}", status));
+    }
+    Ok(())
+}
+
+/// Packs `files` into a `.zip` or `.tar.gz` at `archive`, named by their path
+/// relative to `root`
+fn write_archive(archive: &Path, root: &Path, files: &[&Path]) -> Result<()> {
+    let name = archive.to_string_lossy().to_lowercase();
+    let entries = files.iter().map(|path| {
+        let entry = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        (entry, *path)
+    });
     
+    if let Some(parent) = archive.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::File::create(archive).with_context(|| format!("Failed to create {}", archive.display()))?;
+    if name.ends_with(".zip") {
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (entry, path) in entries {
+            zip.start_file(entry, options)?;
+            std::io::Write::write_all(&mut zip, &fs::read(path)?)?;
+        }
+        zip.finish()?;
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut tar = tar::Builder::new(encoder);
+        for (entry, path) in entries {
+            tar.append_path_with_name(path, entry)?;
+        }
+        tar.into_inner()?.finish()?;
+    } else {
+        return Err(anyhow!("Archive {} must end in .zip or .tar.gz", archive.display()));
+    }
     Ok(())
 }
@@ -0,0 +1,138 @@
+use clap::Parser;
+use anyhow::{anyhow, Context, Result};
+use soulframe_language_downloader::extract::{languages_lookup_keys, languages_read_header, read_language_json, BundledZstd};
+use soulframe_language_downloader::locales::{known_locales, parse_locales, LocaleAliases};
+use soulframe_language_downloader::{get_download_path, get_extract_path, locale_suffix, logging, DEFAULT_PLATFORM};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+#[derive(Parser)]
+#[command(name = "get")]
+#[command(about = "Print the strings of a few keys in one or more locales")]
+struct Args {
+    /// Keys to look up, e.g. /Lotus/Language/Menu/Start
+    #[arg(required = true, value_name = "KEY")]
+    keys: Vec<String>,
+
+    /// Locales to look in (comma-separated), with the same syntax as extract
+    #[arg(short, long, default_value = "en")]
+    locales: String,
+
+    /// Platform the locales were downloaded or extracted for
+    #[arg(long, default_value = DEFAULT_PLATFORM)]
+    platform: String,
+
+    /// Read the downloaded Languages.bin_H instead of the extracted JSON, stopping
+    /// once every key is found
+    #[arg(long)]
+    from_bin: bool,
+
+    /// Print a JSON object of locale -> key -> value instead, with null for keys a
+    /// locale doesn't have and for locales that aren't there
+    #[arg(long)]
+    json: bool,
+
+    /// Log more: -v adds each URL tried and other details, -vv parse offsets.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.verbose, args.quiet);
+
+    let aliases = LocaleAliases::load_default()?;
+    let locales = parse_locales(&aliases.translate_spec(&args.locales), &known_locales())?;
+    let keys: Vec<&str> = args.keys.iter().map(String::as_str).collect();
+
+    let source = if args.from_bin { "downloaded Languages.bin" } else { "extracted JSON" };
+    // None for a locale with nothing to read, so it isn't mistaken for missing keys
+    let mut found: BTreeMap<&str, Option<BTreeMap<String, String>>> = BTreeMap::new();
+    for locale in &locales {
+        let values = if args.from_bin {
+            lookup_bin(locale, &args.platform, &keys)?
+        } else {
+            lookup_json(locale, &args.platform, &keys)?
+        };
+        // Kept off stdout with --json, where the nulls say the same
+        if values.is_none() && !args.json {
+            warn!("[{}] No {} (run {} first)", locale, source, if args.from_bin { "download" } else { "extract" });
+        }
+        found.insert(locale.as_str(), values);
+    }
+
+    if args.json {
+        let output: BTreeMap<&str, Option<BTreeMap<&str, Option<&String>>>> = found
+            .iter()
+            .map(|(locale, values)| {
+                let values = values.as_ref().map(|values| keys.iter().map(|key| (*key, values.get(*key))).collect());
+                (*locale, values)
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        for key in &keys {
+            println!("{}", key);
+            for locale in &locales {
+                match found[locale.as_str()].as_ref().map(|values| values.get(*key)) {
+                    Some(Some(value)) => println!("  {:<6} {:?}", locale, value),
+                    Some(None) => println!("  {:<6} (key not found)", locale),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    let missing_locales: Vec<&str> = found.iter().filter(|(_, values)| values.is_none()).map(|(locale, _)| *locale).collect();
+    let read: Vec<&BTreeMap<String, String>> = found.values().flatten().collect();
+    let missing_keys: usize = read
+        .iter()
+        .map(|values| keys.iter().filter(|key| !values.contains_key(**key)).count())
+        .sum();
+    let mut problems = Vec::new();
+    if missing_keys > 0 {
+        problems.push(format!("{} of {} keys not found (counting each locale)", missing_keys, keys.len() * read.len()));
+    }
+    if !missing_locales.is_empty() {
+        problems.push(format!("no {} for {}", source, missing_locales.join(", ")));
+    }
+    if !problems.is_empty() {
+        return Err(anyhow!("{}", problems.join("; ")));
+    }
+    Ok(())
+}
+
+/// Values of `keys` in the locale's extracted JSON, or `None` if it hasn't been extracted
+fn lookup_json(locale: &str, platform: &str, keys: &[&str]) -> Result<Option<BTreeMap<String, String>>> {
+    let stem = &locale_suffix(locale, platform)[1..];
+    let path = get_extract_path(&format!("/Languages/{}.json", stem), None)?;
+    let Ok(json) = fs::read_to_string(&path) else {
+        debug!("[{}] Can't read {}", locale, path.display());
+        return Ok(None);
+    };
+    let mut entries = read_language_json(&json).with_context(|| format!("parsing {}", path.display()))?;
+    Ok(Some(keys.iter().filter_map(|key| Some((key.to_string(), entries.remove(*key)?))).collect()))
+}
+
+/// Values of `keys` in the locale's downloaded `Languages.bin_H`, read only as far
+/// as the last key found, or `None` if it hasn't been downloaded
+fn lookup_bin(locale: &str, platform: &str, keys: &[&str]) -> Result<Option<BTreeMap<String, String>>> {
+    let h_path = get_download_path("/Languages.bin", Some(&locale_suffix(locale, platform)))?;
+    let h_path = PathBuf::from(format!("{}_H", h_path.to_string_lossy()));
+    let Ok(bin) = fs::read(&h_path) else {
+        debug!("[{}] Can't read {}", locale, h_path.display());
+        return Ok(None);
+    };
+    let (_, dict) = languages_read_header(&bin).with_context(|| format!("parsing {}", h_path.display()))?;
+    let values = languages_lookup_keys(&bin, keys, &BundledZstd::new(&dict)?)
+        .with_context(|| format!("parsing {}", h_path.display()))?;
+    debug!("[{}] {} of {} keys found in {}", locale, values.len(), keys.len(), h_path.display());
+    Ok(Some(values))
+}
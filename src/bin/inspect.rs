@@ -0,0 +1,87 @@
+use clap::Parser;
+use anyhow::{anyhow, Result};
+use soulframe_language_downloader::manifest::SoulframeManifest;
+use soulframe_language_downloader::{logging, shcc_inspect, sniff_payload, unwrap_payload, ManifestFormat, PayloadFormat};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "inspect")]
+#[command(about = "Print the structure of a downloaded SHCC payload for debugging")]
+struct Args {
+    /// Raw downloaded payload or SHCC file to inspect
+    file: PathBuf,
+
+    /// Treat FILE as a manifest `_H` file and list each entry's unknown 4 bytes
+    #[arg(long)]
+    manifest: bool,
+
+    /// Log more: -v adds each URL tried and other details, -vv parse offsets.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.verbose, args.quiet);
+    
+    let bin = fs::read(&args.file)
+        .map_err(|e| anyhow!("Failed to read {}: {}", args.file.display(), e))?;
+    
+    println!("=== {} ===", args.file.display());
+    
+    if args.manifest {
+        return print_manifest_unks(bin);
+    }
+    
+    // Downloaded payloads may wrap the container in Oodle or zstd; unwrap them the
+    // way the downloader does so the container itself is shown
+    let mut container = None;
+    match sniff_payload(&bin) {
+        Ok(PayloadFormat::Shcc) => {}
+        Ok(format) => match unwrap_payload(bin.clone()) {
+            Ok((unwrapped, _)) => {
+                println!("Payload is {:?}-compressed; inspecting the {} decompressed bytes", format, unwrapped.len());
+                container = Some(unwrapped);
+            }
+            Err(e) => {
+                println!("Payload looks {:?}-compressed but could not be unwrapped: {}", format, e);
+            }
+        },
+        Err(e) => {
+            println!("{}", e);
+        }
+    }
+    
+    let inspection = shcc_inspect(container.as_deref().unwrap_or(&bin));
+    print!("{}", inspection.report);
+    
+    if !inspection.ends_cleanly {
+        return Err(anyhow!("{} is not a well-formed SHCC container", args.file.display()));
+    }
+    
+    Ok(())
+}
+
+/// Lists the `unk` bytes of every manifest entry as hex and as a little-endian u32
+fn print_manifest_unks(bin: Vec<u8>) -> Result<()> {
+    let mut manifest = SoulframeManifest::from_bytes(bin, ManifestFormat::Current)?;
+    let entries = manifest.entries();
+    
+    println!("{:<11} {:>10}  Path", "unk (hex)", "unk (u32)");
+    for entry in &entries {
+        let hex: Vec<String> = entry.unk.iter().map(|b| format!("{:02x}", b)).collect();
+        println!("{:<11} {:>10}  {}", hex.join(" "), entry.unk_u32(), entry.path);
+    }
+    println!("{} entries", entries.len());
+    
+    if let Some(offset) = manifest.malformed_at() {
+        return Err(anyhow!("Manifest is truncated at offset {}", offset));
+    }
+    Ok(())
+}
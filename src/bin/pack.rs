@@ -0,0 +1,79 @@
+use clap::Parser;
+use anyhow::{anyhow, Result};
+use soulframe_language_downloader::extract::{languages_pack, languages_read_header, read_language_json};
+use soulframe_language_downloader::output::{self, Status};
+use soulframe_language_downloader::{get_download_path, get_extract_path, logging};
+use std::fs;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Parser)]
+#[command(name = "pack")]
+#[command(about = "Pack an extracted locale JSON back into a Languages.bin_H blob")]
+struct Args {
+    /// Locale whose downloaded Languages.bin provides the hash, suffixes and dictionary
+    #[arg(short, long, default_value = "en")]
+    locale: String,
+
+    /// Extracted JSON to pack (defaults to ./extracted-data/0/Languages/<locale>.json)
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+
+    /// Output file (defaults to ./extracted-data/0_<locale>/Languages.bin_H)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Log more: -v adds each URL tried and other details, -vv parse offsets.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Don't color status markers (also off when NO_COLOR is set or stdout isn't a
+    /// terminal)
+    #[arg(long, global = true)]
+    no_color: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.verbose, args.quiet);
+    output::init(args.no_color);
+
+    info!("=== Pack JSON -> Languages.bin ===");
+
+    let suffix = format!("_{}", args.locale);
+
+    // The original file supplies everything the JSON doesn't carry
+    let h_path = get_download_path("/Languages.bin", Some(&suffix))?;
+    let h_file_path = format!("{}_H", h_path.to_string_lossy());
+    let original = fs::read(&h_file_path)
+        .map_err(|_| anyhow!("Languages.bin_H not found for locale {}", args.locale))?;
+    let (mut file, dict) = languages_read_header(&original)?;
+
+    let input = match args.input {
+        Some(input) => input,
+        None => get_extract_path(&format!("/Languages/{}.json", args.locale), None)?,
+    };
+    info!("[{}] Reading {}", args.locale, input.display());
+    let json = fs::read_to_string(&input)?;
+    file.entries.extend(read_language_json(&json)?);
+
+    let packed = languages_pack(&file, &dict)?;
+
+    let output = match args.output {
+        Some(output) => output,
+        None => PathBuf::from(format!("{}_H", get_extract_path("/Languages.bin", Some(&suffix))?.to_string_lossy())),
+    };
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&output, &packed)?;
+
+    info!("  {} {} strings -> {}", Status::Ok, file.entries.len(), output.display());
+
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use clap::Parser;
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use soulframe_language_downloader::extract::{
+    languages_read_header, languages_unpack, string_stats, BundledZstd, KeyFilter, StringStats, UnpackOptions,
+};
+use soulframe_language_downloader::locales::{available_platform_locales, known_locales, parse_locales, LocaleAliases};
+use soulframe_language_downloader::{get_download_path, locale_suffix, logging, DEFAULT_PLATFORM};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+#[derive(Parser)]
+#[command(name = "stats")]
+#[command(about = "Count the keys, characters and words of downloaded locales")]
+struct Args {
+    /// Locales to count (comma-separated), with the same syntax as extract
+    #[arg(short, long, default_value = "en")]
+    locales: String,
+
+    /// Platform the locales were downloaded for
+    #[arg(long, default_value = DEFAULT_PLATFORM)]
+    platform: String,
+
+    /// Count this Languages.bin_H instead of the downloaded one; --locales names the
+    /// one locale it holds
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// Also count each group of keys sharing the first N segments of their path
+    #[arg(long, value_name = "N")]
+    depth: Option<usize>,
+
+    /// Only count keys starting with one of these prefixes (comma-separated or repeated)
+    #[arg(long, value_name = "PREFIX", value_delimiter = ',')]
+    include_keys: Vec<String>,
+
+    /// Leave out keys starting with one of these prefixes (comma-separated or repeated)
+    #[arg(long, value_name = "PREFIX", value_delimiter = ',')]
+    exclude_keys: Vec<String>,
+
+    /// Also write every row as CSV
+    #[arg(long, value_name = "PATH")]
+    csv: Option<PathBuf>,
+
+    /// Also write every row as JSON, per locale
+    #[arg(long, value_name = "PATH")]
+    json: Option<PathBuf>,
+
+    /// Log more: -v adds each URL tried and other details, -vv parse offsets.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// A locale's counts, as written by --json
+#[derive(Serialize)]
+struct LocaleStats {
+    total: StringStats,
+    /// With --depth, one entry per path prefix
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    prefixes: Vec<StringStats>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.verbose, args.quiet);
+
+    let aliases = LocaleAliases::load_default()?;
+    let locales = parse_locales(&aliases.translate_spec(&args.locales), &known_locales())?;
+    let locales = match &args.input {
+        Some(_) if locales.len() != 1 => {
+            return Err(anyhow!("--input reads a single locale; name it with --locales (got {})", locales.join(",")));
+        }
+        Some(_) => locales,
+        None => {
+            let downloads = std::env::current_dir()?.join("downloaded-data");
+            let present = available_platform_locales(&downloads, &locales, &args.platform);
+            let missing: Vec<&str> = locales.iter().filter(|l| !present.contains(l)).map(String::as_str).collect();
+            if !missing.is_empty() {
+                warn!("Warning: no downloaded Languages.bin for {}", missing.join(", "));
+            }
+            present
+        }
+    };
+    if locales.is_empty() {
+        return Err(anyhow!("No downloaded Languages.bin found. Run download command first."));
+    }
+
+    let options = UnpackOptions {
+        keys: KeyFilter { include: args.include_keys.clone(), exclude: args.exclude_keys.clone() },
+        ..Default::default()
+    };
+    let mut stats: BTreeMap<String, LocaleStats> = BTreeMap::new();
+    for locale in &locales {
+        let h_file_path = match &args.input {
+            Some(input) => input.clone(),
+            None => {
+                let h_path = get_download_path("/Languages.bin", Some(&locale_suffix(locale, &args.platform)))?;
+                PathBuf::from(format!("{}_H", h_path.to_string_lossy()))
+            }
+        };
+        info!("[{}] Reading {}", locale, h_file_path.display());
+        let bin = fs::read(&h_file_path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", h_file_path.display(), e))?;
+        let (_, dict) = languages_read_header(&bin)
+            .with_context(|| format!("parsing {}", h_file_path.display()))?;
+        let entries = languages_unpack(&bin, &BundledZstd::new(&dict)?, &options)
+            .with_context(|| format!("parsing {}", h_file_path.display()))?
+            .entries;
+
+        let total = string_stats(&entries, None).remove(0);
+        let prefixes = args.depth.map(|depth| string_stats(&entries, Some(depth))).unwrap_or_default();
+        stats.insert(locale.clone(), LocaleStats { total, prefixes });
+    }
+
+    let rows: Vec<(&str, &StringStats)> = stats
+        .iter()
+        .flat_map(|(locale, stats)| {
+            std::iter::once(&stats.total).chain(&stats.prefixes).map(move |row| (locale.as_str(), row))
+        })
+        .collect();
+    print_table(&rows);
+
+    if let Some(csv) = &args.csv {
+        write_csv(csv, &rows)?;
+        info!("\nCSV written: {}", csv.display());
+    }
+    if let Some(json) = &args.json {
+        create_parent(json)?;
+        fs::write(json, serde_json::to_string_pretty(&stats)?)?;
+        info!("\nJSON written: {}", json.display());
+    }
+
+    Ok(())
+}
+
+const COLUMNS: [&str; 8] = ["Locale", "Prefix", "Keys", "Characters", "Words", "Min", "Median", "Max"];
+
+/// A row's cells in `COLUMNS` order; a locale's total has an empty prefix
+fn cells(locale: &str, row: &StringStats) -> [String; 8] {
+    [
+        locale.to_string(),
+        row.prefix.clone(),
+        row.keys.to_string(),
+        row.characters.to_string(),
+        row.words.to_string(),
+        row.min_length.to_string(),
+        row.median_length.to_string(),
+        row.max_length.to_string(),
+    ]
+}
+
+/// Prints the rows with aligned columns, numbers to the right. Totals show `*` as
+/// their prefix.
+fn print_table(rows: &[(&str, &StringStats)]) {
+    let mut lines: Vec<[String; 8]> = vec![COLUMNS.map(String::from)];
+    for (locale, row) in rows {
+        let mut cells = cells(locale, row);
+        if cells[1].is_empty() {
+            cells[1] = "*".to_string();
+        }
+        lines.push(cells);
+    }
+
+    let mut widths = [0; 8];
+    for line in &lines {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for line in &lines {
+        let text: Vec<String> = line
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(index, (cell, width))| {
+                if index < 2 {
+                    format!("{:<width$}", cell, width = width)
+                } else {
+                    format!("{:>width$}", cell, width = width)
+                }
+            })
+            .collect();
+        println!("{}", text.join("  ").trim_end());
+    }
+}
+
+fn write_csv(path: &Path, rows: &[(&str, &StringStats)]) -> Result<()> {
+    let header = ["locale", "prefix", "keys", "characters", "words", "min_length", "median_length", "max_length"];
+    let mut csv = header.join(",") + "\n";
+    for (locale, row) in rows {
+        let line: Vec<String> = cells(locale, row).iter().map(|cell| csv_field(cell)).collect();
+        csv.push_str(&line.join(","));
+        csv.push('\n');
+    }
+    create_parent(path)?;
+    fs::write(path, csv)?;
+    Ok(())
+}
+
+/// `field` quoted when it holds a comma, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
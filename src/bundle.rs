@@ -0,0 +1,436 @@
+//! Single-file bundles of `downloaded-data` for moving downloads to a machine
+//! without internet access. A bundle holds the primary and localized manifests,
+//! each locale's `Languages.bin_H/_B` and their `.meta.json` sidecars, plus a
+//! `bundle.json` listing every file with its MD5.
+
+use crate::snapshot::is_snapshot_id;
+use crate::{safe_join, SoulframeError, MANIFEST_HASH_LEN};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Name of the contents listing inside a bundle; it is always the first entry
+pub const BUNDLE_MANIFEST: &str = "bundle.json";
+
+/// Layout version of `bundle.json`, bumped when older tools can't read a bundle
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Primary manifest `_H`, relative to the downloads folder
+const PRIMARY_MANIFEST_H: &str = "0/H.Cache.bin_H";
+
+/// Archive type, picked from the output file name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleFormat {
+    TarZst,
+    Zip,
+}
+
+impl BundleFormat {
+    /// `.tar.zst` or `.zip`, by the extension of `path`
+    pub fn from_path(path: &Path) -> Result<Self, SoulframeError> {
+        let name = path.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.zst") {
+            Ok(BundleFormat::TarZst)
+        } else if name.ends_with(".zip") {
+            Ok(BundleFormat::Zip)
+        } else {
+            Err(anyhow!("Bundle {} must end in .tar.zst or .zip", path.display()).into())
+        }
+    }
+}
+
+/// One file in a bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleFile {
+    /// Path relative to the downloads folder, with `/` separators
+    pub path: String,
+    pub size: u64,
+    /// Hex MD5 of the contents
+    pub md5: String,
+}
+
+/// Contents of `bundle.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    /// Version of the tool that wrote the bundle
+    pub tool_version: String,
+    /// Hex header hash of the bundled primary manifest
+    pub manifest_hash: String,
+    pub files: Vec<BundleFile>,
+}
+
+/// Files under `downloads` that belong in a bundle, as sorted relative paths.
+/// Snapshot folders and run state such as `state.json` are left out.
+pub fn bundle_files(downloads: &Path) -> Result<Vec<String>, SoulframeError> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(downloads)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else { continue };
+        if entry.file_type()?.is_dir() && (name == "0" || name.starts_with("0_")) && !is_snapshot_id(&name) {
+            collect_files(&entry.path(), &name, &mut files)?;
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn collect_files(dir: &Path, relative: &str, files: &mut Vec<String>) -> Result<(), SoulframeError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else { continue };
+        let path = format!("{}/{}", relative, name);
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &path, files)?;
+        } else if name.ends_with("_H") || name.ends_with("_B") || name.ends_with(".meta.json") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Hex header hash of a primary manifest `_H`
+fn manifest_hash(manifest_h: &[u8]) -> Result<String, SoulframeError> {
+    let hash = manifest_h
+        .get(..MANIFEST_HASH_LEN)
+        .ok_or_else(|| anyhow!("{} is too short to hold a hash", PRIMARY_MANIFEST_H))?;
+    Ok(hash.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Packs the files of `downloads` into `output`, returning what was written
+pub fn write_bundle(downloads: &Path, output: &Path, format: BundleFormat) -> Result<BundleManifest, SoulframeError> {
+    let primary = fs::read(safe_join(downloads, PRIMARY_MANIFEST_H)?)
+        .with_context(|| format!("No primary manifest in {}; run download first", downloads.display()))?;
+
+    let mut contents = Vec::new();
+    let mut files = Vec::new();
+    for path in bundle_files(downloads)? {
+        let data = fs::read(safe_join(downloads, &path)?)?;
+        files.push(BundleFile {
+            path: path.clone(),
+            size: data.len() as u64,
+            md5: format!("{:x}", md5::compute(&data)),
+        });
+        contents.push((path, data));
+    }
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        manifest_hash: manifest_hash(&primary)?,
+        files,
+    };
+    let listing = serde_json::to_vec_pretty(&manifest).map_err(anyhow::Error::from)?;
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = File::create(output)?;
+    match format {
+        BundleFormat::TarZst => {
+            let encoder = zstd::stream::write::Encoder::new(file, 0)?.auto_finish();
+            let mut tar = tar::Builder::new(encoder);
+            append_tar(&mut tar, BUNDLE_MANIFEST, &listing)?;
+            for (path, data) in &contents {
+                append_tar(&mut tar, path, data)?;
+            }
+            tar.into_inner()?.flush()?;
+        }
+        BundleFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(file);
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated);
+            for (path, data) in std::iter::once((BUNDLE_MANIFEST, listing.as_slice()))
+                .chain(contents.iter().map(|(path, data)| (path.as_str(), data.as_slice())))
+            {
+                zip.start_file(path, options).map_err(anyhow::Error::from)?;
+                zip.write_all(data)?;
+            }
+            zip.finish().map_err(anyhow::Error::from)?;
+        }
+    }
+    Ok(manifest)
+}
+
+fn append_tar<W: Write>(tar: &mut tar::Builder<W>, path: &str, data: &[u8]) -> Result<(), SoulframeError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// Reads every entry of a bundle into memory, `bundle.json` included
+fn read_entries(input: &Path, format: BundleFormat) -> Result<Vec<(String, Vec<u8>)>, SoulframeError> {
+    let file = File::open(input).with_context(|| format!("Failed to open {}", input.display()))?;
+    let mut entries = Vec::new();
+    match format {
+        BundleFormat::TarZst => {
+            let mut tar = tar::Archive::new(zstd::stream::read::Decoder::new(file)?);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let path = entry.path()?.to_string_lossy().replace('\\', "/");
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data)?;
+                entries.push((path, data));
+            }
+        }
+        BundleFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file).map_err(anyhow::Error::from)?;
+            for index in 0..zip.len() {
+                let mut entry = zip.by_index(index).map_err(anyhow::Error::from)?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let path = entry.name().replace('\\', "/");
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data)?;
+                entries.push((path, data));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Unpacks `input` into `downloads` after checking every file against
+/// `bundle.json`. Nothing is written unless the whole bundle verifies.
+///
+/// If `downloads` already holds a different primary manifest the bundle is
+/// refused, unless `overwrite` is set.
+pub fn unbundle(input: &Path, downloads: &Path, overwrite: bool) -> Result<BundleManifest, SoulframeError> {
+    let format = BundleFormat::from_path(input)?;
+    let mut entries = read_entries(input, format)?;
+
+    let listing = entries
+        .iter()
+        .position(|(path, _)| path == BUNDLE_MANIFEST)
+        .map(|index| entries.remove(index))
+        .ok_or_else(|| anyhow!("{} has no {}; not a bundle", input.display(), BUNDLE_MANIFEST))?;
+    let manifest: BundleManifest = serde_json::from_slice(&listing.1)
+        .map_err(|e| anyhow!("Invalid {} in {}: {}", BUNDLE_MANIFEST, input.display(), e))?;
+    if manifest.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(anyhow!(
+            "{} uses bundle format {} but this tool reads up to {}; it was written by version {}",
+            input.display(),
+            manifest.format_version,
+            BUNDLE_FORMAT_VERSION,
+            manifest.tool_version
+        )
+        .into());
+    }
+
+    // Every listed file must be present with the listed contents, and nothing else
+    for file in &manifest.files {
+        let Some((_, data)) = entries.iter().find(|(path, _)| *path == file.path) else {
+            return Err(anyhow!("{} is listed in {} but missing from the bundle", file.path, BUNDLE_MANIFEST).into());
+        };
+        if data.len() as u64 != file.size || format!("{:x}", md5::compute(data)) != file.md5 {
+            return Err(SoulframeError::HashMismatch { path: file.path.clone() });
+        }
+    }
+    if let Some((path, _)) = entries.iter().find(|(path, _)| !manifest.files.iter().any(|f| f.path == *path)) {
+        return Err(anyhow!("{} is in the bundle but not listed in {}", path, BUNDLE_MANIFEST).into());
+    }
+
+    let primary = entries
+        .iter()
+        .find(|(path, _)| path == PRIMARY_MANIFEST_H)
+        .ok_or_else(|| anyhow!("Bundle has no primary manifest {}", PRIMARY_MANIFEST_H))?;
+    if manifest_hash(&primary.1)? != manifest.manifest_hash {
+        return Err(SoulframeError::HashMismatch { path: PRIMARY_MANIFEST_H.to_string() });
+    }
+    if !overwrite {
+        if let Ok(existing) = fs::read(safe_join(downloads, PRIMARY_MANIFEST_H)?) {
+            let existing = manifest_hash(&existing)?;
+            if existing != manifest.manifest_hash {
+                return Err(SoulframeError::BundleMismatch { bundled: manifest.manifest_hash.clone(), existing });
+            }
+        }
+    }
+
+    for (path, data) in &entries {
+        let local_path = safe_join(downloads, path)?;
+        if let Some(parent) = local_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&local_path, data)?;
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::{extract_languages_with_storage, JsonStyle};
+    use crate::fixtures::{LanguagesBuilder, ManifestBuilder};
+    use crate::storage::{LocalFs, MemoryStorage, Storage};
+    use std::path::PathBuf;
+
+    /// Fills `dir` as a download would, returning the files a bundle should hold
+    fn fixture_downloads(dir: &Path) -> Vec<(String, Vec<u8>)> {
+        let primary = ManifestBuilder::new()
+            .with_hash([7; MANIFEST_HASH_LEN])
+            .with_entry("/Languages.bin", [1; MANIFEST_HASH_LEN])
+            .build()
+            .unwrap();
+        let languages = LanguagesBuilder::new()
+            .with_label("/Menu/Play", "Play")
+            .with_compressed_label("/Menu/Quit", "Quit the game")
+            .build()
+            .unwrap();
+        let files = vec![
+            (PRIMARY_MANIFEST_H.to_string(), primary),
+            ("0_en/Languages.bin_H".to_string(), languages),
+            ("0_en/Languages.bin_H.meta.json".to_string(), br#"{"size":1}"#.to_vec()),
+        ];
+        for (path, data) in &files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        // Run state stays out of bundles
+        fs::write(dir.join("state.json"), "{}").unwrap();
+        files
+    }
+
+    /// `en.json` extracted from the downloads folder `downloads`
+    fn extracted_json(downloads: &Path) -> Vec<u8> {
+        let extracted = MemoryStorage::new();
+        extract_languages_with_storage("en", None, JsonStyle::Pretty, &LocalFs::new(downloads), &extracted).unwrap();
+        extracted.read("0/Languages/en.json").unwrap()
+    }
+
+    /// A zip bundle of fixture downloads, in a folder of its own
+    fn fixture_bundle() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let downloads = dir.path().join("downloaded-data");
+        fixture_downloads(&downloads);
+        let bundle = dir.path().join("bundle.zip");
+        write_bundle(&downloads, &bundle, BundleFormat::Zip).unwrap();
+        (dir, bundle)
+    }
+
+    /// Copies the zip bundle `input` to `output`, passing each entry through `edit`
+    fn rewrite_bundle(input: &Path, output: &Path, edit: impl Fn(&str, &mut Vec<u8>)) {
+        let mut zip = zip::ZipWriter::new(File::create(output).unwrap());
+        for (path, mut data) in read_entries(input, BundleFormat::Zip).unwrap() {
+            edit(&path, &mut data);
+            zip.start_file(path, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(&data).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    /// Applies `edit` to the `bundle.json` in `data`
+    fn edit_listing(data: &mut Vec<u8>, edit: impl FnOnce(&mut BundleManifest)) {
+        let mut manifest: BundleManifest = serde_json::from_slice(data).unwrap();
+        edit(&mut manifest);
+        *data = serde_json::to_vec(&manifest).unwrap();
+    }
+
+    /// Paths of every file under `dir`
+    fn files_under(dir: &Path) -> Vec<String> {
+        if !dir.exists() {
+            return Vec::new();
+        }
+        let mut files = Vec::new();
+        collect_files(dir, "", &mut files).unwrap();
+        files
+    }
+
+    #[test]
+    fn round_trips_downloads() {
+        let source = tempfile::tempdir().unwrap();
+        let mut files = fixture_downloads(source.path());
+        files.sort();
+        for name in ["bundle.tar.zst", "bundle.zip"] {
+            let out = tempfile::tempdir().unwrap();
+            let bundle = out.path().join(name);
+            let written = write_bundle(source.path(), &bundle, BundleFormat::from_path(&bundle).unwrap()).unwrap();
+            let listed: Vec<&str> = written.files.iter().map(|file| file.path.as_str()).collect();
+            assert_eq!(listed, files.iter().map(|(path, _)| path.as_str()).collect::<Vec<_>>());
+
+            let target = tempfile::tempdir().unwrap();
+            let read = unbundle(&bundle, target.path(), false).unwrap();
+            assert_eq!(read.manifest_hash, "07".repeat(MANIFEST_HASH_LEN));
+            for (path, data) in &files {
+                assert_eq!(&fs::read(target.path().join(path)).unwrap(), data, "{} in {}", path, name);
+            }
+            assert!(!target.path().join("state.json").exists());
+            assert_eq!(extracted_json(target.path()), extracted_json(source.path()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_a_bundle_from_a_newer_tool() {
+        let (dir, bundle) = fixture_bundle();
+        let newer = dir.path().join("newer.zip");
+        rewrite_bundle(&bundle, &newer, |path, data| {
+            if path == BUNDLE_MANIFEST {
+                edit_listing(data, |manifest| {
+                    manifest.format_version = BUNDLE_FORMAT_VERSION + 1;
+                    manifest.tool_version = "99.0.0".to_string();
+                });
+            }
+        });
+
+        let target = dir.path().join("target");
+        let err = unbundle(&newer, &target, false).unwrap_err().to_string();
+        assert!(err.contains(&format!("bundle format {}", BUNDLE_FORMAT_VERSION + 1)), "{}", err);
+        assert!(err.contains("written by version 99.0.0"), "{}", err);
+        assert!(files_under(&target).is_empty());
+    }
+
+    #[test]
+    fn rejects_a_mismatched_manifest_hash() {
+        let (dir, bundle) = fixture_bundle();
+        let relisted = dir.path().join("relisted.zip");
+        rewrite_bundle(&bundle, &relisted, |path, data| {
+            if path == BUNDLE_MANIFEST {
+                edit_listing(data, |manifest| manifest.manifest_hash = "00".repeat(MANIFEST_HASH_LEN));
+            }
+        });
+        let target = dir.path().join("target");
+        assert!(matches!(
+            unbundle(&relisted, &target, false),
+            Err(SoulframeError::HashMismatch { path }) if path == PRIMARY_MANIFEST_H
+        ));
+        assert!(files_under(&target).is_empty());
+
+        // Downloads holding another manifest are only replaced with `overwrite`
+        let other = ManifestBuilder::new().with_hash([9; MANIFEST_HASH_LEN]).build().unwrap();
+        fs::create_dir_all(target.join("0")).unwrap();
+        fs::write(target.join(PRIMARY_MANIFEST_H), &other).unwrap();
+        assert!(matches!(
+            unbundle(&bundle, &target, false),
+            Err(SoulframeError::BundleMismatch { bundled, existing })
+                if bundled == "07".repeat(MANIFEST_HASH_LEN) && existing == "09".repeat(MANIFEST_HASH_LEN)
+        ));
+        assert_eq!(fs::read(target.join(PRIMARY_MANIFEST_H)).unwrap(), other);
+        unbundle(&bundle, &target, true).unwrap();
+        assert_eq!(manifest_hash(&fs::read(target.join(PRIMARY_MANIFEST_H)).unwrap()).unwrap(), "07".repeat(MANIFEST_HASH_LEN));
+    }
+
+    #[test]
+    fn rejects_a_tampered_file() {
+        let (dir, bundle) = fixture_bundle();
+        let tampered = dir.path().join("tampered.zip");
+        rewrite_bundle(&bundle, &tampered, |path, data| {
+            if path == "0_en/Languages.bin_H" {
+                let last = data.len() - 1;
+                data[last] ^= 0xff;
+            }
+        });
+        let target = dir.path().join("target");
+        assert!(matches!(
+            unbundle(&tampered, &target, false),
+            Err(SoulframeError::HashMismatch { path }) if path == "0_en/Languages.bin_H"
+        ));
+        assert!(files_under(&target).is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+//! Content-addressed store of downloaded files, shared between `downloaded-data` and
+//! its snapshots: `<cache>/<b64m-hash>_H`, `_B` and `.meta.json`, keyed by the hash
+//! the manifest lists for them.
+//!
+//! `_H` and `_B` files are hard-linked in and out where the filesystem allows and
+//! copied otherwise. The SHCC writers replace files instead of rewriting them, so a
+//! later download never changes a cached file through a link; sidecars, which are
+//! rewritten in place, are always copied.
+
+use crate::manifest::SoulframeManifest;
+use crate::snapshot::list_snapshots;
+use crate::{b64m_decode, b64m_encode, ManifestFormat, SoulframeError, MANIFEST_HASH_LEN};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Parts stored per hash; only `_H` is required
+const PARTS: [&str; 3] = ["_H", "_B", ".meta.json"];
+
+pub struct ContentCache {
+    dir: PathBuf,
+}
+
+impl ContentCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Cache path for `hash`, without a part suffix
+    fn entry(&self, hash: &[u8]) -> PathBuf {
+        self.dir.join(b64m_encode(hash))
+    }
+
+    /// Puts the cached files for `hash` at `local_path` (`<local_path>_H` and so on),
+    /// replacing whatever is there. Returns `false`, changing nothing, when the cache
+    /// has no `_H` starting with `hash`.
+    pub fn restore(&self, hash: &[u8], local_path: &Path) -> Result<bool, SoulframeError> {
+        let entry = self.entry(hash);
+        if header_hash(&with_part(&entry, "_H")).as_deref() != Some(hash) {
+            return Ok(false);
+        }
+
+        if let Some(parent) = local_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        for part in PARTS {
+            let target = with_part(local_path, part);
+            let _ = fs::remove_file(&target);
+            let source = with_part(&entry, part);
+            if source.is_file() {
+                link_or_copy(&source, &target, part)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Adds the files at `local_path` to the cache under `hash`, if its `_H` starts
+    /// with `hash`. Returns whether they were stored.
+    pub fn store(&self, hash: &[u8], local_path: &Path) -> Result<bool, SoulframeError> {
+        if header_hash(&with_part(local_path, "_H")).as_deref() != Some(hash) {
+            return Ok(false);
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let entry = self.entry(hash);
+        for part in PARTS {
+            let target = with_part(&entry, part);
+            let _ = fs::remove_file(&target);
+            let source = with_part(local_path, part);
+            if source.is_file() {
+                link_or_copy(&source, &target, part)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Removes cached files whose hash is not in `referenced`, returning their paths
+    /// and sizes. With `dry_run` nothing is removed.
+    pub fn gc(&self, referenced: &HashSet<Vec<u8>>, dry_run: bool) -> Result<Vec<(PathBuf, u64)>, SoulframeError> {
+        let mut removed = Vec::new();
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return Ok(removed);
+        };
+        let mut files = dir.collect::<Result<Vec<_>, _>>()?;
+        files.sort_by_key(|entry| entry.file_name());
+
+        for file in files {
+            if !file.file_type()?.is_file() {
+                continue;
+            }
+            let Ok(name) = file.file_name().into_string() else { continue };
+            let Some(b64m) = PARTS.iter().find_map(|part| name.strip_suffix(part)) else { continue };
+            let hash = b64m_decode(b64m).ok().filter(|hash| hash.len() == MANIFEST_HASH_LEN);
+            if hash.is_some_and(|hash| referenced.contains(&hash)) {
+                continue;
+            }
+
+            let path = file.path();
+            let size = file.metadata()?.len();
+            if !dry_run {
+                fs::remove_file(&path)?;
+            }
+            removed.push((path, size));
+        }
+        Ok(removed)
+    }
+}
+
+/// Every hash listed by the primary and localized manifests in `downloads` and in
+/// its snapshots, i.e. every hash a download from them could ask the cache for
+pub fn known_manifest_hashes(downloads: &Path, format: ManifestFormat) -> Result<HashSet<Vec<u8>>, SoulframeError> {
+    let mut roots = vec![downloads.to_path_buf()];
+    roots.extend(list_snapshots(downloads)?.into_iter().map(|snapshot| downloads.join(snapshot.id)));
+
+    let mut hashes = HashSet::new();
+    for root in roots {
+        let Ok(dir) = fs::read_dir(root.join("0")) else { continue };
+        for file in dir {
+            let file = file?;
+            let Ok(name) = file.file_name().into_string() else { continue };
+            let is_manifest = name == "H.Cache.bin_H" || (name.starts_with("B.Cache.") && name.ends_with(".bin_H"));
+            if !is_manifest {
+                continue;
+            }
+
+            let mut manifest = SoulframeManifest::from_bytes(fs::read(file.path())?, format)?;
+            hashes.extend(manifest.entries().into_iter().map(|entry| entry.hash));
+        }
+    }
+    Ok(hashes)
+}
+
+fn with_part(path: &Path, part: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}", path.to_string_lossy(), part))
+}
+
+fn link_or_copy(source: &Path, target: &Path, part: &str) -> Result<(), SoulframeError> {
+    if part == ".meta.json" || fs::hard_link(source, target).is_err() {
+        fs::copy(source, target)?;
+    }
+    Ok(())
+}
+
+/// First `MANIFEST_HASH_LEN` bytes of an `_H` file, or `None` if it is missing or shorter
+fn header_hash(h_path: &Path) -> Option<Vec<u8>> {
+    let mut hash = vec![0u8; MANIFEST_HASH_LEN];
+    fs::File::open(h_path).ok()?.read_exact(&mut hash).ok()?;
+    Some(hash)
+}
@@ -0,0 +1,294 @@
+//! Removal of stale and orphaned files from `downloaded-data`: `_H`/`_B` files and
+//! their `.meta.json` sidecars that no manifest on disk lists, whose header hash no
+//! longer matches the manifest, or that belong to locales no longer downloaded.
+//!
+//! Only the `0` folder and the `0_<locale>` folders of the selected platform are
+//! examined. Snapshots, other platforms' folders and files this crate doesn't
+//! write (`state.json`, `watch.json`, ...) are left alone.
+
+use crate::manifest::SoulframeManifest;
+use crate::{locale_suffix, manifest_platforms, ManifestFormat, SoulframeError, DEFAULT_PLATFORM, MANIFEST_HASH_LEN};
+use anyhow::anyhow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Primary manifest, as listed in the manifest itself
+const PRIMARY_MANIFEST: &str = "/H.Cache.bin";
+
+/// Suffixes of the files a download writes, after the manifest path
+const PARTS: [&str; 3] = ["_H", "_B", ".meta.json"];
+
+#[derive(Debug, Clone)]
+pub struct CleanOptions {
+    pub platform: String,
+    /// Locales still downloaded; the folders and localized manifests of any others go
+    pub locales: Vec<String>,
+    pub format: ManifestFormat,
+    /// Report what would be removed without removing anything
+    pub dry_run: bool,
+    /// Also remove folders left empty
+    pub prune_empty_dirs: bool,
+}
+
+/// Why a file is removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanReason {
+    /// No manifest on disk lists it
+    Unreferenced,
+    /// Its `_H` header hash differs from the manifest's
+    Stale,
+    /// A `_B` or sidecar without its `_H`
+    MissingHeader,
+    /// It belongs to a locale not in `CleanOptions::locales`
+    UntrackedLocale,
+}
+
+impl fmt::Display for CleanReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CleanReason::Unreferenced => "not in any manifest",
+            CleanReason::Stale => "hash differs from manifest",
+            CleanReason::MissingHeader => "no _H file",
+            CleanReason::UntrackedLocale => "locale not tracked",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Removal {
+    pub path: PathBuf,
+    pub size: u64,
+    pub reason: CleanReason,
+}
+
+/// What `clean` removed, or would remove with `dry_run`
+#[derive(Debug, Clone, Default)]
+pub struct CleanReport {
+    pub files: Vec<Removal>,
+    /// Folders removed by `prune_empty_dirs`, deepest first
+    pub dirs: Vec<PathBuf>,
+}
+
+impl CleanReport {
+    pub fn bytes(&self) -> u64 {
+        self.files.iter().map(|removal| removal.size).sum()
+    }
+}
+
+/// Removes stale and orphaned files under `downloads`, judged against the primary
+/// manifest and the localized manifests on disk. Refuses to run without a complete
+/// primary manifest, since every file would then look unreferenced, and never
+/// removes anything that resolves outside `downloads`.
+pub fn clean(downloads: &Path, options: &CleanOptions) -> Result<CleanReport, SoulframeError> {
+    let root = fs::canonicalize(downloads)
+        .map_err(|e| anyhow!("Cannot clean {}: {}", downloads.display(), e))?;
+
+    let mut primary = read_manifest(&root.join("0"), PRIMARY_MANIFEST, options.format)?
+        .ok_or_else(|| anyhow!("No primary manifest in {}; refusing to clean", root.display()))?;
+    let primary_entries = primary.entries();
+    let primary_hashes: HashMap<String, Vec<u8>> =
+        primary_entries.iter().map(|entry| (entry.path.clone(), entry.hash.clone())).collect();
+    let primary_paths: Vec<String> = primary_entries.into_iter().map(|entry| entry.path).collect();
+    let platforms = manifest_platforms(&primary_paths);
+
+    let mut removals = Vec::new();
+    let mut examined = Vec::new();
+    let mut folders = fs::read_dir(&root)?.collect::<Result<Vec<_>, _>>()?;
+    folders.sort_by_key(|entry| entry.file_name());
+    for entry in folders {
+        let Ok(name) = entry.file_name().into_string() else { continue };
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        if name == "0" {
+            let untracked = |path: &str| {
+                let prefix = format!("/B.Cache.{}_", options.platform);
+                path.strip_prefix(prefix.as_str())
+                    .and_then(|rest| rest.strip_suffix(".bin"))
+                    .is_some_and(|lang| !options.locales.iter().any(|l| l == lang))
+            };
+            let expected = |path: &str| {
+                if path == PRIMARY_MANIFEST {
+                    Expected::Keep
+                } else if untracked(path) {
+                    Expected::Remove(CleanReason::UntrackedLocale)
+                } else {
+                    primary_hashes.get(path).map_or(Expected::Remove(CleanReason::Unreferenced), |h| Expected::Hash(h.clone()))
+                }
+            };
+            scan_folder(&entry.path(), &expected, &mut removals)?;
+            examined.push(entry.path());
+            continue;
+        }
+
+        let Some(suffix) = name.strip_prefix('0').filter(|suffix| suffix.starts_with('_')) else { continue };
+        let Some(lang) = folder_locale(suffix, &options.platform, &platforms) else { continue };
+
+        if !options.locales.iter().any(|l| l == lang) {
+            scan_folder(&entry.path(), &|_| Expected::Remove(CleanReason::UntrackedLocale), &mut removals)?;
+        } else {
+            // The localized manifest lists the locale's files; without one on disk,
+            // they came from the primary manifest
+            let localized = format!("/B.Cache.{}_{}.bin", options.platform, lang);
+            let mut hashes = primary_hashes.clone();
+            if let Some(mut manifest) = read_manifest(&root.join("0"), &localized, options.format)? {
+                hashes = manifest.entries().into_iter().map(|entry| (entry.path, entry.hash)).collect();
+            }
+            let expected = |path: &str| {
+                hashes.get(path).map_or(Expected::Remove(CleanReason::Unreferenced), |h| Expected::Hash(h.clone()))
+            };
+            scan_folder(&entry.path(), &expected, &mut removals)?;
+        }
+        examined.push(entry.path());
+    }
+
+    for removal in &removals {
+        let parent = removal.path.parent().map(fs::canonicalize).transpose()?;
+        if !parent.is_some_and(|parent| parent.starts_with(&root)) {
+            return Err(anyhow!("{} is outside {}; refusing to clean", removal.path.display(), root.display()).into());
+        }
+    }
+
+    if !options.dry_run {
+        for removal in &removals {
+            fs::remove_file(&removal.path)?;
+        }
+    }
+
+    let mut dirs = Vec::new();
+    if options.prune_empty_dirs {
+        let removed: HashSet<&Path> = removals.iter().map(|removal| removal.path.as_path()).collect();
+        for folder in &examined {
+            empty_dirs(folder, &removed, &mut dirs)?;
+        }
+        if !options.dry_run {
+            for dir in &dirs {
+                fs::remove_dir(dir)?;
+            }
+        }
+    }
+
+    Ok(CleanReport { files: removals, dirs })
+}
+
+/// What a manifest path's files should be
+enum Expected {
+    Keep,
+    Hash(Vec<u8>),
+    Remove(CleanReason),
+}
+
+/// The locale of a `0<suffix>` folder if it holds `platform`'s files, which for the
+/// default platform means it has no other platform's suffix
+fn folder_locale<'a>(suffix: &'a str, platform: &str, platforms: &[String]) -> Option<&'a str> {
+    let lang = if platform == DEFAULT_PLATFORM {
+        let other_platform = platforms
+            .iter()
+            .filter(|p| p.as_str() != DEFAULT_PLATFORM)
+            .any(|p| suffix.ends_with(&format!("_{}", p)));
+        if other_platform {
+            return None;
+        }
+        &suffix[1..]
+    } else {
+        suffix[1..].strip_suffix(&format!("_{}", platform))?
+    };
+    (!lang.is_empty() && locale_suffix(lang, platform) == suffix).then_some(lang)
+}
+
+/// Loads the manifest at `path` from `folder`, or `None` if it isn't there
+fn read_manifest(folder: &Path, path: &str, format: ManifestFormat) -> Result<Option<SoulframeManifest>, SoulframeError> {
+    let Ok(bin) = fs::read(folder.join(format!("{}_H", &path[1..]))) else {
+        return Ok(None);
+    };
+    let mut manifest = SoulframeManifest::from_bytes(bin, format)?;
+    manifest.seek(None);
+    if let Some(offset) = manifest.malformed_at() {
+        return Err(SoulframeError::ManifestMalformed { offset });
+    }
+    Ok(Some(manifest))
+}
+
+/// Adds the files under `folder` that `expected` rejects to `removals`. Files are
+/// grouped by manifest path, e.g. `/Languages.bin` for `Languages.bin_H`, `_B` and
+/// `.meta.json`; anything else, and symlinks, are skipped.
+fn scan_folder(folder: &Path, expected: &dyn Fn(&str) -> Expected, removals: &mut Vec<Removal>) -> Result<(), SoulframeError> {
+    let mut groups: BTreeMap<String, Vec<(PathBuf, &str, u64)>> = BTreeMap::new();
+    collect_parts(folder, folder, &mut groups)?;
+
+    for (path, parts) in groups {
+        let reason = match expected(&path) {
+            Expected::Keep => None,
+            Expected::Remove(reason) => Some(reason),
+            Expected::Hash(hash) => match parts.iter().find(|(_, part, _)| *part == "_H") {
+                Some((h_path, _, _)) => (header_hash(h_path)?.as_deref() != Some(&hash[..])).then_some(CleanReason::Stale),
+                None => Some(CleanReason::MissingHeader),
+            },
+        };
+        if let Some(reason) = reason {
+            removals.extend(parts.into_iter().map(|(path, _, size)| Removal { path, size, reason }));
+        }
+    }
+    Ok(())
+}
+
+fn collect_parts(
+    folder: &Path,
+    dir: &Path,
+    groups: &mut BTreeMap<String, Vec<(PathBuf, &'static str, u64)>>,
+) -> Result<(), SoulframeError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_parts(folder, &entry.path(), groups)?;
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        let Some(relative) = path.strip_prefix(folder).ok().and_then(|p| p.to_str()) else { continue };
+        let relative = relative.replace('\\', "/");
+        let Some((stem, part)) = PARTS.iter().find_map(|part| Some((relative.strip_suffix(part)?, *part))) else { continue };
+        groups
+            .entry(format!("/{}", stem))
+            .or_default()
+            .push((path.clone(), part, entry.metadata()?.len()));
+    }
+    Ok(())
+}
+
+fn header_hash(h_path: &Path) -> Result<Option<Vec<u8>>, SoulframeError> {
+    let mut hash = vec![0u8; MANIFEST_HASH_LEN];
+    let mut file = fs::File::open(h_path)?;
+    match file.read_exact(&mut hash) {
+        Ok(()) => Ok(Some(hash)),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Adds `dir` and the folders under it that hold nothing once `removed` is gone to
+/// `dirs`, deepest first. Returns whether `dir` itself is one of them.
+fn empty_dirs(dir: &Path, removed: &HashSet<&Path>, dirs: &mut Vec<PathBuf>) -> Result<bool, SoulframeError> {
+    let mut empty = true;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            empty &= empty_dirs(&path, removed, dirs)?;
+        } else if !removed.contains(path.as_path()) {
+            empty = false;
+        }
+    }
+    if empty {
+        dirs.push(dir.to_path_buf());
+    }
+    Ok(empty)
+}
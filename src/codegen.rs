@@ -0,0 +1,137 @@
+//! Source code naming every key of an extracted locale, so applications can refer to
+//! keys through checked identifiers instead of string literals. The output depends
+//! only on the keys, so it can be committed and regenerated without spurious diffs.
+
+use crate::SoulframeError;
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodegenTarget {
+    /// `export const Keys = { ... } as const;` and a `Key` union type
+    TypeScript,
+    /// A `pub const` per key and an `ALL` array of every key
+    Rust,
+}
+
+/// Identifier for `key`: its runs of ASCII letters and digits joined by `_`, e.g.
+/// `Lotus_Language_Menu_Start` for `/Lotus/Language/Menu/Start`, and uppercased for
+/// Rust. A leading digit, or a key with no letters or digits, gets a `_` prefix.
+pub fn key_identifier(key: &str, target: CodegenTarget) -> String {
+    let parts: Vec<&str> = key
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect();
+    let mut identifier = parts.join("_");
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    match target {
+        CodegenTarget::TypeScript => identifier,
+        CodegenTarget::Rust => identifier.to_ascii_uppercase(),
+    }
+}
+
+/// Generates the module for `keys`, sorted by key, with a comment giving the key each
+/// identifier stands for. `source` names the input in the header comment. Fails,
+/// listing every collision, when several keys sanitize to the same identifier.
+pub fn generate<'a>(keys: impl IntoIterator<Item = &'a str>, target: CodegenTarget, source: &str) -> Result<String, SoulframeError> {
+    let mut keys: Vec<&str> = keys.into_iter().collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let mut by_identifier: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for key in &keys {
+        by_identifier.entry(key_identifier(key, target)).or_default().push(key);
+    }
+    let collisions: Vec<String> = by_identifier
+        .iter()
+        .filter(|(_, keys)| keys.len() > 1)
+        .map(|(identifier, keys)| format!("  {}: {}", identifier, keys.join(", ")))
+        .collect();
+    if !collisions.is_empty() {
+        return Err(anyhow!("Several keys would share an identifier:\n{}", collisions.join("\n")).into());
+    }
+
+    let header = format!(
+        "// Generated by codegen from {} ({} keys). Do not edit.\n",
+        comment_text(source),
+        keys.len()
+    );
+    let mut code = header;
+    match target {
+        CodegenTarget::TypeScript => {
+            code.push_str("\nexport const Keys = {\n");
+            for key in &keys {
+                code.push_str(&format!("  // {}\n", comment_text(key)));
+                code.push_str(&format!("  {}: {},\n", key_identifier(key, target), js_string(key)));
+            }
+            code.push_str("} as const;\n\nexport type Key = (typeof Keys)[keyof typeof Keys];\n");
+        }
+        CodegenTarget::Rust => {
+            for key in &keys {
+                code.push_str(&format!("\n// {}\n", comment_text(key)));
+                code.push_str(&format!("pub const {}: &str = {:?};\n", key_identifier(key, target), key));
+            }
+            code.push_str("\n/// Every key, sorted\npub const ALL: &[&str] = &[\n");
+            for key in &keys {
+                code.push_str(&format!("    {},\n", key_identifier(key, target)));
+            }
+            code.push_str("];\n");
+        }
+    }
+    Ok(code)
+}
+
+/// `text` for a line comment, with line breaks and other control characters escaped
+fn comment_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() {
+            escaped.extend(c.escape_default());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// `text` as a JavaScript string literal
+fn js_string(text: &str) -> String {
+    serde_json::to_string(text).expect("strings always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys with a leading digit, punctuation, non-ASCII, quotes and a line break
+    const KEYS: [&str; 5] = [
+        "/Lotus/Language/Menu/Start",
+        "/Lotus/Language/2Player/Ready?",
+        "/Lotus/Language/Caf\u{e9}/\"Quoted\"",
+        "/Lotus/Language/Multi\nLine",
+        "/Lotus/Language/Menu/Start",
+    ];
+
+    #[test]
+    fn typescript_snapshot() {
+        let code = generate(KEYS, CodegenTarget::TypeScript, "en.json").unwrap();
+        // insta ignores the final line break, so check it here
+        assert!(code.ends_with(";\n"));
+        insta::assert_snapshot!(code);
+    }
+
+    #[test]
+    fn rust_snapshot() {
+        let code = generate(KEYS, CodegenTarget::Rust, "en.json").unwrap();
+        assert!(code.ends_with("];\n"));
+        insta::assert_snapshot!(code);
+    }
+
+    #[test]
+    fn reports_colliding_identifiers() {
+        let result = generate(["/Menu/Start", "/Menu-Start"], CodegenTarget::Rust, "en.json");
+        assert!(result.unwrap_err().to_string().contains("MENU_START: /Menu-Start, /Menu/Start"));
+    }
+}
@@ -1,228 +1,1240 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use rand::Rng;
-use soulframe_language_downloader::*;
-use std::collections::HashMap;
+use crate::*;
+use crate::cache::ContentCache;
+use crate::storage::{LocalFs, Storage};
+pub use crate::manifest::{ManifestEntry, SoulframeManifest};
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Whole-request timeout of `build_http_client`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Builds the HTTP client the downloader uses: HTTP/1.1 only, no automatic
+/// decompression, `DEFAULT_TIMEOUT`. Build it once per run and reuse it, since it
+/// keeps the connection pool that lets requests skip new TLS handshakes.
+pub fn build_http_client() -> reqwest::Result<reqwest::blocking::Client> {
+    build_http_client_with_timeouts(DEFAULT_TIMEOUT, None)
+}
+
+/// `build_http_client` with its timeouts: `timeout` covers a whole request from
+/// connecting to the end of the body, and `connect_timeout`, if set, only the
+/// connection, so dead hosts can fail fast while slow transfers continue. A connect
+/// timeout longer than `timeout` has no effect.
+pub fn build_http_client_with_timeouts(
+    timeout: Duration,
+    connect_timeout: Option<Duration>,
+) -> reqwest::Result<reqwest::blocking::Client> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .http1_only()
+        .no_gzip()
+        .no_brotli()
+        .no_deflate()
+        .timeout(timeout);
+    if let Some(connect_timeout) = connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+    builder.build()
+}
+
+/// Largest response body, and largest payload after whole-payload decompression,
+/// accepted unless configured otherwise
+pub const DEFAULT_MAX_DOWNLOAD_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// `Content-Length` of a response, if present and a valid number
+pub fn content_length(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    headers.get(reqwest::header::CONTENT_LENGTH)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Reads the body of `response`, preallocated from its `Content-Length`. Fails
+/// without reading anything if the declared length is over `max_size`, and stops
+/// reading just past `max_size` otherwise; `check_body` then rejects such a body.
+pub fn read_body(response: reqwest::blocking::Response, max_size: u64) -> Result<Vec<u8>, SoulframeError> {
+    let declared = content_length(response.headers());
+    if let Some(size) = declared.filter(|&size| size > max_size) {
+        return Err(SoulframeError::TooLarge { what: format!("Response from {}", response.url()), size, max: max_size });
+    }
+    let mut body = Vec::with_capacity(declared.unwrap_or(0) as usize);
+    response.take(max_size.saturating_add(1)).read_to_end(&mut body)?;
+    Ok(body)
+}
+
+/// Checks a successful response's body from `url` against `max_size` and against
+/// its `Content-Length`, when the headers have one
+pub fn check_body(url: &str, headers: &reqwest::header::HeaderMap, body_len: usize, max_size: u64) -> Result<(), SoulframeError> {
+    let actual = body_len as u64;
+    if actual > max_size {
+        return Err(SoulframeError::TooLarge { what: format!("Response from {}", url), size: actual, max: max_size });
+    }
+    match content_length(headers) {
+        Some(expected) if expected != actual => Err(SoulframeError::LengthMismatch { url: url.to_string(), expected, actual }),
+        _ => Ok(()),
+    }
+}
+
+/// What to do about a body `check_body` rejected: retry a short or overlong body
+/// like a server error, but move on from one that is too large
+pub fn body_retry_decision(err: &SoulframeError) -> RetryDecision {
+    match err {
+        SoulframeError::LengthMismatch { .. } => RetryDecision::Backoff,
+        _ => RetryDecision::NextUrl,
+    }
+}
+
+/// Status, headers and body of an HTTP response
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: reqwest::StatusCode,
+    pub headers: reqwest::header::HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Sends the GET requests of a `DownloadClient`. Implemented for the blocking
+/// reqwest client; implement it over canned responses to run the download flow
+/// without a network.
+pub trait HttpTransport: Send + Sync {
+    /// `Err` for failures without a response, e.g. DNS, TLS or a broken body.
+    /// Reading may stop once the body is longer than `max_size`; the client
+    /// rejects such responses either way.
+    fn get(&self, url: &str, max_size: u64) -> Result<HttpResponse>;
+}
+
+impl HttpTransport for reqwest::blocking::Client {
+    fn get(&self, url: &str, max_size: u64) -> Result<HttpResponse> {
+        let response = reqwest::blocking::Client::get(self, url).send()?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = if status.is_success() { read_body(response, max_size)? } else { Vec::new() };
+        Ok(HttpResponse { status, headers, body })
+    }
+}
 
 pub struct DownloadClient {
-    client: reqwest::blocking::Client,
+    transport: Box<dyn HttpTransport>,
+    limiter: Arc<RateLimiter>,
+    requests: AtomicU64,
+    max_download_size: u64,
+    decompress_multiplier: usize,
+    storage: Arc<dyn Storage>,
+    cache: Option<ContentCache>,
+    oodle: Option<Box<dyn BlockDecompressor + Send>>,
+    verify: bool,
+}
+
+impl Default for DownloadClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DownloadClient {
     pub fn new() -> Self {
+        Self::with_client(reqwest::blocking::Client::new())
+    }
+    
+    /// Uses `client`, e.g. from `build_http_client`, so its connection pool is shared
+    /// with the rest of the run. Clones of a client share the same pool.
+    pub fn with_client(client: reqwest::blocking::Client) -> Self {
+        Self::with_transport(client)
+    }
+    
+    /// Sends every request through `transport` instead of a reqwest client
+    pub fn with_transport(transport: impl HttpTransport + 'static) -> Self {
         Self {
-            client: reqwest::blocking::Client::new(),
+            transport: Box::new(transport),
+            limiter: Arc::new(RateLimiter::unlimited()),
+            requests: AtomicU64::new(0),
+            max_download_size: DEFAULT_MAX_DOWNLOAD_SIZE,
+            decompress_multiplier: DEFAULT_DECOMPRESS_MULTIPLIER,
+            storage: Arc::new(LocalFs::downloads()),
+            cache: None,
+            oodle: None,
+            verify: true,
         }
     }
+    
+    /// Requests sent so far, all through the one transport
+    pub fn requests_sent(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+    
+    /// Paces every request through `limiter`; share one between clients to bound their combined rate
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+    
+    /// Rejects responses, and payloads after decompression, larger than `max_size`
+    /// bytes (default `DEFAULT_MAX_DOWNLOAD_SIZE`)
+    pub fn with_max_download_size(mut self, max_size: u64) -> Self {
+        self.max_download_size = max_size;
+        self
+    }
+    
+    /// Assumes Oodle payloads decompress to at most `multiplier` times their size
+    /// (default `DEFAULT_DECOMPRESS_MULTIPLIER`); see `PayloadLimits`
+    pub fn with_decompress_multiplier(mut self, multiplier: usize) -> Self {
+        self.decompress_multiplier = multiplier.max(1);
+        self
+    }
+    
+    /// Stores downloads in `storage` instead of `downloaded-data` in the working
+    /// directory
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = storage;
+        self
+    }
+    
+    /// Satisfies `SoulframeManifest::download_file` from `cache` when it holds the
+    /// manifest's hash, and adds verified downloads to it. Only used while the
+    /// storage is on the local filesystem.
+    pub fn with_cache(mut self, cache: ContentCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+    
+    /// Decodes Oodle blocks and payloads with `oodle` instead of loading the Oodle
+    /// library for each download, e.g. a stand-in for tests run without it
+    pub fn with_oodle(mut self, oodle: impl BlockDecompressor + Send + 'static) -> Self {
+        self.oodle = Some(Box::new(oodle));
+        self
+    }
+    
+    /// With `false`, stores downloads whose hash differs from the requested one
+    /// instead of rejecting them, for dev builds known to serve such files
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
 
+    /// Downloads and stores one file. Returns `Ok(false)` if every URL failed.
     pub fn download_soulframe_file(
         &self,
         path: &str,
         file_type: u8,
         b64m_hash: Option<&str>,
         suffix: Option<&str>,
-    ) -> Result<bool> {
-        let b64m_hash = b64m_hash.unwrap_or("---------------------w");
+    ) -> Result<bool, SoulframeError> {
+        match self.download_to(path, file_type, b64m_hash, suffix, suffix) {
+            Ok(_) => Ok(true),
+            Err(SoulframeError::Http { .. } | SoulframeError::DownloadFailed { .. }) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+    
+    /// Like `download_soulframe_file`, but stores the file under `local_suffix`
+    /// instead of the CDN folder `suffix` and returns what was stored. Failing every
+    /// URL is an `Http` error carrying the last status received, or `DownloadFailed`
+    /// if no URL answered.
+    pub fn download_to(
+        &self,
+        path: &str,
+        file_type: u8,
+        b64m_hash: Option<&str>,
+        suffix: Option<&str>,
+        local_suffix: Option<&str>,
+    ) -> Result<StoredDownload, SoulframeError> {
+        let b64m_hash = b64m_hash.unwrap_or(DEFAULT_B64M_HASH);
         let suffix = suffix.unwrap_or("");
+        let local_suffix = local_suffix.unwrap_or("");
+        let normalized_path = normalize_path(path);
+        // Reject hostile paths before making any request
+        get_download_path(&normalized_path, Some(suffix))?;
+        get_download_path(&normalized_path, Some(local_suffix))?;
         
-        let normalized_path = if path.starts_with('/') {
-            path.to_string()
-        } else {
-            format!("/{}", path)
-        };
-        
-        let req_path = format!("/0{}{}!{:X}_{}", suffix, normalized_path, file_type, b64m_hash);
-        
-        let mut urls = Vec::new();
-        
-        // Prefer the CDN, but include origin endpoints and a cache-busting origin URL as fallbacks.
-        urls.push(format!("https://content.soulframe.com{}", req_path));
-        urls.push(format!("https://origin.soulframe.com{}", req_path));
-
-        let random_id: u32 = rand::thread_rng().gen();
-        urls.push(format!("https://origin.soulframe.com/origin/{:08X}{}", random_id, req_path));
-        urls.push(format!("https://origin.soulframe.com/origin/0{}", req_path));
-        
-        for url in urls {
-            println!("Attempting download from {}", url);
-            
-            match self.client.get(&url).send() {
-                Ok(response) if response.status().is_success() => {
-                    println!("Successfully downloaded from {}", url);
-                    
-                    let mut bin = response.bytes()?.to_vec();
-                    let local_path = get_download_path(&normalized_path, Some(suffix));
-                    
-                    // Create parent directories
-                    if let Some(parent) = local_path.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-                    
-                    let shcc_itself_compressed = !bin.starts_with(b"SHCC");
-                    
-                    if shcc_itself_compressed {
-                        let oodle = Oodle::new()?;
-                        // Estimate decompressed size (the original uses bin size * 10)
-                        bin = oodle.decompress(&bin, bin.len() * 10)?;
-                    }
-                    
-                    let oodle = Oodle::new()?;
-                    let data = shcc_unpack(&bin, &oodle)?;
-                    
-                    // Write H data
-                    let h_path = format!("{}_H", local_path.to_string_lossy());
-                    fs::write(&h_path, &data.h)?;
-                    
-                    // Write B data if present
-                    if let Some(ref b_data) = data.b {
-                        let b_path = format!("{}_B", local_path.to_string_lossy());
-                        fs::write(&b_path, b_data)?;
+        let mut last_status = None;
+        for url in candidate_urls(&normalized_path, file_type, b64m_hash, suffix) {
+            let mut retries = 0;
+            loop {
+                std::thread::sleep(self.limiter.reserve());
+                debug!("Attempting download from {}", url);
+                
+                self.requests.fetch_add(1, Ordering::Relaxed);
+                match self.transport.get(&url, self.max_download_size) {
+                    Ok(response) if response.status.is_success() => {
+                        if let Err(err) = check_body(&url, &response.headers, response.body.len(), self.max_download_size) {
+                            debug!("Download failed from {}: {}", url, err);
+                            if let Some(wait) = retry_delay(body_retry_decision(&err), retries) {
+                                debug!("Retrying {} in {:.1}s", url, wait.as_secs_f64());
+                                self.limiter.pause(wait);
+                                retries += 1;
+                                continue;
+                            }
+                            break;
+                        }
+                        info!("Successfully downloaded from {}", url);
+                        
+                        let target = StoreTarget { storage: self.storage.as_ref(), normalized_path: &normalized_path, suffix: local_suffix };
+                        let options = StoreOptions {
+                            limits: PayloadLimits { max_size: self.max_download_size, decompress_multiplier: self.decompress_multiplier },
+                            oodle: self.oodle.as_deref().map(|oodle| oodle as &dyn BlockDecompressor),
+                            verify: self.verify,
+                        };
+                        return store_download(&target, b64m_hash, response.body, &url, &response.headers, &options);
                     }
-                    
-                    // Verify hash if not default
-                    if b64m_hash != "---------------------w" && !shcc_itself_compressed {
-                        let computed_hash = shcc_hash(&data);
-                        let expected_hash = b64m_decode(b64m_hash)?;
-                        if computed_hash != expected_hash {
-                            return Err(anyhow!("Hash mismatch for {}", normalized_path));
+                    Ok(response) => {
+                        let status = response.status;
+                        debug!("Download failed from {} (HTTP {})", url, status.as_u16());
+                        last_status = Some(status.as_u16());
+                        
+                        let decision = classify_response(status, &response.headers);
+                        if decision == RetryDecision::Abort {
+                            return Err(SoulframeError::Forbidden { url });
+                        }
+                        if let Some(wait) = retry_delay(decision, retries) {
+                            debug!("Retrying {} in {:.1}s", url, wait.as_secs_f64());
+                            self.limiter.pause(wait);
+                            retries += 1;
+                            continue;
                         }
                     }
-                    
-                    return Ok(true);
-                }
-                Ok(response) => {
-                    println!(
-                        "Download failed from {} (HTTP {})",
-                        url,
-                        response.status().as_u16()
-                    );
-                }
-                Err(e) => {
-                    println!("Download failed from {}: {}", url, e);
+                    Err(e) => {
+                        debug!("Download failed from {}: {}", url, e);
+                    }
                 }
+                break;
             }
         }
         
-        println!("All download attempts failed for {}", normalized_path);
-        Ok(false)
+        warn!("All download attempts failed for {}", normalized_path);
+        Err(download_failure(normalized_path, last_status))
+    }
+}
+
+fn download_failure(path: String, last_status: Option<u16>) -> SoulframeError {
+    match last_status {
+        Some(status) => SoulframeError::Http { path, status },
+        None => SoulframeError::DownloadFailed { path },
     }
 }
 
-pub struct SoulframeManifest {
+fn normalize_path(path: &str) -> String {
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// Request URLs to try in order for one file
+fn candidate_urls(normalized_path: &str, file_type: u8, b64m_hash: &str, suffix: &str) -> Vec<String> {
+    let req_path = build_request_path(normalized_path, file_type, b64m_hash, Some(suffix));
+    
+    let mut urls = Vec::new();
+    
+    // Prefer the CDN, but include origin endpoints and a cache-busting origin URL as fallbacks.
+    urls.push(format!("https://content.soulframe.com{}", req_path));
+    urls.push(format!("https://origin.soulframe.com{}", req_path));
+
+    let random_id: u32 = rand::thread_rng().gen();
+    urls.push(format!("https://origin.soulframe.com/origin/{:08X}{}", random_id, req_path));
+    urls.push(format!("https://origin.soulframe.com/origin/0{}", req_path));
+    
+    urls
+}
+
+/// Where `store_download` puts a file: `normalized_path` with `suffix`, in `storage`
+struct StoreTarget<'a> {
+    storage: &'a dyn Storage,
+    normalized_path: &'a str,
+    suffix: &'a str,
+}
+
+/// How `store_download` decodes and checks a payload
+struct StoreOptions<'a> {
+    limits: PayloadLimits,
+    /// Decodes Oodle data; the Oodle library is loaded when `None`
+    oodle: Option<&'a dyn BlockDecompressor>,
+    /// Rejects a payload whose hash differs from the requested one
+    verify: bool,
+}
+
+/// A file `DownloadClient::download_to` stored
+#[derive(Debug, Clone)]
+pub struct StoredDownload {
+    /// The sidecar written next to it
+    pub meta: DownloadMeta,
+    /// Time spent decompressing the payload and unpacking its SHCC container
+    pub decode_time: Duration,
+}
+
+/// Decodes a downloaded payload and writes its `_H`/`_B` files and sidecar, verifying
+/// the hash unless it is the default one, the payload was compressed as a whole or
+/// `options.verify` is off
+fn store_download(
+    target: &StoreTarget,
+    b64m_hash: &str,
     bin: Vec<u8>,
-    i: usize,
-    entry_i: usize,
-    remaining_entries: u32,
-    paths: Vec<String>,
-    hashes: HashMap<String, Vec<u8>>,
-    unks: HashMap<String, Vec<u8>>,
+    url: &str,
+    headers: &reqwest::header::HeaderMap,
+    options: &StoreOptions,
+) -> Result<StoredDownload, SoulframeError> {
+    let normalized_path = target.normalized_path;
+    let key = download_key(normalized_path, Some(target.suffix));
+    
+    let loaded;
+    let oodle = match options.oodle {
+        Some(oodle) => oodle,
+        None => {
+            loaded = Oodle::new()?;
+            &loaded as &dyn BlockDecompressor
+        }
+    };
+    
+    let decode_started = Instant::now();
+    let payload_size = bin.len();
+    let (bin, format) = unwrap_payload_with(bin, options.limits, oodle)?;
+    let shcc_itself_compressed = format != PayloadFormat::Shcc;
+    
+    // The sidecar marks the files as verified, so drop the old one before they are
+    // replaced; files left without one are downloaded again
+    target.storage.remove(&format!("{}.meta.json", key))?;
+    let summary = match target.storage.local_path(&key) {
+        // Stream H data, and B data if present, to disk
+        Some(local_path) => write_shcc_stream(&local_path, &bin, oodle)?,
+        None => {
+            let (mut h, mut b) = (Vec::new(), Vec::new());
+            let summary = shcc_unpack_to(&bin, oodle, &mut h, &mut b)?;
+            target.storage.write(&format!("{}_H", key), &h)?;
+            match summary.b_len {
+                Some(_) => target.storage.write(&format!("{}_B", key), &b)?,
+                None => target.storage.remove(&format!("{}_B", key))?,
+            }
+            summary
+        }
+    };
+    let decode_time = decode_started.elapsed();
+    
+    // The hash covers the SHCC container, so a payload compressed as a whole can't
+    // be checked
+    if is_default_b64m_hash(b64m_hash) {
+        debug!("Not verifying {}: no hash was requested", normalized_path);
+    } else if shcc_itself_compressed {
+        debug!("Not verifying {}: the payload was compressed as a whole", normalized_path);
+    } else if options.verify {
+        let computed_hash = &summary.hash;
+        let expected_hash = b64m_decode(b64m_hash)?;
+        if *computed_hash != expected_hash {
+            warn!("{} hash {} does not match the manifest's {}", normalized_path, hex(computed_hash), hex(&expected_hash));
+            for part in ["_H", "_B"] {
+                target.storage.remove(&format!("{}{}", key, part))?;
+            }
+            return Err(SoulframeError::HashMismatch { path: normalized_path.to_string() });
+        }
+        info!("{} matches its manifest hash", normalized_path);
+    }
+    
+    let meta = DownloadMeta::new(url, headers, b64m_hash, payload_size, &summary);
+    meta.store(target.storage, &key)?;
+    Ok(StoredDownload { meta, decode_time })
 }
 
-impl SoulframeManifest {
-    pub fn new(path: &str) -> Result<Self> {
-        let file_path = get_download_path(path, None);
-        let h_path = format!("{}_H", file_path.to_string_lossy());
-        
-        let bin = fs::read(&h_path)
-            .map_err(|_| anyhow!("{} was not found on disk.", path))?;
-        
-        Ok(Self {
-            bin,
-            i: 20, // Skip initial 20 bytes
-            entry_i: 0,
-            remaining_entries: 0,
-            paths: Vec::new(),
-            hashes: HashMap::new(),
-            unks: HashMap::new(),
-        })
-    }
-    
-    pub fn seek(&mut self, opt_stop_at_path: Option<&str>) -> Option<Vec<u8>> {
-        while self.i < self.bin.len() {
-            while self.remaining_entries == 0 {
-                if self.i + 4 > self.bin.len() {
-                    return None;
-                }
-                
-                self.remaining_entries = u32::from_le_bytes([
-                    self.bin[self.i],
-                    self.bin[self.i + 1],
-                    self.bin[self.i + 2],
-                    self.bin[self.i + 3],
-                ]);
-                self.i += 4;
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Where and when a file was downloaded, stored next to its `_H` file as
+/// `<file>.meta.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadMeta {
+    /// URL that served the file
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_length: Option<u64>,
+    /// Hash requested from the CDN (b64m), normally the manifest's
+    pub manifest_hash: String,
+    /// SHCC hash of the stored data, as hex
+    pub shcc_hash: String,
+    /// Bytes received, before any decompression
+    pub payload_size: usize,
+    /// Size of the `_H` file written
+    pub h_size: usize,
+    /// Size of the `_B` file written, if any
+    pub b_size: Option<usize>,
+    /// Seconds since the Unix epoch
+    pub downloaded_at: u64,
+}
+
+impl DownloadMeta {
+    pub fn new(
+        url: &str,
+        headers: &reqwest::header::HeaderMap,
+        manifest_hash: &str,
+        payload_size: usize,
+        summary: &ShccSummary,
+    ) -> Self {
+        let header = |name: reqwest::header::HeaderName| {
+            headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string())
+        };
+        Self {
+            url: url.to_string(),
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+            content_length: header(reqwest::header::CONTENT_LENGTH).and_then(|len| len.parse().ok()),
+            manifest_hash: manifest_hash.to_string(),
+            shcc_hash: hex(&summary.hash),
+            payload_size,
+            h_size: summary.h_len,
+            b_size: summary.b_len,
+            downloaded_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        }
+    }
+    
+    /// Sidecar path for the file downloaded to `local_path`
+    pub fn path(local_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.meta.json", local_path.to_string_lossy()))
+    }
+    
+    pub fn write(&self, local_path: &Path) -> Result<(), SoulframeError> {
+        let json = serde_json::to_string_pretty(self).map_err(anyhow::Error::from)?;
+        fs::write(Self::path(local_path), json)?;
+        Ok(())
+    }
+    
+    /// Writes the sidecar of the file stored at `key` in `storage`
+    pub fn store(&self, storage: &dyn Storage, key: &str) -> Result<(), SoulframeError> {
+        let json = serde_json::to_string_pretty(self).map_err(anyhow::Error::from)?;
+        storage.write(&format!("{}.meta.json", key), json.as_bytes())
+    }
+    
+    /// Reads the sidecar of `local_path`, or `None` if it has none
+    pub fn read(local_path: &Path) -> Result<Option<Self>, SoulframeError> {
+        match fs::read_to_string(Self::path(local_path)) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json).map_err(anyhow::Error::from)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+    
+    /// Checks that the `_H` and `_B` files of `local_path` still have the sizes the
+    /// sidecar recorded. Files without a sidecar fail with `MissingSidecar`.
+    pub fn verify(local_path: &Path) -> Result<(), SoulframeError> {
+        let Some(meta) = Self::read(local_path)? else {
+            return Err(SoulframeError::MissingSidecar { path: format!("{}_H", local_path.to_string_lossy()) });
+        };
+        
+        let expected = [("_H", Some(meta.h_size)), ("_B", meta.b_size)];
+        for (part, expected) in expected {
+            let Some(expected) = expected else { continue };
+            let part_path = format!("{}{}", local_path.to_string_lossy(), part);
+            let actual = fs::metadata(&part_path).map(|m| m.len()).ok();
+            if actual != Some(expected as u64) {
+                return Err(SoulframeError::SizeMismatch { path: part_path, expected, actual });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// True if the `_H` file in `storage` already starts with `manifest_hash` and has
+/// the size its sidecar recorded. A file without a sidecar never passed its hash
+/// check, so it is not up to date.
+fn is_up_to_date(storage: &dyn Storage, path: &str, suffix: Option<&str>, manifest_hash: &[u8]) -> bool {
+    let key = download_key(path, suffix);
+    let Ok(contents) = storage.read(&format!("{}_H", key)) else {
+        return false;
+    };
+    let meta = storage
+        .read(&format!("{}.meta.json", key))
+        .ok()
+        .and_then(|json| serde_json::from_slice::<DownloadMeta>(&json).ok());
+    
+    contents.get(0..MANIFEST_HASH_LEN) == Some(manifest_hash) && meta.is_some_and(|meta| meta.h_size == contents.len())
+}
+
+/// Longest `Retry-After` that is honored; larger values are clamped to this
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// Paces CDN requests with a fixed delay between consecutive requests and/or a token
+/// bucket capping the average rate. Slots are reserved under a lock, so one limiter
+/// shared through an `Arc` bounds the total rate of all downloads using it.
+pub struct RateLimiter {
+    delay: Duration,
+    max_rps: Option<f64>,
+    state: Mutex<LimiterState>,
+}
+
+struct LimiterState {
+    tokens: f64,
+    refilled_at: Instant,
+    next_request_at: Instant,
+}
+
+impl RateLimiter {
+    /// `delay` is kept between request starts; `max_rps` allows bursts of up to
+    /// `max_rps` requests (at least one) and refills at that rate
+    pub fn new(delay: Duration, max_rps: Option<f64>) -> Self {
+        let max_rps = max_rps.filter(|rps| *rps > 0.0);
+        let now = Instant::now();
+        Self {
+            delay,
+            max_rps,
+            state: Mutex::new(LimiterState {
+                tokens: max_rps.map_or(0.0, |rps| rps.max(1.0)),
+                refilled_at: now,
+                next_request_at: now,
+            }),
+        }
+    }
+    
+    pub fn unlimited() -> Self {
+        Self::new(Duration::ZERO, None)
+    }
+    
+    /// Reserves the next request slot and returns how long to wait before sending
+    pub fn reserve(&self) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let mut start = state.next_request_at.max(now);
+        
+        if let Some(rps) = self.max_rps {
+            let capacity = rps.max(1.0);
+            let refill = start.saturating_duration_since(state.refilled_at).as_secs_f64() * rps;
+            state.tokens = (state.tokens + refill).min(capacity);
+            if state.tokens < 1.0 {
+                start += Duration::from_secs_f64((1.0 - state.tokens) / rps);
+                state.tokens = 1.0;
             }
-            
-            self.entry_i += 1;
-            self.remaining_entries -= 1;
-            
-            // Read path (4-byte length prefix + string)
-            if self.i + 4 > self.bin.len() {
+            state.tokens -= 1.0;
+            state.refilled_at = start;
+        }
+        
+        state.next_request_at = start + self.delay;
+        start - now
+    }
+    
+    /// Holds back every request until `pause` from now has passed
+    pub fn pause(&self, pause: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.next_request_at = state.next_request_at.max(Instant::now() + pause);
+    }
+}
+
+/// Parses a `Retry-After` header given in seconds, clamped to `MAX_RETRY_AFTER`.
+/// The HTTP-date form is not supported and yields `None`.
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let seconds: u64 = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_RETRY_AFTER))
+}
+
+/// Times a URL is retried after a 429 or 5xx before moving on to the next one
+pub const MAX_URL_RETRIES: u32 = 3;
+
+/// Backoff before the first retry of a URL, doubled for each further retry
+pub const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// What to do after a non-success response, as decided by `classify_response`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Try the next candidate URL; a 404 usually means a wrong hash for this host
+    NextUrl,
+    /// Wait the server-given duration, then retry the same URL
+    RetryAfter(Duration),
+    /// Retry the same URL with exponential backoff
+    Backoff,
+    /// Give up on this file; retrying elsewhere will not help
+    Abort,
+}
+
+/// Decides how to react to a failed response: 404 and other 4xx move to the next
+/// URL, 429 and 503 honor `Retry-After` (or back off without one), other 5xx back
+/// off, and 403 aborts since it usually means the CDN is blocking the region.
+pub fn classify_response(status: reqwest::StatusCode, headers: &reqwest::header::HeaderMap) -> RetryDecision {
+    use reqwest::StatusCode;
+    
+    match status {
+        StatusCode::FORBIDDEN => RetryDecision::Abort,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => match retry_after(headers) {
+            Some(wait) => RetryDecision::RetryAfter(wait),
+            None => RetryDecision::Backoff,
+        },
+        status if status.is_server_error() => RetryDecision::Backoff,
+        _ => RetryDecision::NextUrl,
+    }
+}
+
+/// Wait before the next retry of a URL already retried `retries` times, or `None`
+/// to stop retrying it
+pub fn retry_delay(decision: RetryDecision, retries: u32) -> Option<Duration> {
+    if retries >= MAX_URL_RETRIES {
+        return None;
+    }
+    
+    match decision {
+        RetryDecision::RetryAfter(wait) => Some(wait),
+        RetryDecision::Backoff => Some(RETRY_BACKOFF * 2u32.pow(retries)),
+        RetryDecision::NextUrl | RetryDecision::Abort => None,
+    }
+}
+
+/// Async counterpart of `DownloadClient`. Only the network layer is async; SHCC and
+/// Oodle decoding run on the blocking thread pool, so a tokio runtime is required.
+#[cfg(feature = "async")]
+pub struct AsyncDownloadClient {
+    client: reqwest::Client,
+    platform: String,
+    format: ManifestFormat,
+    limiter: Arc<RateLimiter>,
+    max_download_size: u64,
+    decompress_multiplier: usize,
+    storage: Arc<dyn Storage>,
+}
+
+#[cfg(feature = "async")]
+impl Default for AsyncDownloadClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncDownloadClient {
+    pub fn new() -> Self {
+        Self::with_client(reqwest::Client::new())
+    }
+    
+    /// Uses `client`, whose connection pool is then shared by every concurrent locale
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            platform: DEFAULT_PLATFORM.to_string(),
+            format: ManifestFormat::Current,
+            limiter: Arc::new(RateLimiter::unlimited()),
+            max_download_size: DEFAULT_MAX_DOWNLOAD_SIZE,
+            decompress_multiplier: DEFAULT_DECOMPRESS_MULTIPLIER,
+            storage: Arc::new(LocalFs::downloads()),
+        }
+    }
+    
+    /// Paces every request through `limiter`, which concurrent locales share
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+    
+    /// Rejects responses, and payloads after decompression, larger than `max_size`
+    /// bytes (default `DEFAULT_MAX_DOWNLOAD_SIZE`)
+    pub fn with_max_download_size(mut self, max_size: u64) -> Self {
+        self.max_download_size = max_size;
+        self
+    }
+    
+    /// Assumes Oodle payloads decompress to at most `multiplier` times their size
+    /// (default `DEFAULT_DECOMPRESS_MULTIPLIER`); see `PayloadLimits`
+    pub fn with_decompress_multiplier(mut self, multiplier: usize) -> Self {
+        self.decompress_multiplier = multiplier.max(1);
+        self
+    }
+    
+    /// Stores downloads in `storage`, and reads localized manifests from it, instead
+    /// of `downloaded-data` in the working directory
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = storage;
+        self
+    }
+    
+    /// Uses `platform` instead of `Windows` in localized manifest paths
+    pub fn with_platform(mut self, platform: &str) -> Self {
+        self.platform = platform.to_string();
+        self
+    }
+    
+    /// Uses `format`'s type IDs and manifest layout instead of the 40.0.0+ ones
+    pub fn with_format(mut self, format: ManifestFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub async fn download_soulframe_file(
+        &self,
+        path: &str,
+        file_type: u8,
+        b64m_hash: Option<&str>,
+        suffix: Option<&str>,
+    ) -> Result<bool, SoulframeError> {
+        let b64m_hash = b64m_hash.unwrap_or(DEFAULT_B64M_HASH);
+        let suffix = suffix.unwrap_or("");
+        let normalized_path = normalize_path(path);
+        // Reject hostile paths before making any request
+        get_download_path(&normalized_path, Some(suffix))?;
+        
+        for url in candidate_urls(&normalized_path, file_type, b64m_hash, suffix) {
+            let mut retries = 0;
+            loop {
+                tokio::time::sleep(self.limiter.reserve()).await;
+                debug!("Attempting download from {}", url);
+                
+                match self.client.get(&url).send().await {
+                    Ok(response) if response.status().is_success() => {
+                        let headers = response.headers().clone();
+                        let bin = match self.read_body(response).await {
+                            Ok(bin) => bin,
+                            Err(err) => {
+                                debug!("Download failed from {}: {}", url, err);
+                                break;
+                            }
+                        };
+                        if let Err(err) = check_body(&url, &headers, bin.len(), self.max_download_size) {
+                            debug!("Download failed from {}: {}", url, err);
+                            if let Some(wait) = retry_delay(body_retry_decision(&err), retries) {
+                                debug!("Retrying {} in {:.1}s", url, wait.as_secs_f64());
+                                self.limiter.pause(wait);
+                                retries += 1;
+                                continue;
+                            }
+                            break;
+                        }
+                        info!("Successfully downloaded from {}", url);
+                        
+                        let (normalized_path, suffix, b64m_hash, url) =
+                            (normalized_path.clone(), suffix.to_string(), b64m_hash.to_string(), url.clone());
+                        let limits = PayloadLimits { max_size: self.max_download_size, decompress_multiplier: self.decompress_multiplier };
+                        let storage = Arc::clone(&self.storage);
+                        tokio::task::spawn_blocking(move || {
+                            let target = StoreTarget { storage: storage.as_ref(), normalized_path: &normalized_path, suffix: &suffix };
+                            let options = StoreOptions { limits, oodle: None, verify: true };
+                            store_download(&target, &b64m_hash, bin, &url, &headers, &options)
+                        })
+                        .await
+                        .map_err(anyhow::Error::from)??;
+                        
+                        return Ok(true);
+                    }
+                    Ok(response) => {
+                        let status = response.status();
+                        debug!("Download failed from {} (HTTP {})", url, status.as_u16());
+                        
+                        let decision = classify_response(status, response.headers());
+                        if decision == RetryDecision::Abort {
+                            return Err(SoulframeError::Forbidden { url });
+                        }
+                        if let Some(wait) = retry_delay(decision, retries) {
+                            debug!("Retrying {} in {:.1}s", url, wait.as_secs_f64());
+                            self.limiter.pause(wait);
+                            retries += 1;
+                            continue;
+                        }
+                    }
+                    Err(e) => {
+                        debug!("Download failed from {}: {}", url, e);
+                    }
+                }
                 break;
             }
-            
-            let path_len = u32::from_le_bytes([
-                self.bin[self.i],
-                self.bin[self.i + 1],
-                self.bin[self.i + 2],
-                self.bin[self.i + 3],
-            ]) as usize;
-            self.i += 4;
-            
-            if self.i + path_len + 20 > self.bin.len() {
+        }
+        
+        warn!("All download attempts failed for {}", normalized_path);
+        Ok(false)
+    }
+    
+    /// Async `read_body`: the body, read a chunk at a time until it passes the
+    /// maximum download size
+    async fn read_body(&self, mut response: reqwest::Response) -> Result<Vec<u8>, SoulframeError> {
+        let declared = content_length(response.headers());
+        if let Some(size) = declared.filter(|&size| size > self.max_download_size) {
+            let what = format!("Response from {}", response.url());
+            return Err(SoulframeError::TooLarge { what, size, max: self.max_download_size });
+        }
+        let mut body = Vec::with_capacity(declared.unwrap_or(0) as usize);
+        while let Some(chunk) = response.chunk().await.map_err(anyhow::Error::from)? {
+            body.extend_from_slice(&chunk);
+            if body.len() as u64 > self.max_download_size {
                 break;
             }
-            
-            let path = String::from_utf8_lossy(&self.bin[self.i..self.i + path_len]).to_string();
-            self.i += path_len;
-            
-            // Read hash (16 bytes) and unk (4 bytes)
-            let hash = self.bin[self.i..self.i + 16].to_vec();
-            let unk = self.bin[self.i + 16..self.i + 20].to_vec();
-            self.i += 20;
-            
-            self.paths.push(path.clone());
-            self.hashes.insert(path.clone(), hash.clone());
-            self.unks.insert(path.clone(), unk);
-            
-            if let Some(target_path) = opt_stop_at_path {
-                if path == target_path {
-                    return Some(hash);
+        }
+        Ok(body)
+    }
+    
+    /// Downloads `path` unless the file on disk already has `manifest_hash`
+    pub async fn download_file(&self, path: &str, file_type: u8, suffix: Option<&str>, manifest_hash: &[u8]) -> Result<bool, SoulframeError> {
+        if is_up_to_date(self.storage.as_ref(), path, suffix, manifest_hash) {
+            return Ok(true);
+        }
+        
+        let hash_b64 = b64m_encode(manifest_hash);
+        self.download_soulframe_file(path, file_type, Some(&hash_b64), suffix).await
+    }
+    
+    /// Fetches the localized manifest and then `Languages.bin` for one locale. Without a
+    /// localized manifest, `Languages.bin` is looked up in the primary manifest instead.
+    async fn download_locale(&self, lang: &str, manifest_hash: Option<Vec<u8>>, primary_languages_hash: Option<Vec<u8>>) -> Result<bool, SoulframeError> {
+        let localized_manifest = localized_manifest_path(&self.platform, lang);
+        
+        let languages_hash = match manifest_hash {
+            Some(manifest_hash) => {
+                if !self.download_file(&localized_manifest, self.format.type_manifest(), None, &manifest_hash).await? {
+                    return Ok(false);
+                }
+                
+                let mut localized_man = SoulframeManifest::from_storage(self.storage.as_ref(), &localized_manifest, self.format)?;
+                localized_man.get_hash("/Languages.bin")
+                    .ok_or_else(|| SoulframeError::NotInManifest { path: "/Languages.bin".to_string() })?
+            }
+            None => primary_languages_hash
+                .ok_or_else(|| SoulframeError::NotInManifest { path: "/Languages.bin".to_string() })?,
+        };
+        
+        let suffix = format!("_{}", lang);
+        self.download_file("/Languages.bin", self.format.type_bin(), Some(&suffix), &languages_hash).await
+    }
+    
+    /// Downloads `Languages.bin` for each locale, running up to `concurrency` locales
+    /// at once. `primary` must be the already-downloaded `/H.Cache.bin` manifest.
+    /// Results are returned in completion order.
+    pub async fn download_locales(
+        &self,
+        primary: &mut SoulframeManifest,
+        locales: &[String],
+        concurrency: usize,
+    ) -> Vec<(String, Result<bool, SoulframeError>)> {
+        use futures::stream::{self, StreamExt};
+        
+        let primary_languages_hash = primary.get_hash("/Languages.bin");
+        let jobs: Vec<(String, Option<Vec<u8>>)> = locales
+            .iter()
+            .map(|lang| (lang.clone(), primary.get_hash(&localized_manifest_path(&self.platform, lang))))
+            .collect();
+        
+        stream::iter(jobs)
+            .map(|(lang, manifest_hash)| {
+                let primary_languages_hash = primary_languages_hash.clone();
+                async move {
+                    let result = self.download_locale(&lang, manifest_hash, primary_languages_hash).await;
+                    (lang, result)
                 }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await
+    }
+}
+
+impl SoulframeManifest {
+    /// Downloads `path` unless it is already up to date. Unlike
+    /// `DownloadClient::download_soulframe_file`, failing every URL is an error.
+    pub fn download_file(&mut self, path: &str, file_type: u8, suffix: Option<&str>, client: &DownloadClient) -> Result<(), SoulframeError> {
+        let manifest_hash = match self.get_hash(path) {
+            Some(manifest_hash) => manifest_hash,
+            None => {
+                return Err(match self.malformed_at() {
+                    Some(offset) => SoulframeError::ManifestMalformed { offset },
+                    None => SoulframeError::NotInManifest { path: path.to_string() },
+                });
+            }
+        };
+        
+        if is_up_to_date(client.storage.as_ref(), path, suffix, &manifest_hash) {
+            return Ok(());
+        }
+        
+        // The cache links files in and out, so it only applies to local storage
+        let local_path = client.storage.local_path(&download_key(path, suffix));
+        let cache = client.cache.as_ref().zip(local_path);
+        if let Some((cache, local_path)) = &cache {
+            if cache.restore(&manifest_hash, local_path)? {
+                info!("Restored {} from the cache", path);
+                return Ok(());
             }
         }
         
-        None
+        let hash_b64 = b64m_encode(&manifest_hash);
+        client.download_to(path, file_type, Some(&hash_b64), suffix, suffix)?;
+        if let Some((cache, local_path)) = &cache {
+            cache.store(&manifest_hash, local_path)?;
+        }
+        
+        Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cdn_payload, LanguagesBuilder, ManifestBuilder, MockCdn};
+    use crate::storage::MemoryStorage;
+    use std::sync::atomic::AtomicBool;
     
-    pub fn get_hash(&mut self, path: &str) -> Option<Vec<u8>> {
-        if let Some(hash) = self.hashes.get(path) {
-            return Some(hash.clone());
+    /// Stands in for Oodle without the library: copies the input after its marker
+    /// byte and zero-fills the rest of the output
+    struct StubOodle;
+    
+    impl BlockDecompressor for StubOodle {
+        fn decompress_into(&self, compressed: &[u8], output: &mut [u8]) -> Result<()> {
+            let input = compressed.get(1..).unwrap_or_default();
+            let len = input.len().min(output.len());
+            output[..len].copy_from_slice(&input[..len]);
+            output[len..].fill(0);
+            Ok(())
         }
+    }
+    
+    /// URL of `/0_en/Languages.bin` with `b64m_hash` on `host`
+    fn languages_url(host: &str, b64m_hash: &str) -> String {
+        format!("https://{}.soulframe.com/0_en/Languages.bin!2C_{}", host, b64m_hash)
+    }
+    
+    fn client(cdn: &MockCdn, storage: &Arc<MemoryStorage>) -> DownloadClient {
+        DownloadClient::with_transport(cdn.clone()).with_storage(storage.clone()).with_oodle(StubOodle)
+    }
+    
+    #[test]
+    fn falls_back_from_the_cdn_to_the_origin() {
+        let h = LanguagesBuilder::new()
+            .with_label("/Lotus/Language/Menu/Start", "Start")
+            .build()
+            .unwrap();
+        let (payload, hash) = cdn_payload(h).unwrap();
+        let b64m_hash = b64m_encode(&hash);
+        let cdn = MockCdn::new()
+            .with_response(&languages_url("content", &b64m_hash), 404, Vec::new())
+            .with_response(&languages_url("origin", &b64m_hash), 200, payload);
+        let storage = Arc::new(MemoryStorage::new());
         
-        self.seek(Some(path))
+        let downloaded = client(&cdn, &storage)
+            .download_soulframe_file("/Languages.bin", TYPE_BIN, Some(&b64m_hash), Some("_en"))
+            .unwrap();
+        assert!(downloaded);
+        assert_eq!(cdn.requests(), [languages_url("content", &b64m_hash), languages_url("origin", &b64m_hash)]);
+        assert_eq!(storage.read("0_en/Languages.bin_H").unwrap()[..MANIFEST_HASH_LEN], hash);
+        let meta: DownloadMeta = serde_json::from_slice(&storage.read("0_en/Languages.bin.meta.json").unwrap()).unwrap();
+        assert_eq!(meta.url, languages_url("origin", &b64m_hash));
     }
     
-    pub fn get_paths(&mut self) -> Vec<String> {
-        self.seek(None);
-        self.paths.clone()
+    #[test]
+    fn tries_every_url_in_order() {
+        let cdn = MockCdn::new();
+        let storage = Arc::new(MemoryStorage::new());
+        
+        let downloaded = client(&cdn, &storage)
+            .download_soulframe_file("/Languages.bin", TYPE_BIN, None, Some("_en"))
+            .unwrap();
+        assert!(!downloaded);
+        let request_path = format!("/0_en/Languages.bin!2C_{}", DEFAULT_B64M_HASH);
+        let requests = cdn.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0], languages_url("content", DEFAULT_B64M_HASH));
+        assert_eq!(requests[1], languages_url("origin", DEFAULT_B64M_HASH));
+        // The third asks the origin through a random cache-busting folder
+        let busted = requests[2].strip_prefix("https://origin.soulframe.com/origin/").unwrap();
+        assert_eq!(busted.strip_suffix(&request_path).map(str::len), Some(8));
+        assert_eq!(requests[3], format!("https://origin.soulframe.com/origin/0{}", request_path));
+        assert!(storage.paths().is_empty());
     }
     
-    pub fn download_file(&mut self, path: &str, file_type: u8, suffix: Option<&str>, client: &DownloadClient) -> Result<()> {
-        let manifest_hash = self.get_hash(path)
-            .ok_or_else(|| anyhow!("file not in manifest"))?;
+    /// A Languages.bin payload and its hash
+    fn languages_payload() -> (Vec<u8>, [u8; MANIFEST_HASH_LEN]) {
+        let h = LanguagesBuilder::new()
+            .with_label("/Lotus/Language/Menu/Start", "Start")
+            .build()
+            .unwrap();
+        cdn_payload(h).unwrap()
+    }
+    
+    #[test]
+    fn hash_mismatch_leaves_no_files() {
+        let (payload, _) = languages_payload();
+        let b64m_hash = b64m_encode(&[7; MANIFEST_HASH_LEN]);
+        let cdn = MockCdn::new().with_response(&languages_url("content", &b64m_hash), 200, payload);
+        
+        // Files from an earlier download must not survive either, or the sidecar
+        // would vouch for the mismatching data
+        let storage = Arc::new(MemoryStorage::new());
+        for part in ["_H", "_B", ".meta.json"] {
+            storage.write(&format!("0_en/Languages.bin{}", part), b"old").unwrap();
+        }
+        let result = client(&cdn, &storage).download_soulframe_file("/Languages.bin", TYPE_BIN, Some(&b64m_hash), Some("_en"));
+        assert!(matches!(result, Err(SoulframeError::HashMismatch { .. })), "{:?}", result);
+        assert!(storage.paths().is_empty(), "{:?}", storage.paths());
+        
+        // Streamed to disk
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(LocalFs::new(dir.path()));
+        let client = DownloadClient::with_transport(cdn).with_storage(storage).with_oodle(StubOodle);
+        let result = client.download_soulframe_file("/Languages.bin", TYPE_BIN, Some(&b64m_hash), Some("_en"));
+        assert!(matches!(result, Err(SoulframeError::HashMismatch { .. })), "{:?}", result);
+        assert_eq!(fs::read_dir(dir.path().join("0_en")).unwrap().count(), 0);
+    }
+    
+    #[test]
+    fn files_without_a_sidecar_are_downloaded_again() {
+        let (payload, hash) = languages_payload();
+        let b64m_hash = b64m_encode(&hash);
+        let cdn = MockCdn::new().with_response(&languages_url("content", &b64m_hash), 200, payload);
+        let manifest = ManifestBuilder::new().with_entry("/Languages.bin", hash).build().unwrap();
+        let mut manifest = SoulframeManifest::from_bytes(manifest, ManifestFormat::Current).unwrap();
+        
+        // What a download that failed its hash check used to leave: an _H starting
+        // with the manifest's hash, but no sidecar
+        let storage = Arc::new(MemoryStorage::new());
+        let mut stale = vec![0; 64];
+        stale[..MANIFEST_HASH_LEN].copy_from_slice(&hash);
+        storage.write("0_en/Languages.bin_H", &stale).unwrap();
+        let client = client(&cdn, &storage);
+        
+        manifest.download_file("/Languages.bin", TYPE_BIN, Some("_en"), &client).unwrap();
+        assert_eq!(cdn.requests().len(), 1);
+        assert!(storage.exists("0_en/Languages.bin.meta.json"));
+        assert_ne!(storage.read("0_en/Languages.bin_H").unwrap(), stale);
+        
+        // Now verified, so the next call finds it up to date
+        manifest.download_file("/Languages.bin", TYPE_BIN, Some("_en"), &client).unwrap();
+        assert_eq!(cdn.requests().len(), 1);
+    }
+    
+    #[test]
+    fn verify_requires_a_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_path = dir.path().join("Languages.bin");
+        fs::write(dir.path().join("Languages.bin_H"), b"0123456789abcdef data").unwrap();
+        
+        let result = DownloadMeta::verify(&local_path);
+        assert!(matches!(result, Err(SoulframeError::MissingSidecar { .. })), "{:?}", result);
         
-        let local_path = get_download_path(path, suffix);
-        let h_path = format!("{}_H", local_path.to_string_lossy());
+        let summary = ShccSummary { h_len: 21, b_len: None, hash: vec![0; MANIFEST_HASH_LEN] };
+        let meta = DownloadMeta::new("https://content.soulframe.com/", &Default::default(), DEFAULT_B64M_HASH, 30, &summary);
+        meta.write(&local_path).unwrap();
+        DownloadMeta::verify(&local_path).unwrap();
         
-        let header_hash = fs::read(&h_path).ok()
-            .and_then(|contents| contents.get(0..16).map(|slice| slice.to_vec()));
+        fs::write(dir.path().join("Languages.bin_H"), b"truncated").unwrap();
+        assert!(matches!(DownloadMeta::verify(&local_path), Err(SoulframeError::SizeMismatch { .. })));
+    }
+    
+    #[test]
+    fn stores_payloads_compressed_as_a_whole() {
+        let (payload, hash) = languages_payload();
+        let b64m_hash = b64m_encode(&hash);
+        // StubOodle drops the marker byte; a multiplier of 1 leaves one zero byte
+        // after the container, which is not enough for a B chunk
+        let oodle = [&[0x8C][..], &payload].concat();
+        let zstd = zstd::bulk::compress(&payload, 3).unwrap();
         
-        if Some(&manifest_hash) != header_hash.as_ref() {
-            let hash_b64 = b64m_encode(&manifest_hash);
-            client.download_soulframe_file(path, file_type, Some(&hash_b64), suffix)?;
+        for body in [oodle, zstd] {
+            let cdn = MockCdn::new().with_response(&languages_url("content", &b64m_hash), 200, body);
+            let storage = Arc::new(MemoryStorage::new());
+            let stored = client(&cdn, &storage)
+                .with_decompress_multiplier(1)
+                .download_to("/Languages.bin", TYPE_BIN, Some(&b64m_hash), Some("_en"), Some("_en"))
+                .unwrap();
+            assert_eq!(stored.meta.shcc_hash, hex(&hash));
+            assert_eq!(storage.read("0_en/Languages.bin_H").unwrap()[..MANIFEST_HASH_LEN], hash);
+            assert!(!storage.exists("0_en/Languages.bin_B"));
         }
+    }
+    
+    /// Serves `cdn`'s responses, but cuts the first successful body in half while
+    /// its `Content-Length` still gives the full size, like a dropped connection
+    struct CutOnce {
+        cdn: MockCdn,
+        cut: AtomicBool,
+    }
+    
+    impl HttpTransport for CutOnce {
+        fn get(&self, url: &str, max_size: u64) -> Result<HttpResponse> {
+            let mut response = self.cdn.get(url, max_size)?;
+            if response.status.is_success() && !self.cut.swap(true, Ordering::Relaxed) {
+                response.headers.insert(reqwest::header::CONTENT_LENGTH, response.body.len().into());
+                response.body.truncate(response.body.len() / 2);
+            }
+            Ok(response)
+        }
+    }
+    
+    #[test]
+    fn retries_a_body_cut_short() {
+        let (payload, hash) = languages_payload();
+        let b64m_hash = b64m_encode(&hash);
+        let cdn = MockCdn::new().with_response(&languages_url("content", &b64m_hash), 200, payload);
+        let storage = Arc::new(MemoryStorage::new());
+        let client = DownloadClient::with_transport(CutOnce { cdn: cdn.clone(), cut: AtomicBool::new(false) })
+            .with_storage(storage.clone())
+            .with_oodle(StubOodle);
         
-        Ok(())
+        let downloaded = client.download_soulframe_file("/Languages.bin", TYPE_BIN, Some(&b64m_hash), Some("_en")).unwrap();
+        assert!(downloaded);
+        // The same URL again after the backoff, not the next one
+        assert_eq!(cdn.requests(), [languages_url("content", &b64m_hash), languages_url("content", &b64m_hash)]);
+        assert_eq!(storage.read("0_en/Languages.bin_H").unwrap()[..MANIFEST_HASH_LEN], hash);
+    }
+    
+    #[test]
+    fn corrupt_bodies_leave_no_files() {
+        let (payload, hash) = languages_payload();
+        let b64m_hash = b64m_encode(&hash);
+        let truncated = payload[..payload.len() / 2].to_vec();
+        let unrecognized = b"<html>Service Unavailable</html>".to_vec();
+        
+        for body in [truncated, unrecognized] {
+            let cdn = MockCdn::new().with_response(&languages_url("content", &b64m_hash), 200, body);
+            let storage = Arc::new(MemoryStorage::new());
+            let result = client(&cdn, &storage).download_soulframe_file("/Languages.bin", TYPE_BIN, Some(&b64m_hash), Some("_en"));
+            assert!(result.is_err(), "{:?}", result);
+            assert!(storage.paths().is_empty(), "{:?}", storage.paths());
+            assert_eq!(cdn.requests().len(), 1);
+        }
+    }
+    
+    #[test]
+    fn verify_off_keeps_mismatching_files_under_the_local_suffix() {
+        let (payload, hash) = languages_payload();
+        let b64m_hash = b64m_encode(&[7; MANIFEST_HASH_LEN]);
+        let cdn = MockCdn::new().with_response(&languages_url("content", &b64m_hash), 200, payload);
+        let storage = Arc::new(MemoryStorage::new());
+        
+        let stored = client(&cdn, &storage)
+            .with_verify(false)
+            .download_to("/Languages.bin", TYPE_BIN, Some(&b64m_hash), Some("_en"), Some("_en_Windows"))
+            .unwrap();
+        assert_eq!(stored.meta.shcc_hash, hex(&hash));
+        assert_eq!(stored.meta.manifest_hash, b64m_hash);
+        assert_eq!(storage.paths(), ["0_en_Windows/Languages.bin.meta.json", "0_en_Windows/Languages.bin_H"]);
+    }
+    
+    #[test]
+    fn classifies_failed_responses() {
+        use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+        use reqwest::StatusCode;
+        
+        let with_retry_after = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(value));
+            headers
+        };
+        let none = HeaderMap::new();
+        let seconds = with_retry_after("5");
+        // Only the seconds form is understood; a date backs off like no header
+        let date = with_retry_after("Wed, 21 Oct 2026 07:28:00 GMT");
+        let long = with_retry_after("86400");
+        let cases = [
+            (StatusCode::NOT_FOUND, &none, RetryDecision::NextUrl),
+            (StatusCode::NOT_FOUND, &seconds, RetryDecision::NextUrl),
+            (StatusCode::TOO_MANY_REQUESTS, &seconds, RetryDecision::RetryAfter(Duration::from_secs(5))),
+            (StatusCode::TOO_MANY_REQUESTS, &date, RetryDecision::Backoff),
+            (StatusCode::TOO_MANY_REQUESTS, &none, RetryDecision::Backoff),
+            (StatusCode::TOO_MANY_REQUESTS, &long, RetryDecision::RetryAfter(MAX_RETRY_AFTER)),
+            (StatusCode::SERVICE_UNAVAILABLE, &seconds, RetryDecision::RetryAfter(Duration::from_secs(5))),
+            (StatusCode::SERVICE_UNAVAILABLE, &none, RetryDecision::Backoff),
+            (StatusCode::INTERNAL_SERVER_ERROR, &none, RetryDecision::Backoff),
+            (StatusCode::BAD_GATEWAY, &none, RetryDecision::Backoff),
+            (StatusCode::BAD_GATEWAY, &seconds, RetryDecision::Backoff),
+            (StatusCode::FORBIDDEN, &none, RetryDecision::Abort),
+            (StatusCode::FORBIDDEN, &seconds, RetryDecision::Abort),
+        ];
+        for (status, headers, expected) in cases {
+            assert_eq!(classify_response(status, headers), expected, "{} with {:?}", status, headers);
+        }
     }
 }
@@ -0,0 +1,137 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+use crate::ShccError;
+
+/// Error returned by the library's public decoding and download functions.
+///
+/// Internally the crate still uses `anyhow`; typed failures travel through it as
+/// `SoulframeError`s and are recovered when converting back at the API boundary, so
+/// anything not covered by a dedicated variant ends up in `Other`.
+#[derive(Debug, Error)]
+pub enum SoulframeError {
+    #[error(
+        "Missing required runtime library {name}. Tried:\n{}\n\
+Set SOULFRAME_LIB_DIR to a folder containing the DLL/SO, or place it in ./lib/ next to the executable.",
+        searched.iter().map(|p| format!("  - {}", p.display())).collect::<Vec<_>>().join("\n")
+    )]
+    LibraryNotFound { name: String, searched: Vec<PathBuf> },
+
+    /// The library was found but could not be loaded or lacks a required symbol
+    #[error("Failed to load {name}: {message}")]
+    LibraryLoad { name: String, message: String },
+
+    /// `OodleLZ_Decompress` returned `actual` instead of the expected size
+    #[error("Oodle decompression failed: produced {actual} of {expected} bytes")]
+    OodleDecompress { expected: usize, actual: i64 },
+
+    #[error("ZSTD decompression failed: {0}")]
+    ZstdDecompress(String),
+
+    /// A Languages.bin `_H` whose magic words aren't `LANG_MAGIC_1..3`
+    #[error(
+        "Unexpected Languages.bin magic {:#04x} {:#04x} {:#04x} (expected {:#04x} {:#04x} {:#04x}); \
+the Languages.bin format may have changed with a new game version",
+        found[0], found[1], found[2],
+        crate::extract::LANG_MAGIC_1, crate::extract::LANG_MAGIC_2, crate::extract::LANG_MAGIC_3
+    )]
+    LanguagesMagic { found: [u32; 3] },
+
+    /// A manifest `_H` file that ends inside its header or an entry
+    #[error("Malformed manifest at offset {offset}")]
+    ManifestMalformed { offset: usize },
+
+    #[error("{path} is not in the manifest")]
+    NotInManifest { path: String },
+
+    /// A `--locales` token that isn't a known locale, with the closest match
+    #[error(
+        "Unknown locale '{locale}'{}",
+        suggestion.as_ref().map(|s| format!("; did you mean '{}'?", s)).unwrap_or_default()
+    )]
+    UnknownLocale { locale: String, suggestion: Option<String> },
+
+    #[error("Locale list '{spec}' selects no locales")]
+    NoLocalesSelected { spec: String },
+
+    /// The downloaded file's SHCC hash differs from the manifest's
+    #[error("Hash mismatch for {path}")]
+    HashMismatch { path: String },
+
+    /// A bundle made from a different primary manifest than the one already in
+    /// the downloads folder
+    #[error(
+        "Bundle is for primary manifest {bundled} but downloaded-data holds {existing}; \
+unbundle into an empty folder or pass --overwrite"
+    )]
+    BundleMismatch { bundled: String, existing: String },
+
+    /// A downloaded file's size differs from its `.meta.json` sidecar; `actual` is
+    /// `None` when the file is missing
+    #[error(
+        "{path} is {} but its sidecar recorded {expected} bytes",
+        actual.map_or("missing".to_string(), |actual| format!("{} bytes", actual))
+    )]
+    SizeMismatch { path: String, expected: usize, actual: Option<u64> },
+
+    /// A downloaded file has no `.meta.json` sidecar. The sidecar is only written
+    /// once a download is verified, so the file may be left from one that failed.
+    #[error("{path} has no sidecar, so it was never verified")]
+    MissingSidecar { path: String },
+
+    /// A response, or a payload after decompression, larger than the configured
+    /// maximum download size
+    #[error("{what} is {size} bytes, over the {max}-byte limit (--max-download-size)")]
+    TooLarge { what: String, size: u64, max: u64 },
+
+    /// A response body whose length differs from its `Content-Length`, usually a
+    /// dropped connection
+    #[error("Response from {url} was {actual} bytes but its Content-Length is {expected}")]
+    LengthMismatch { url: String, expected: u64, actual: u64 },
+
+    /// Another run holds the `downloaded-data` lock
+    #[error("{} is held by {holder}; pass --wait to wait for it to finish", path.display())]
+    Locked { path: PathBuf, holder: String },
+
+    /// Every download URL failed; `status` is the last HTTP status received
+    #[error("Download of {path} failed (last HTTP status {status})")]
+    Http { path: String, status: u16 },
+
+    /// HTTP 403, which the CDN returns when it refuses to serve a region or network
+    #[error(
+        "Access denied by {url} (HTTP 403). The CDN may be blocking your region or network; \
+try again from another connection or through a VPN."
+    )]
+    Forbidden { url: String },
+
+    /// Every download URL failed without an HTTP response
+    #[error("All download attempts failed for {path}")]
+    DownloadFailed { path: String },
+
+    #[error(transparent)]
+    Shcc(#[from] ShccError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for SoulframeError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<SoulframeError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ShccError>() {
+            Ok(err) => return SoulframeError::Shcc(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<std::io::Error>() {
+            Ok(err) => return SoulframeError::Io(err),
+            Err(err) => err,
+        };
+        SoulframeError::Other(err)
+    }
+}
@@ -1,14 +1,28 @@
-use anyhow::{anyhow, Result};
-use byteorder::{LittleEndian, ReadBytesExt};
+use anyhow::{anyhow, Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use serde_json::json;
-use soulframe_language_downloader::*;
-use std::collections::BTreeMap;
+use crate::*;
+use crate::storage::{LocalFs, Storage};
+use crate::transform::{apply_transforms, ValueTransform};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::{c_char, c_int, c_void};
-use std::fs;
-use std::io::Cursor;
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Write};
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
+use std::sync::Mutex;
 use libloading::{Library, Symbol};
+use tracing::{info, trace};
 
-/// ZSTD library interface for language decompression
+/// ZSTD library interface for language decompression.
+///
+/// `Zstd` is `Send + Sync`: the symbols are plain function pointers into a library
+/// that stays loaded, and the only state is the DDict cache behind a mutex. A
+/// DDict is read-only once created and libzstd allows several DCtx to use it at
+/// once, so threads can share one `Zstd` and call `with_dict` for their own DCtx.
 pub struct Zstd {
     #[allow(dead_code)]
     lib: Library,
@@ -18,43 +32,54 @@ pub struct Zstd {
     decompress_using_ddict: Symbol<'static, unsafe extern "C" fn(usize, *mut c_void, usize, *const c_char, usize, usize) -> usize>,
     free_dctx: Symbol<'static, unsafe extern "C" fn(usize) -> usize>,
     free_ddict: Symbol<'static, unsafe extern "C" fn(usize) -> usize>,
+    /// DDicts keyed by the MD5 of the dictionary bytes, so locales that embed a
+    /// byte-identical dictionary share one `ZSTD_createDDict`
+    ddicts: Mutex<HashMap<String, usize>>,
 }
 
 impl Zstd {
-    pub fn new() -> Result<Self> {
-        let lib_path = if cfg!(windows) {
-            "./lib/libzstd.dll"
+    pub fn new() -> Result<Self, SoulframeError> {
+        let lib_name = if cfg!(windows) {
+            "libzstd.dll"
         } else {
-            "./lib/libzstd.so"
+            "libzstd.so"
         };
+
+        let lib_path = find_runtime_lib(lib_name)?;
         
         unsafe {
-            let lib = Library::new(lib_path)
-                .map_err(|e| anyhow!("Failed to load ZSTD library: {}", e))?;
+            let lib = Library::new(&lib_path).map_err(|e| SoulframeError::LibraryLoad {
+                name: lib_name.to_string(),
+                message: format!("{:?}: {}", lib_path, e),
+            })?;
+            let missing_symbol = |e: libloading::Error| SoulframeError::LibraryLoad {
+                name: lib_name.to_string(),
+                message: e.to_string(),
+            };
             
             let create_ddict: Symbol<unsafe extern "C" fn(*const c_char, usize) -> usize> = 
                 lib.get(b"ZSTD_createDDict\0")
-                    .map_err(|e| anyhow!("Failed to get ZSTD_createDDict: {}", e))?;
+                    .map_err(missing_symbol)?;
             
             let create_dctx: Symbol<unsafe extern "C" fn() -> usize> = 
                 lib.get(b"ZSTD_createDCtx\0")
-                    .map_err(|e| anyhow!("Failed to get ZSTD_createDCtx: {}", e))?;
+                    .map_err(missing_symbol)?;
             
             let dctx_set_parameter: Symbol<unsafe extern "C" fn(usize, c_int, c_int) -> usize> = 
                 lib.get(b"ZSTD_DCtx_setParameter\0")
-                    .map_err(|e| anyhow!("Failed to get ZSTD_DCtx_setParameter: {}", e))?;
+                    .map_err(missing_symbol)?;
             
             let decompress_using_ddict: Symbol<unsafe extern "C" fn(usize, *mut c_void, usize, *const c_char, usize, usize) -> usize> = 
                 lib.get(b"ZSTD_decompress_usingDDict\0")
-                    .map_err(|e| anyhow!("Failed to get ZSTD_decompress_usingDDict: {}", e))?;
+                    .map_err(missing_symbol)?;
             
             let free_dctx: Symbol<unsafe extern "C" fn(usize) -> usize> = 
                 lib.get(b"ZSTD_freeDCtx\0")
-                    .map_err(|e| anyhow!("Failed to get ZSTD_freeDCtx: {}", e))?;
+                    .map_err(missing_symbol)?;
             
             let free_ddict: Symbol<unsafe extern "C" fn(usize) -> usize> = 
                 lib.get(b"ZSTD_freeDDict\0")
-                    .map_err(|e| anyhow!("Failed to get ZSTD_freeDDict: {}", e))?;
+                    .map_err(missing_symbol)?;
             
             // Extend lifetimes to 'static - safe because we keep the library alive
             let create_ddict: Symbol<'static, _> = std::mem::transmute(create_ddict);
@@ -72,152 +97,2178 @@ impl Zstd {
                 decompress_using_ddict,
                 free_dctx,
                 free_ddict,
+                ddicts: Mutex::new(HashMap::new()),
             })
         }
     }
+    
+    /// Returns the dictionary's hash and its DDict handle, creating the DDict on first use.
+    ///
+    /// A dictionary libzstd fails to load is an error and is not cached, so a later
+    /// call tries again rather than handing a NULL DDict to the decompressor.
+    pub fn ddict(&self, dict_bin: &[u8]) -> Result<(String, usize), SoulframeError> {
+        let dict_hash = format!("{:x}", md5::compute(dict_bin));
+        let mut ddicts = self.ddicts.lock().unwrap();
+        if let Some(handle) = ddicts.get(&dict_hash) {
+            return Ok((dict_hash, *handle));
+        }
+        
+        let handle = unsafe {
+            (self.create_ddict)(dict_bin.as_ptr() as *const c_char, dict_bin.len())
+        };
+        if handle == 0 {
+            let message = format!("ZSTD_createDDict failed for the {}-byte dictionary {}", dict_bin.len(), dict_hash);
+            return Err(SoulframeError::ZstdDecompress(message));
+        }
+        ddicts.insert(dict_hash.clone(), handle);
+        Ok((dict_hash, handle))
+    }
 }
 
-pub fn languages_unpack(bin: &[u8], zstd: &Zstd) -> Result<BTreeMap<String, String>> {
-    let mut cursor = Cursor::new(bin);
-    let mut entries = BTreeMap::new();
+impl Drop for Zstd {
+    fn drop(&mut self) {
+        for handle in self.ddicts.lock().unwrap().values() {
+            unsafe {
+                (self.free_ddict)(*handle);
+            }
+        }
+    }
+}
+
+/// Decompresses label values with a ZSTD dictionary fixed at construction.
+///
+/// Values are magicless ZSTD frames, so implementations must decode that format.
+pub trait DictDecompressor {
+    /// Decompresses `src`, which must produce exactly `dst_len` bytes
+    fn decompress(&self, src: &[u8], dst_len: usize) -> Result<Vec<u8>>;
+}
+
+impl Zstd {
+    /// Returns a decompressor for `dict_bin`, reusing the cached DDict if another
+    /// locale already loaded a byte-identical dictionary.
+    pub fn with_dict(&self, dict_bin: &[u8]) -> Result<ZstdDict<'_>, SoulframeError> {
+        let (_, ddict) = self.ddict(dict_bin)?;
+        
+        unsafe {
+            let dctx = (self.create_dctx)();
+            if dctx == 0 {
+                return Err(SoulframeError::ZstdDecompress("ZSTD_createDCtx failed".to_string()));
+            }
+            (self.dctx_set_parameter)(dctx, 1000, 1); // ZSTD_d_format = ZSTD_f_zstd1_magicless
+            
+            Ok(ZstdDict { zstd: self, ddict, dctx, _not_sync: PhantomData })
+        }
+    }
+}
+
+/// `DictDecompressor` backed by the shared libzstd loaded by `Zstd`.
+///
+/// It owns a DCtx, which libzstd does not allow two threads to use at the same
+/// time, so it is `Send` but not `Sync`; each worker creates its own with
+/// `Zstd::with_dict`.
+pub struct ZstdDict<'a> {
+    zstd: &'a Zstd,
+    ddict: usize,
+    dctx: usize,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl DictDecompressor for ZstdDict<'_> {
+    fn decompress(&self, src: &[u8], dst_len: usize) -> Result<Vec<u8>> {
+        let mut output = vec![0u8; dst_len];
+        
+        let result = unsafe {
+            (self.zstd.decompress_using_ddict)(
+                self.dctx,
+                output.as_mut_ptr() as *mut c_void,
+                dst_len,
+                src.as_ptr() as *const c_char,
+                src.len(),
+                self.ddict
+            )
+        };
+        
+        if result != dst_len {
+            let message = format!("size mismatch: {} != {}", result, dst_len);
+            return Err(SoulframeError::ZstdDecompress(message).into());
+        }
+        
+        Ok(output)
+    }
+}
+
+impl Drop for ZstdDict<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            (self.zstd.free_dctx)(self.dctx);
+        }
+    }
+}
+
+/// Magic number of a standard zstd frame, which the magicless label values omit
+const ZSTD_FRAME_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Magic number of a zstd dictionary with entropy tables. libzstd loads any other
+/// bytes as a raw-content dictionary, and `BundledZstd` does the same.
+const ZSTD_DICT_MAGIC: [u8; 4] = [0x37, 0xA4, 0x30, 0xEC];
+
+/// ID given to a raw-content dictionary, which has none of its own. Frames made
+/// with one carry no dictionary ID, so it is only used to select the dictionary.
+const RAW_CONTENT_DICT_ID: u32 = 1;
+
+/// `DictDecompressor` on the pure-Rust `ruzstd` decoder, so neither a `libzstd`
+/// shared library nor any C code is needed. Like `ZstdDict` it is `Send` but not
+/// `Sync`, one per worker.
+pub struct BundledZstd {
+    decoder: std::cell::RefCell<ruzstd::decoding::FrameDecoder>,
+    dict_id: u32,
+}
+
+impl BundledZstd {
+    pub fn new(dict_bin: &[u8]) -> Result<Self, SoulframeError> {
+        let dict = if dict_bin.starts_with(&ZSTD_DICT_MAGIC) {
+            // decode_dict slices the tables without checking their length first, so
+            // a truncated dictionary panics rather than returning an error
+            std::panic::catch_unwind(|| ruzstd::decoding::Dictionary::decode_dict(dict_bin))
+                .map_err(|_| SoulframeError::ZstdDecompress("failed to load dictionary: it is truncated".to_string()))?
+                .map_err(|e| SoulframeError::ZstdDecompress(format!("failed to load dictionary: {}", e)))?
+        } else {
+            ruzstd::decoding::Dictionary {
+                id: RAW_CONTENT_DICT_ID,
+                fse: Default::default(),
+                huf: Default::default(),
+                dict_content: dict_bin.to_vec(),
+                // The repeat offsets a frame starts with when there is no dictionary
+                offset_hist: [1, 4, 8],
+            }
+        };
+        let dict_id = dict.id;
+        
+        let mut decoder = ruzstd::decoding::FrameDecoder::new();
+        decoder
+            .add_dict(dict)
+            .map_err(|e| SoulframeError::ZstdDecompress(format!("failed to load dictionary: {}", e)))?;
+        
+        Ok(Self {
+            decoder: std::cell::RefCell::new(decoder),
+            dict_id,
+        })
+    }
+}
+
+impl DictDecompressor for BundledZstd {
+    fn decompress(&self, src: &[u8], dst_len: usize) -> Result<Vec<u8>> {
+        let error = |e: ruzstd::decoding::errors::FrameDecoderError| SoulframeError::ZstdDecompress(e.to_string());
+        let frame = [&ZSTD_FRAME_MAGIC[..], src].concat();
+        let mut input = frame.as_slice();
+        let mut decoder = self.decoder.borrow_mut();
+        
+        decoder.reset(&mut input).map_err(error)?;
+        // A frame made with a raw-content dictionary doesn't name it
+        decoder.force_dict(self.dict_id).map_err(error)?;
+        let declared = decoder.content_size();
+        if declared != 0 && declared != dst_len as u64 {
+            let message = format!("size mismatch: frame declares {} != {}", declared, dst_len);
+            return Err(SoulframeError::ZstdDecompress(message).into());
+        }
+        
+        // Stop one byte past the expected size, so a corrupt frame can't make it
+        // decode much more than that
+        let finished = decoder
+            .decode_blocks(&mut input, ruzstd::decoding::BlockDecodingStrategy::UptoBytes(dst_len + 1))
+            .map_err(error)?;
+        if !finished {
+            let message = format!("size mismatch: more than {} bytes", dst_len);
+            return Err(SoulframeError::ZstdDecompress(message).into());
+        }
+        if !input.is_empty() {
+            let message = format!("{} bytes left after the frame", input.len());
+            return Err(SoulframeError::ZstdDecompress(message).into());
+        }
+        
+        let output = decoder.collect().unwrap_or_default();
+        if output.len() != dst_len {
+            let message = format!("size mismatch: {} != {}", output.len(), dst_len);
+            return Err(SoulframeError::ZstdDecompress(message).into());
+        }
+        
+        Ok(output)
+    }
+}
+
+/// Languages.bin contents in the shape `languages_pack` writes back out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LanguageFile {
+    /// 16-byte hash at the start of the `_H` file
+    pub hash: Vec<u8>,
+    pub suffixes: Vec<Vec<u8>>,
+    pub entries: BTreeMap<String, String>,
+}
+
+/// Magic words after the `_H` hash. A game update that changes the format is
+/// expected to bump these, so they are checked before anything else is parsed.
+pub const LANG_MAGIC_1: u32 = 0x14;
+pub const LANG_MAGIC_2: u32 = 0x2B;
+pub const LANG_MAGIC_3: u32 = 0x01;
+
+fn read_languages_header<'a>(cursor: &mut Cursor<&'a [u8]>) -> Result<(LanguageFile, &'a [u8])> {
+    let bin: &'a [u8] = cursor.get_ref();
+    if bin.len() < 16 {
+        return Err(anyhow!("Languages.bin too short"));
+    }
+    let hash = bin[..16].to_vec();
     
     // Skip hash (16 bytes)
     cursor.set_position(16);
     
     // Read and verify magic numbers
-    let magic1 = cursor.read_u32::<LittleEndian>()?; // 0x14
-    let magic2 = cursor.read_u32::<LittleEndian>()?; // 0x2B
-    let magic3 = cursor.read_u32::<LittleEndian>()?; // 0x01
+    let magic1 = cursor.read_u32::<LittleEndian>()?;
+    let magic2 = cursor.read_u32::<LittleEndian>()?;
+    let magic3 = cursor.read_u32::<LittleEndian>()?;
     
-    if magic1 != 0x14 || magic2 != 0x2B || magic3 != 0x01 {
-        return Err(anyhow!("Invalid language file magic numbers"));
+    if (magic1, magic2, magic3) != (LANG_MAGIC_1, LANG_MAGIC_2, LANG_MAGIC_3) {
+        return Err(SoulframeError::LanguagesMagic { found: [magic1, magic2, magic3] }.into());
     }
     
-    // Read number of suffixes
+    // Read suffixes
     let num_suffixes = cursor.read_u32::<LittleEndian>()?;
-    
-    // Skip suffixes
+    check_count(cursor, "num_suffixes", num_suffixes, usize::MAX, 4)?;
+    let mut suffixes = Vec::new();
     for _ in 0..num_suffixes {
-        let suffix_len = cursor.read_u32::<LittleEndian>()?;
-        cursor.set_position(cursor.position() + suffix_len as u64);
+        suffixes.push(read_s4(cursor)?.to_vec());
     }
     
     // Read dictionary
-    let dict_len = cursor.read_u32::<LittleEndian>()?;
-    let dict_start = cursor.position() as usize;
-    cursor.set_position(cursor.position() + dict_len as u64);
-    let dict_bin = &bin[dict_start..dict_start + dict_len as usize];
+    let dict_bin = read_s4(cursor)?;
+    
+    let header = LanguageFile {
+        hash,
+        suffixes,
+        entries: BTreeMap::new(),
+    };
+    Ok((header, dict_bin))
+}
+
+fn read_s4<'a>(cursor: &mut Cursor<&'a [u8]>) -> Result<&'a [u8]> {
+    let bin: &'a [u8] = cursor.get_ref();
+    let len = cursor.read_u32::<LittleEndian>()? as usize;
+    let start = cursor.position() as usize;
+    if start + len > bin.len() {
+        return Err(anyhow!("Unexpected EOF reading s4"));
+    }
+    cursor.set_position((start + len) as u64);
+    Ok(&bin[start..start + len])
+}
+
+pub(crate) fn write_s4(out: &mut Vec<u8>, data: &[u8]) -> Result<()> {
+    let len = u32::try_from(data.len())
+        .map_err(|_| anyhow!("Field of {} bytes is too large to pack", data.len()))?;
+    out.write_u32::<LittleEndian>(len)?;
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+/// Reads everything before the path table: the header fields (with no entries)
+/// and the embedded ZSTD dictionary.
+pub fn languages_read_header(bin: &[u8]) -> Result<(LanguageFile, Vec<u8>), SoulframeError> {
+    let mut cursor = Cursor::new(bin);
+    let (header, dict_bin) = read_languages_header(&mut cursor)?;
+    Ok((header, dict_bin.to_vec()))
+}
+
+/// Largest decompressed size a compressed label may claim by default. Label values
+/// are at most 64 KiB compressed, and real strings are far smaller than this.
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 1 << 20;
+
+/// Largest `num_paths` accepted by default. Real files have a few thousand paths.
+pub const DEFAULT_MAX_PATHS: usize = 100_000;
+
+/// Largest `num_labels` of a single path accepted by default
+pub const DEFAULT_MAX_LABELS: usize = 100_000;
+
+/// Which keys are unpacked: those starting with one of `include` (every key when it
+/// is empty) and with none of `exclude`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl KeyFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|prefix| key.starts_with(prefix.as_str())))
+            && !self.exclude.iter().any(|prefix| key.starts_with(prefix.as_str()))
+    }
+}
+
+/// Controls optional work done by `languages_unpack`
+#[derive(Debug, Clone)]
+pub struct UnpackOptions {
+    /// Record each label's table fields in `UnpackedLanguages::metadata`
+    pub collect_metadata: bool,
+    /// Record the label names of each path in `UnpackedLanguages::paths`
+    pub collect_paths: bool,
+    /// Record the keys in file order in `UnpackedLanguages::order`
+    pub collect_order: bool,
+    /// Reject compressed labels whose dyn-u32 prefix claims more than this many
+    /// bytes, rather than allocating whatever a corrupt prefix asks for
+    pub max_value_size: usize,
+    /// Reject a path table claiming more paths than this
+    pub max_paths: usize,
+    /// Reject a path claiming more labels than this
+    pub max_labels: usize,
+    /// Keys to unpack; the rest are skipped without being decompressed
+    pub keys: KeyFilter,
+    /// Clean-up steps applied to every value, in order
+    pub transforms: Vec<ValueTransform>,
+}
+
+impl Default for UnpackOptions {
+    fn default() -> Self {
+        Self {
+            collect_metadata: false,
+            collect_paths: false,
+            collect_order: false,
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
+            max_paths: DEFAULT_MAX_PATHS,
+            max_labels: DEFAULT_MAX_LABELS,
+            keys: KeyFilter::default(),
+            transforms: Vec::new(),
+        }
+    }
+}
+
+/// A label's entry in the path's label table
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LabelMetadata {
+    pub offset: u32,
+    pub size: u16,
+    pub flags: u16,
+    /// Whether the value is ZSTD-compressed (flag `0x200`)
+    pub compressed: bool,
+    /// Size given by the dyn-u32 prefix of a compressed value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decompressed_size: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UnpackedLanguages {
+    pub entries: BTreeMap<String, String>,
+    /// Per-key label fields, only filled with `UnpackOptions::collect_metadata`
+    pub metadata: BTreeMap<String, LabelMetadata>,
+    /// Label names of each path, in file order, only filled with
+    /// `UnpackOptions::collect_paths`. A key is its path followed by its name.
+    pub paths: BTreeMap<String, Vec<String>>,
+    /// Keys in the order their labels appear in the file, only filled with
+    /// `UnpackOptions::collect_order`
+    pub order: Vec<String>,
+    /// The embedded ZSTD dictionary
+    pub dict: Vec<u8>,
+    /// MD5 of the dictionary, as hex
+    pub dict_hash: String,
+    /// Labels stored ZSTD-compressed (flag `0x200`) and stored as-is
+    pub compressed_labels: usize,
+    pub raw_labels: usize,
+}
+
+/// Unpacks every label of a `_H` file. `decompressor` must have been built for the
+/// dictionary embedded in `bin` (see `languages_read_header`).
+pub fn languages_unpack(bin: &[u8], decompressor: &dyn DictDecompressor, options: &UnpackOptions) -> Result<UnpackedLanguages, SoulframeError> {
+    let mut cursor = Cursor::new(bin);
     
-    // Read number of paths
+    let (_, dict_bin) = read_languages_header(&mut cursor)?;
+    
+    let mut unpacked = UnpackedLanguages {
+        dict: dict_bin.to_vec(),
+        dict_hash: format!("{:x}", md5::compute(dict_bin)),
+        ..Default::default()
+    };
+    
+    read_paths(&mut cursor, decompressor, options, &mut unpacked)?;
+    
+    Ok(unpacked)
+}
+
+/// A label table entry and the slice of its path's chunk that holds the value
+pub(crate) struct RawLabel<'a> {
+    /// Path and label name concatenated
+    pub key: String,
+    /// Length of the path at the start of `key`
+    pub path_len: usize,
+    pub offset: u32,
+    pub size: u16,
+    pub flags: u16,
+    /// The stored value, still compressed if `flags & 0x200`
+    pub data: &'a [u8],
+}
+
+/// Checks a count read from the file against `cap` and against the bytes left in
+/// `cursor`, given that every counted item takes at least `min_item_len` bytes
+fn check_count(cursor: &Cursor<&[u8]>, field: &str, count: u32, cap: usize, min_item_len: usize) -> Result<()> {
+    let count = count as usize;
+    if count > cap {
+        return Err(anyhow!("{} is {}, above the limit of {}", field, count, cap));
+    }
+    
+    let remaining = cursor.get_ref().len().saturating_sub(cursor.position() as usize);
+    if count.saturating_mul(min_item_len) > remaining {
+        return Err(anyhow!(
+            "{} is {}, but only {} bytes remain at offset {} (at least {} per item)",
+            field,
+            count,
+            remaining,
+            cursor.position(),
+            min_item_len
+        ));
+    }
+    Ok(())
+}
+
+/// Walks the path table following the header, handing each label to `f` without
+/// decoding its value. Stops early when `f` returns `ControlFlow::Break`. Path and
+/// label counts are checked against `options.max_paths` and `options.max_labels`.
+pub(crate) fn read_labels<'a>(
+    cursor: &mut Cursor<&'a [u8]>,
+    options: &UnpackOptions,
+    f: &mut dyn FnMut(RawLabel<'a>) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    // Read number of paths; each has a name, a chunk and a label count
     let num_paths = cursor.read_u32::<LittleEndian>()?;
+    check_count(cursor, "num_paths", num_paths, options.max_paths, 12)?;
     
-    unsafe {
-        // Create ZSTD dictionary and context
-        let dict = (zstd.create_ddict)(dict_bin.as_ptr() as *const c_char, dict_bin.len());
-        let ctx = (zstd.create_dctx)();
-        (zstd.dctx_set_parameter)(ctx, 1000, 1); // ZSTD_d_refMultipleDDicts = 1000
-        
-        // Process each path
-        for _ in 0..num_paths {
-            let path_len = cursor.read_u32::<LittleEndian>()?;
-            let path_start = cursor.position() as usize;
-            cursor.set_position(cursor.position() + path_len as u64);
-            let path = String::from_utf8_lossy(&bin[path_start..path_start + path_len as usize]);
+    // Process each path
+    for _ in 0..num_paths {
+        let path_offset = cursor.position();
+        let path = String::from_utf8_lossy(read_s4(cursor)?).to_string();
+        let chunk = read_s4(cursor)?;
+        
+        // Each label has a name, offset, size and flags
+        let num_labels = cursor.read_u32::<LittleEndian>()?;
+        trace!("Path {} at offset {}: {}-byte chunk, {} labels", path, path_offset, chunk.len(), num_labels);
+        check_count(cursor, &format!("num_labels of {}", path), num_labels, options.max_labels, 12)?;
+        
+        for _ in 0..num_labels {
+            let name = String::from_utf8_lossy(read_s4(cursor)?).to_string();
             
-            let chunk_len = cursor.read_u32::<LittleEndian>()?;
-            let chunk_start = cursor.position() as usize;
-            cursor.set_position(cursor.position() + chunk_len as u64);
-            let chunk = &bin[chunk_start..chunk_start + chunk_len as usize];
+            let offset = cursor.read_u32::<LittleEndian>()?;
+            let size = cursor.read_u16::<LittleEndian>()?;
+            let flags = cursor.read_u16::<LittleEndian>()?;
             
-            let num_labels = cursor.read_u32::<LittleEndian>()?;
+            let start = offset as usize;
+            let end = start + size as usize;
+            if end > chunk.len() {
+                return Err(anyhow!("Label slice out of bounds"));
+            }
             
-            for _ in 0..num_labels {
-                let name_len = cursor.read_u32::<LittleEndian>()?;
-                let name_start = cursor.position() as usize;
-                cursor.set_position(cursor.position() + name_len as u64);
-                let name = String::from_utf8_lossy(&bin[name_start..name_start + name_len as usize]);
-                
-                let offset = cursor.read_u32::<LittleEndian>()?;
-                let size = cursor.read_u16::<LittleEndian>()?;
-                let flags = cursor.read_u16::<LittleEndian>()?;
-                
-                let mut data = chunk[offset as usize..(offset + size as u32) as usize].to_vec();
-                
-                // Check if compressed
-                if (flags & 0x200) != 0 {
-                    let mut data_cursor = Cursor::new(&data);
-                    let (decompressed_size, data_offset) = unpack_u32_dyn_le(&data, 0)?;
-                    
-                    let compressed_data = &data[data_offset..];
-                    let mut output = vec![0u8; decompressed_size as usize];
-                    
-                    let result = (zstd.decompress_using_ddict)(
-                        ctx,
-                        output.as_mut_ptr() as *mut c_void,
-                        decompressed_size as usize,
-                        compressed_data.as_ptr() as *const c_char,
-                        compressed_data.len(),
-                        dict
-                    );
-                    
-                    if result != decompressed_size as usize {
-                        return Err(anyhow!("ZSTD decompression failed"));
-                    }
-                    
-                    data = output;
+            let flow = f(RawLabel {
+                key: format!("{}{}", path, name),
+                path_len: path.len(),
+                offset,
+                size,
+                flags,
+                data: &chunk[start..end],
+            })?;
+            if flow.is_break() {
+                return Ok(());
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Parses the header and label table of a `_H` file without decompressing anything.
+/// Used by the fuzz targets.
+#[cfg(feature = "fuzzing")]
+pub(crate) fn scan_labels<'a>(bin: &'a [u8], f: &mut dyn FnMut(RawLabel<'a>) -> Result<ControlFlow<()>>) -> Result<()> {
+    let mut cursor = Cursor::new(bin);
+    read_languages_header(&mut cursor)?;
+    read_labels(&mut cursor, &UnpackOptions::default(), f)
+}
+
+/// Reads the path table following the header, decompressing labels with `decompressor`.
+fn read_paths(
+    cursor: &mut Cursor<&[u8]>,
+    decompressor: &dyn DictDecompressor,
+    options: &UnpackOptions,
+    unpacked: &mut UnpackedLanguages,
+) -> Result<()> {
+    read_labels(cursor, options, &mut |label| {
+        if !options.keys.matches(&label.key) {
+            return Ok(ControlFlow::Continue(()));
+        }
+        let (data, decompressed_size) = decode_label(&label, decompressor, options.max_value_size)?;
+        if decompressed_size.is_some() {
+            unpacked.compressed_labels += 1;
+        } else {
+            unpacked.raw_labels += 1;
+        }
+        
+        if options.collect_metadata {
+            unpacked.metadata.insert(label.key.clone(), LabelMetadata {
+                offset: label.offset,
+                size: label.size,
+                flags: label.flags,
+                compressed: decompressed_size.is_some(),
+                decompressed_size,
+            });
+        }
+        if options.collect_paths {
+            let (path, name) = label.key.split_at(label.path_len);
+            match unpacked.paths.get_mut(path) {
+                Some(names) => names.push(name.to_string()),
+                None => {
+                    unpacked.paths.insert(path.to_string(), vec![name.to_string()]);
                 }
-                
-                let full_key = format!("{}{}", path, name);
-                let text = String::from_utf8_lossy(&data).to_string();
-                entries.insert(full_key, text);
             }
         }
+        if options.collect_order {
+            unpacked.order.push(label.key.clone());
+        }
         
-        // Cleanup ZSTD resources
-        (zstd.free_dctx)(ctx);
-        (zstd.free_ddict)(dict);
+        let text = apply_transforms(&options.transforms, String::from_utf8_lossy(&data).to_string());
+        unpacked.entries.insert(label.key, text);
+        Ok(ControlFlow::Continue(()))
+    })
+}
+
+/// Returns a label's value, decompressing it if flagged `0x200`, along with the
+/// size from its dyn-u32 prefix when compressed
+fn decode_label(label: &RawLabel, decompressor: &dyn DictDecompressor, max_value_size: usize) -> Result<(Vec<u8>, Option<u32>)> {
+    if (label.flags & 0x200) == 0 {
+        return Ok((label.data.to_vec(), None));
     }
     
-    Ok(entries)
+    let (out_size, data_offset) = unpack_u32_dyn_le(label.data, 0)?;
+    if out_size as usize > max_value_size {
+        return Err(anyhow!(
+            "Label {} claims {} decompressed bytes from {} compressed bytes, above the {}-byte limit",
+            label.key,
+            out_size,
+            label.data.len() - data_offset,
+            max_value_size
+        ));
+    }
+    
+    // An empty value needs no decoding, and a zero-sized destination leaves it to
+    // the decompressor whether a frame that would produce data counts as an error
+    if out_size == 0 {
+        return Ok((Vec::new(), Some(0)));
+    }
+    
+    let data = decompressor.decompress(&label.data[data_offset..], out_size as usize)?;
+    Ok((data, Some(out_size)))
+}
+
+/// Looks up a single `path + name` key, decompressing only that label's value.
+///
+/// Stops at the first match instead of building the full map like `languages_unpack`.
+/// `decompressor` must have been built for the dictionary embedded in `bin`.
+pub fn languages_lookup(bin: &[u8], key: &str, decompressor: &dyn DictDecompressor) -> Result<Option<String>, SoulframeError> {
+    Ok(languages_lookup_keys(bin, &[key], decompressor)?.remove(key))
 }
 
-pub fn extract_languages_for_locale(locale: &str, zstd: &Zstd) -> Result<usize> {
-    let h_path_suffix = format!("_{}", locale);
-    let h_path = get_download_path("/Languages.bin", Some(&h_path_suffix));
-    let h_file_path = format!("{}_H", h_path.to_string_lossy());
+/// Like `languages_lookup` for several keys, stopping as soon as all of them are
+/// found. Keys absent from the file are absent from the returned map.
+pub fn languages_lookup_keys(bin: &[u8], keys: &[&str], decompressor: &dyn DictDecompressor) -> Result<BTreeMap<String, String>, SoulframeError> {
+    let mut cursor = Cursor::new(bin);
+    read_languages_header(&mut cursor)?;
     
-    let bin = fs::read(&h_file_path)
-        .map_err(|_| anyhow!("Languages.bin_H not found for locale {}", locale))?;
+    let wanted: HashSet<&str> = keys.iter().copied().collect();
+    let mut found = BTreeMap::new();
+    read_labels(&mut cursor, &UnpackOptions::default(), &mut |label| {
+        if !wanted.contains(label.key.as_str()) || found.contains_key(&label.key) {
+            return Ok(ControlFlow::Continue(()));
+        }
+        
+        let (data, _) = decode_label(&label, decompressor, DEFAULT_MAX_VALUE_SIZE)?;
+        found.insert(label.key, String::from_utf8_lossy(&data).to_string());
+        if found.len() == wanted.len() {
+            return Ok(ControlFlow::Break(()));
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    
+    Ok(found)
+}
+
+/// Packs `file` back into the Languages.bin `_H` layout read by `languages_unpack`.
+///
+/// Keys are split into path and label name at the last `/`. Every label is
+/// stored uncompressed (no `0x200` flag), so `dict` is only carried along for
+/// readers that expect it.
+pub fn languages_pack(file: &LanguageFile, dict: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    
+    let mut hash = file.hash.clone();
+    hash.resize(16, 0);
+    out.extend_from_slice(&hash);
+    
+    out.write_u32::<LittleEndian>(LANG_MAGIC_1)?;
+    out.write_u32::<LittleEndian>(LANG_MAGIC_2)?;
+    out.write_u32::<LittleEndian>(LANG_MAGIC_3)?;
+    
+    out.write_u32::<LittleEndian>(file.suffixes.len() as u32)?;
+    for suffix in &file.suffixes {
+        write_s4(&mut out, suffix)?;
+    }
+    
+    write_s4(&mut out, dict)?;
+    
+    // Group labels by path
+    let mut paths: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    for (key, value) in &file.entries {
+        let split = key.rfind('/').map_or(0, |i| i + 1);
+        let (path, name) = key.split_at(split);
+        paths.entry(path).or_default().push((name, value));
+    }
     
-    let entries = languages_unpack(&bin, zstd)?;
+    out.write_u32::<LittleEndian>(paths.len() as u32)?;
     
+    for (path, labels) in &paths {
+        write_s4(&mut out, path.as_bytes())?;
+        
+        let mut chunk = Vec::new();
+        let mut table = Vec::new();
+        for (name, value) in labels {
+            let size = u16::try_from(value.len())
+                .map_err(|_| anyhow!("Value for {}{} is too long to store uncompressed", path, name))?;
+            
+            write_s4(&mut table, name.as_bytes())?;
+            table.write_u32::<LittleEndian>(chunk.len() as u32)?;
+            table.write_u16::<LittleEndian>(size)?;
+            table.write_u16::<LittleEndian>(0)?; // flags: uncompressed
+            
+            chunk.extend_from_slice(value.as_bytes());
+        }
+        
+        write_s4(&mut out, &chunk)?;
+        out.write_u32::<LittleEndian>(labels.len() as u32)?;
+        out.extend_from_slice(&table);
+    }
+    
+    Ok(out)
+}
+
+/// How the keys of an extracted locale are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyOrder {
+    /// Byte order, so `Item10` sorts before `Item2`
+    #[default]
+    Lexicographic,
+    /// Runs of digits compare by their numeric value, so `Item2` sorts before
+    /// `Item10`; see `natural_cmp`
+    Natural,
+}
+
+impl KeyOrder {
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        match self {
+            KeyOrder::Lexicographic => a.cmp(b),
+            KeyOrder::Natural => natural_cmp(a, b),
+        }
+    }
+}
+
+/// Compares `a` and `b` with runs of ASCII digits taken as numbers. Numbers that
+/// are equal apart from leading zeros fall back to byte order, so distinct
+/// strings never compare equal.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.as_bytes(), b.as_bytes());
+    while let (Some(&cx), Some(&cy)) = (x.first(), y.first()) {
+        if cx.is_ascii_digit() && cy.is_ascii_digit() {
+            let x_len = x.iter().take_while(|c| c.is_ascii_digit()).count();
+            let y_len = y.iter().take_while(|c| c.is_ascii_digit()).count();
+            let x_digits = trim_leading_zeros(&x[..x_len]);
+            let y_digits = trim_leading_zeros(&y[..y_len]);
+            let order = x_digits.len().cmp(&y_digits.len()).then_with(|| x_digits.cmp(y_digits));
+            if order != Ordering::Equal {
+                return order;
+            }
+            x = &x[x_len..];
+            y = &y[y_len..];
+        } else if cx != cy {
+            return cx.cmp(&cy);
+        } else {
+            x = &x[1..];
+            y = &y[1..];
+        }
+    }
+    x.len().cmp(&y.len()).then_with(|| a.cmp(b))
+}
+
+fn trim_leading_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&c| c == b'0').count();
+    &digits[zeros..]
+}
+
+/// Layout of the JSON files written for extracted locales
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonStyle {
+    /// Indented with two spaces, one member per line
+    #[default]
+    Pretty,
+    /// No whitespace, for embedding in apps
+    Compact,
+}
+
+impl JsonStyle {
+    /// Serializes `value` to `writer` in this style
+    pub fn write<W: Write, T: Serialize + ?Sized>(self, writer: W, value: &T) -> Result<()> {
+        match self {
+            JsonStyle::Pretty => serde_json::to_writer_pretty(writer, value)?,
+            JsonStyle::Compact => serde_json::to_writer(writer, value)?,
+        }
+        Ok(())
+    }
+}
+
+/// Writes `entries` as the pretty-printed JSON object used for extracted locales:
+/// every key mapped to its string, plus an `__order` array of the sorted keys.
+/// Returns the number of strings written.
+pub fn write_language_json<W: Write>(entries: &BTreeMap<String, String>, writer: W) -> Result<usize> {
+    write_language_json_ordered(entries, KeyOrder::Lexicographic, JsonStyle::Pretty, true, writer)
+}
+
+/// Like `write_language_json`, with both the object's keys and `__order` sorted
+/// by `order`, written in `style`. `__order` is left out unless `include_order`,
+/// for consumers that would take it for a string.
+pub fn write_language_json_ordered<W: Write>(
+    entries: &BTreeMap<String, String>,
+    order: KeyOrder,
+    style: JsonStyle,
+    include_order: bool,
+    writer: W,
+) -> Result<usize> {
     // Create ordered JSON with __order field
     let mut keys: Vec<&String> = entries.keys().collect();
-    keys.sort();
-    
-    let mut ordered = BTreeMap::new();
-    ordered.insert("__order".to_string(), json!(keys));
+    keys.sort_by(|a, b| order.compare(a, b));
     
+    let mut ordered: Vec<(&str, serde_json::Value)> = Vec::new();
+    if include_order {
+        ordered.push(("__order", json!(keys)));
+    }
     for key in &keys {
         if let Some(value) = entries.get(*key) {
-            ordered.insert((*key).clone(), json!(value));
+            ordered.push((key.as_str(), json!(value)));
         }
     }
+    ordered.sort_by(|(a, _), (b, _)| order.compare(a, b));
+    
+    style.write(writer, &OrderedObject(&ordered))?;
+    
+    Ok(keys.len())
+}
+
+/// Like `write_language_json_ordered`, with the object's keys and `__order` in the
+/// order of `keys` instead of sorted, e.g. the file order kept in
+/// `UnpackedLanguages::order`. `__order` comes last. Keys missing from `entries`,
+/// and repeats after the first, are left out.
+pub fn write_language_json_in_order<W: Write>(
+    entries: &BTreeMap<String, String>,
+    keys: &[String],
+    style: JsonStyle,
+    include_order: bool,
+    writer: W,
+) -> Result<usize> {
+    let mut seen = HashSet::new();
+    let keys: Vec<&String> = keys
+        .iter()
+        .filter(|key| entries.contains_key(*key) && seen.insert(key.as_str()))
+        .collect();
     
-    // Write to JSON file
-    let output_path = get_extract_path(&format!("/Languages/{}.json", locale), None);
-    if let Some(parent) = output_path.parent() {
-        fs::create_dir_all(parent)?;
+    let mut ordered: Vec<(&str, serde_json::Value)> = keys
+        .iter()
+        .map(|key| (key.as_str(), json!(entries[*key])))
+        .collect();
+    if include_order {
+        ordered.push(("__order", json!(keys)));
     }
     
-    let json_content = serde_json::to_string_pretty(&ordered)?;
-    fs::write(&output_path, json_content)?;
+    style.write(writer, &OrderedObject(&ordered))?;
     
-    println!(
-        "  ✓ {} strings -> {}",
-        keys.len(),
-        output_path.to_string_lossy()
-    );
+    Ok(keys.len())
+}
+
+/// Like `write_language_json_ordered`, with each string replaced by an object
+/// holding it and its label table fields, for reverse-engineering:
+/// `{"value": "...", "offset": N, "size": N, "flags": "0x0200"}`. `unpacked` must
+/// come from `languages_unpack` with `UnpackOptions::collect_metadata`.
+pub fn write_debug_language_json<W: Write>(
+    unpacked: &UnpackedLanguages,
+    order: KeyOrder,
+    style: JsonStyle,
+    include_order: bool,
+    writer: W,
+) -> Result<usize> {
+    let mut keys: Vec<&String> = unpacked.entries.keys().collect();
+    keys.sort_by(|a, b| order.compare(a, b));
+    
+    let mut ordered: Vec<(&str, DebugMember)> = Vec::new();
+    if include_order {
+        ordered.push(("__order", DebugMember::Order(keys.clone())));
+    }
+    for (key, value) in &unpacked.entries {
+        let label = unpacked
+            .metadata
+            .get(key)
+            .ok_or_else(|| anyhow!("No label metadata for {}; unpack with collect_metadata", key))?;
+        ordered.push((key.as_str(), DebugMember::Label {
+            value,
+            offset: label.offset,
+            size: label.size,
+            flags: format!("{:#06x}", label.flags),
+        }));
+    }
+    ordered.sort_by(|(a, _), (b, _)| order.compare(a, b));
+    
+    style.write(writer, &OrderedObject(&ordered))?;
     
     Ok(keys.len())
-}
\ No newline at end of file
+}
+
+/// A member of `write_debug_language_json`'s object, with the label fields in a
+/// fixed order
+#[derive(Serialize)]
+#[serde(untagged)]
+enum DebugMember<'a> {
+    Order(Vec<&'a String>),
+    Label { value: &'a str, offset: u32, size: u16, flags: String },
+}
+
+/// Serializes as a JSON object with the members in the given order
+struct OrderedObject<'a, T>(&'a [(&'a str, T)]);
+
+impl<T: Serialize> Serialize for OrderedObject<'_, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+    }
+}
+
+/// Reads a locale JSON written by `write_language_json` back into its entries,
+/// ignoring `__order`
+pub fn read_language_json(json: &str) -> Result<BTreeMap<String, String>> {
+    let values: BTreeMap<String, serde_json::Value> = serde_json::from_str(json)?;
+    
+    let mut entries = BTreeMap::new();
+    for (key, value) in values {
+        if key == "__order" {
+            continue;
+        }
+        let text = value
+            .as_str()
+            .ok_or_else(|| anyhow!("Value for {} is not a string", key))?;
+        entries.insert(key, text.to_string());
+    }
+    Ok(entries)
+}
+
+/// File name (without extension) for the strings of a manifest path, e.g.
+/// `Lotus.Language.Menu` for `/Lotus/Language/Menu/`. The leading and trailing `/`
+/// are dropped, or marked with `~` when missing, the other `/` become `.`, and bytes
+/// other than ASCII letters, digits, `-` and `_` are written as `%XX`. Nothing is
+/// left between the markers of `/` or `//`, so their name is `_root` instead. Distinct
+/// paths get distinct names, except `//` and `/_root/`, which are both `_root`.
+pub fn path_file_name(path: &str) -> String {
+    let (lead, rest) = match path.strip_prefix('/') {
+        Some(rest) => ("", rest),
+        None => ("~", path),
+    };
+    let (core, trail) = match rest.strip_suffix('/') {
+        Some(core) => (core, ""),
+        None => (rest, "~"),
+    };
+    let mut name = lead.to_string();
+    if core.is_empty() {
+        name.push_str("_root");
+    }
+    for &byte in core.as_bytes() {
+        match byte {
+            b'/' => name.push('.'),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => name.push(byte as char),
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    name.push_str(trail);
+    name
+}
+
+/// `path_file_name` of each path, with `~2`, `~3`, ... appended to names that match
+/// an earlier one (in byte order of the paths) or only differ from it by case, so
+/// the files don't overwrite each other, also on case-insensitive file systems
+pub fn split_file_names<'a>(paths: impl IntoIterator<Item = &'a str>) -> BTreeMap<&'a str, String> {
+    let paths: BTreeSet<&str> = paths.into_iter().collect();
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    let mut names = BTreeMap::new();
+    for path in paths {
+        let mut name = path_file_name(path);
+        let count = seen.entry(name.to_ascii_lowercase()).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            name.push_str(&format!("~{}", count));
+        }
+        names.insert(path, name);
+    }
+    names
+}
+
+/// A string whose value differs between two extractions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValueChange {
+    pub old: String,
+    pub new: String,
+}
+
+/// Summary of one locale's extraction for dashboards, written next to its JSON as
+/// `<locale>.stats.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionStats {
+    pub locale: String,
+    /// Strings written to the locale's JSON
+    pub strings: usize,
+    /// Bytes of all values after decompression
+    pub decoded_bytes: usize,
+    pub compressed_labels: usize,
+    pub raw_labels: usize,
+    pub dict_size: usize,
+    /// MD5 of the dictionary, as hex
+    pub dict_hash: String,
+    /// Header hash of the source `_H` file, as hex; the one the manifest lists
+    pub source_hash: String,
+    /// MD5 of the whole source `_H` file, as hex
+    pub source_md5: String,
+    /// Output options the JSON was written with, as the caller describes them
+    pub options: String,
+    pub tool_version: String,
+    /// Wall-clock time to unpack and write the locale, in milliseconds
+    pub duration_ms: u64,
+}
+
+impl ExtractionStats {
+    /// Stats for `unpacked`, read from the `_H` contents `bin`, with `strings` written
+    pub fn new(locale: &str, bin: &[u8], unpacked: &UnpackedLanguages, strings: usize, options: &str, duration: std::time::Duration) -> Self {
+        Self {
+            locale: locale.to_string(),
+            strings,
+            decoded_bytes: unpacked.entries.values().map(|value| value.len()).sum(),
+            compressed_labels: unpacked.compressed_labels,
+            raw_labels: unpacked.raw_labels,
+            dict_size: unpacked.dict.len(),
+            dict_hash: unpacked.dict_hash.clone(),
+            source_hash: bin.iter().take(MANIFEST_HASH_LEN).map(|b| format!("{:02x}", b)).collect(),
+            source_md5: format!("{:x}", md5::compute(bin)),
+            options: options.to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+    
+    /// Reads the stats file at `path` in `storage`, or `None` if it is missing or
+    /// unreadable
+    pub fn read(storage: &dyn Storage, path: &str) -> Option<Self> {
+        serde_json::from_slice(&storage.read(path).ok()?).ok()
+    }
+}
+
+/// A string that moved to another key between two extractions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyMove {
+    /// The key it moved to
+    pub to: String,
+    pub value: String,
+    /// The value under the old key, for moves matched by similarity whose string
+    /// also changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_value: Option<String>,
+}
+
+/// Removed and added keys that match each other, but not one to one, so which key
+/// moved where can't be told
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnresolvedMove {
+    /// Removed keys with their last value
+    pub from: BTreeMap<String, String>,
+    pub to: BTreeMap<String, String>,
+}
+
+/// Keys added, changed, removed and moved between two extractions of a locale
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LocaleDelta {
+    pub added: BTreeMap<String, String>,
+    pub changed: BTreeMap<String, ValueChange>,
+    /// Removed keys with their last value
+    pub removed: BTreeMap<String, String>,
+    /// Moves found by `detect_moves`, by the key moved from
+    pub moved: BTreeMap<String, KeyMove>,
+    pub unresolved_moves: Vec<UnresolvedMove>,
+}
+
+impl LocaleDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.changed.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.unresolved_moves.is_empty()
+    }
+    
+    /// Reports removed and added keys holding the same string as moves rather than
+    /// as a removal and an addition. With `min_similarity` (0 to 1), keys still
+    /// unpaired are also matched when their strings share at least that fraction of
+    /// their words. A key matching several others is never paired; its group goes
+    /// to `unresolved_moves` instead.
+    pub fn detect_moves(&mut self, min_similarity: Option<f64>) {
+        // Index removed strings by value, so pairing is linear in the keys. Empty
+        // strings say nothing about where a key went.
+        let mut by_value: HashMap<&str, (Vec<String>, Vec<String>)> = HashMap::new();
+        for (key, value) in self.removed.iter().filter(|(_, value)| !value.is_empty()) {
+            by_value.entry(value).or_default().0.push(key.clone());
+        }
+        for (key, value) in &self.added {
+            if let Some((_, to)) = by_value.get_mut(value.as_str()) {
+                to.push(key.clone());
+            }
+        }
+        let mut groups: Vec<(Vec<String>, Vec<String>)> = by_value.into_values().filter(|(_, to)| !to.is_empty()).collect();
+        
+        if let Some(min_similarity) = min_similarity {
+            let matched: HashSet<&str> = groups.iter().flat_map(|(from, to)| from.iter().chain(to)).map(|key| key.as_str()).collect();
+            let unmatched = |entries: &'_ BTreeMap<String, String>| -> Vec<(String, String)> {
+                entries
+                    .iter()
+                    .filter(|(key, _)| !matched.contains(key.as_str()))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            };
+            let similar = similar_groups(&unmatched(&self.removed), &unmatched(&self.added), min_similarity);
+            groups.extend(similar);
+        }
+        
+        for (from, to) in groups {
+            if let ([from], [to]) = (from.as_slice(), to.as_slice()) {
+                let old = self.removed.remove(from).unwrap_or_default();
+                let value = self.added.remove(to).unwrap_or_default();
+                let old_value = (old != value).then_some(old);
+                self.moved.insert(from.clone(), KeyMove { to: to.clone(), value, old_value });
+            } else {
+                let from = from.into_iter().filter_map(|key| Some((key.clone(), self.removed.remove(&key)?))).collect();
+                let to = to.into_iter().filter_map(|key| Some((key.clone(), self.added.remove(&key)?))).collect();
+                self.unresolved_moves.push(UnresolvedMove { from, to });
+            }
+        }
+        self.unresolved_moves.sort_by(|a, b| a.from.keys().next().cmp(&b.from.keys().next()));
+    }
+}
+
+/// Representative of `node`'s set in the union-find forest `parent`
+fn root(parent: &mut [usize], mut node: usize) -> usize {
+    while parent[node] != node {
+        parent[node] = parent[parent[node]];
+        node = parent[node];
+    }
+    node
+}
+
+/// The words of `words` that `similar_groups` indexes: the rarest ones by
+/// `frequency`, as many as a match at `min_similarity` must share one of
+fn rare_prefix<'a>(words: &'a HashSet<String>, frequency: &HashMap<&str, usize>, min_similarity: f64) -> Vec<&'a str> {
+    let mut sorted: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+    sorted.sort_by_key(|word| (frequency[word], *word));
+    let required = (min_similarity * sorted.len() as f64 - 1e-9).ceil() as usize;
+    sorted.truncate(sorted.len() + 1 - required.max(1));
+    sorted
+}
+
+/// Lowercased runs of letters and digits in `value`
+fn words(value: &str) -> HashSet<String> {
+    value
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Groups of removed and added keys linked by strings sharing at least
+/// `min_similarity` of their words (Jaccard index), one group per connected set.
+///
+/// Candidates come from prefix filtering: with each string's words sorted rarest
+/// first, two strings that similar must share one of the first
+/// `len - ceil(min_similarity * len) + 1` words of each, so only those are indexed
+/// and common words don't make every string a candidate for every other.
+fn similar_groups(removed: &[(String, String)], added: &[(String, String)], min_similarity: f64) -> Vec<(Vec<String>, Vec<String>)> {
+    let removed_words: Vec<HashSet<String>> = removed.iter().map(|(_, value)| words(value)).collect();
+    let added_words: Vec<HashSet<String>> = added.iter().map(|(_, value)| words(value)).collect();
+    let mut frequency: HashMap<&str, usize> = HashMap::new();
+    for word in removed_words.iter().chain(&added_words).flatten() {
+        *frequency.entry(word).or_default() += 1;
+    }
+    let prefix = |words| rare_prefix(words, &frequency, min_similarity);
+    
+    let mut index: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (j, words) in added_words.iter().enumerate() {
+        for word in prefix(words) {
+            index.entry(word).or_default().push(j);
+        }
+    }
+    
+    // Removed key i is node i, added key j is node removed.len() + j
+    let mut parent: Vec<usize> = (0..removed.len() + added.len()).collect();
+    let mut linked = vec![false; parent.len()];
+    for (i, words) in removed_words.iter().enumerate() {
+        let candidates: HashSet<usize> = prefix(words)
+            .into_iter()
+            .flat_map(|word| index.get(word).into_iter().flatten().copied())
+            .collect();
+        for j in candidates {
+            let shared = words.intersection(&added_words[j]).count();
+            let union = words.len() + added_words[j].len() - shared;
+            if shared as f64 / union as f64 >= min_similarity {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, removed.len() + j));
+                parent[a] = b;
+                linked[i] = true;
+                linked[removed.len() + j] = true;
+            }
+        }
+    }
+    
+    let mut groups: BTreeMap<usize, (Vec<String>, Vec<String>)> = BTreeMap::new();
+    for node in (0..parent.len()).filter(|&node| linked[node]) {
+        let group = groups.entry(root(&mut parent, node)).or_default();
+        match node.checked_sub(removed.len()) {
+            None => group.0.push(removed[node].0.clone()),
+            Some(j) => group.1.push(added[j].0.clone()),
+        }
+    }
+    groups.into_values().collect()
+}
+
+/// Compares the entries of an earlier extraction, `old`, with `new`
+pub fn diff_entries(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> LocaleDelta {
+    let mut delta = LocaleDelta::default();
+    for (key, value) in new {
+        match old.get(key) {
+            None => {
+                delta.added.insert(key.clone(), value.clone());
+            }
+            Some(old_value) if old_value != value => {
+                delta.changed.insert(key.clone(), ValueChange { old: old_value.clone(), new: value.clone() });
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, value) in old {
+        if !new.contains_key(key) {
+            delta.removed.insert(key.clone(), value.clone());
+        }
+    }
+    delta
+}
+
+/// Longest value `markdown_changelog` shows by default, in characters
+pub const DEFAULT_MARKDOWN_VALUE_LEN: usize = 120;
+
+/// Renders `deltas` as a Markdown document titled `title`: Added, Changed, Removed
+/// and Moved tables, under a heading per locale when there is more than one. Values
+/// longer than `max_value_len` characters are cut short with an ellipsis (0 keeps them
+/// whole). Keys and values are escaped, so game markup shows as written.
+pub fn markdown_changelog(title: &str, deltas: &BTreeMap<&str, &LocaleDelta>, max_value_len: usize) -> String {
+    let mut md = format!("# {}\n", markdown_text(title, 0));
+    let per_locale = deltas.len() > 1;
+    let section = if per_locale { "###" } else { "##" };
+    let value = |value: &str| markdown_value(value, max_value_len);
+    
+    if deltas.is_empty() {
+        md.push_str("\nNo locales were compared.\n");
+    }
+    for (locale, delta) in deltas {
+        // Without a heading per locale, the summary line names it
+        let label = if per_locale {
+            md.push_str(&format!("\n## {}\n", markdown_text(locale, 0)));
+            String::new()
+        } else {
+            format!("{}: ", markdown_text(locale, 0))
+        };
+        md.push_str(&format!(
+            "\n{}{} added, {} changed, {} removed, {} moved\n",
+            label,
+            delta.added.len(),
+            delta.changed.len(),
+            delta.removed.len(),
+            delta.moved.len()
+        ));
+        if delta.is_empty() {
+            continue;
+        }
+        
+        if !delta.added.is_empty() {
+            md.push_str(&format!("\n{} Added\n\n| Key | Value |\n| --- | --- |\n", section));
+            for (key, added) in &delta.added {
+                md.push_str(&format!("| {} | {} |\n", markdown_text(key, 0), value(added)));
+            }
+        }
+        if !delta.changed.is_empty() {
+            md.push_str(&format!("\n{} Changed\n\n| Key | Old | New |\n| --- | --- | --- |\n", section));
+            for (key, change) in &delta.changed {
+                md.push_str(&format!("| {} | {} | {} |\n", markdown_text(key, 0), value(&change.old), value(&change.new)));
+            }
+        }
+        if !delta.removed.is_empty() {
+            md.push_str(&format!("\n{} Removed\n\n| Key | Last value |\n| --- | --- |\n", section));
+            for (key, removed) in &delta.removed {
+                md.push_str(&format!("| {} | {} |\n", markdown_text(key, 0), value(removed)));
+            }
+        }
+        if !delta.moved.is_empty() {
+            md.push_str(&format!("\n{} Moved\n\n| From | To | Value |\n| --- | --- | --- |\n", section));
+            for (key, moved) in &delta.moved {
+                let shown = match &moved.old_value {
+                    Some(old_value) => format!("{} \u{2192} {}", value(old_value), value(&moved.value)),
+                    None => value(&moved.value),
+                };
+                md.push_str(&format!("| {} | {} | {} |\n", markdown_text(key, 0), markdown_text(&moved.to, 0), shown));
+            }
+        }
+        if !delta.unresolved_moves.is_empty() {
+            md.push_str(&format!("\n{} Ambiguous moves\n\n", section));
+            for unresolved in &delta.unresolved_moves {
+                let keys = |keys: &BTreeMap<String, String>| {
+                    keys.keys().map(|key| markdown_text(key, 0)).collect::<Vec<_>>().join(", ")
+                };
+                md.push_str(&format!("- {} \u{2192} {}\n", keys(&unresolved.from), keys(&unresolved.to)));
+            }
+        }
+    }
+    md
+}
+
+/// A value for a `markdown_changelog` table cell: escaped, cut to `max_len`, and
+/// shown as *(empty)* when empty
+fn markdown_value(value: &str, max_len: usize) -> String {
+    if value.is_empty() {
+        "*(empty)*".to_string()
+    } else {
+        markdown_text(value, max_len)
+    }
+}
+
+/// `text` cut to `max_len` characters (0 for no limit) and escaped for a Markdown
+/// table cell: Markdown-significant punctuation is backslash-escaped and line breaks
+/// become `<br>`
+fn markdown_text(text: &str, max_len: usize) -> String {
+    let truncated = max_len > 0 && text.chars().count() > max_len;
+    let text: String = if truncated { text.chars().take(max_len).collect() } else { text.to_string() };
+    
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~' | '#' | '&' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                escaped.push_str("<br>");
+            }
+            '\n' => escaped.push_str("<br>"),
+            c => escaped.push(c),
+        }
+    }
+    if truncated {
+        escaped.push('\u{2026}');
+    }
+    escaped
+}
+
+/// How `duplicate_values` compares strings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValueNormalization {
+    /// Ignore leading and trailing whitespace
+    pub trim: bool,
+    /// Ignore case
+    pub case_fold: bool,
+}
+
+impl ValueNormalization {
+    pub fn apply(self, value: &str) -> String {
+        let value = if self.trim { value.trim() } else { value };
+        if self.case_fold {
+            value.to_lowercase()
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+/// Keys holding the same string
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateGroup {
+    /// The string, normalized
+    pub value: String,
+    pub keys: Vec<String>,
+    /// Bytes the copies beyond the first take up
+    pub redundant_bytes: usize,
+}
+
+/// How much of a locale's text is the same string under several keys
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateReport {
+    pub strings: usize,
+    /// Distinct strings after normalization
+    pub unique_values: usize,
+    /// Bytes of all normalized strings
+    pub total_bytes: usize,
+    pub redundant_bytes: usize,
+    /// `total_bytes` over the bytes left if every string were stored once
+    pub dedup_ratio: f64,
+    /// Strings held by more than one key, most keys first
+    pub groups: Vec<DuplicateGroup>,
+}
+
+/// Groups the keys of `entries` by their string, compared after `normalization`
+pub fn duplicate_values(entries: &BTreeMap<String, String>, normalization: ValueNormalization) -> DuplicateReport {
+    let mut by_value: HashMap<String, Vec<String>> = HashMap::new();
+    let mut total_bytes = 0;
+    for (key, value) in entries {
+        let value = normalization.apply(value);
+        total_bytes += value.len();
+        by_value.entry(value).or_default().push(key.clone());
+    }
+    let unique_values = by_value.len();
+    
+    let mut groups: Vec<DuplicateGroup> = by_value
+        .into_iter()
+        .filter(|(_, keys)| keys.len() > 1)
+        .map(|(value, keys)| DuplicateGroup { redundant_bytes: value.len() * (keys.len() - 1), value, keys })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.keys.len().cmp(&a.keys.len())
+            .then(b.redundant_bytes.cmp(&a.redundant_bytes))
+            .then_with(|| a.value.cmp(&b.value))
+    });
+    
+    let redundant_bytes: usize = groups.iter().map(|group| group.redundant_bytes).sum();
+    let stored_bytes = total_bytes - redundant_bytes;
+    DuplicateReport {
+        strings: entries.len(),
+        unique_values,
+        total_bytes,
+        redundant_bytes,
+        dedup_ratio: if stored_bytes == 0 { 1.0 } else { total_bytes as f64 / stored_bytes as f64 },
+        groups,
+    }
+}
+
+/// Size of a group of strings, for estimating translation work
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StringStats {
+    /// Key prefix the group shares, empty when the group is the whole locale
+    pub prefix: String,
+    pub keys: usize,
+    /// Unicode scalar values
+    pub characters: usize,
+    /// See `word_count`
+    pub words: usize,
+    /// Shortest, median and longest string, in characters
+    pub min_length: usize,
+    pub median_length: f64,
+    pub max_length: usize,
+}
+
+/// Whether `c` is written without spaces between words: Han ideographs and Japanese
+/// kana. Hangul is spaced like Latin text.
+fn is_unspaced_script(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}' // Hiragana, Katakana
+            | '\u{31F0}'..='\u{31FF}' // Katakana phonetic extensions
+            | '\u{3400}'..='\u{4DBF}' // CJK extension A
+            | '\u{4E00}'..='\u{9FFF}' // CJK unified ideographs
+            | '\u{F900}'..='\u{FAFF}' // CJK compatibility ideographs
+            | '\u{FF66}'..='\u{FF9F}' // Halfwidth Katakana
+            | '\u{20000}'..='\u{3134F}' // CJK extensions B to G
+    )
+}
+
+/// Words in `value`: runs of letters and digits, which may be joined by `'` or `-`
+/// (`don't`, `well-known`). Han and kana characters count as a word each, since
+/// those scripts don't separate words with spaces.
+pub fn word_count(value: &str) -> usize {
+    let mut words = 0;
+    let mut in_word = false;
+    for c in value.chars() {
+        if is_unspaced_script(c) {
+            words += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                words += 1;
+                in_word = true;
+            }
+        } else if !(in_word && matches!(c, '\'' | '\u{2019}' | '-')) {
+            in_word = false;
+        }
+    }
+    words
+}
+
+/// The first `depth` segments of `key`'s path, the part before its label name, e.g.
+/// `/Lotus/Language` for `/Lotus/Language/Menu/Start` at depth 2. Keys with a
+/// shorter path keep all of it.
+pub fn key_prefix(key: &str, depth: usize) -> String {
+    let mut segments: Vec<&str> = key.split('/').filter(|segment| !segment.is_empty()).collect();
+    segments.pop();
+    segments.truncate(depth);
+    format!("/{}", segments.join("/"))
+}
+
+/// Counts the strings of `entries`, as one group or, with `depth`, grouped by
+/// `key_prefix(key, depth)` in prefix order
+pub fn string_stats(entries: &BTreeMap<String, String>, depth: Option<usize>) -> Vec<StringStats> {
+    let mut groups: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    if depth.is_none() {
+        groups.insert(String::new(), Vec::new());
+    }
+    for (key, value) in entries {
+        let prefix = depth.map_or_else(String::new, |depth| key_prefix(key, depth));
+        groups.entry(prefix).or_default().push(value);
+    }
+    
+    groups
+        .into_iter()
+        .map(|(prefix, values)| {
+            let mut lengths: Vec<usize> = values.iter().map(|value| value.chars().count()).collect();
+            lengths.sort_unstable();
+            let median_length = match lengths.len() {
+                0 => 0.0,
+                n if n % 2 == 1 => lengths[n / 2] as f64,
+                n => (lengths[n / 2 - 1] + lengths[n / 2]) as f64 / 2.0,
+            };
+            StringStats {
+                prefix,
+                keys: values.len(),
+                characters: lengths.iter().sum(),
+                words: values.iter().map(|value| word_count(value)).sum(),
+                min_length: lengths.first().copied().unwrap_or(0),
+                median_length,
+                max_length: lengths.last().copied().unwrap_or(0),
+            }
+        })
+        .collect()
+}
+
+/// Appended to a string's name when it collides with a branch of the same name in
+/// `nest_entries`, repeatedly until the name is free
+pub const NESTED_LEAF_SUFFIX: &str = "_value";
+
+/// Builds a tree of JSON objects from the `/`-separated keys of `entries`, e.g.
+/// `/Lotus/Menu/Start` becomes `{"Lotus": {"Menu": {"Start": ...}}}`. Empty segments
+/// are skipped.
+///
+/// When a key is both a string and a branch (`/Menu/Start` and `/Menu/Start/Title`),
+/// the branch keeps the name and the string moves to `Start_value`; see
+/// `NESTED_LEAF_SUFFIX`.
+pub fn nest_entries(entries: &BTreeMap<String, String>) -> serde_json::Value {
+    let mut root = serde_json::Map::new();
+    
+    for (key, value) in entries {
+        let segments: Vec<&str> = key.split('/').filter(|segment| !segment.is_empty()).collect();
+        let Some((name, parents)) = segments.split_last() else {
+            let name = free_name(&root, "");
+            root.insert(name, json!(value));
+            continue;
+        };
+        
+        let mut node = &mut root;
+        for parent in parents {
+            if node.get(*parent).is_some_and(|child| !child.is_object()) {
+                let leaf_name = free_name(node, parent);
+                let leaf = node.remove(*parent).unwrap();
+                node.insert(leaf_name, leaf);
+            }
+            node = node
+                .entry(parent.to_string())
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+                .unwrap();
+        }
+        
+        let name = free_name(node, name);
+        node.insert(name, json!(value));
+    }
+    
+    serde_json::Value::Object(root)
+}
+
+/// Writes `entries` as the tree from `nest_entries` in `style`, the `--nested`
+/// counterpart of `write_language_json`. Returns the number of strings written.
+pub fn write_nested_language_json<W: Write>(entries: &BTreeMap<String, String>, style: JsonStyle, writer: W) -> Result<usize> {
+    style.write(writer, &nest_entries(entries))?;
+    Ok(entries.len())
+}
+
+/// `name`, or `name` with `NESTED_LEAF_SUFFIX` appended until it is unused in `node`
+fn free_name(node: &serde_json::Map<String, serde_json::Value>, name: &str) -> String {
+    let mut name = name.to_string();
+    while node.contains_key(&name) {
+        name.push_str(NESTED_LEAF_SUFFIX);
+    }
+    name
+}
+
+/// Layout, key order and style of the JSON `extract_languages_to_writer` writes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub style: JsonStyle,
+    /// Ignored when `nested` is set, since the tree's keys are always sorted
+    pub order: KeyOrder,
+    /// Write the tree from `nest_entries` instead of the flat object
+    pub nested: bool,
+    /// Leave the `__order` array out of the flat object
+    pub omit_order: bool,
+}
+
+/// Unpacks the `Languages.bin_H` contents `bin` and writes its strings to `w` as a
+/// locale JSON in `format`, without touching the filesystem. Returns the number of
+/// strings written.
+pub fn extract_languages_to_writer<W: Write>(bin: &[u8], zstd: &Zstd, w: W, format: OutputFormat) -> Result<usize> {
+    write_formatted(&unpack_entries(bin, Some(zstd))?, w, format)
+}
+
+/// Strings of the `Languages.bin_H` contents `bin`, decoded with `zstd`, or with
+/// `BundledZstd` when it is `None`
+fn unpack_entries(bin: &[u8], zstd: Option<&Zstd>) -> Result<BTreeMap<String, String>> {
+    let (_, dict) = languages_read_header(bin)?;
+    let options = UnpackOptions::default();
+    let unpacked = match zstd {
+        Some(zstd) => languages_unpack(bin, &zstd.with_dict(&dict)?, &options)?,
+        None => languages_unpack(bin, &BundledZstd::new(&dict)?, &options)?,
+    };
+    Ok(unpacked.entries)
+}
+
+/// Writes `entries` to `w` as a locale JSON in `format`
+fn write_formatted<W: Write>(entries: &BTreeMap<String, String>, w: W, format: OutputFormat) -> Result<usize> {
+    if format.nested {
+        write_nested_language_json(entries, format.style, w)
+    } else {
+        write_language_json_ordered(entries, format.order, format.style, !format.omit_order, w)
+    }
+}
+
+pub fn extract_languages_for_locale(locale: &str, zstd: &Zstd, style: JsonStyle) -> Result<usize> {
+    extract_languages_with_storage(locale, Some(zstd), style, &LocalFs::downloads(), &LocalFs::extracted())
+}
+
+/// Like `extract_languages_for_locale`, reading the locale's `Languages.bin_H` from
+/// `downloads` and writing `0/Languages/<locale>.json` to `extracted`. With `zstd`
+/// set to `None` the strings are decoded by `BundledZstd`.
+pub fn extract_languages_with_storage(
+    locale: &str,
+    zstd: Option<&Zstd>,
+    style: JsonStyle,
+    downloads: &dyn Storage,
+    extracted: &dyn Storage,
+) -> Result<usize> {
+    let h_key = format!("{}_H", download_key("/Languages.bin", Some(&format!("_{}", locale))));
+    let bin = downloads
+        .read(&h_key)
+        .map_err(|_| anyhow!("Languages.bin_H not found for locale {}", locale))?;
+    
+    // Write to JSON file, streamed when the storage is on disk
+    let output_key = format!("0/Languages/{}.json", locale);
+    let format = OutputFormat { style, ..Default::default() };
+    let entries = unpack_entries(&bin, zstd).with_context(|| format!("parsing {}", h_key))?;
+    let (count, output) = match extracted.local_path(&output_key) {
+        Some(output_path) => {
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let file = BufWriter::new(File::create(&output_path)?);
+            let count = write_formatted(&entries, file, format)?;
+            (count, output_path.to_string_lossy().into_owned())
+        }
+        None => {
+            let mut json = Vec::new();
+            let count = write_formatted(&entries, &mut json, format)?;
+            extracted.write(&output_key, &json)?;
+            (count, output_key)
+        }
+    };
+    
+    info!(
+        "  {} {} strings -> {}",
+        crate::output::Status::Ok,
+        count,
+        output
+    );
+    
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{LanguagesBuilder, FIXTURE_DICTIONARY};
+    use crate::storage::MemoryStorage;
+    
+    fn unpack(bin: &[u8], options: &UnpackOptions) -> Result<UnpackedLanguages, SoulframeError> {
+        let (_, dict) = languages_read_header(bin)?;
+        languages_unpack(bin, &BundledZstd::new(&dict)?, options)
+    }
+    
+    fn entries(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+    
+    #[test]
+    fn reads_the_header() {
+        let bin = LanguagesBuilder::new()
+            .with_hash([7; MANIFEST_HASH_LEN])
+            .with_suffix(b"_en")
+            .with_suffix(b"_fr")
+            .build()
+            .unwrap();
+        
+        let (header, dict) = languages_read_header(&bin).unwrap();
+        assert_eq!(header.hash, [7; MANIFEST_HASH_LEN]);
+        assert_eq!(header.suffixes, [b"_en".to_vec(), b"_fr".to_vec()]);
+        assert!(header.entries.is_empty());
+        assert_eq!(dict, FIXTURE_DICTIONARY);
+    }
+    
+    #[test]
+    fn rejects_unknown_magic() {
+        let mut bin = LanguagesBuilder::new().build().unwrap();
+        bin[MANIFEST_HASH_LEN] = 0x15;
+        assert!(languages_read_header(&bin).is_err());
+    }
+    
+    #[test]
+    fn unpacks_raw_and_compressed_labels() {
+        let bin = LanguagesBuilder::new()
+            .with_label("/Lotus/Language/Menu/Start", "Start")
+            .with_compressed_label("/Lotus/Language/Menu/Quit", "Quit the game")
+            .with_compressed_label("/Lotus/Language/Items/Sword", "A \"sword\"\nwith \u{e9}t\u{e9}")
+            .build()
+            .unwrap();
+        
+        let unpacked = unpack(&bin, &UnpackOptions::default()).unwrap();
+        assert_eq!(unpacked.entries, entries(&[
+            ("/Lotus/Language/Items/Sword", "A \"sword\"\nwith \u{e9}t\u{e9}"),
+            ("/Lotus/Language/Menu/Quit", "Quit the game"),
+            ("/Lotus/Language/Menu/Start", "Start"),
+        ]));
+        assert_eq!(unpacked.compressed_labels, 2);
+        assert_eq!(unpacked.raw_labels, 1);
+        assert_eq!(unpacked.dict, FIXTURE_DICTIONARY);
+        assert_eq!(unpacked.dict_hash, format!("{:x}", md5::compute(FIXTURE_DICTIONARY)));
+    }
+    
+    #[test]
+    fn collects_metadata_paths_and_order() {
+        let bin = LanguagesBuilder::new()
+            .with_label("/Lotus/Language/Menu/Start", "Start")
+            .with_compressed_label("/Lotus/Language/Menu/Quit", "Quit")
+            .with_label("/Lotus/Language/Items/Sword", "Sword")
+            .build()
+            .unwrap();
+        let options = UnpackOptions {
+            collect_metadata: true,
+            collect_paths: true,
+            collect_order: true,
+            ..Default::default()
+        };
+        
+        let unpacked = unpack(&bin, &options).unwrap();
+        let start = &unpacked.metadata["/Lotus/Language/Menu/Start"];
+        assert_eq!((start.offset, start.size, start.flags, start.compressed), (0, 5, 0, false));
+        let quit = &unpacked.metadata["/Lotus/Language/Menu/Quit"];
+        assert_eq!((quit.offset, quit.flags, quit.compressed, quit.decompressed_size), (5, 0x200, true, Some(4)));
+        assert_eq!(unpacked.paths["/Lotus/Language/Menu/"], ["Start", "Quit"]);
+        assert_eq!(unpacked.order, [
+            "/Lotus/Language/Items/Sword",
+            "/Lotus/Language/Menu/Start",
+            "/Lotus/Language/Menu/Quit",
+        ]);
+    }
+    
+    #[test]
+    fn filters_keys() {
+        let bin = LanguagesBuilder::new()
+            .with_label("/Lotus/Language/Menu/Start", "Start")
+            .with_compressed_label("/Lotus/Language/Menu/Quit", "Quit")
+            .with_label("/Lotus/Language/Items/Sword", "Sword")
+            .build()
+            .unwrap();
+        let options = UnpackOptions {
+            keys: KeyFilter {
+                include: vec!["/Lotus/Language/Menu/".to_string()],
+                exclude: vec!["/Lotus/Language/Menu/Quit".to_string()],
+            },
+            ..Default::default()
+        };
+        
+        let unpacked = unpack(&bin, &options).unwrap();
+        assert_eq!(unpacked.entries, entries(&[("/Lotus/Language/Menu/Start", "Start")]));
+        assert_eq!(unpacked.compressed_labels, 0);
+    }
+    
+    #[test]
+    fn looks_up_keys() {
+        let bin = LanguagesBuilder::new()
+            .with_label("/Lotus/Language/Menu/Start", "Start")
+            .with_compressed_label("/Lotus/Language/Menu/Quit", "Quit")
+            .build()
+            .unwrap();
+        let (_, dict) = languages_read_header(&bin).unwrap();
+        let decompressor = BundledZstd::new(&dict).unwrap();
+        
+        let found = languages_lookup_keys(&bin, &["/Lotus/Language/Menu/Quit", "/Lotus/Missing"], &decompressor).unwrap();
+        assert_eq!(found, entries(&[("/Lotus/Language/Menu/Quit", "Quit")]));
+        assert_eq!(languages_lookup(&bin, "/Lotus/Missing", &decompressor).unwrap(), None);
+    }
+    
+    #[test]
+    fn pack_round_trips() {
+        let file = LanguageFile {
+            hash: vec![9; MANIFEST_HASH_LEN],
+            suffixes: vec![b"_en".to_vec()],
+            entries: entries(&[
+                ("/Lotus/Language/Menu/Start", "Start"),
+                ("/Lotus/Language/Menu/Sub/Deep/Name", "Nested \"quotes\"\nand \u{e9}"),
+                ("/Lotus/Language/Menu/Empty", ""),
+                ("/Lotus/Language/Menu/", "No label name"),
+                ("NoSlash", "No path"),
+            ]),
+        };
+        
+        let bin = languages_pack(&file, FIXTURE_DICTIONARY).unwrap();
+        let (header, dict) = languages_read_header(&bin).unwrap();
+        assert_eq!(header.hash, file.hash);
+        assert_eq!(header.suffixes, file.suffixes);
+        assert_eq!(dict, FIXTURE_DICTIONARY);
+        
+        let options = UnpackOptions { collect_paths: true, ..Default::default() };
+        let unpacked = unpack(&bin, &options).unwrap();
+        assert_eq!(unpacked.entries, file.entries);
+        assert_eq!(unpacked.raw_labels, 5);
+        // Split at the last '/', so deeper keys get a path of their own
+        assert_eq!(unpacked.paths["/Lotus/Language/Menu/Sub/Deep/"], ["Name"]);
+        assert_eq!(unpacked.paths["/Lotus/Language/Menu/"], ["", "Empty", "Start"]);
+        assert_eq!(unpacked.paths[""], ["NoSlash"]);
+        
+        let repacked = LanguageFile { entries: unpacked.entries, ..header };
+        assert_eq!(languages_pack(&repacked, &dict).unwrap(), bin);
+    }
+    
+    #[test]
+    fn pack_rejects_values_too_long_to_store_raw() {
+        let file = LanguageFile {
+            entries: entries(&[("/Lotus/Long", &"x".repeat(u16::MAX as usize + 1))]),
+            ..Default::default()
+        };
+        assert!(languages_pack(&file, FIXTURE_DICTIONARY).is_err());
+    }
+    
+    #[test]
+    fn rejects_a_truncated_label_table() {
+        let bin = LanguagesBuilder::new()
+            .with_label("/Lotus/Language/Menu/Start", "Start")
+            .build()
+            .unwrap();
+        for len in [bin.len() - 1, bin.len() - 9] {
+            assert!(unpack(&bin[..len], &UnpackOptions::default()).is_err(), "{} bytes", len);
+        }
+    }
+    
+    /// `value` compressed as a magicless frame, the way label values are stored
+    fn compress_magicless(value: &[u8], dict: &[u8]) -> Vec<u8> {
+        let mut compressor = zstd::bulk::Compressor::with_dictionary(3, dict).unwrap();
+        compressor
+            .set_parameter(zstd::zstd_safe::CParameter::Format(zstd::zstd_safe::FrameFormat::Magicless))
+            .unwrap();
+        compressor.compress(value).unwrap()
+    }
+    
+    #[test]
+    fn bundled_zstd_decodes_with_a_raw_content_dictionary() {
+        let value = b"/Lotus/Language/Menu/ the quick brown fox, again and again";
+        let frame = compress_magicless(value, FIXTURE_DICTIONARY);
+        
+        let decompressor = BundledZstd::new(FIXTURE_DICTIONARY).unwrap();
+        assert_eq!(decompressor.decompress(&frame, value.len()).unwrap(), value);
+        // The decoder is reused, so a second value must not see the first one's state
+        assert_eq!(decompressor.decompress(&frame, value.len()).unwrap(), value);
+    }
+    
+    #[test]
+    fn bundled_zstd_decodes_with_a_trained_dictionary() {
+        let samples: Vec<Vec<u8>> = (0..400)
+            .map(|i| format!("/Lotus/Language/Items/Item{}Name: Sword of the {} Realm, level {}", i % 37, i % 11, i).into_bytes())
+            .collect();
+        let dict = zstd::dict::from_samples(&samples, 4096).unwrap();
+        assert!(dict.starts_with(&ZSTD_DICT_MAGIC));
+        
+        let decompressor = BundledZstd::new(&dict).unwrap();
+        for value in [&samples[0], &samples[123], &b"Unrelated text"[..].to_vec()] {
+            let frame = compress_magicless(value, &dict);
+            assert_eq!(&decompressor.decompress(&frame, value.len()).unwrap(), value);
+        }
+    }
+    
+    #[test]
+    fn bundled_zstd_rejects_the_wrong_size() {
+        let value = b"Start the quick brown fox";
+        let frame = compress_magicless(value, FIXTURE_DICTIONARY);
+        let decompressor = BundledZstd::new(FIXTURE_DICTIONARY).unwrap();
+        
+        for dst_len in [0, value.len() - 1, value.len() + 1] {
+            assert!(decompressor.decompress(&frame, dst_len).is_err(), "dst_len {}", dst_len);
+        }
+        assert!(decompressor.decompress(&frame[..frame.len() - 1], value.len()).is_err());
+        assert!(decompressor.decompress(&[frame.as_slice(), &[0]].concat(), value.len()).is_err());
+    }
+    
+    #[test]
+    fn bundled_zstd_rejects_a_truncated_dictionary() {
+        let samples: Vec<Vec<u8>> = (0..400).map(|i| format!("/Lotus/Language/Label{} value {}", i % 29, i).into_bytes()).collect();
+        let dict = zstd::dict::from_samples(&samples, 4096).unwrap();
+        for len in [4, 8, 12, 40] {
+            assert!(BundledZstd::new(&dict[..len]).is_err(), "{} bytes", len);
+        }
+    }
+    
+    #[test]
+    fn rejects_values_above_the_size_limit() {
+        let bin = LanguagesBuilder::new()
+            .with_compressed_label("/Lotus/Language/Menu/Quit", "Quit the game")
+            .build()
+            .unwrap();
+        let options = UnpackOptions { max_value_size: 12, ..Default::default() };
+        assert!(unpack(&bin, &options).is_err());
+        let options = UnpackOptions { max_value_size: 13, ..Default::default() };
+        assert_eq!(unpack(&bin, &options).unwrap().entries, entries(&[("/Lotus/Language/Menu/Quit", "Quit the game")]));
+        
+        // A corrupt prefix claiming u32::MAX bytes is refused before anything is allocated
+        let mut data = pack_u32_dyn_le(u32::MAX);
+        data.extend_from_slice(b"frame");
+        let bin = LanguagesBuilder::new()
+            .with_stored_label("/Lotus/Language/Menu/Huge", &data, 0x200)
+            .build()
+            .unwrap();
+        let error = unpack(&bin, &UnpackOptions::default()).unwrap_err().to_string();
+        assert!(error.contains("/Lotus/Language/Menu/Huge"), "{}", error);
+        assert!(error.contains(&format!("{}-byte limit", DEFAULT_MAX_VALUE_SIZE)), "{}", error);
+    }
+    
+    /// Fails every call, to show a value was never handed to the decompressor
+    struct UnusedDecompressor;
+    
+    impl DictDecompressor for UnusedDecompressor {
+        fn decompress(&self, _src: &[u8], dst_len: usize) -> Result<Vec<u8>> {
+            Err(anyhow!("decompressor called for {} bytes", dst_len))
+        }
+    }
+    
+    #[test]
+    fn empty_compressed_values_skip_the_decompressor() {
+        let mut data = pack_u32_dyn_le(0);
+        data.extend_from_slice(b"not a frame");
+        let bin = LanguagesBuilder::new()
+            .with_compressed_label("/Lotus/Language/Menu/Empty", "")
+            .with_stored_label("/Lotus/Language/Menu/Garbage", &data, 0x200)
+            .build()
+            .unwrap();
+        let options = UnpackOptions { collect_metadata: true, ..Default::default() };
+        
+        let unpacked = languages_unpack(&bin, &UnusedDecompressor, &options).unwrap();
+        assert_eq!(unpacked.entries, entries(&[("/Lotus/Language/Menu/Empty", ""), ("/Lotus/Language/Menu/Garbage", "")]));
+        assert_eq!(unpacked.compressed_labels, 2);
+        assert_eq!(unpacked.metadata["/Lotus/Language/Menu/Empty"].decompressed_size, Some(0));
+        assert_eq!(languages_lookup(&bin, "/Lotus/Language/Menu/Empty", &UnusedDecompressor).unwrap(), Some(String::new()));
+    }
+    
+    /// Strings that trip up writers: quotes, backslashes, line breaks, markup,
+    /// placeholder tokens and characters outside ASCII and the BMP. `/Menu/Title`
+    /// is both a string and a branch once nested.
+    fn tricky_entries() -> BTreeMap<String, String> {
+        entries(&[
+            ("/Menu/Item10", "Press \"Start\" to begin"),
+            ("/Menu/Item2", "Line one\nLine two\r\n\twith a tab"),
+            ("/Menu/Path", "C:\\Soulframe\\|PLAYER_NAME|"),
+            ("/Menu/Greeting", "Bienvenue, {0}! \u{c9}t\u{e9} \u{2014} \u{65e5}\u{672c}\u{8a9e} \u{1f5e1}"),
+            ("/Menu/Markup", "<DT_FIRE>Deals %d damage | *bold* `code` [link]"),
+            ("/Menu/Empty", ""),
+            ("/Menu/Title", "Soulframe"),
+            ("/Menu/Title/Sub", "Subtitle"),
+        ])
+    }
+    
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> Result<usize>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+    
+    #[test]
+    fn language_json_snapshot() {
+        let json = written(|out| write_language_json(&tricky_entries(), out));
+        // insta ignores a final line break, so check there is none
+        assert!(json.ends_with('}'));
+        insta::assert_snapshot!(json);
+    }
+    
+    #[test]
+    fn compact_natural_language_json_snapshot() {
+        let json = written(|out| write_language_json_ordered(&tricky_entries(), KeyOrder::Natural, JsonStyle::Compact, true, out));
+        insta::assert_snapshot!(json);
+    }
+    
+    #[test]
+    fn ascii_escaped_language_json_snapshot() {
+        let json = written(|out| write_language_json_ordered(&tricky_entries(), KeyOrder::Lexicographic, JsonStyle::Pretty, false, out));
+        insta::assert_snapshot!(crate::transform::ascii_escape_json(&json));
+    }
+    
+    #[test]
+    fn language_json_in_file_order_snapshot() {
+        // A key missing from the entries and a repeat are left out
+        let keys: Vec<String> = ["/Menu/Path", "/Menu/Gone", "/Menu/Item2", "/Menu/Path", "/Menu/Greeting"]
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+        let json = written(|out| write_language_json_in_order(&tricky_entries(), &keys, JsonStyle::Pretty, true, out));
+        insta::assert_snapshot!(json);
+    }
+    
+    #[test]
+    fn nested_language_json_snapshot() {
+        let json = written(|out| write_nested_language_json(&tricky_entries(), JsonStyle::Pretty, out));
+        insta::assert_snapshot!(json);
+    }
+    
+    #[test]
+    fn debug_language_json_snapshot() {
+        let bin = LanguagesBuilder::new()
+            .with_label("/Menu/Item10", "Press \"Start\" to begin")
+            .with_label("/Menu/Greeting", "Bienvenue, {0}! \u{c9}t\u{e9} \u{1f5e1}")
+            .with_label("/Menu/Item2", "Line one\nLine two")
+            .build()
+            .unwrap();
+        let unpacked = unpack(&bin, &UnpackOptions { collect_metadata: true, ..Default::default() }).unwrap();
+        let json = written(|out| write_debug_language_json(&unpacked, KeyOrder::Natural, JsonStyle::Pretty, true, out));
+        insta::assert_snapshot!(json);
+    }
+    
+    #[test]
+    fn markdown_changelog_snapshot() {
+        let old = tricky_entries();
+        let mut new = old.clone();
+        new.remove("/Menu/Empty");
+        new.insert("/Menu/Item2".to_string(), "Line one | Line two".to_string());
+        new.insert("/Menu/New_*".to_string(), "Added <b>#1</b> & more".to_string());
+        let path = new.remove("/Menu/Path").unwrap();
+        new.insert("/Settings/Path".to_string(), path);
+        let mut delta = diff_entries(&old, &new);
+        delta.detect_moves(None);
+        
+        let single = BTreeMap::from([("en", &delta)]);
+        let md = markdown_changelog("Changes since `abc123`", &single, 24);
+        assert!(md.ends_with(" |\n"));
+        insta::assert_snapshot!("markdown_changelog_one_locale", md);
+        
+        let unchanged = LocaleDelta::default();
+        let both = BTreeMap::from([("en", &delta), ("fr", &unchanged)]);
+        insta::assert_snapshot!("markdown_changelog_two_locales", markdown_changelog("Changes | 2 locales", &both, 0));
+    }
+    
+    #[test]
+    fn extracts_from_memory_storage() {
+        let bin = LanguagesBuilder::new()
+            .with_label("/Menu/Play", "Play")
+            .with_compressed_label("/Menu/Quit", "Quit the game")
+            .build()
+            .unwrap();
+        let downloads = MemoryStorage::new();
+        downloads.write("0_en/Languages.bin_H", &bin).unwrap();
+        let extracted = MemoryStorage::new();
+        
+        let count = extract_languages_with_storage("en", None, JsonStyle::Compact, &downloads, &extracted).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(extracted.paths(), ["0/Languages/en.json"]);
+        let json = String::from_utf8(extracted.read("0/Languages/en.json").unwrap()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"/Menu/Play":"Play","/Menu/Quit":"Quit the game","__order":["/Menu/Play","/Menu/Quit"]}"#
+        );
+        
+        assert!(extract_languages_with_storage("fr", None, JsonStyle::Compact, &downloads, &extracted).is_err());
+    }
+    
+    #[test]
+    fn path_file_names() {
+        assert_eq!(path_file_name("/Lotus/Language/Menu/"), "Lotus.Language.Menu");
+        assert_eq!(path_file_name("/Lotus/Language/Menu"), "Lotus.Language.Menu~");
+        assert_eq!(path_file_name("Lotus/Menu/"), "~Lotus.Menu");
+        assert_eq!(path_file_name("/Menu Items/\u{e9}:x/"), "Menu%20Items.%C3%A9%3Ax");
+        // Paths with nothing between the slashes still get a file name
+        assert_eq!(path_file_name("//"), "_root");
+        assert_eq!(path_file_name("/"), "_root~");
+        assert_eq!(path_file_name(""), "~_root~");
+    }
+    
+    #[test]
+    fn split_file_names_suffix_collisions() {
+        let names = split_file_names(["/Menu/", "/menu/", "/MENU/", "//", "/_root/", "/Other/"]);
+        assert_eq!(names["/MENU/"], "MENU");
+        assert_eq!(names["/Menu/"], "Menu~2");
+        assert_eq!(names["/menu/"], "menu~3");
+        assert_eq!(names["//"], "_root");
+        assert_eq!(names["/_root/"], "_root~2");
+        assert_eq!(names["/Other/"], "Other");
+        let unique: BTreeSet<String> = names.values().map(|name| name.to_ascii_lowercase()).collect();
+        assert_eq!(unique.len(), names.len());
+    }
+    
+    #[test]
+    fn split_file_names_are_the_same_across_calls() {
+        let paths = ["/b/", "/B/", "/a/", "//", "/_root/", "/A/"];
+        let first = split_file_names(paths);
+        let mut reversed = paths;
+        reversed.reverse();
+        assert_eq!(split_file_names(reversed), first);
+        // Repeats don't use up a suffix
+        assert_eq!(split_file_names(paths.iter().chain(&paths).copied()), first);
+        assert_eq!(first["/A/"], "A");
+        assert_eq!(first["/a/"], "a~2");
+    }
+    
+    #[test]
+    fn detect_moves_pairs_exact_matches() {
+        let old = entries(&[("/Menu/Start", "Start game"), ("/Menu/Blank", ""), ("/Menu/Kept", "Kept")]);
+        let new = entries(&[("/Main/Start", "Start game"), ("/Main/Blank", ""), ("/Menu/Kept", "Kept")]);
+        let mut delta = diff_entries(&old, &new);
+        delta.detect_moves(None);
+        
+        assert_eq!(
+            delta.moved,
+            BTreeMap::from([(
+                "/Menu/Start".to_string(),
+                KeyMove { to: "/Main/Start".to_string(), value: "Start game".to_string(), old_value: None }
+            )])
+        );
+        // Empty strings are not evidence of a move
+        assert_eq!(delta.removed, entries(&[("/Menu/Blank", "")]));
+        assert_eq!(delta.added, entries(&[("/Main/Blank", "")]));
+        assert!(delta.unresolved_moves.is_empty());
+    }
+    
+    #[test]
+    fn detect_moves_fuzzy_threshold() {
+        // 4 shared words of 6 in total: a similarity of 2/3
+        let old = entries(&[("/Menu/Open", "Open the main menu now")]);
+        let new = entries(&[("/Main/Open", "Open the main menu later")]);
+        let detect = |min_similarity| {
+            let mut delta = diff_entries(&old, &new);
+            delta.detect_moves(min_similarity);
+            delta
+        };
+        
+        let above = detect(Some(0.66));
+        assert_eq!(
+            above.moved["/Menu/Open"],
+            KeyMove {
+                to: "/Main/Open".to_string(),
+                value: "Open the main menu later".to_string(),
+                old_value: Some("Open the main menu now".to_string()),
+            }
+        );
+        assert!(above.removed.is_empty() && above.added.is_empty());
+        
+        for below in [detect(Some(0.67)), detect(None)] {
+            assert!(below.moved.is_empty());
+            assert_eq!(below.removed, old);
+            assert_eq!(below.added, new);
+        }
+    }
+    
+    #[test]
+    fn detect_moves_leaves_ambiguous_matches_unresolved() {
+        let old = entries(&[("/Old/A", "Same"), ("/Old/B", "Same"), ("/Old/C", "Twice")]);
+        let new = entries(&[("/New/A", "Same"), ("/New/B", "Same"), ("/New/C", "Twice"), ("/New/D", "Twice")]);
+        let mut delta = diff_entries(&old, &new);
+        delta.detect_moves(Some(0.5));
+        
+        assert!(delta.moved.is_empty());
+        assert!(delta.removed.is_empty() && delta.added.is_empty());
+        assert_eq!(
+            delta.unresolved_moves,
+            [
+                UnresolvedMove {
+                    from: entries(&[("/Old/A", "Same"), ("/Old/B", "Same")]),
+                    to: entries(&[("/New/A", "Same"), ("/New/B", "Same")]),
+                },
+                UnresolvedMove {
+                    from: entries(&[("/Old/C", "Twice")]),
+                    to: entries(&[("/New/C", "Twice"), ("/New/D", "Twice")]),
+                },
+            ]
+        );
+    }
+    
+    const ENGLISH: &str = "Don't press the well-known button - twice!";
+    /// "Start the game" in Japanese, with the katakana length mark
+    const JAPANESE: &str = "\u{30b2}\u{30fc}\u{30e0}\u{3092}\u{958b}\u{59cb}\u{3059}\u{308b}";
+    /// "Start the game" in Chinese, ending in an ideographic full stop
+    const CHINESE: &str = "\u{5f00}\u{59cb}\u{6e38}\u{620f}\u{3002}";
+    const MIXED: &str = "\u{6309} Enter \u{5f00}\u{59cb} v2.0";
+    
+    #[test]
+    fn word_count_splits_by_script() {
+        // Apostrophes and hyphens inside a word join it; a free-standing hyphen is no word
+        assert_eq!(word_count(ENGLISH), 6);
+        assert_eq!(word_count("rock-paper - scissors"), 2);
+        assert_eq!(word_count("'quoted' it\u{2019}s"), 2);
+        // Each Han or kana character is a word; punctuation is not
+        assert_eq!(word_count(JAPANESE), 8);
+        assert_eq!(word_count(CHINESE), 4);
+        // Latin runs between Han characters count once; `.` splits v2.0
+        assert_eq!(word_count(MIXED), 6);
+        assert_eq!(word_count("Level 5\u{30ec}\u{30d9}\u{30eb}"), 5);
+        // Hangul is spaced like Latin text
+        assert_eq!(word_count("\u{ac8c}\u{c784} \u{c2dc}\u{c791}"), 2);
+        assert_eq!(word_count(" - "), 0);
+    }
+    
+    #[test]
+    fn string_stats_by_prefix() {
+        let entries = entries(&[
+            ("/Hud/Start", ENGLISH),
+            ("/Menu/Ja", JAPANESE),
+            ("/Menu/Zh", CHINESE),
+            ("/Menu/Mixed", MIXED),
+        ]);
+        let group = |prefix: &str, keys, characters, words, min_length, median_length, max_length| StringStats {
+            prefix: prefix.to_string(),
+            keys,
+            characters,
+            words,
+            min_length,
+            median_length,
+            max_length,
+        };
+        
+        assert_eq!(string_stats(&entries, None), [group("", 4, 70, 24, 5, 11.5, 42)]);
+        assert_eq!(
+            string_stats(&entries, Some(1)),
+            [group("/Hud", 1, 42, 6, 42, 42.0, 42), group("/Menu", 3, 28, 18, 5, 8.0, 15)]
+        );
+        assert_eq!(string_stats(&BTreeMap::new(), None), [group("", 0, 0, 0, 0, 0.0, 0)]);
+        assert!(string_stats(&BTreeMap::new(), Some(1)).is_empty());
+    }
+}
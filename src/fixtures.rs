@@ -0,0 +1,274 @@
+// Builders for synthetic Languages.bin and manifest `_H` files, so the parsers can
+// be exercised without game data. Wrap the output with `shcc_pack` to get an SHCC
+// container, and decode compressed labels with `BundledZstd`, which needs no
+// shared library. With the `download` feature, `MockCdn` serves such payloads to a
+// `DownloadClient` in place of the CDN. The crate's own unit tests are built on
+// these, so the module is compiled for tests as well as with the feature.
+
+use anyhow::{anyhow, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+use crate::manifest::ManifestEntry;
+use crate::extract::{write_s4, LANG_MAGIC_1, LANG_MAGIC_2, LANG_MAGIC_3};
+use crate::*;
+use std::collections::BTreeMap;
+#[cfg(feature = "download")]
+use crate::download::{HttpResponse, HttpTransport};
+#[cfg(feature = "download")]
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "download")]
+use std::sync::{Arc, Mutex};
+
+/// Dictionary embedded by `LanguagesBuilder` unless another one is set. ZSTD takes
+/// any bytes without the dictionary magic as a raw-content dictionary.
+pub const FIXTURE_DICTIONARY: &[u8] = b"/Lotus/Language/Menu/ Soulframe fixture dictionary: the quick brown fox";
+
+enum FixtureValue {
+    Raw(String),
+    Compressed(String),
+    Stored(Vec<u8>, u16),
+}
+
+struct FixtureLabel {
+    key: String,
+    value: FixtureValue,
+}
+
+/// Builds a Languages.bin `_H` file: hash, magic words, suffixes, dictionary and a
+/// path table whose labels are stored raw or ZSTD-compressed (flag `0x200`)
+pub struct LanguagesBuilder {
+    hash: [u8; MANIFEST_HASH_LEN],
+    suffixes: Vec<Vec<u8>>,
+    dictionary: Vec<u8>,
+    labels: Vec<FixtureLabel>,
+}
+
+impl Default for LanguagesBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguagesBuilder {
+    pub fn new() -> Self {
+        Self {
+            hash: [0; MANIFEST_HASH_LEN],
+            suffixes: Vec::new(),
+            dictionary: FIXTURE_DICTIONARY.to_vec(),
+            labels: Vec::new(),
+        }
+    }
+    
+    pub fn with_hash(mut self, hash: [u8; MANIFEST_HASH_LEN]) -> Self {
+        self.hash = hash;
+        self
+    }
+    
+    pub fn with_suffix(mut self, suffix: &[u8]) -> Self {
+        self.suffixes.push(suffix.to_vec());
+        self
+    }
+    
+    pub fn with_dictionary(mut self, dictionary: &[u8]) -> Self {
+        self.dictionary = dictionary.to_vec();
+        self
+    }
+    
+    /// Adds a label stored as-is. `key` is split into path and name at its last `/`.
+    pub fn with_label(mut self, key: &str, value: &str) -> Self {
+        self.labels.push(FixtureLabel { key: key.to_string(), value: FixtureValue::Raw(value.to_string()) });
+        self
+    }
+    
+    /// Adds a label compressed as a magicless ZSTD frame with the dictionary
+    pub fn with_compressed_label(mut self, key: &str, value: &str) -> Self {
+        self.labels.push(FixtureLabel { key: key.to_string(), value: FixtureValue::Compressed(value.to_string()) });
+        self
+    }
+    
+    /// Adds a label whose bytes and flags are written exactly as given, for labels
+    /// no valid encoder would produce
+    pub fn with_stored_label(mut self, key: &str, data: &[u8], flags: u16) -> Self {
+        self.labels.push(FixtureLabel { key: key.to_string(), value: FixtureValue::Stored(data.to_vec(), flags) });
+        self
+    }
+    
+    /// The `_H` bytes. Paths appear in sorted order, labels in the order added.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let mut out = self.hash.to_vec();
+        out.write_u32::<LittleEndian>(LANG_MAGIC_1)?;
+        out.write_u32::<LittleEndian>(LANG_MAGIC_2)?;
+        out.write_u32::<LittleEndian>(LANG_MAGIC_3)?;
+        
+        out.write_u32::<LittleEndian>(self.suffixes.len() as u32)?;
+        for suffix in &self.suffixes {
+            write_s4(&mut out, suffix)?;
+        }
+        write_s4(&mut out, &self.dictionary)?;
+        
+        let mut compressor = zstd::bulk::Compressor::with_dictionary(3, &self.dictionary)?;
+        compressor.set_parameter(zstd::zstd_safe::CParameter::Format(zstd::zstd_safe::FrameFormat::Magicless))?;
+        
+        let mut paths: BTreeMap<&str, Vec<&FixtureLabel>> = BTreeMap::new();
+        for label in &self.labels {
+            let split = label.key.rfind('/').map_or(0, |i| i + 1);
+            paths.entry(&label.key[..split]).or_default().push(label);
+        }
+        
+        out.write_u32::<LittleEndian>(paths.len() as u32)?;
+        for (path, labels) in &paths {
+            write_s4(&mut out, path.as_bytes())?;
+            
+            let mut chunk = Vec::new();
+            let mut table = Vec::new();
+            for label in labels {
+                let (data, flags) = match &label.value {
+                    FixtureValue::Raw(value) => (value.as_bytes().to_vec(), 0),
+                    FixtureValue::Compressed(value) => {
+                        let mut data = pack_u32_dyn_le(value.len() as u32);
+                        data.extend(compressor.compress(value.as_bytes())?);
+                        (data, 0x200u16)
+                    }
+                    FixtureValue::Stored(data, flags) => (data.clone(), *flags),
+                };
+                let size = u16::try_from(data.len())
+                    .map_err(|_| anyhow!("Value for {} is too long for a label", label.key))?;
+                
+                write_s4(&mut table, &label.key.as_bytes()[path.len()..])?;
+                table.write_u32::<LittleEndian>(chunk.len() as u32)?;
+                table.write_u16::<LittleEndian>(size)?;
+                table.write_u16::<LittleEndian>(flags)?;
+                chunk.extend(data);
+            }
+            
+            write_s4(&mut out, &chunk)?;
+            out.write_u32::<LittleEndian>(labels.len() as u32)?;
+            out.extend_from_slice(&table);
+        }
+        
+        Ok(out)
+    }
+}
+
+/// Builds a manifest `_H` file in the current layout: the header hash and 4 unknown
+/// bytes, then groups of entries each preceded by their count
+#[derive(Default)]
+pub struct ManifestBuilder {
+    hash: [u8; MANIFEST_HASH_LEN],
+    groups: Vec<Vec<ManifestEntry>>,
+}
+
+impl ManifestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn with_hash(mut self, hash: [u8; MANIFEST_HASH_LEN]) -> Self {
+        self.hash = hash;
+        self
+    }
+    
+    /// Adds a group of entries; an empty group writes a zero count
+    pub fn with_group(mut self, entries: Vec<ManifestEntry>) -> Self {
+        self.groups.push(entries);
+        self
+    }
+    
+    /// Adds `path` with `hash` and zeroed unknown bytes to the last group, starting
+    /// one if there is none
+    pub fn with_entry(mut self, path: &str, hash: [u8; MANIFEST_HASH_LEN]) -> Self {
+        if self.groups.is_empty() {
+            self.groups.push(Vec::new());
+        }
+        self.groups.last_mut().unwrap().push(ManifestEntry {
+            path: path.to_string(),
+            hash: hash.to_vec(),
+            unk: [0; MANIFEST_UNK_LEN],
+        });
+        self
+    }
+    
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let mut out = self.hash.to_vec();
+        out.resize(MANIFEST_HEADER_SKIP, 0);
+        
+        for group in &self.groups {
+            out.write_u32::<LittleEndian>(group.len() as u32)?;
+            for entry in group {
+                if entry.hash.len() != MANIFEST_HASH_LEN {
+                    return Err(anyhow!("Hash of {} is not {} bytes", entry.path, MANIFEST_HASH_LEN));
+                }
+                write_s4(&mut out, entry.path.as_bytes())?;
+                out.extend_from_slice(&entry.hash);
+                out.extend_from_slice(&entry.unk);
+            }
+        }
+        
+        Ok(out)
+    }
+}
+
+/// Wraps the `_H` contents `h` as the CDN serves them: the SHCC hash is written
+/// over its first `MANIFEST_HASH_LEN` bytes, as in a real download, and the result
+/// packed with `shcc_pack`. Returns the payload and the hash, which is what a
+/// manifest entry for it lists.
+pub fn cdn_payload(mut h: Vec<u8>) -> Result<(Vec<u8>, [u8; MANIFEST_HASH_LEN])> {
+    if h.len() < MANIFEST_HASH_LEN {
+        return Err(anyhow!("_H data is shorter than its {}-byte hash", MANIFEST_HASH_LEN));
+    }
+    let hash = shcc_hash(&ShccData { h: h.clone(), b: None, b_raw: None });
+    h[..MANIFEST_HASH_LEN].copy_from_slice(&hash);
+    let payload = shcc_pack(&ShccData { h, b: None, b_raw: None })?;
+    Ok((payload, hash.try_into().expect("MD5 is MANIFEST_HASH_LEN bytes")))
+}
+
+/// Status and body queued for one URL
+#[cfg(feature = "download")]
+type CannedResponses = VecDeque<(u16, Vec<u8>)>;
+
+/// `HttpTransport` serving canned responses, to run `DownloadClient` without a
+/// network. Each URL answers with its queued responses in order, repeating the last
+/// one; URLs without any get a 404. Clones share their responses and request log,
+/// so keep one to inspect the requests made by a client given another.
+#[cfg(feature = "download")]
+#[derive(Clone, Default)]
+pub struct MockCdn {
+    responses: Arc<Mutex<HashMap<String, CannedResponses>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+#[cfg(feature = "download")]
+impl MockCdn {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Queues a response with `status` and `body` for `url`
+    pub fn with_response(self, url: &str, status: u16, body: Vec<u8>) -> Self {
+        self.responses.lock().unwrap().entry(url.to_string()).or_default().push_back((status, body));
+        self
+    }
+    
+    /// Every URL requested so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(feature = "download")]
+impl HttpTransport for MockCdn {
+    fn get(&self, url: &str, _max_size: u64) -> Result<HttpResponse> {
+        self.requests.lock().unwrap().push(url.to_string());
+        
+        let mut responses = self.responses.lock().unwrap();
+        let (status, body) = match responses.get_mut(url) {
+            Some(queue) if queue.len() > 1 => queue.pop_front().unwrap(),
+            Some(queue) => queue.front().cloned().unwrap_or((404, Vec::new())),
+            None => (404, Vec::new()),
+        };
+        Ok(HttpResponse {
+            status: reqwest::StatusCode::from_u16(status)?,
+            headers: reqwest::header::HeaderMap::new(),
+            body,
+        })
+    }
+}
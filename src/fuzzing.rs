@@ -0,0 +1,44 @@
+// Entry points for the cargo-fuzz targets in fuzz/. They run the parsers over
+// arbitrary bytes without the Oodle or ZSTD shared libraries; any panic is a bug,
+// since malformed input must come back as an Err.
+
+use anyhow::Result;
+use crate::extract::scan_labels;
+use crate::*;
+use std::ops::ControlFlow;
+
+/// Stands in for Oodle: copies the block's compressed bytes into the output,
+/// zero-filling or truncating to the expected size
+pub struct PassthroughDecompressor;
+
+impl BlockDecompressor for PassthroughDecompressor {
+    fn decompress_into(&self, compressed: &[u8], output: &mut [u8]) -> Result<()> {
+        let len = compressed.len().min(output.len());
+        output[..len].copy_from_slice(&compressed[..len]);
+        output[len..].fill(0);
+        Ok(())
+    }
+}
+
+/// Runs the buffered and streaming SHCC unpackers and the inspector over `data`
+pub fn fuzz_shcc(data: &[u8]) {
+    let decompressor = PassthroughDecompressor;
+    
+    if let Ok(unpacked) = shcc_unpack(data, &decompressor) {
+        let _ = shcc_hash(&unpacked);
+    }
+    let _ = shcc_unpack_to(data, &decompressor, &mut std::io::sink(), &mut std::io::sink());
+    let _ = shcc_inspect(data);
+    let _ = sniff_payload(data);
+}
+
+/// Parses the Languages.bin header and label table, including the dyn-u32 size
+/// prefix of compressed labels, without decompressing any values
+pub fn fuzz_languages(data: &[u8]) {
+    let _ = scan_labels(data, &mut |label| {
+        if label.flags & 0x200 != 0 {
+            unpack_u32_dyn_le(label.data, 0)?;
+        }
+        Ok(ControlFlow::Continue(()))
+    });
+}
@@ -1,18 +1,149 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use base64::prelude::*;
 use libloading::{Library, Symbol};
+use rayon::prelude::*;
 use std::ffi::{c_char, c_int, c_void};
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{collections::HashSet, env};
+use tracing::{debug, info, trace, warn};
 
 // This library provides core functionality that can be used by the binaries
-// For now, we'll keep it minimal to avoid import issues
+
+pub mod bundle;
+pub mod cache;
+pub mod clean;
+pub mod codegen;
+#[cfg(feature = "download")]
+pub mod download;
+pub mod error;
+pub mod extract;
+pub mod locales;
+pub mod lock;
+pub mod logging;
+pub mod manifest;
+pub mod output;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod status;
+pub mod storage;
+pub mod transform;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+#[cfg(any(test, feature = "fixtures"))]
+pub mod fixtures;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+
+pub use error::SoulframeError;
 
 // Manifest type IDs changed with Soulframe 40.0.0 (Pluto tool uses 0xE for 40+).
 pub const TYPE_MANIFEST: u8 = 0xE;
 pub const TYPE_BIN: u8 = 0x2C;
 
-pub fn find_runtime_lib(lib_filename: &str) -> Result<PathBuf> {
+// Manifest `_H` layout: a header, then groups of entries, each entry being a
+// length-prefixed path followed by an MD5 hash and 4 bytes of unknown data.
+pub const MANIFEST_HEADER_SKIP: usize = 20;
+pub const MANIFEST_HASH_LEN: usize = 16;
+pub const MANIFEST_UNK_LEN: usize = 4;
+
+// Type ID of manifests before Soulframe 40.0.0; `.bin` files kept `TYPE_BIN`.
+pub const LEGACY_TYPE_MANIFEST: u8 = 0x3;
+
+/// Manifest layout and type IDs, which changed with Soulframe 40.0.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ManifestFormat {
+    /// 40.0.0 and later: `TYPE_MANIFEST` and a `MANIFEST_HEADER_SKIP`-byte header
+    #[default]
+    Current,
+    /// Builds before 40.0.0, as found in archived CDN dumps. The header length
+    /// differs and is detected from the first entry group.
+    Legacy,
+}
+
+impl ManifestFormat {
+    /// Format used by game version `version` (`40`, `40.0.0`, `39.5.1`, ...)
+    pub fn from_game_version(version: &str) -> Result<Self, SoulframeError> {
+        let major: u32 = version
+            .split('.')
+            .next()
+            .and_then(|major| major.trim().parse().ok())
+            .ok_or_else(|| anyhow!("Invalid game version '{}', expected e.g. 40.0.0", version))?;
+        Ok(if major < 40 { Self::Legacy } else { Self::Current })
+    }
+    
+    /// Type byte requested for manifests
+    pub fn type_manifest(self) -> u8 {
+        match self {
+            Self::Current => TYPE_MANIFEST,
+            Self::Legacy => LEGACY_TYPE_MANIFEST,
+        }
+    }
+    
+    /// Type byte requested for `.bin` files
+    pub fn type_bin(self) -> u8 {
+        TYPE_BIN
+    }
+    
+    /// Offset of the first entry group in the manifest `_H` file `bin`, or `None`
+    /// if no plausible group was found
+    pub fn header_len(self, bin: &[u8]) -> Option<usize> {
+        match self {
+            Self::Current => (bin.len() >= MANIFEST_HEADER_SKIP).then_some(MANIFEST_HEADER_SKIP),
+            Self::Legacy => (0..=64).find(|&i| looks_like_entry_group(bin, i)),
+        }
+    }
+}
+
+/// Whether `bin[i..]` starts with an entry count followed by an entry whose path
+/// begins with `/`
+fn looks_like_entry_group(bin: &[u8], i: usize) -> bool {
+    let read_u32 = |at: usize| Some(u32::from_le_bytes(bin.get(at..at + 4)?.try_into().ok()?) as usize);
+    let (Some(count), Some(path_len)) = (read_u32(i), read_u32(i + 4)) else {
+        return false;
+    };
+    (1..=1 << 20).contains(&count)
+        && (1..=4096).contains(&path_len)
+        && bin.get(i + 8) == Some(&b'/')
+        && i + 8 + path_len + MANIFEST_HASH_LEN + MANIFEST_UNK_LEN <= bin.len()
+}
+
+// Platform token in localized manifest names, e.g. `/B.Cache.Windows_en.bin`.
+pub const DEFAULT_PLATFORM: &str = "Windows";
+
+/// Path of the localized manifest for `lang` on `platform`
+pub fn localized_manifest_path(platform: &str, lang: &str) -> String {
+    format!("/B.Cache.{}_{}.bin", platform, lang)
+}
+
+/// Local folder suffix for `lang`'s files on `platform`: `_<lang>` for the default
+/// platform, so existing downloads stay put, and `_<lang>_<platform>` otherwise.
+/// The CDN URL always uses `_<lang>`.
+pub fn locale_suffix(lang: &str, platform: &str) -> String {
+    if platform == DEFAULT_PLATFORM {
+        format!("_{}", lang)
+    } else {
+        format!("_{}_{}", lang, platform)
+    }
+}
+
+/// Platform tokens of the `/B.Cache.<platform>_<lang>.bin` entries in `paths`
+pub fn manifest_platforms(paths: &[String]) -> Vec<String> {
+    let mut platforms: Vec<String> = paths
+        .iter()
+        .filter_map(|p| p.strip_prefix("/B.Cache.")?.strip_suffix(".bin")?.split_once('_'))
+        .map(|(platform, _)| platform.to_string())
+        .collect();
+    platforms.sort();
+    platforms.dedup();
+    platforms
+}
+
+pub fn find_runtime_lib(lib_filename: &str) -> Result<PathBuf, SoulframeError> {
     let mut candidates: Vec<PathBuf> = Vec::new();
 
     if let Ok(dir) = env::var("SOULFRAME_LIB_DIR") {
@@ -49,51 +180,163 @@ pub fn find_runtime_lib(lib_filename: &str) -> Result<PathBuf> {
         }
     }
 
-    let attempted = candidates
-        .into_iter()
-        .map(|p| format!("  - {}", p.display()))
-        .collect::<Vec<_>>()
-        .join("\n");
+    Err(SoulframeError::LibraryNotFound {
+        name: lib_filename.to_string(),
+        searched: candidates,
+    })
+}
 
-    Err(anyhow!(
-        "Missing required runtime library {lib_filename}. Tried:\n{attempted}\n\
-Set SOULFRAME_LIB_DIR to a folder containing the DLL/SO, or place it in ./lib/ next to the executable."
-    ))
+/// Wall-clock time and bytes processed, accumulated per named phase
+#[derive(Debug, Default)]
+pub struct PhaseTimings {
+    phases: Vec<(String, Duration, u64)>,
 }
 
-pub fn get_download_path(path: &str, suffix: Option<&str>) -> PathBuf {
-    let suffix = suffix.unwrap_or("");
+impl PhaseTimings {
+    pub fn record(&mut self, phase: &str, elapsed: Duration, bytes: u64) {
+        match self.phases.iter_mut().find(|(name, _, _)| name == phase) {
+            Some((_, total, total_bytes)) => {
+                *total += elapsed;
+                *total_bytes += bytes;
+            }
+            None => self.phases.push((phase.to_string(), elapsed, bytes)),
+        }
+    }
+    
+    pub fn print(&self) {
+        info!("\nTimings:");
+        for (name, elapsed, bytes) in &self.phases {
+            info!("  {:<28} {:>8.2}s {:>14} bytes", name, elapsed.as_secs_f64(), bytes);
+        }
+    }
+}
+
+pub fn get_download_path(path: &str, suffix: Option<&str>) -> Result<PathBuf> {
     let root = std::env::current_dir().unwrap();
-    root.join("downloaded-data").join(format!("0{}{}", suffix, path))
+    safe_join(&root.join("downloaded-data"), &download_key(path, suffix))
 }
 
-pub fn get_extract_path(path: &str, suffix: Option<&str>) -> PathBuf {
+/// `Storage` path of a downloaded file, the part of `get_download_path` below
+/// `downloaded-data`
+pub fn download_key(path: &str, suffix: Option<&str>) -> String {
+    format!("0{}{}", suffix.unwrap_or(""), path)
+}
+
+/// Path requested from the CDN for a file: `/0<suffix><path>!<type>_<b64m_hash>`,
+/// with the type byte in uppercase hex, e.g. `/0_en/Languages.bin!2C_<hash>`. A
+/// `path` without a leading `/` gets one.
+pub fn build_request_path(path: &str, file_type: u8, b64m_hash: &str, suffix: Option<&str>) -> String {
+    let slash = if path.starts_with('/') { "" } else { "/" };
+    format!("/0{}{}{}!{:X}_{}", suffix.unwrap_or(""), slash, path, file_type, b64m_hash)
+}
+
+pub fn get_extract_path(path: &str, suffix: Option<&str>) -> Result<PathBuf> {
     let suffix = suffix.unwrap_or("");
     let root = std::env::current_dir().unwrap();
-    root.join("extracted-data").join(format!("0{}{}", suffix, path))
+    safe_join(&root.join("extracted-data"), &format!("0{}{}", suffix, path))
+}
+
+/// Size of the `_B` file next to the manifest downloaded to `local_path`, if it is
+/// non-empty. Manifest entries are only read from `_H`; no manifest seen so far has
+/// had a B chunk, but entries spilling into one would be missed, so the loaders
+/// warn when this is `Some`.
+pub fn manifest_b_len(local_path: &Path) -> Option<u64> {
+    let b_path = format!("{}_B", local_path.to_string_lossy());
+    fs::metadata(b_path).ok().map(|m| m.len()).filter(|len| *len > 0)
+}
+
+/// Prints a warning if the manifest `path`, downloaded to `local_path`, has a
+/// `_B` file that is being ignored
+pub fn warn_ignored_manifest_b(path: &str, local_path: &Path) {
+    if let Some(len) = manifest_b_len(local_path) {
+        warn_manifest_b_len(path, len);
+    }
+}
+
+pub(crate) fn warn_manifest_b_len(path: &str, len: u64) {
+    warn!(
+        "Warning: {} has a {}-byte B chunk that is not parsed; any manifest entries in it are missed",
+        path, len
+    );
+}
+
+/// Joins a `/`-separated path taken from a manifest (or built from a locale) onto
+/// `root`. Paths that could resolve outside `root` are rejected rather than
+/// normalized: `..` segments, backslashes, drive letters or other `:` prefixes, and
+/// NUL bytes. Empty and `.` segments are skipped, so a leading `/` stays relative.
+pub fn safe_join(root: &Path, relative: &str) -> Result<PathBuf> {
+    if relative.contains('\0') {
+        return Err(anyhow!("Refusing path with a NUL byte: {:?}", relative));
+    }
+    if relative.contains('\\') {
+        return Err(anyhow!("Refusing path with a backslash: {:?}", relative));
+    }
+    
+    let mut joined = root.to_path_buf();
+    for segment in relative.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return Err(anyhow!("Refusing path with a '..' segment: {:?}", relative)),
+            _ if segment.contains(':') => {
+                return Err(anyhow!("Refusing path with a drive or stream prefix: {:?}", relative));
+            }
+            _ => joined.push(segment),
+        }
+    }
+    
+    Ok(joined)
+}
+
+/// Hash requested when the real one isn't known yet, e.g. for the primary manifest,
+/// which lists every other hash but not its own. It decodes to 16 `0xFF` bytes
+/// rather than a real hash, and the CDN answers it with the current version of the
+/// path instead of a specific one, so such downloads can't be verified.
+pub const DEFAULT_B64M_HASH: &str = "---------------------w";
+
+/// Whether `b64m_hash` is `DEFAULT_B64M_HASH` rather than a hash to verify against
+pub fn is_default_b64m_hash(b64m_hash: &str) -> bool {
+    b64m_hash == DEFAULT_B64M_HASH
 }
 
 pub fn b64m_encode(data: &[u8]) -> String {
     BASE64_STANDARD_NO_PAD.encode(data).replace('/', "-")
 }
 
-pub fn b64m_decode(data: &str) -> Result<Vec<u8>> {
+pub fn b64m_decode(data: &str) -> Result<Vec<u8>, SoulframeError> {
     let normalized = data.replace('-', "/");
-    BASE64_STANDARD_NO_PAD.decode(normalized).map_err(|e| anyhow!("Base64 decode error: {}", e))
+    BASE64_STANDARD_NO_PAD
+        .decode(normalized)
+        .map_err(|e| SoulframeError::Other(anyhow!("Base64 decode error: {}", e)))
+}
+
+/// Signature of `OodleLZ_Decompress`
+type OodleDecompressFn = unsafe extern "C" fn(
+    *const c_char, usize, *mut c_void, usize,
+    c_int, c_int, c_int, usize, usize, usize, usize, usize, usize, c_int
+) -> c_int;
+
+/// Decompresses the Oodle blocks inside SHCC chunks. Implemented by `Oodle`; other
+/// implementations let the SHCC parser run without the shared library.
+pub trait BlockDecompressor: Sync {
+    /// Decompresses `compressed` into `output`, which must be exactly the decompressed size
+    fn decompress_into(&self, compressed: &[u8], output: &mut [u8]) -> Result<()>;
 }
 
-/// Oodle compression library interface
+/// Oodle compression library interface.
+///
+/// `Oodle` is `Send + Sync`. It only holds the loaded library and a function
+/// pointer, and `OodleLZ_Decompress` keeps no global state: called without a
+/// decoder memory buffer it allocates its scratch space per call, so concurrent
+/// calls on distinct buffers are independent. One instance can be shared by every
+/// worker thread.
 pub struct Oodle {
     #[allow(dead_code)]
     lib: Library,
-    decompress_fn: Symbol<'static, unsafe extern "C" fn(
-        *const c_char, usize, *mut c_void, usize,
-        c_int, c_int, c_int, usize, usize, usize, usize, usize, usize, c_int
-    ) -> c_int>,
+    decompress_fn: Symbol<'static, OodleDecompressFn>,
 }
 
 impl Oodle {
-    pub fn new() -> Result<Self> {
+    pub fn new() -> Result<Self, SoulframeError> {
         let lib_name = if cfg!(windows) {
             "oo2core_9.dll"
         } else {
@@ -103,14 +346,16 @@ impl Oodle {
         let lib_path = find_runtime_lib(lib_name)?;
         
         unsafe {
-            let lib = Library::new(&lib_path)
-                .map_err(|e| anyhow!("Failed to load Oodle library from {:?}: {}", lib_path, e))?;
+            let lib = Library::new(&lib_path).map_err(|e| SoulframeError::LibraryLoad {
+                name: lib_name.to_string(),
+                message: format!("{:?}: {}", lib_path, e),
+            })?;
             
-            let decompress_fn: Symbol<unsafe extern "C" fn(
-                *const c_char, usize, *mut c_void, usize,
-                c_int, c_int, c_int, usize, usize, usize, usize, usize, usize, c_int
-            ) -> c_int> = lib.get(b"OodleLZ_Decompress\0")
-                .map_err(|e| anyhow!("Failed to get OodleLZ_Decompress function: {}", e))?;
+            let decompress_fn: Symbol<OodleDecompressFn> = lib.get(b"OodleLZ_Decompress\0")
+                .map_err(|e| SoulframeError::LibraryLoad {
+                    name: lib_name.to_string(),
+                    message: format!("no OodleLZ_Decompress function: {}", e),
+                })?;
             
             // Extend the lifetime to 'static - this is safe because we keep the library alive
             let decompress_fn: Symbol<'static, _> = std::mem::transmute(decompress_fn);
@@ -119,8 +364,16 @@ impl Oodle {
         }
     }
     
-    pub fn decompress(&self, compressed: &[u8], decompressed_size: usize) -> Result<Vec<u8>> {
+    pub fn decompress(&self, compressed: &[u8], decompressed_size: usize) -> Result<Vec<u8>, SoulframeError> {
         let mut output = vec![0u8; decompressed_size];
+        self.decompress_into(compressed, &mut output)?;
+        Ok(output)
+    }
+    
+    /// Decompresses into `output`, which must be exactly the decompressed size.
+    /// Calls are independent, so this may be used from several threads at once.
+    pub fn decompress_into(&self, compressed: &[u8], output: &mut [u8]) -> Result<(), SoulframeError> {
+        let decompressed_size = output.len();
         
         unsafe {
             let result = (self.decompress_fn)(
@@ -132,11 +385,28 @@ impl Oodle {
             );
             
             if result as usize != decompressed_size {
-                return Err(anyhow!("Oodle decompression failed"));
+                return Err(SoulframeError::OodleDecompress {
+                    expected: decompressed_size,
+                    actual: result as i64,
+                });
             }
         }
         
-        Ok(output)
+        Ok(())
+    }
+}
+
+// The parallel download and extract paths share these across threads; fail the
+// build rather than lose that if a field ever makes them thread-bound
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Oodle>();
+    assert_send_sync::<extract::Zstd>();
+};
+
+impl BlockDecompressor for Oodle {
+    fn decompress_into(&self, compressed: &[u8], output: &mut [u8]) -> Result<()> {
+        Ok(Oodle::decompress_into(self, compressed, output)?)
     }
 }
 
@@ -147,101 +417,426 @@ pub struct ShccData {
     pub b_raw: Option<Vec<u8>>,
 }
 
-pub fn shcc_decompress_chunk_oodle(bin: &[u8], start: usize, decompressed_size: usize, oodle: &Oodle) -> Result<(Vec<u8>, usize)> {
+/// Receives decompressed data a block at a time
+pub type ChunkSink<'a> = dyn FnMut(&[u8]) -> Result<()> + 'a;
+
+/// Sizes and `shcc_hash` result produced by `shcc_unpack_to`
+#[derive(Debug, Clone)]
+pub struct ShccSummary {
+    pub h_len: usize,
+    /// `None` when there is no B chunk or it failed to decode
+    pub b_len: Option<usize>,
+    pub hash: Vec<u8>,
+}
+
+/// What went wrong while parsing an SHCC container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShccErrorKind {
+    /// Shorter than the 8-byte prelude
+    TooShort,
+    /// Fewer than 9 bytes left for a chunk header
+    TruncatedChunkHeader,
+    /// A raw chunk whose compressed and decompressed sizes differ
+    RawSizeMismatch { compressed: usize, decompressed: usize },
+    /// Chunk data runs to `end`, past the end of the data
+    TruncatedChunk { end: usize, available: usize },
+    UnknownChunkType { chunk_type: u8, decompressed: usize, compressed: usize },
+    /// Fewer than 8 bytes left for an Oodle block header
+    TruncatedBlockHeader,
+    /// Oodle block header not starting with 0x80
+    InvalidBlockHeader,
+    /// Oodle block header whose last byte's low nibble isn't 1
+    InvalidBlockFooter,
+    /// Oodle block data not starting with 0x8C
+    InvalidBlockMarker,
+    /// Oodle block data runs to `end`, past the end of the data
+    BlockOverrun { end: usize, available: usize },
+    /// An Oodle block that would take the chunk to `end` bytes, past its declared
+    /// decompressed size
+    BlockOvershoot { end: usize, expected: usize },
+    /// The decompressor rejected the data
+    Decompression(String),
+}
+
+impl std::fmt::Display for ShccErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShccErrorKind::TooShort => write!(f, "SHCC data too short"),
+            ShccErrorKind::TruncatedChunkHeader => write!(f, "Not enough data for SHCC chunk header"),
+            ShccErrorKind::RawSizeMismatch { compressed, decompressed } => write!(
+                f,
+                "Compressed size mismatch for uncompressed chunk ({} != {})",
+                compressed, decompressed
+            ),
+            ShccErrorKind::TruncatedChunk { end, available } => write!(
+                f,
+                "Chunk data ends at {}, past the end of data at {}",
+                end, available
+            ),
+            ShccErrorKind::UnknownChunkType { chunk_type, decompressed, compressed } => write!(
+                f,
+                "Unknown SHCC chunk type 0x{:02X} (decompressed size {}, compressed size {})",
+                chunk_type, decompressed, compressed
+            ),
+            ShccErrorKind::TruncatedBlockHeader => write!(f, "Unexpected end of data in SHCC Oodle chunk"),
+            ShccErrorKind::InvalidBlockHeader => write!(f, "Invalid block header"),
+            ShccErrorKind::InvalidBlockFooter => write!(f, "Invalid block footer"),
+            ShccErrorKind::InvalidBlockMarker => write!(f, "Invalid Oodle block marker"),
+            ShccErrorKind::BlockOverrun { end, available } => write!(
+                f,
+                "Block compressed size exceeds available data (ends at {}, data ends at {})",
+                end, available
+            ),
+            ShccErrorKind::BlockOvershoot { end, expected } => write!(
+                f,
+                "Block decompresses past the end of the chunk ({} of {} bytes)",
+                end, expected
+            ),
+            ShccErrorKind::Decompression(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// SHCC parse error with the absolute byte offset it was found at, the chunk and
+/// Oodle block it belongs to, and the bytes at that offset.
+///
+/// The public unpacking functions return it as `SoulframeError::Shcc`; lower-level
+/// functions return it inside `anyhow::Error`, where `downcast_ref::<ShccError>()`
+/// recovers it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShccError {
+    pub kind: ShccErrorKind,
+    pub offset: usize,
+    /// 0 for H, 1 for B; `None` when not known to the function that failed
+    pub chunk: Option<usize>,
+    pub block: Option<usize>,
+    /// Up to 16 bytes starting at `offset`
+    pub bytes: Vec<u8>,
+}
+
+impl ShccError {
+    pub fn new(kind: ShccErrorKind, bin: &[u8], offset: usize) -> Self {
+        let start = offset.min(bin.len());
+        let end = start.saturating_add(16).min(bin.len());
+        Self {
+            kind,
+            offset,
+            chunk: None,
+            block: None,
+            bytes: bin[start..end].to_vec(),
+        }
+    }
+    
+    fn in_block(mut self, block: usize) -> Self {
+        self.block = Some(block);
+        self
+    }
+}
+
+impl std::fmt::Display for ShccError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)?;
+        match (self.chunk, self.block) {
+            (Some(chunk), Some(block)) => write!(f, " (chunk {}, block {})", chunk, block)?,
+            (Some(chunk), None) => write!(f, " (chunk {})", chunk)?,
+            (None, Some(block)) => write!(f, " (block {})", block)?,
+            (None, None) => {}
+        }
+        let bytes = self.bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+        write!(f, "; bytes: {}", bytes)
+    }
+}
+
+impl std::error::Error for ShccError {}
+
+/// Tags an `ShccError` inside `err` with the index of the chunk being decoded
+fn in_chunk(mut err: anyhow::Error, chunk: usize) -> anyhow::Error {
+    if let Some(shcc_err) = err.downcast_mut::<ShccError>() {
+        shcc_err.chunk = Some(chunk);
+    }
+    err
+}
+
+pub fn shcc_decompress_chunk_oodle(bin: &[u8], start: usize, decompressed_size: usize, oodle: &dyn BlockDecompressor) -> Result<(Vec<u8>, usize)> {
     let mut decompressed = Vec::new();
+    let i = shcc_decompress_chunk_oodle_to(bin, start, decompressed_size, oodle, &mut |block| {
+        decompressed.extend_from_slice(block);
+        Ok(())
+    })?;
+    
+    Ok((decompressed, i))
+}
+
+/// Like `shcc_decompress_chunk_oodle`, but hands each block to `sink` as soon as it
+/// is decompressed instead of collecting the chunk. Returns the end offset.
+pub fn shcc_decompress_chunk_oodle_to(bin: &[u8], start: usize, decompressed_size: usize, oodle: &dyn BlockDecompressor, sink: &mut ChunkSink) -> Result<usize> {
+    let (blocks, end) = oodle_block_spans(bin, start, decompressed_size)?;
+    let error = |e: anyhow::Error, block: &OodleBlockSpan| {
+        ShccError::new(ShccErrorKind::Decompression(e.to_string()), bin, block.input.start).in_block(block.index)
+    };
+    
+    if blocks.len() < OODLE_PARALLEL_MIN_BLOCKS || rayon::current_num_threads() == 1 {
+        for block in &blocks {
+            let mut block_data = vec![0u8; block.output_size];
+            oodle
+                .decompress_into(&bin[block.input.clone()], &mut block_data)
+                .map_err(|e| error(e, block))?;
+            sink(&block_data)?;
+        }
+        return Ok(end);
+    }
+    
+    // Decompress a few blocks per thread at a time, so memory stays bounded while
+    // the sink still receives the data in order
+    for window in blocks.chunks(rayon::current_num_threads() * 4) {
+        let mut output = vec![0u8; window.iter().map(|block| block.output_size).sum()];
+        
+        let mut slices = Vec::with_capacity(window.len());
+        let mut rest = output.as_mut_slice();
+        for block in window {
+            let (slice, tail) = rest.split_at_mut(block.output_size);
+            slices.push((block, slice));
+            rest = tail;
+        }
+        
+        slices
+            .into_par_iter()
+            .try_for_each(|(block, slice)| {
+                oodle
+                    .decompress_into(&bin[block.input.clone()], slice)
+                    .map_err(|e| error(e, block))
+            })?;
+        
+        sink(&output)?;
+    }
+    
+    Ok(end)
+}
+
+/// Chunks with fewer Oodle blocks than this are always decompressed sequentially
+pub const OODLE_PARALLEL_MIN_BLOCKS: usize = 4;
+
+/// Where one Oodle block's compressed data lives and how large it decompresses
+struct OodleBlockSpan {
+    index: usize,
+    input: Range<usize>,
+    output_size: usize,
+}
+
+/// Validates the block headers of an Oodle chunk starting at `start` without
+/// decompressing anything. The blocks' decompressed sizes must add up to exactly
+/// `decompressed_size`. Returns each block's span and the chunk's end offset.
+fn oodle_block_spans(bin: &[u8], start: usize, decompressed_size: usize) -> Result<(Vec<OodleBlockSpan>, usize)> {
+    let mut blocks = Vec::new();
+    let mut written = 0;
     let mut i = start;
+    let mut block = 0;
+    let error = |kind, offset, block| ShccError::new(kind, bin, offset).in_block(block);
     
-    while decompressed.len() < decompressed_size {
+    while written < decompressed_size {
         if i + 8 > bin.len() {
-            return Err(anyhow!("Unexpected end of data in SHCC Oodle chunk"));
+            return Err(error(ShccErrorKind::TruncatedBlockHeader, i, block).into());
         }
         
         let block_info = &bin[i..i + 8];
-        i += 8;
         
         if block_info[0] != 0x80 {
-            return Err(anyhow!("Invalid block header"));
+            return Err(error(ShccErrorKind::InvalidBlockHeader, i, block).into());
         }
         
         if (block_info[7] & 0x0F) != 0x01 {
-            return Err(anyhow!("Invalid block footer"));
+            return Err(error(ShccErrorKind::InvalidBlockFooter, i + 7, block).into());
         }
         
-        let num1 = ((block_info[0] as u32) << 24) | 
-                   ((block_info[1] as u32) << 16) | 
-                   ((block_info[2] as u32) << 8) | 
-                   (block_info[3] as u32);
-        let num2 = ((block_info[4] as u32) << 24) | 
-                   ((block_info[5] as u32) << 16) | 
-                   ((block_info[6] as u32) << 8) | 
-                   (block_info[7] as u32);
-        
-        let block_compressed_size = ((num1 >> 2) & 0xFFFFFF) as usize;
-        let block_decompressed_size = ((num2 >> 5) & 0xFFFFFF) as usize;
+        let (block_compressed_size, block_decompressed_size) = oodle_block_sizes(block_info);
+        i += 8;
         
         if i >= bin.len() || bin[i] != 0x8C {
-            return Err(anyhow!("Invalid Oodle block marker"));
+            return Err(error(ShccErrorKind::InvalidBlockMarker, i, block).into());
         }
         
         if i + block_compressed_size > bin.len() {
-            return Err(anyhow!("Block compressed size exceeds available data"));
+            let kind = ShccErrorKind::BlockOverrun {
+                end: i + block_compressed_size,
+                available: bin.len(),
+            };
+            return Err(error(kind, i, block).into());
+        }
+        
+        // The blocks must add up to exactly the chunk's size; a block running past
+        // it would leave the next chunk read at the wrong offset
+        if written + block_decompressed_size > decompressed_size {
+            let kind = ShccErrorKind::BlockOvershoot {
+                end: written + block_decompressed_size,
+                expected: decompressed_size,
+            };
+            return Err(error(kind, i - 8, block).into());
         }
         
-        let block_data = oodle.decompress(&bin[i..i + block_compressed_size], block_decompressed_size)?;
-        decompressed.extend_from_slice(&block_data);
+        trace!(
+            "Oodle block {} at offset {}: {} compressed, {} decompressed bytes",
+            block, i - 8, block_compressed_size, block_decompressed_size
+        );
+        blocks.push(OodleBlockSpan {
+            index: block,
+            input: i..i + block_compressed_size,
+            output_size: block_decompressed_size,
+        });
+        written += block_decompressed_size;
         i += block_compressed_size;
+        block += 1;
     }
     
-    Ok((decompressed, i))
+    Ok((blocks, i))
+}
+
+/// Compressed and decompressed sizes packed into an 8-byte Oodle block header
+fn oodle_block_sizes(block_info: &[u8]) -> (usize, usize) {
+    let num1 = u32::from_be_bytes([block_info[0], block_info[1], block_info[2], block_info[3]]);
+    let num2 = u32::from_be_bytes([block_info[4], block_info[5], block_info[6], block_info[7]]);
+    
+    (((num1 >> 2) & 0xFFFFFF) as usize, ((num2 >> 5) & 0xFFFFFF) as usize)
+}
+
+/// SHCC chunk compression, from the first byte of the 9-byte chunk header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkType {
+    /// Stored as-is
+    Raw,
+    /// A run of Oodle blocks, each with an 8-byte block header
+    Oodle,
+    /// A single zstd frame, as seen in sibling tools for newer builds
+    Zstd,
+    Unknown(u8),
 }
 
-pub fn shcc_decompress_chunk(bin: &[u8], start: usize, oodle: &Oodle) -> Result<(Vec<u8>, usize)> {
+impl From<u8> for ChunkType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => ChunkType::Raw,
+            2 => ChunkType::Oodle,
+            3 => ChunkType::Zstd,
+            other => ChunkType::Unknown(other),
+        }
+    }
+}
+
+/// Formats up to `len` bytes of `bin` starting at `start` as space-separated hex.
+pub fn hexdump(bin: &[u8], start: usize, len: usize) -> String {
+    let start = start.min(bin.len());
+    let end = start.saturating_add(len).min(bin.len());
+    bin[start..end]
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn shcc_decompress_chunk(bin: &[u8], start: usize, oodle: &dyn BlockDecompressor) -> Result<(Vec<u8>, usize)> {
+    let mut data = Vec::new();
+    let i = shcc_decompress_chunk_to(bin, start, oodle, &mut |block| {
+        data.extend_from_slice(block);
+        Ok(())
+    })?;
+    
+    Ok((data, i))
+}
+
+/// Like `shcc_decompress_chunk`, but streams the decompressed data to `sink`.
+/// Returns the end offset.
+pub fn shcc_decompress_chunk_to(bin: &[u8], start: usize, oodle: &dyn BlockDecompressor, sink: &mut ChunkSink) -> Result<usize> {
     if start + 9 > bin.len() {
-        return Err(anyhow!("Not enough data for SHCC chunk header"));
+        return Err(ShccError::new(ShccErrorKind::TruncatedChunkHeader, bin, start).into());
     }
     
-    let chunk_type = bin[start];
+    let chunk_type = ChunkType::from(bin[start]);
     let decompressed_size = u32::from_le_bytes([
         bin[start + 1], bin[start + 2], bin[start + 3], bin[start + 4]
     ]) as usize;
     let compressed_size = u32::from_le_bytes([
         bin[start + 5], bin[start + 6], bin[start + 7], bin[start + 8]
     ]) as usize;
+    trace!(
+        "{:?} chunk at offset {}: {} compressed, {} decompressed bytes",
+        chunk_type, start, compressed_size, decompressed_size
+    );
     
     let mut i = start + 9;
     
     match chunk_type {
-        0 => {
-            // Uncompressed
+        ChunkType::Raw => {
             if compressed_size != decompressed_size {
-                return Err(anyhow!("Compressed size mismatch for uncompressed chunk"));
+                let kind = ShccErrorKind::RawSizeMismatch {
+                    compressed: compressed_size,
+                    decompressed: decompressed_size,
+                };
+                return Err(ShccError::new(kind, bin, start).into());
             }
             
             if i + compressed_size > bin.len() {
-                return Err(anyhow!("Not enough data for uncompressed chunk"));
+                let kind = ShccErrorKind::TruncatedChunk {
+                    end: i + compressed_size,
+                    available: bin.len(),
+                };
+                return Err(ShccError::new(kind, bin, i).into());
             }
             
-            let data = bin[i..i + compressed_size].to_vec();
+            sink(&bin[i..i + compressed_size])?;
             i += decompressed_size;
-            Ok((data, i))
+            Ok(i)
         }
-        2 => {
-            // Oodle compressed
-            shcc_decompress_chunk_oodle(bin, i, decompressed_size, oodle)
+        ChunkType::Oodle => {
+            shcc_decompress_chunk_oodle_to(bin, i, decompressed_size, oodle, sink)
+        }
+        ChunkType::Zstd => {
+            if i + compressed_size > bin.len() {
+                let kind = ShccErrorKind::TruncatedChunk {
+                    end: i + compressed_size,
+                    available: bin.len(),
+                };
+                return Err(ShccError::new(kind, bin, i).into());
+            }
+            
+            // decode_all grows with the actual output rather than preallocating the
+            // declared size, which may be corrupt
+            let data = zstd::stream::decode_all(&bin[i..i + compressed_size])
+                .map_err(|e| {
+                    let kind = ShccErrorKind::Decompression(format!("Zstd chunk decompression failed: {}", e));
+                    ShccError::new(kind, bin, i)
+                })?;
+            if data.len() != decompressed_size {
+                let kind = ShccErrorKind::Decompression(format!(
+                    "Zstd chunk decompressed to {} bytes, expected {}",
+                    data.len(),
+                    decompressed_size
+                ));
+                return Err(ShccError::new(kind, bin, i).into());
+            }
+            
+            sink(&data)?;
+            i += compressed_size;
+            Ok(i)
+        }
+        ChunkType::Unknown(value) => {
+            let kind = ShccErrorKind::UnknownChunkType {
+                chunk_type: value,
+                decompressed: decompressed_size,
+                compressed: compressed_size,
+            };
+            Err(ShccError::new(kind, bin, start).into())
         }
-        _ => Err(anyhow!("Unknown chunk type: {}", chunk_type))
     }
 }
 
-pub fn shcc_unpack(bin: &[u8], oodle: &Oodle) -> Result<ShccData> {
+pub fn shcc_unpack(bin: &[u8], oodle: &dyn BlockDecompressor) -> Result<ShccData, SoulframeError> {
     if bin.len() < 8 {
-        return Err(anyhow!("SHCC data too short"));
+        return Err(ShccError::new(ShccErrorKind::TooShort, bin, 0).into());
     }
     
     let mut i = 8; // Skip initial 8 bytes
     
     // Decompress H chunk
-    let (h_data, new_i) = shcc_decompress_chunk(bin, i, oodle)?;
+    let (h_data, new_i) = shcc_decompress_chunk(bin, i, oodle).map_err(|e| in_chunk(e, 0))?;
     i = new_i;
     
     // Try to decompress B chunk
@@ -251,12 +846,9 @@ pub fn shcc_unpack(bin: &[u8], oodle: &Oodle) -> Result<ShccData> {
     if i < bin.len() {
         let b_start = i;
         match shcc_decompress_chunk(bin, i, oodle) {
-            Ok((b, _)) => {
+            Ok((b, b_end)) => {
                 b_data = Some(b);
-                // B_raw is the compressed data without the 9-byte header and 15-byte footer
-                if b_start + 9 < bin.len() && bin.len() >= 15 {
-                    b_raw = Some(bin[b_start + 9..bin.len() - 15].to_vec());
-                }
+                b_raw = Some(bin[b_raw_span(bin, b_start, b_end)?].to_vec());
             }
             Err(_) => {
                 // B chunk is optional
@@ -271,9 +863,635 @@ pub fn shcc_unpack(bin: &[u8], oodle: &Oodle) -> Result<ShccData> {
     })
 }
 
+/// The B chunk of an SHCC container, as parsed by `shcc_parse_b_chunk`
+#[derive(Debug, Clone)]
+pub struct BChunk {
+    /// Offset of the chunk's 9-byte header
+    pub offset: usize,
+    pub chunk_type: ChunkType,
+    pub decompressed_size: usize,
+    pub compressed_size: usize,
+    /// Number of Oodle blocks; `None` for other chunk types
+    pub blocks: Option<usize>,
+    pub data: Vec<u8>,
+    /// The compressed data, identical to `ShccData::b_raw`
+    pub raw: Vec<u8>,
+    /// Everything after the chunk, normally `SHCC_FOOTER_LEN` bytes. The footer's
+    /// fields have not been identified: it is not covered by `shcc_hash`, its length
+    /// is constant, and `shcc_pack` writes it as zeros. It is kept verbatim.
+    pub footer: Vec<u8>,
+}
+
+/// A B chunk that could not be parsed, with its undecoded bytes kept
+#[derive(Debug)]
+pub struct BChunkError {
+    pub error: Box<SoulframeError>,
+    /// Where the B chunk starts, or 8 (just past the prelude) if the H chunk
+    /// could not be walked to find it
+    pub offset: usize,
+    /// Every byte from `offset` to the end of the container
+    pub raw: Vec<u8>,
+}
+
+impl std::fmt::Display for BChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "B chunk at offset {} ({} bytes kept): {}", self.offset, self.raw.len(), self.error)
+    }
+}
+
+impl std::error::Error for BChunkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+/// End offset of the chunk at `start`, found from its header (and Oodle block
+/// headers) without decompressing it
+fn shcc_chunk_end(bin: &[u8], start: usize) -> Result<usize> {
+    if start + 9 > bin.len() {
+        return Err(ShccError::new(ShccErrorKind::TruncatedChunkHeader, bin, start).into());
+    }
+    
+    let decompressed_size = u32::from_le_bytes(bin[start + 1..start + 5].try_into().unwrap()) as usize;
+    let compressed_size = u32::from_le_bytes(bin[start + 5..start + 9].try_into().unwrap()) as usize;
+    
+    match ChunkType::from(bin[start]) {
+        ChunkType::Raw | ChunkType::Zstd => Ok(start + 9 + compressed_size),
+        ChunkType::Oodle => Ok(oodle_block_spans(bin, start + 9, decompressed_size)?.1),
+        ChunkType::Unknown(value) => {
+            let kind = ShccErrorKind::UnknownChunkType {
+                chunk_type: value,
+                decompressed: decompressed_size,
+                compressed: compressed_size,
+            };
+            Err(ShccError::new(kind, bin, start).into())
+        }
+    }
+}
+
+/// Parses the B chunk without decompressing the H chunk. Returns `Ok(None)` when
+/// the container ends after H.
+///
+/// Unlike `shcc_unpack`, which silently drops a B chunk it cannot decode, failures
+/// are returned as a `BChunkError` that keeps the undecoded bytes.
+pub fn shcc_parse_b_chunk(bin: &[u8], oodle: &dyn BlockDecompressor) -> Result<Option<BChunk>, BChunkError> {
+    let fail = |error: anyhow::Error, offset: usize| BChunkError {
+        error: Box::new(error.into()),
+        offset,
+        raw: bin.get(offset..).unwrap_or_default().to_vec(),
+    };
+    
+    if bin.len() < 8 {
+        return Err(fail(ShccError::new(ShccErrorKind::TooShort, bin, 0).into(), 0));
+    }
+    
+    let b_start = shcc_chunk_end(bin, 8).map_err(|e| fail(in_chunk(e, 0), 8))?;
+    if b_start >= bin.len() {
+        return Ok(None);
+    }
+    
+    let parse = || -> Result<BChunk> {
+        let (data, b_end) = shcc_decompress_chunk(bin, b_start, oodle)?;
+        let chunk_type = ChunkType::from(bin[b_start]);
+        let decompressed_size = u32::from_le_bytes(bin[b_start + 1..b_start + 5].try_into().unwrap()) as usize;
+        let blocks = match chunk_type {
+            ChunkType::Oodle => Some(oodle_block_spans(bin, b_start + 9, decompressed_size)?.0.len()),
+            _ => None,
+        };
+        
+        Ok(BChunk {
+            offset: b_start,
+            chunk_type,
+            decompressed_size,
+            compressed_size: u32::from_le_bytes(bin[b_start + 5..b_start + 9].try_into().unwrap()) as usize,
+            blocks,
+            data,
+            raw: bin[b_raw_span(bin, b_start, b_end)?].to_vec(),
+            footer: bin[b_end..].to_vec(),
+        })
+    };
+    
+    parse().map(Some).map_err(|e| fail(in_chunk(e, 1), b_start))
+}
+
+/// Outer wrapping of a downloaded payload, identified by its leading bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// A bare SHCC container
+    Shcc,
+    /// An Oodle stream; its first byte has a low nibble of 0xC (e.g. 0x8C, 0xCC)
+    Oodle,
+    /// A zstd frame (`28 B5 2F FD`)
+    Zstd,
+}
+
+/// Identifies how a downloaded payload is wrapped, or errors with its first bytes
+/// if it matches no known magic.
+pub fn sniff_payload(bin: &[u8]) -> Result<PayloadFormat, SoulframeError> {
+    if bin.starts_with(b"SHCC") {
+        Ok(PayloadFormat::Shcc)
+    } else if bin.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+        Ok(PayloadFormat::Zstd)
+    } else if bin.first().is_some_and(|b| b & 0x0F == 0x0C) {
+        Ok(PayloadFormat::Oodle)
+    } else {
+        Err(SoulframeError::Other(anyhow!(
+            "Unrecognized container ({} bytes, first bytes: {})",
+            bin.len(),
+            hexdump(bin, 0, 16)
+        )))
+    }
+}
+
+/// Default for `PayloadLimits::decompress_multiplier`
+pub const DEFAULT_DECOMPRESS_MULTIPLIER: usize = 10;
+
+/// Bounds on decompressing a downloaded payload in `unwrap_payload_limited`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadLimits {
+    /// Largest decompressed payload accepted, in bytes
+    pub max_size: u64,
+    /// An Oodle payload doesn't record its decompressed size, so it is taken to be
+    /// this multiple of the compressed size. Must be at least 1.
+    pub decompress_multiplier: usize,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            max_size: u64::MAX,
+            decompress_multiplier: DEFAULT_DECOMPRESS_MULTIPLIER,
+        }
+    }
+}
+
+/// Unwraps a downloaded payload into the SHCC container it carries, returning the
+/// container and the detected outer format.
+pub fn unwrap_payload(bin: Vec<u8>) -> Result<(Vec<u8>, PayloadFormat), SoulframeError> {
+    unwrap_payload_limited(bin, PayloadLimits::default())
+}
+
+/// Like `unwrap_payload`, refusing to decompress a payload to more than
+/// `limits.max_size` bytes. An Oodle payload is rejected up front if its size
+/// estimate is over the limit, and ZSTD decoding stops once it passes it.
+pub fn unwrap_payload_limited(bin: Vec<u8>, limits: PayloadLimits) -> Result<(Vec<u8>, PayloadFormat), SoulframeError> {
+    // The library is only loaded for an Oodle payload
+    unwrap_payload_via(bin, limits, |compressed, output| Oodle::new()?.decompress_into(compressed, output))
+}
+
+/// Like `unwrap_payload_limited`, decoding an Oodle payload with `oodle` instead of
+/// loading the Oodle library
+pub fn unwrap_payload_with(bin: Vec<u8>, limits: PayloadLimits, oodle: &dyn BlockDecompressor) -> Result<(Vec<u8>, PayloadFormat), SoulframeError> {
+    unwrap_payload_via(bin, limits, |compressed, output| {
+        oodle.decompress_into(compressed, output).map_err(|e| e.downcast().unwrap_or_else(SoulframeError::Other))
+    })
+}
+
+fn unwrap_payload_via(
+    bin: Vec<u8>,
+    limits: PayloadLimits,
+    decompress_oodle: impl FnOnce(&[u8], &mut [u8]) -> Result<(), SoulframeError>,
+) -> Result<(Vec<u8>, PayloadFormat), SoulframeError> {
+    let format = sniff_payload(&bin)?;
+    let max_size = limits.max_size;
+    let too_large = |size: u64| SoulframeError::TooLarge {
+        what: format!("{:?} payload after decompression", format),
+        size,
+        max: max_size,
+    };
+    let container = match format {
+        PayloadFormat::Shcc => bin,
+        PayloadFormat::Oodle => {
+            // Estimate decompressed size (the original uses bin size * 10)
+            let multiplier = limits.decompress_multiplier.max(1);
+            let estimate = bin.len().saturating_mul(multiplier);
+            debug!("Decompressing {} byte Oodle payload into up to {} bytes ({}x)", bin.len(), estimate, multiplier);
+            if estimate as u64 > max_size {
+                return Err(too_large(estimate as u64));
+            }
+            let mut container = vec![0u8; estimate];
+            decompress_oodle(&bin, &mut container)?;
+            container
+        }
+        PayloadFormat::Zstd => {
+            let decoder = zstd::stream::read::Decoder::new(bin.as_slice())
+                .map_err(|e| SoulframeError::ZstdDecompress(format!("payload: {}", e)))?;
+            let mut container = Vec::new();
+            decoder
+                .take(max_size.saturating_add(1))
+                .read_to_end(&mut container)
+                .map_err(|e| SoulframeError::ZstdDecompress(format!("payload: {}", e)))?;
+            if container.len() as u64 > max_size {
+                return Err(too_large(container.len() as u64));
+            }
+            container
+        }
+    };
+    
+    if !container.starts_with(b"SHCC") {
+        return Err(SoulframeError::Other(anyhow!(
+            "{:?} payload did not contain an SHCC container (first bytes: {})",
+            format,
+            hexdump(&container, 0, 16)
+        )));
+    }
+    
+    Ok((container, format))
+}
+
+/// SHCC container prelude, also the prefix `shcc_hash` feeds to MD5
+pub const SHCC_PRELUDE: &[u8; 8] = b"SHCC\x1F\x00\x00\x00";
+
+/// Length of the footer that follows the B chunk. See `BChunk::footer`.
+pub const SHCC_FOOTER_LEN: usize = 15;
+
+fn shcc_pack_chunk(out: &mut Vec<u8>, data: &[u8]) -> Result<()> {
+    let size = u32::try_from(data.len())
+        .map_err(|_| anyhow!("SHCC chunk of {} bytes is too large", data.len()))?;
+    out.push(0); // Uncompressed
+    out.extend_from_slice(&size.to_le_bytes()); // Decompressed size
+    out.extend_from_slice(&size.to_le_bytes()); // Compressed size
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+/// Inverse of `shcc_unpack`: packs H (and B, if present) into an SHCC container
+/// using uncompressed chunks, so no Oodle compressor is needed.
+///
+/// `b_raw` is ignored; the B chunk is followed by a zeroed footer, and unpacking
+/// yields a `b_raw` equal to the B data, so `shcc_hash` is reproducible.
+pub fn shcc_pack(data: &ShccData) -> Result<Vec<u8>> {
+    let mut out = SHCC_PRELUDE.to_vec();
+    
+    shcc_pack_chunk(&mut out, &data.h)?;
+    
+    if let Some(ref b_data) = data.b {
+        shcc_pack_chunk(&mut out, b_data)?;
+        out.extend_from_slice(&[0u8; SHCC_FOOTER_LEN]);
+    }
+    
+    Ok(out)
+}
+
+/// Result of `shcc_inspect`
+#[derive(Debug, Clone)]
+pub struct ShccInspection {
+    /// Human-readable layout, one line per prelude/chunk/block/footer
+    pub report: String,
+    /// True if every chunk parsed and nothing unexpected follows the last one
+    pub ends_cleanly: bool,
+}
+
+/// Formats the bytes around `offset` for error reports: up to 8 bytes before and 24 from it.
+pub fn hexdump_around(bin: &[u8], offset: usize) -> String {
+    let start = offset.saturating_sub(8).min(bin.len());
+    format!("@{}: {}", start, hexdump(bin, start, offset.saturating_sub(start) + 24))
+}
+
+/// Walks the Oodle blocks of a chunk without decompressing them, noting each block
+/// header. Returns the offset after the last block, or `Err(offset)` of the first bad one.
+fn inspect_oodle_blocks(bin: &[u8], start: usize, decompressed_size: usize, out: &mut String) -> std::result::Result<usize, usize> {
+    let mut written = 0;
+    let mut i = start;
+    let mut index = 0;
+    
+    while written < decompressed_size {
+        if i + 8 > bin.len() {
+            out.push_str(&format!("    ! Block {} @{}: header truncated, data ends at {}\n", index, i, bin.len()));
+            return Err(i);
+        }
+        
+        let block_info = &bin[i..i + 8];
+        let (compressed_size, block_decompressed_size) = oodle_block_sizes(block_info);
+        out.push_str(&format!(
+            "    Block {} @{}: [{}] compressed {}, decompressed {}\n",
+            index,
+            i,
+            hexdump(bin, i, 8),
+            compressed_size,
+            block_decompressed_size
+        ));
+        
+        if block_info[0] != 0x80 {
+            out.push_str(&format!("    ! Invalid block header 0x{:02X} (expected 0x80)\n", block_info[0]));
+            return Err(i);
+        }
+        if (block_info[7] & 0x0F) != 0x01 {
+            out.push_str(&format!("    ! Invalid block footer 0x{:02X} (low nibble should be 1)\n", block_info[7]));
+            return Err(i + 7);
+        }
+        
+        i += 8;
+        if i >= bin.len() || bin[i] != 0x8C {
+            out.push_str(&format!("    ! Missing 0x8C block marker @{}\n", i));
+            return Err(i);
+        }
+        if i + compressed_size > bin.len() {
+            out.push_str(&format!(
+                "    ! Block data runs to {}, past the end of data at {}\n",
+                i + compressed_size,
+                bin.len()
+            ));
+            return Err(i);
+        }
+        if block_decompressed_size == 0 {
+            out.push_str("    ! Block declares a decompressed size of 0\n");
+            return Err(i - 8);
+        }
+        if written + block_decompressed_size > decompressed_size {
+            out.push_str(&format!(
+                "    ! Block decompresses to {} of the chunk's {} bytes\n",
+                written + block_decompressed_size,
+                decompressed_size
+            ));
+            return Err(i - 8);
+        }
+        
+        written += block_decompressed_size;
+        i += compressed_size;
+        index += 1;
+    }
+    
+    Ok(i)
+}
+
+/// Describes the structure of an SHCC container without decompressing it: the
+/// 8-byte prelude, each chunk's type and sizes, each Oodle block header, byte
+/// offsets, and whatever follows the last chunk.
+///
+/// Never stops at the first problem; each one is reported with a hexdump of the
+/// surrounding bytes and scanning resumes at the chunk's declared end if possible.
+pub fn shcc_inspect(bin: &[u8]) -> ShccInspection {
+    let mut out = String::new();
+    let mut clean = true;
+    
+    out.push_str(&format!("Size: {} bytes\n", bin.len()));
+    
+    if bin.len() < 8 {
+        out.push_str(&format!("! Too short for the 8-byte prelude: {}\n", hexdump(bin, 0, 8)));
+        return ShccInspection { report: out, ends_cleanly: false };
+    }
+    
+    if bin.starts_with(SHCC_PRELUDE) {
+        out.push_str(&format!("Prelude @0: [{}] ok\n", hexdump(bin, 0, 8)));
+    } else if bin.starts_with(b"SHCC") {
+        out.push_str(&format!("Prelude @0: [{}] unexpected version bytes\n", hexdump(bin, 0, 8)));
+    } else {
+        out.push_str(&format!(
+            "! Prelude @0: [{}] is not SHCC; the payload may be Oodle-compressed as a whole\n",
+            hexdump(bin, 0, 8)
+        ));
+        clean = false;
+    }
+    
+    let mut i = 8;
+    let mut chunks = 0;
+    for name in ["H", "B"] {
+        if i >= bin.len() {
+            if name == "H" {
+                out.push_str("! Missing H chunk\n");
+                clean = false;
+            }
+            break;
+        }
+        if i + 9 > bin.len() {
+            out.push_str(&format!(
+                "! Chunk {} @{}: header truncated ({} bytes left)\n  {}\n",
+                name,
+                i,
+                bin.len() - i,
+                hexdump_around(bin, i)
+            ));
+            clean = false;
+            i = bin.len();
+            break;
+        }
+        
+        let chunk_type = ChunkType::from(bin[i]);
+        let decompressed_size = u32::from_le_bytes([bin[i + 1], bin[i + 2], bin[i + 3], bin[i + 4]]) as usize;
+        let compressed_size = u32::from_le_bytes([bin[i + 5], bin[i + 6], bin[i + 7], bin[i + 8]]) as usize;
+        let data_start = i + 9;
+        let declared_end = data_start + compressed_size;
+        
+        out.push_str(&format!(
+            "Chunk {} @{}: type {:?} (0x{:02X}), decompressed {}, compressed {}, data @{}\n",
+            name, i, chunk_type, bin[i], decompressed_size, compressed_size, data_start
+        ));
+        chunks += 1;
+        
+        let end = match chunk_type {
+            ChunkType::Raw => {
+                if compressed_size != decompressed_size {
+                    out.push_str("  ! Raw chunk with differing compressed/decompressed sizes\n");
+                    clean = false;
+                }
+                declared_end
+            }
+            ChunkType::Oodle => match inspect_oodle_blocks(bin, data_start, decompressed_size, &mut out) {
+                Ok(end) => {
+                    if end != declared_end {
+                        out.push_str(&format!(
+                            "  Blocks end @{} (compressed size would put the end @{})\n",
+                            end, declared_end
+                        ));
+                    }
+                    end
+                }
+                Err(at) => {
+                    out.push_str(&format!("    {}\n", hexdump_around(bin, at)));
+                    clean = false;
+                    declared_end
+                }
+            },
+            ChunkType::Zstd => declared_end,
+            ChunkType::Unknown(_) => {
+                out.push_str(&format!("  ! Unknown chunk type\n  {}\n", hexdump_around(bin, i)));
+                clean = false;
+                declared_end
+            }
+        };
+        
+        if end > bin.len() {
+            out.push_str(&format!(
+                "  ! Chunk ends @{}, past the end of data at {}\n",
+                end,
+                bin.len()
+            ));
+            clean = false;
+            i = bin.len();
+            break;
+        }
+        
+        out.push_str(&format!("  Chunk {} ends @{}\n", name, end));
+        i = end;
+    }
+    
+    let trailing = bin.len() - i;
+    if trailing == 0 {
+        out.push_str(&format!("End @{}\n", i));
+    } else if chunks == 2 && trailing == SHCC_FOOTER_LEN {
+        out.push_str(&format!("Footer @{}: [{}]\n", i, hexdump(bin, i, SHCC_FOOTER_LEN)));
+    } else {
+        out.push_str(&format!(
+            "! {} unexpected trailing bytes @{}: {}\n",
+            trailing,
+            i,
+            hexdump(bin, i, 32)
+        ));
+        clean = false;
+    }
+    
+    out.push_str(if clean { "Ends cleanly\n" } else { "Does not end cleanly\n" });
+    
+    ShccInspection { report: out, ends_cleanly: clean }
+}
+
+/// B_raw is the B chunk's compressed data: the bytes between its 9-byte header and
+/// `b_end`, the end offset returned by the chunk decoder. The footer (normally
+/// `SHCC_FOOTER_LEN` bytes) and any padding after it are not part of it.
+fn b_raw_span(bin: &[u8], b_start: usize, b_end: usize) -> Result<Range<usize>> {
+    let data_start = b_start + 9;
+    if data_start > b_end || b_end > bin.len() {
+        return Err(anyhow!(
+            "Inconsistent B chunk bounds: data {}..{} in {} bytes",
+            data_start,
+            b_end,
+            bin.len()
+        ));
+    }
+    
+    Ok(data_start..b_end)
+}
+
+/// Streaming counterpart of `shcc_unpack`: writes H and B to the given writers block
+/// by block and computes the `shcc_hash` as it goes, so the decompressed data is
+/// never held in memory as a whole.
+///
+/// If the B chunk fails to decode, `b_len` is `None` and anything already written
+/// to `b_writer` should be discarded.
+pub fn shcc_unpack_to<H: Write, B: Write>(bin: &[u8], oodle: &dyn BlockDecompressor, h_writer: &mut H, b_writer: &mut B) -> Result<ShccSummary, SoulframeError> {
+    if bin.len() < 8 {
+        return Err(ShccError::new(ShccErrorKind::TooShort, bin, 0).into());
+    }
+    
+    let mut hasher = md5::Context::new();
+    hasher.consume(SHCC_PRELUDE);
+    
+    // Decompress H chunk; the hash covers everything after its first 16 bytes
+    let mut h_len = 0;
+    let i = shcc_decompress_chunk_to(bin, 8, oodle, &mut |block| {
+        h_writer.write_all(block)?;
+        let skip = 16usize.saturating_sub(h_len).min(block.len());
+        hasher.consume(&block[skip..]);
+        h_len += block.len();
+        Ok(())
+    })
+    .map_err(|e| in_chunk(e, 0))?;
+    
+    // Try to decompress B chunk
+    let mut b_len = None;
+    if i < bin.len() {
+        let mut written = 0;
+        let result = shcc_decompress_chunk_to(bin, i, oodle, &mut |block| {
+            b_writer.write_all(block)?;
+            written += block.len();
+            Ok(())
+        });
+        
+        // B chunk is optional
+        if let Ok(b_end) = result {
+            b_len = Some(written);
+            hasher.consume(&bin[b_raw_span(bin, i, b_end)?]);
+        }
+    }
+    
+    Ok(ShccSummary {
+        h_len,
+        b_len,
+        hash: hasher.compute().0.to_vec(),
+    })
+}
+
+/// Streams the `_H` and `_B` files for `local_path` out of the SHCC container `bin`.
+///
+/// Cleans up like `write_shcc_data`, and removes the `_B` file when there is no B chunk.
+pub fn write_shcc_stream(local_path: &Path, bin: &[u8], oodle: &dyn BlockDecompressor) -> Result<ShccSummary> {
+    if let Some(parent) = local_path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    
+    let h_path = format!("{}_H", local_path.to_string_lossy());
+    let b_path = format!("{}_B", local_path.to_string_lossy());
+    
+    // Replaced rather than truncated, so files hard-linked from the content cache
+    // keep their contents
+    let _ = fs::remove_file(&h_path);
+    let _ = fs::remove_file(&b_path);
+    
+    let result = (|| {
+        let mut h_writer = BufWriter::new(
+            File::create(&h_path).with_context(|| format!("writing {}", h_path))?,
+        );
+        let mut b_writer = BufWriter::new(
+            File::create(&b_path).with_context(|| format!("writing {}", b_path))?,
+        );
+        
+        let summary = shcc_unpack_to(bin, oodle, &mut h_writer, &mut b_writer)
+            .with_context(|| format!("unpacking SHCC data for {}", local_path.display()))?;
+        h_writer.flush().with_context(|| format!("writing {}", h_path))?;
+        b_writer.flush().with_context(|| format!("writing {}", b_path))?;
+        Ok(summary)
+    })();
+    
+    match result {
+        Ok(summary) => {
+            if summary.b_len.is_none() {
+                let _ = fs::remove_file(&b_path);
+            }
+            Ok(summary)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&h_path);
+            let _ = fs::remove_file(&b_path);
+            Err(e)
+        }
+    }
+}
+
+/// Writes the `_H` and (if present) `_B` files for `local_path`.
+///
+/// If either write fails, both files are removed so a half-written download
+/// can't later be mistaken for a complete one.
+pub fn write_shcc_data(local_path: &Path, data: &ShccData) -> Result<()> {
+    if let Some(parent) = local_path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    
+    let h_path = format!("{}_H", local_path.to_string_lossy());
+    let b_path = format!("{}_B", local_path.to_string_lossy());
+    
+    // Replaced rather than truncated, like `write_shcc_stream`
+    let _ = fs::remove_file(&h_path);
+    let _ = fs::remove_file(&b_path);
+    
+    let result = fs::write(&h_path, &data.h)
+        .with_context(|| format!("writing {}", h_path))
+        .and_then(|_| match data.b {
+            Some(ref b_data) => fs::write(&b_path, b_data).with_context(|| format!("writing {}", b_path)),
+            None => Ok(()),
+        });
+    
+    if result.is_err() {
+        let _ = fs::remove_file(&h_path);
+        let _ = fs::remove_file(&b_path);
+    }
+    
+    result
+}
+
 pub fn shcc_hash(data: &ShccData) -> Vec<u8> {
     let mut hasher = md5::Context::new();
-    hasher.consume(b"SHCC\x1F\x00\x00\x00");
+    hasher.consume(SHCC_PRELUDE);
     
     if data.h.len() >= 17 {
         hasher.consume(&data.h[16..]);
@@ -286,6 +1504,27 @@ pub fn shcc_hash(data: &ShccData) -> Vec<u8> {
     hasher.compute().0.to_vec()
 }
 
+/// Encodes `value` in the format read by `unpack_u32_dyn_le`: 7 bits per byte with
+/// the high bit as a continuation flag, and a fifth byte holding the top 4 bits.
+pub fn pack_u32_dyn_le(value: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(5);
+    let mut rest = value;
+    
+    for _ in 0..4 {
+        if rest < 0x80 {
+            out.push(rest as u8);
+            return out;
+        }
+        
+        out.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    
+    // The final byte carries the remaining 4 bits without a continuation flag
+    out.push(rest as u8);
+    out
+}
+
 pub fn unpack_u32_dyn_le(bin: &[u8], start: usize) -> Result<(u32, usize)> {
     let mut value = 0u32;
     let mut i = start;
@@ -324,3 +1563,400 @@ pub fn unpack_u32_dyn_le(bin: &[u8], start: usize) -> Result<(u32, usize)> {
     
     Ok((value, i))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cdn_payload, LanguagesBuilder, ManifestBuilder};
+    use proptest::prelude::*;
+    
+    /// Stands in for Oodle: fills each block's output by repeating its compressed bytes
+    struct RepeatDecompressor;
+    
+    impl BlockDecompressor for RepeatDecompressor {
+        fn decompress_into(&self, compressed: &[u8], output: &mut [u8]) -> Result<()> {
+            for (i, byte) in output.iter_mut().enumerate() {
+                *byte = compressed[i % compressed.len()];
+            }
+            Ok(())
+        }
+    }
+    
+    /// A 9-byte chunk header of `chunk_type` followed by `data`
+    fn chunk(chunk_type: u8, decompressed_size: usize, data: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![chunk_type];
+        chunk.extend((decompressed_size as u32).to_le_bytes());
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+    
+    /// Oodle block headers and data for blocks of `(compressed bytes after the 0x8C
+    /// marker, decompressed size)`
+    fn oodle_blocks(blocks: &[(&[u8], usize)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (compressed, decompressed_size) in blocks {
+            let compressed_size = compressed.len() as u32 + 1;
+            data.extend((0x8000_0000 | (compressed_size << 2)).to_be_bytes());
+            data.extend(((*decompressed_size as u32) << 5 | 0x01).to_be_bytes());
+            data.push(0x8C);
+            data.extend_from_slice(compressed);
+        }
+        data
+    }
+    
+    /// What `RepeatDecompressor` makes of `oodle_blocks(blocks)`
+    fn repeated(blocks: &[(&[u8], usize)]) -> Vec<u8> {
+        let mut output = Vec::new();
+        for (compressed, decompressed_size) in blocks {
+            let input = [&[0x8C][..], compressed].concat();
+            output.extend(input.iter().cycle().take(*decompressed_size));
+        }
+        output
+    }
+    
+    fn shcc_kind(err: SoulframeError) -> ShccErrorKind {
+        match err {
+            SoulframeError::Shcc(err) => err.kind,
+            other => panic!("expected an SHCC error, got {}", other),
+        }
+    }
+    
+    #[test]
+    fn unpacks_a_cdn_payload() {
+        let h = LanguagesBuilder::new()
+            .with_label("/Lotus/Language/Menu/Start", "Start")
+            .build()
+            .unwrap();
+        let (payload, hash) = cdn_payload(h.clone()).unwrap();
+        
+        let data = shcc_unpack(&payload, &RepeatDecompressor).unwrap();
+        assert_eq!(data.h[MANIFEST_HASH_LEN..], h[MANIFEST_HASH_LEN..]);
+        assert_eq!(data.h[..MANIFEST_HASH_LEN], hash);
+        assert!(data.b.is_none());
+        assert_eq!(shcc_hash(&data), hash);
+    }
+    
+    #[test]
+    fn pack_round_trips_h_and_b() {
+        let h = ManifestBuilder::new()
+            .with_entry("/Lotus/A.bin", [1; MANIFEST_HASH_LEN])
+            .build()
+            .unwrap();
+        let data = ShccData { h: h.clone(), b: Some(b"B chunk".to_vec()), b_raw: None };
+        
+        let unpacked = shcc_unpack(&shcc_pack(&data).unwrap(), &RepeatDecompressor).unwrap();
+        assert_eq!(unpacked.h, h);
+        assert_eq!(unpacked.b.as_deref(), Some(&b"B chunk"[..]));
+        assert_eq!(unpacked.b_raw, unpacked.b);
+    }
+    
+    #[test]
+    fn streaming_unpack_matches_buffered() {
+        let h = ManifestBuilder::new()
+            .with_entry("/Lotus/A.bin", [1; MANIFEST_HASH_LEN])
+            .build()
+            .unwrap();
+        let packed = shcc_pack(&ShccData { h, b: Some(b"B chunk".to_vec()), b_raw: None }).unwrap();
+        let unpacked = shcc_unpack(&packed, &RepeatDecompressor).unwrap();
+        
+        let (mut h, mut b) = (Vec::new(), Vec::new());
+        let summary = shcc_unpack_to(&packed, &RepeatDecompressor, &mut h, &mut b).unwrap();
+        assert_eq!(h, unpacked.h);
+        assert_eq!(Some(b), unpacked.b);
+        assert_eq!((summary.h_len, summary.b_len), (unpacked.h.len(), Some(7)));
+        assert_eq!(summary.hash, shcc_hash(&unpacked));
+    }
+    
+    #[test]
+    fn rejects_truncated_containers() {
+        let h = LanguagesBuilder::new().build().unwrap();
+        let packed = shcc_pack(&ShccData { h, b: None, b_raw: None }).unwrap();
+        
+        assert_eq!(shcc_kind(shcc_unpack(&packed[..7], &RepeatDecompressor).unwrap_err()), ShccErrorKind::TooShort);
+        assert_eq!(
+            shcc_kind(shcc_unpack(&packed[..12], &RepeatDecompressor).unwrap_err()),
+            ShccErrorKind::TruncatedChunkHeader
+        );
+        assert_eq!(
+            shcc_kind(shcc_unpack(&packed[..packed.len() - 1], &RepeatDecompressor).unwrap_err()),
+            ShccErrorKind::TruncatedChunk { end: packed.len(), available: packed.len() - 1 }
+        );
+    }
+    
+    #[test]
+    fn builds_request_paths() {
+        // 0xFC bytes encode with '/', which b64m writes as '-'
+        let hash = b64m_encode(&[0xFC; MANIFEST_HASH_LEN]);
+        assert_eq!(hash, "-Pz8-Pz8-Pz8-Pz8-Pz8-A");
+        assert_eq!(
+            build_request_path("/Languages.bin", TYPE_BIN, &hash, Some("_en")),
+            "/0_en/Languages.bin!2C_-Pz8-Pz8-Pz8-Pz8-Pz8-A"
+        );
+        assert_eq!(
+            build_request_path("Lotus/Language/Languages.bin", TYPE_BIN, "AAECAwQFBgcICQoLDA0ODw", Some("_zh")),
+            "/0_zh/Lotus/Language/Languages.bin!2C_AAECAwQFBgcICQoLDA0ODw"
+        );
+        assert_eq!(
+            build_request_path("/H.Cache.bin", TYPE_MANIFEST, DEFAULT_B64M_HASH, None),
+            "/0/H.Cache.bin!E_---------------------w"
+        );
+        assert_eq!(
+            build_request_path("/B.Cache.Windows_en.bin", LEGACY_TYPE_MANIFEST, DEFAULT_B64M_HASH, Some("_en")),
+            "/0_en/B.Cache.Windows_en.bin!3_---------------------w"
+        );
+    }
+    
+    #[test]
+    fn decompresses_raw_chunks() {
+        let bin = chunk(0, 5, b"plain");
+        assert_eq!(shcc_decompress_chunk(&bin, 0, &RepeatDecompressor).unwrap(), (b"plain".to_vec(), bin.len()));
+        
+        let err = shcc_decompress_chunk(&chunk(0, 6, b"plain"), 0, &RepeatDecompressor).unwrap_err();
+        let kind = &err.downcast_ref::<ShccError>().unwrap().kind;
+        assert_eq!(*kind, ShccErrorKind::RawSizeMismatch { compressed: 5, decompressed: 6 });
+    }
+    
+    #[test]
+    fn decompresses_oodle_chunks() {
+        let blocks: &[(&[u8], usize)] = &[(b"ab", 7), (b"xyz", 3)];
+        let bin = chunk(2, 10, &oodle_blocks(blocks));
+        assert_eq!(shcc_decompress_chunk(&bin, 0, &RepeatDecompressor).unwrap(), (repeated(blocks), bin.len()));
+    }
+    
+    #[test]
+    fn rejects_oodle_blocks_past_the_chunk() {
+        // The second block's decompressed size takes the total to 12 of 10 bytes
+        let blocks: &[(&[u8], usize)] = &[(b"ab", 7), (b"xyz", 5)];
+        let bin = chunk(2, 10, &oodle_blocks(blocks));
+        let err = shcc_decompress_chunk(&bin, 0, &RepeatDecompressor).unwrap_err();
+        let err = err.downcast_ref::<ShccError>().unwrap();
+        assert_eq!(err.kind, ShccErrorKind::BlockOvershoot { end: 12, expected: 10 });
+        assert_eq!((err.offset, err.block), (9 + 11, Some(1)));
+        
+        // The second block's compressed size runs past the end of the buffer
+        let data = oodle_blocks(blocks);
+        let bin = chunk(2, 12, &data[..data.len() - 1]);
+        let err = shcc_decompress_chunk(&bin, 0, &RepeatDecompressor).unwrap_err();
+        let kind = &err.downcast_ref::<ShccError>().unwrap().kind;
+        assert_eq!(*kind, ShccErrorKind::BlockOverrun { end: bin.len() + 1, available: bin.len() });
+    }
+    
+    #[test]
+    fn decompresses_zstd_chunks() {
+        let data = b"zstd chunk data, zstd chunk data, zstd chunk data".to_vec();
+        let bin = chunk(3, data.len(), &zstd::bulk::compress(&data, 3).unwrap());
+        assert_eq!(shcc_decompress_chunk(&bin, 0, &RepeatDecompressor).unwrap(), (data.clone(), bin.len()));
+        
+        let wrong_size = chunk(3, data.len() + 1, &zstd::bulk::compress(&data, 3).unwrap());
+        let err = shcc_decompress_chunk(&wrong_size, 0, &RepeatDecompressor).unwrap_err();
+        assert!(matches!(err.downcast_ref::<ShccError>().unwrap().kind, ShccErrorKind::Decompression(_)));
+    }
+    
+    #[test]
+    fn reports_unknown_chunk_types() {
+        let bin = [b"padding".to_vec(), chunk(7, 20, b"0123456789")].concat();
+        let err = shcc_decompress_chunk(&bin, 7, &RepeatDecompressor).unwrap_err();
+        let err = err.downcast_ref::<ShccError>().unwrap();
+        assert_eq!(err.kind, ShccErrorKind::UnknownChunkType { chunk_type: 7, decompressed: 20, compressed: 10 });
+        assert_eq!(err.offset, 7);
+        assert_eq!(err.bytes, bin[7..23]);
+        assert!(err.to_string().contains("type 0x07"), "{}", err);
+        assert!(err.to_string().ends_with("bytes: 07 14 00 00 00 0A 00 00 00 30 31 32 33 34 35 36"), "{}", err);
+    }
+    
+    #[test]
+    fn unpacks_mixed_chunk_types() {
+        let h_blocks: &[(&[u8], usize)] = &[(b"header", 32), (b"more", 8)];
+        let b = b"B data compressed with zstd".to_vec();
+        let b_compressed = zstd::bulk::compress(&b, 3).unwrap();
+        let mut bin = SHCC_PRELUDE.to_vec();
+        bin.extend(chunk(2, 40, &oodle_blocks(h_blocks)));
+        bin.extend(chunk(3, b.len(), &b_compressed));
+        bin.extend([0; SHCC_FOOTER_LEN]);
+        
+        let data = shcc_unpack(&bin, &RepeatDecompressor).unwrap();
+        assert_eq!(data.h, repeated(h_blocks));
+        assert_eq!(data.b, Some(b));
+        assert_eq!(data.b_raw, Some(b_compressed));
+    }
+    
+    /// `shcc_hash` computed by hand: the prelude, H after its first 16 bytes, then B_raw
+    fn expected_hash(h: &[u8], b_raw: &[u8]) -> Vec<u8> {
+        md5::compute([&SHCC_PRELUDE[..], &h[16..], b_raw].concat()).0.to_vec()
+    }
+    
+    /// Checks the buffered and streaming unpackers agree on `bin`'s B_raw and hash
+    fn assert_b_raw(bin: &[u8], b_raw: Option<&[u8]>, hash: &[u8]) {
+        let data = shcc_unpack(bin, &RepeatDecompressor).unwrap();
+        assert_eq!(data.b_raw.as_deref(), b_raw);
+        assert_eq!(shcc_hash(&data), hash);
+        
+        let summary = shcc_unpack_to(bin, &RepeatDecompressor, &mut std::io::sink(), &mut std::io::sink()).unwrap();
+        assert_eq!(summary.b_len, b_raw.map(|_| data.b.as_ref().unwrap().len()));
+        assert_eq!(summary.hash, hash);
+    }
+    
+    #[test]
+    fn b_raw_without_a_b_chunk() {
+        let h = [b"0123456789ABCDEF".to_vec(), b"header".to_vec()].concat();
+        let bin = [&SHCC_PRELUDE[..], &chunk(0, h.len(), &h)].concat();
+        assert_b_raw(&bin, None, &expected_hash(&h, b""));
+    }
+    
+    #[test]
+    fn b_raw_with_an_exact_footer() {
+        let h = [b"0123456789ABCDEF".to_vec(), b"header".to_vec()].concat();
+        let b_blocks: &[(&[u8], usize)] = &[(b"b", 12)];
+        let b_data = oodle_blocks(b_blocks);
+        let bin = [&SHCC_PRELUDE[..], &chunk(0, h.len(), &h), &chunk(2, 12, &b_data), &[0xEE; SHCC_FOOTER_LEN]].concat();
+        assert_b_raw(&bin, Some(&b_data), &expected_hash(&h, &b_data));
+    }
+    
+    #[test]
+    fn b_raw_with_trailing_data() {
+        // Anything after the footer was counted into B_raw by the old `len - 15` span
+        let h = [b"0123456789ABCDEF".to_vec(), b"header".to_vec()].concat();
+        let b_data = oodle_blocks(&[(b"b", 12)]);
+        let footer_and_padding = [[0xEE; SHCC_FOOTER_LEN].as_slice(), &[0; 64]].concat();
+        let bin = [&SHCC_PRELUDE[..], &chunk(0, h.len(), &h), &chunk(2, 12, &b_data), &footer_and_padding].concat();
+        assert_b_raw(&bin, Some(&b_data), &expected_hash(&h, &b_data));
+    }
+    
+    #[test]
+    fn b_raw_with_a_short_b_chunk_and_no_footer() {
+        // `len - 15` would fall before the B data here
+        let h = [b"0123456789ABCDEF".to_vec(), b"header".to_vec()].concat();
+        let bin = [&SHCC_PRELUDE[..], &chunk(0, h.len(), &h), &chunk(0, 2, b"bb")].concat();
+        assert_b_raw(&bin, Some(b"bb"), &expected_hash(&h, b"bb"));
+    }
+    
+    #[test]
+    fn b_raw_with_a_truncated_b_chunk() {
+        // A B chunk that fails to decode is left out of the hash rather than sliced
+        let h = [b"0123456789ABCDEF".to_vec(), b"header".to_vec()].concat();
+        let b = chunk(0, 40, &[0xBB; 40]);
+        let bin = [&SHCC_PRELUDE[..], &chunk(0, h.len(), &h), &b[..20]].concat();
+        assert_b_raw(&bin, None, &expected_hash(&h, b""));
+    }
+    
+    #[test]
+    fn b_raw_span_rejects_inconsistent_bounds() {
+        let bin = [0u8; 32];
+        assert_eq!(b_raw_span(&bin, 10, 19).unwrap(), 19..19);
+        assert!(b_raw_span(&bin, 10, 18).is_err());
+        assert!(b_raw_span(&bin, 10, 33).is_err());
+    }
+    
+    #[test]
+    fn parallel_oodle_blocks_match_sequential() {
+        // Enough blocks of uneven sizes for several windows at every thread count
+        let contents: Vec<Vec<u8>> = (0..40u8).map(|i| (i..i + 1 + i % 5).collect()).collect();
+        let blocks: Vec<(&[u8], usize)> = contents
+            .iter()
+            .enumerate()
+            .map(|(i, content)| (content.as_slice(), 100 + i * 37))
+            .collect();
+        let decompressed_size = blocks.iter().map(|(_, size)| size).sum();
+        let bin = [b"prefix".to_vec(), oodle_blocks(&blocks)].concat();
+        
+        let run = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| {
+                let mut sunk = Vec::new();
+                let end = shcc_decompress_chunk_oodle_to(&bin, 6, decompressed_size, &RepeatDecompressor, &mut |data| {
+                    sunk.extend_from_slice(data);
+                    Ok(())
+                })
+                .unwrap();
+                (shcc_decompress_chunk_oodle(&bin, 6, decompressed_size, &RepeatDecompressor).unwrap(), sunk, end)
+            })
+        };
+        
+        let sequential = run(1);
+        assert_eq!(sequential.0, (repeated(&blocks), bin.len()));
+        assert_eq!(sequential.1, sequential.0 .0);
+        assert_eq!(sequential.2, bin.len());
+        for threads in [2, 3, 8] {
+            assert_eq!(run(threads), sequential, "{} threads", threads);
+        }
+    }
+    
+    proptest! {
+        #[test]
+        fn dyn_u32_round_trips(value: u32) {
+            let packed = pack_u32_dyn_le(value);
+            prop_assert!(packed.len() <= 5);
+            prop_assert_eq!(unpack_u32_dyn_le(&packed, 0).unwrap(), (value, packed.len()));
+        }
+        
+        #[test]
+        fn dyn_u32_reads_between_other_bytes(prefix: Vec<u8>, value: u32, suffix: Vec<u8>) {
+            let packed = pack_u32_dyn_le(value);
+            let bin = [prefix.clone(), packed.clone(), suffix].concat();
+            prop_assert_eq!(unpack_u32_dyn_le(&bin, prefix.len()).unwrap(), (value, prefix.len() + packed.len()));
+        }
+        
+        #[test]
+        fn dyn_u32_never_panics(bin: Vec<u8>, start in 0usize..8) {
+            let _ = unpack_u32_dyn_le(&bin, start);
+        }
+    }
+    
+    #[test]
+    fn dyn_u32_boundaries() {
+        let cases: &[(u32, &[u8])] = &[
+            (0, &[0x00]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x80, 0x01]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x4000, &[0x80, 0x80, 0x01]),
+            (0x1F_FFFF, &[0xFF, 0xFF, 0x7F]),
+            (0x20_0000, &[0x80, 0x80, 0x80, 0x01]),
+            (0xFFF_FFFF, &[0xFF, 0xFF, 0xFF, 0x7F]),
+            // Five bytes: the final byte holds the top 4 bits, with no continuation flag
+            (0x1000_0000, &[0x80, 0x80, 0x80, 0x80, 0x01]),
+            (u32::MAX, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+        ];
+        for (value, bytes) in cases {
+            assert_eq!(pack_u32_dyn_le(*value), *bytes, "{:#x}", value);
+            assert_eq!(unpack_u32_dyn_le(bytes, 0).unwrap(), (*value, bytes.len()), "{:#x}", value);
+        }
+    }
+    
+    #[test]
+    fn dyn_u32_rejects_bad_input() {
+        let err = unpack_u32_dyn_le(&[0xFF, 0xFF, 0xFF, 0xFF, 0x10], 0).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid final byte"), "{}", err);
+        assert!(unpack_u32_dyn_le(&[0x80, 0x80, 0x80, 0x80, 0x80], 0).is_err());
+        assert!(unpack_u32_dyn_le(&[0xFF, 0xFF, 0xFF, 0xFF], 0).is_err());
+        assert!(unpack_u32_dyn_le(&[0x80], 0).is_err());
+        assert!(unpack_u32_dyn_le(&[0x01], 1).is_err());
+    }
+    
+    #[test]
+    fn safe_join_rejects_hostile_paths() {
+        let root = Path::new("downloaded-data");
+        let hostile = [
+            "../x",
+            "a/../../x",
+            "C:/x",
+            "C:x",
+            "\\\\server\\share",
+            "a\\..\\b",
+            "name:stream",
+            "a/b\0c",
+        ];
+        for path in hostile {
+            assert!(safe_join(root, path).is_err(), "{:?} was accepted", path);
+        }
+    }
+    
+    #[test]
+    fn safe_join_keeps_paths_under_the_root() {
+        let root = Path::new("downloaded-data");
+        assert_eq!(safe_join(root, "//a//./b").unwrap(), root.join("a").join("b"));
+        assert_eq!(safe_join(root, "/0_en/Languages.bin").unwrap(), root.join("0_en").join("Languages.bin"));
+        assert_eq!(safe_join(root, "/").unwrap(), root);
+    }
+}
@@ -0,0 +1,255 @@
+//! Parsing of the `--locales` flag shared by the binaries, and the mapping between
+//! the game's locale tokens and standard codes
+
+use crate::{locale_suffix, safe_join, SoulframeError, DEFAULT_PLATFORM};
+use anyhow::{anyhow, Context};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Locales the CDN is known to serve, in the order `all` expands to when nothing
+/// better is available
+pub const KNOWN_LOCALES: &[&str] = &["en", "fr", "de", "es", "it", "pt", "ru", "pl", "tr", "ja", "ko", "zh"];
+
+/// `KNOWN_LOCALES` as owned strings, for passing to `parse_locales`
+pub fn known_locales() -> Vec<String> {
+    KNOWN_LOCALES.iter().map(|l| l.to_string()).collect()
+}
+
+/// Standard (BCP-47) codes for the game's locale tokens, where the two differ
+pub const DEFAULT_LOCALE_ALIASES: &[(&str, &str)] = &[("pt", "pt-BR"), ("zh", "zh-Hans")];
+
+/// File in the working directory whose entries override `DEFAULT_LOCALE_ALIASES`
+pub const LOCALE_ALIASES_FILE: &str = "locale-aliases.json";
+
+/// Mapping between the game's locale tokens, used in manifest paths and URLs, and
+/// standard codes. Tokens without an alias are their own standard code.
+#[derive(Debug, Clone)]
+pub struct LocaleAliases {
+    /// Game token to standard code
+    standard: BTreeMap<String, String>,
+}
+
+impl Default for LocaleAliases {
+    fn default() -> Self {
+        let standard = DEFAULT_LOCALE_ALIASES
+            .iter()
+            .map(|(token, code)| (token.to_string(), code.to_string()))
+            .collect();
+        Self { standard }
+    }
+}
+
+impl LocaleAliases {
+    /// The defaults with the entries of `json`, an object of game tokens to standard
+    /// codes such as `{"zh": "zh-CN"}`, on top. Mapping a token to itself drops its
+    /// alias. Two tokens may not share a standard code.
+    pub fn from_json(json: &str) -> Result<Self, SoulframeError> {
+        Ok(Self::parse(json)?)
+    }
+
+    fn parse(json: &str) -> anyhow::Result<Self> {
+        let overrides: BTreeMap<String, String> = serde_json::from_str(json)
+            .context("expected an object of game locale tokens to standard codes")?;
+        let mut aliases = Self::default();
+        aliases.standard.extend(overrides);
+        aliases.standard.retain(|token, code| token != code);
+
+        let mut tokens: BTreeMap<String, &str> = BTreeMap::new();
+        for (token, code) in &aliases.standard {
+            if let Some(other) = tokens.insert(code.to_lowercase(), token) {
+                return Err(anyhow!("{} and {} both map to the standard code {}", other, token, code));
+            }
+        }
+        Ok(aliases)
+    }
+
+    /// The defaults, overridden by the JSON file at `path` if there is one
+    pub fn load(path: &Path) -> Result<Self, SoulframeError> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(Self::parse(&json).with_context(|| format!("reading {}", path.display()))?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// `load` of `LOCALE_ALIASES_FILE` in the working directory
+    pub fn load_default() -> Result<Self, SoulframeError> {
+        Self::load(&std::env::current_dir()?.join(LOCALE_ALIASES_FILE))
+    }
+
+    /// Game token for `locale`, a standard code (matched ignoring case) or already a
+    /// token
+    pub fn to_game(&self, locale: &str) -> String {
+        self.standard
+            .iter()
+            .find(|(_, code)| code.eq_ignore_ascii_case(locale))
+            .map_or(locale, |(token, _)| token.as_str())
+            .to_string()
+    }
+
+    /// Standard code for the game token `token`
+    pub fn to_standard(&self, token: &str) -> String {
+        self.standard.get(token).map_or(token, String::as_str).to_string()
+    }
+
+    /// `spec`, a `--locales` list, with its standard codes replaced by game tokens
+    /// (`all,-zh-Hans` becomes `all,-zh`), for `parse_locales`
+    pub fn translate_spec(&self, spec: &str) -> String {
+        spec.split(',')
+            .map(|token| {
+                let token = token.trim();
+                match token.strip_prefix('-') {
+                    Some(locale) => format!("-{}", self.to_game(locale.trim())),
+                    None => self.to_game(token),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Expands a comma-separated locale list against `known`.
+///
+/// `all` adds every known locale and `-<locale>` removes one added earlier, so
+/// `all,-ru` is every locale except Russian. Tokens are trimmed and empty ones are
+/// ignored. Unknown locales fail with the closest known one as a suggestion, and
+/// a list that selects nothing is an error too.
+pub fn parse_locales(spec: &str, known: &[String]) -> Result<Vec<String>, SoulframeError> {
+    let mut locales: Vec<String> = Vec::new();
+    for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        if token == "all" {
+            for locale in known {
+                if !locales.contains(locale) {
+                    locales.push(locale.clone());
+                }
+            }
+            continue;
+        }
+
+        let (remove, locale) = match token.strip_prefix('-') {
+            Some(locale) => (true, locale.trim()),
+            None => (false, token),
+        };
+        if !known.iter().any(|k| k == locale) {
+            return Err(SoulframeError::UnknownLocale {
+                locale: locale.to_string(),
+                suggestion: suggest_locale(locale, known),
+            });
+        }
+
+        if remove {
+            locales.retain(|l| l != locale);
+        } else if !locales.iter().any(|l| l == locale) {
+            locales.push(locale.to_string());
+        }
+    }
+
+    if locales.is_empty() {
+        return Err(SoulframeError::NoLocalesSelected { spec: spec.to_string() });
+    }
+    Ok(locales)
+}
+
+/// Closest known locale to `locale`, if any is close enough to be a typo: one edit
+/// away for short tokens, two for longer ones
+fn suggest_locale(locale: &str, known: &[String]) -> Option<String> {
+    let locale = locale.to_lowercase();
+    let max_distance = if locale.chars().count() <= 3 { 1 } else { 2 };
+    known
+        .iter()
+        .map(|k| (edit_distance(&locale, k), k))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, k)| k.clone())
+}
+
+/// Levenshtein distance between `a` and `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The `candidates` whose `Languages.bin_H` has been downloaded to
+/// `./downloaded-data` for the default platform, in the order given
+pub fn available_locales(candidates: &[String]) -> Vec<String> {
+    let downloads = std::env::current_dir().unwrap_or_default().join("downloaded-data");
+    available_platform_locales(&downloads, candidates, DEFAULT_PLATFORM)
+}
+
+/// Like `available_locales`, for the files downloaded to `downloads` (the folder
+/// holding `0_<locale>`, e.g. a snapshot) with `--platform <platform>`
+pub fn available_platform_locales(downloads: &Path, candidates: &[String], platform: &str) -> Vec<String> {
+    candidates
+        .iter()
+        .filter(|locale| {
+            let relative = format!("0{}/Languages.bin_H", locale_suffix(locale, platform));
+            safe_join(downloads, &relative).is_ok_and(|h_path| fs::metadata(h_path).is_ok())
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(spec: &str) -> Result<Vec<String>, SoulframeError> {
+        parse_locales(spec, &known_locales())
+    }
+
+    #[test]
+    fn all_expands_to_the_known_locales() {
+        assert_eq!(parse("all").unwrap(), known_locales());
+        assert_eq!(parse(" all , ").unwrap(), known_locales());
+    }
+
+    #[test]
+    fn minus_removes_a_locale() {
+        let mut expected = known_locales();
+        expected.retain(|l| l != "ru");
+        assert_eq!(parse("all,-ru").unwrap(), expected);
+        assert_eq!(parse("all, - ru").unwrap(), expected);
+        // A later token adds it back, at the end
+        expected.push("ru".to_string());
+        assert_eq!(parse("all,-ru,ru").unwrap(), expected);
+    }
+
+    #[test]
+    fn removing_an_unselected_locale_is_a_no_op() {
+        assert_eq!(parse("en,fr,-ru").unwrap(), ["en", "fr"]);
+        assert!(matches!(parse("-ru"), Err(SoulframeError::NoLocalesSelected { spec }) if spec == "-ru"));
+        assert!(matches!(parse("en,-en"), Err(SoulframeError::NoLocalesSelected { .. })));
+    }
+
+    #[test]
+    fn duplicates_are_kept_once_in_first_order() {
+        assert_eq!(parse("fr,en,fr,en").unwrap(), ["fr", "en"]);
+        assert_eq!(parse("ja,all").unwrap()[0], "ja");
+        assert_eq!(parse("ja,all").unwrap().len(), KNOWN_LOCALES.len());
+    }
+
+    #[test]
+    fn unknown_tokens_suggest_the_closest_locale() {
+        let err = parse("en,fre").unwrap_err();
+        assert!(matches!(
+            &err,
+            SoulframeError::UnknownLocale { locale, suggestion: Some(suggestion) } if locale == "fre" && suggestion == "fr"
+        ));
+        assert!(err.to_string().contains("did you mean 'fr'"), "{}", err);
+
+        let err = parse("all,-xyzzy").unwrap_err();
+        assert!(matches!(&err, SoulframeError::UnknownLocale { suggestion: None, .. }));
+        assert!(!err.to_string().contains("did you mean"), "{}", err);
+    }
+}
@@ -0,0 +1,192 @@
+//! Advisory lock that keeps runs writing `downloaded-data` and `extracted-data` from
+//! overlapping, e.g. a scheduled job and a manual run.
+//!
+//! The lock is an OS file lock on `downloaded-data/.lock`, so it is released when
+//! the holder exits, however it exits. The file also records the holder's PID,
+//! start time and command for error messages; a record still there when the lock
+//! is acquired was left by a run that died without clearing it.
+
+use crate::SoulframeError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// Lock file name, inside the downloads folder
+pub const LOCK_FILE: &str = ".lock";
+
+/// Environment variable naming a lock already held by a parent process, so that
+/// `download` can run `extract` and `--on-update` hooks without deadlocking
+pub const LOCK_ENV: &str = "SOULFRAME_RUN_LOCK";
+
+/// Who holds a lock, as recorded in the lock file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    pub pid: u32,
+    /// Seconds since the Unix epoch when the lock was taken
+    pub started: u64,
+    /// Binary and subcommand, e.g. `download watch`
+    pub command: String,
+}
+
+impl LockHolder {
+    /// This process, running `command`
+    pub fn current(command: &str) -> Self {
+        Self {
+            pid: std::process::id(),
+            started: unix_now(),
+            command: command.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let age = unix_now().saturating_sub(self.started);
+        write!(f, "`{}` (PID {}, started {}s ago)", self.command, self.pid, age)
+    }
+}
+
+/// Held lock; dropping it clears the record and releases the lock. The file itself
+/// stays, since deleting it would let a waiting process lock an unlinked file.
+#[derive(Debug)]
+pub struct RunLock {
+    /// `None` when a parent process holds the lock on our behalf
+    file: Option<File>,
+    path: PathBuf,
+}
+
+impl RunLock {
+    /// Locks `dir`, creating it if needed. If another process holds the lock, waits
+    /// for it when `wait` is set and fails with `SoulframeError::Locked` otherwise.
+    pub fn acquire(dir: &Path, command: &str, wait: bool) -> Result<Self, SoulframeError> {
+        let path = dir.join(LOCK_FILE);
+        if std::env::var_os(LOCK_ENV).is_some_and(|held| Path::new(&held) == path) {
+            debug!("{} is held by the parent process", path.display());
+            return Ok(Self { file: None, path });
+        }
+
+        fs::create_dir_all(dir)?;
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = read_holder(&mut file).map_or("another process".to_string(), |h| h.to_string());
+                if !wait {
+                    return Err(SoulframeError::Locked { path, holder });
+                }
+                info!("Waiting for {} to release {}", holder, path.display());
+                file.lock()?;
+            }
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        if let Some(stale) = read_holder(&mut file) {
+            warn!("Taking over {} from {}, which exited without releasing it", path.display(), stale);
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        serde_json::to_writer(&mut file, &LockHolder::current(command)).map_err(anyhow::Error::from)?;
+        file.flush()?;
+
+        Ok(Self { file: Some(file), path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            let _ = file.set_len(0);
+            let _ = file.unlock();
+        }
+    }
+}
+
+/// The holder recorded in `file`, if it holds a readable record
+fn read_holder(file: &mut File) -> Option<LockHolder> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn second_holder_fails_then_waits() {
+        let dir = tempfile::tempdir().unwrap();
+        let held = RunLock::acquire(dir.path(), "download", false).unwrap();
+
+        let (ready, waiting) = mpsc::channel();
+        let path = dir.path().to_path_buf();
+        let contender = thread::spawn(move || {
+            let err = RunLock::acquire(&path, "extract", false).unwrap_err();
+            let SoulframeError::Locked { holder, .. } = err else {
+                panic!("expected Locked, got {:?}", err);
+            };
+            assert!(holder.contains("`download`"), "{}", holder);
+
+            ready.send(()).unwrap();
+            let lock = RunLock::acquire(&path, "extract", true).unwrap();
+            serde_json::from_str::<LockHolder>(&fs::read_to_string(lock.path()).unwrap()).unwrap()
+        });
+
+        waiting.recv().unwrap();
+        // Give the contender time to block on the lock before releasing it
+        thread::sleep(Duration::from_millis(100));
+        drop(held);
+        let holder = contender.join().unwrap();
+        assert_eq!(holder.command, "extract");
+    }
+
+    #[test]
+    fn takes_over_a_stale_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let stale = LockHolder { pid: 1, started: 0, command: "download watch".to_string() };
+        fs::write(dir.path().join(LOCK_FILE), serde_json::to_string(&stale).unwrap()).unwrap();
+
+        let lock = RunLock::acquire(dir.path(), "download", false).unwrap();
+        let record: LockHolder = serde_json::from_str(&fs::read_to_string(lock.path()).unwrap()).unwrap();
+        assert_eq!(record.pid, std::process::id());
+        assert_eq!(record.command, "download");
+
+        // Released locks leave an empty file, which is not a stale record
+        drop(lock);
+        assert_eq!(fs::read_to_string(dir.path().join(LOCK_FILE)).unwrap(), "");
+    }
+
+    #[test]
+    fn lock_env_names_a_lock_held_by_the_parent() {
+        let dir = tempfile::tempdir().unwrap();
+        let parent = RunLock::acquire(dir.path(), "download watch", false).unwrap();
+
+        // Only this test's folder matches, so other tests are unaffected
+        std::env::set_var(LOCK_ENV, parent.path());
+        let child = RunLock::acquire(dir.path(), "extract", false);
+        std::env::remove_var(LOCK_ENV);
+
+        let child = child.unwrap();
+        assert!(child.file.is_none());
+        assert_eq!(child.path(), parent.path());
+        // The child doesn't release the parent's lock when it is done
+        drop(child);
+        assert!(matches!(RunLock::acquire(dir.path(), "extract", false), Err(SoulframeError::Locked { .. })));
+    }
+}
@@ -0,0 +1,76 @@
+//! Log output for the binaries. The library only emits `tracing` events and prints
+//! nothing itself; `init` installs the subscriber that turns them into the familiar
+//! plain lines on stdout.
+
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+/// Level shown for `-v` counts and `--quiet`: warnings only when quiet, info by
+/// default, then debug (each URL tried) and trace (parse offsets)
+pub fn level(verbose: u8, quiet: bool) -> Level {
+    match (quiet, verbose) {
+        (true, _) => Level::WARN,
+        (false, 0) => Level::INFO,
+        (false, 1) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    }
+}
+
+/// Installs the stdout subscriber. `RUST_LOG`, when set, takes precedence over
+/// `verbose` and `quiet`, e.g. `RUST_LOG=soulframe_language_downloader=trace`.
+pub fn init(verbose: u8, quiet: bool) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(level(verbose, quiet).as_str()));
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .event_format(PlainFormat)
+        .try_init();
+}
+
+/// Prints info and warning events as bare messages, the way the binaries always
+/// printed them, and tags debug and trace events with their level
+struct PlainFormat;
+
+impl<S, N> FormatEvent<S, N> for PlainFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, _ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        match *event.metadata().level() {
+            Level::DEBUG => write!(writer, "[debug] ")?,
+            Level::TRACE => write!(writer, "[trace] ")?,
+            _ => {}
+        }
+        // Recorded by hand rather than through the context's field formatter, which
+        // escapes the ANSI codes of colored status markers
+        let mut visitor = PlainFields { writer: writer.by_ref(), result: Ok(()) };
+        event.record(&mut visitor);
+        visitor.result?;
+        writeln!(writer)
+    }
+}
+
+/// Writes the message as-is and any other fields as ` name=value`
+struct PlainFields<'a> {
+    writer: Writer<'a>,
+    result: fmt::Result,
+}
+
+impl Visit for PlainFields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.result.is_err() {
+            return;
+        }
+        self.result = if field.name() == "message" {
+            write!(self.writer, "{:?}", value)
+        } else {
+            write!(self.writer, " {}={:?}", field.name(), value)
+        };
+    }
+}
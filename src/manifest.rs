@@ -0,0 +1,261 @@
+//! Manifest `_H` parsing, shared by the downloader and the offline tools. Nothing
+//! here touches the network, so it is built without the `download` feature.
+
+use anyhow::anyhow;
+use crate::*;
+use crate::storage::{LocalFs, Storage};
+use std::collections::HashMap;
+
+/// One manifest entry, as listed by `SoulframeManifest::entries`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub hash: Vec<u8>,
+    /// The 4 bytes after the hash, whose meaning is not known yet
+    pub unk: [u8; MANIFEST_UNK_LEN],
+}
+
+impl ManifestEntry {
+    /// `unk` read as a little-endian u32
+    pub fn unk_u32(&self) -> u32 {
+        u32::from_le_bytes(self.unk)
+    }
+}
+
+pub struct SoulframeManifest {
+    bin: Vec<u8>,
+    i: usize,
+    entry_i: usize,
+    remaining_entries: u32,
+    paths: Vec<String>,
+    hashes: HashMap<String, Vec<u8>>,
+    unks: HashMap<String, Vec<u8>>,
+    malformed_at: Option<usize>,
+}
+
+impl SoulframeManifest {
+    pub fn new(path: &str) -> Result<Self, SoulframeError> {
+        Self::with_format(path, ManifestFormat::Current)
+    }
+    
+    /// Loads a manifest written in `format`, e.g. `ManifestFormat::Legacy` for
+    /// archived pre-40.0.0 dumps
+    pub fn with_format(path: &str, format: ManifestFormat) -> Result<Self, SoulframeError> {
+        Self::from_storage(&LocalFs::downloads(), path, format)
+    }
+    
+    /// Loads the manifest `path` from the downloads kept in `storage`
+    pub fn from_storage(storage: &dyn Storage, path: &str, format: ManifestFormat) -> Result<Self, SoulframeError> {
+        let key = download_key(path, None);
+        let bin = storage
+            .read(&format!("{}_H", key))
+            .map_err(|_| anyhow!("{} was not found on disk.", path))?;
+        if let Some(b) = storage.read(&format!("{}_B", key)).ok().filter(|b| !b.is_empty()) {
+            warn_manifest_b_len(path, b.len() as u64);
+        }
+        Self::from_bytes(bin, format)
+    }
+    
+    /// Parses the contents of a manifest `_H` file read from anywhere
+    pub fn from_bytes(bin: Vec<u8>, format: ManifestFormat) -> Result<Self, SoulframeError> {
+        let Some(header_len) = format.header_len(&bin) else {
+            return Err(SoulframeError::ManifestMalformed { offset: bin.len().min(MANIFEST_HEADER_SKIP) });
+        };
+        
+        Ok(Self {
+            bin,
+            i: header_len,
+            entry_i: 0,
+            remaining_entries: 0,
+            paths: Vec::new(),
+            hashes: HashMap::new(),
+            unks: HashMap::new(),
+            malformed_at: None,
+        })
+    }
+    
+    pub fn seek(&mut self, opt_stop_at_path: Option<&str>) -> Option<Vec<u8>> {
+        while self.i < self.bin.len() {
+            while self.remaining_entries == 0 {
+                if self.i + 4 > self.bin.len() {
+                    return None;
+                }
+                
+                self.remaining_entries = u32::from_le_bytes([
+                    self.bin[self.i],
+                    self.bin[self.i + 1],
+                    self.bin[self.i + 2],
+                    self.bin[self.i + 3],
+                ]);
+                self.i += 4;
+            }
+            
+            self.entry_i += 1;
+            self.remaining_entries -= 1;
+            
+            // Read path (4-byte length prefix + string)
+            if self.i + 4 > self.bin.len() {
+                self.malformed_at = Some(self.i);
+                break;
+            }
+            
+            let path_len = u32::from_le_bytes([
+                self.bin[self.i],
+                self.bin[self.i + 1],
+                self.bin[self.i + 2],
+                self.bin[self.i + 3],
+            ]) as usize;
+            self.i += 4;
+            
+            if self.i + path_len + MANIFEST_HASH_LEN + MANIFEST_UNK_LEN > self.bin.len() {
+                self.malformed_at = Some(self.i - 4);
+                break;
+            }
+            
+            let path = String::from_utf8_lossy(&self.bin[self.i..self.i + path_len]).to_string();
+            self.i += path_len;
+            
+            // Read hash and unk
+            let hash = self.bin[self.i..self.i + MANIFEST_HASH_LEN].to_vec();
+            let unk_start = self.i + MANIFEST_HASH_LEN;
+            let unk = self.bin[unk_start..unk_start + MANIFEST_UNK_LEN].to_vec();
+            self.i += MANIFEST_HASH_LEN + MANIFEST_UNK_LEN;
+            
+            self.paths.push(path.clone());
+            self.hashes.insert(path.clone(), hash.clone());
+            self.unks.insert(path.clone(), unk);
+            
+            if let Some(target_path) = opt_stop_at_path {
+                if path == target_path {
+                    return Some(hash);
+                }
+            }
+        }
+        
+        None
+    }
+    
+    pub fn get_hash(&mut self, path: &str) -> Option<Vec<u8>> {
+        if let Some(hash) = self.hashes.get(path) {
+            return Some(hash.clone());
+        }
+        
+        self.seek(Some(path))
+    }
+    
+    /// The `_H` contents the manifest is parsed from
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bin
+    }
+    
+    /// Offset of the truncated entry that stopped parsing, if any
+    pub fn malformed_at(&self) -> Option<usize> {
+        self.malformed_at
+    }
+    
+    pub fn get_paths(&mut self) -> Vec<String> {
+        self.seek(None);
+        self.paths.clone()
+    }
+    
+    /// The unknown 4 bytes stored after `path`'s hash
+    pub fn get_unk(&mut self, path: &str) -> Option<Vec<u8>> {
+        self.get_hash(path)?;
+        self.unks.get(path).cloned()
+    }
+    
+    /// Every entry in file order, with its hash and unknown bytes
+    pub fn entries(&mut self) -> Vec<ManifestEntry> {
+        self.seek(None);
+        self.paths
+            .iter()
+            .map(|path| ManifestEntry {
+                path: path.clone(),
+                hash: self.hashes[path].clone(),
+                unk: self.unks[path].as_slice().try_into().expect("unk is MANIFEST_UNK_LEN bytes"),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::ManifestBuilder;
+    
+    fn entry(path: &str, hash: u8, unk: u8) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            hash: vec![hash; MANIFEST_HASH_LEN],
+            unk: [unk; MANIFEST_UNK_LEN],
+        }
+    }
+    
+    #[test]
+    fn reads_entries_across_groups() {
+        let bin = ManifestBuilder::new()
+            .with_entry("/Lotus/A.bin", [1; MANIFEST_HASH_LEN])
+            .with_group(Vec::new())
+            .with_group(vec![entry("/Lotus/B.bin", 2, 0x22), entry("/Lotus/C.bin", 3, 0x33)])
+            .build()
+            .unwrap();
+        
+        let mut manifest = SoulframeManifest::from_bytes(bin, ManifestFormat::Current).unwrap();
+        assert_eq!(manifest.entries(), [
+            entry("/Lotus/A.bin", 1, 0),
+            entry("/Lotus/B.bin", 2, 0x22),
+            entry("/Lotus/C.bin", 3, 0x33),
+        ]);
+        assert_eq!(manifest.malformed_at(), None);
+    }
+    
+    #[test]
+    fn seeks_lazily() {
+        let bin = ManifestBuilder::new()
+            .with_entry("/Lotus/A.bin", [1; MANIFEST_HASH_LEN])
+            .with_entry("/Lotus/B.bin", [2; MANIFEST_HASH_LEN])
+            .build()
+            .unwrap();
+        
+        let mut manifest = SoulframeManifest::from_bytes(bin, ManifestFormat::Current).unwrap();
+        assert_eq!(manifest.get_hash("/Lotus/A.bin"), Some(vec![1; MANIFEST_HASH_LEN]));
+        assert_eq!(manifest.paths, ["/Lotus/A.bin"]);
+        assert_eq!(manifest.get_unk("/Lotus/B.bin"), Some(vec![0; MANIFEST_UNK_LEN]));
+        assert_eq!(manifest.get_hash("/Lotus/Missing.bin"), None);
+        assert_eq!(manifest.get_paths(), ["/Lotus/A.bin", "/Lotus/B.bin"]);
+    }
+    
+    #[test]
+    fn reports_a_truncated_entry() {
+        let mut bin = ManifestBuilder::new()
+            .with_entry("/Lotus/A.bin", [1; MANIFEST_HASH_LEN])
+            .with_entry("/Lotus/B.bin", [2; MANIFEST_HASH_LEN])
+            .build()
+            .unwrap();
+        bin.truncate(bin.len() - 1);
+        
+        let mut manifest = SoulframeManifest::from_bytes(bin, ManifestFormat::Current).unwrap();
+        assert_eq!(manifest.get_paths(), ["/Lotus/A.bin"]);
+        let second_entry = MANIFEST_HEADER_SKIP + 4 + (4 + 12 + MANIFEST_HASH_LEN + MANIFEST_UNK_LEN);
+        assert_eq!(manifest.malformed_at(), Some(second_entry));
+    }
+    
+    #[test]
+    fn rejects_a_short_header() {
+        assert!(SoulframeManifest::from_bytes(vec![0; MANIFEST_HEADER_SKIP - 1], ManifestFormat::Current).is_err());
+    }
+    
+    #[test]
+    fn detects_the_legacy_header() {
+        let bin = ManifestBuilder::new()
+            .with_entry("/Lotus/A.bin", [1; MANIFEST_HASH_LEN])
+            .build()
+            .unwrap();
+        // Legacy manifests have a shorter header before the first entry group
+        let legacy = [&[0u8; 8][..], &bin[MANIFEST_HEADER_SKIP..]].concat();
+        
+        assert_eq!(ManifestFormat::Legacy.header_len(&legacy), Some(8));
+        let mut manifest = SoulframeManifest::from_bytes(legacy, ManifestFormat::Legacy).unwrap();
+        assert_eq!(manifest.get_paths(), ["/Lotus/A.bin"]);
+    }
+}
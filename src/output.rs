@@ -0,0 +1,228 @@
+//! Status markers and result tables shared by the binaries' console output. Color
+//! is off until `init` turns it on, so library callers get plain text.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Turns color on when stdout is a terminal, unless `no_color` is set or the
+/// `NO_COLOR` environment variable is non-empty
+pub fn init(no_color: bool) {
+    let enabled = color_enabled(no_color, std::env::var_os("NO_COLOR").as_deref(), std::io::stdout().is_terminal());
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether to color output given `--no-color`, the value of `NO_COLOR` and whether
+/// stdout is a terminal
+pub fn color_enabled(no_color: bool, no_color_env: Option<&OsStr>, is_terminal: bool) -> bool {
+    !no_color && no_color_env.is_none_or(|value| value.is_empty()) && is_terminal
+}
+
+/// Forces color on or off, e.g. for output captured into a buffer
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Outcome shown at the start of a status line. Displays as its label padded to
+/// the width of the longest one, in green, yellow or red when color is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Skip,
+    /// Usable but needs attention, e.g. stale output
+    Warn,
+    Fail,
+}
+
+impl Status {
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Skip => "SKIP",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        }
+    }
+    
+    fn ansi(self) -> &'static str {
+        match self {
+            Status::Ok => "\x1b[32m",
+            Status::Skip | Status::Warn => "\x1b[33m",
+            Status::Fail => "\x1b[31m",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if color() {
+            write!(f, "{}{:<4}\x1b[0m", self.ansi(), self.label())
+        } else {
+            write!(f, "{:<4}", self.label())
+        }
+    }
+}
+
+/// Rows of text cells, each with a `Status`, rendered with aligned columns. The
+/// status column comes second, after the row's name.
+pub struct Table {
+    headers: Vec<String>,
+    right_aligned: Vec<bool>,
+    rows: Vec<(Status, Vec<String>)>,
+}
+
+impl Table {
+    /// `headers` names the cell columns; the status column has no header
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            right_aligned: vec![false; headers.len()],
+            rows: Vec::new(),
+        }
+    }
+    
+    /// Aligns column `index` (counting cell columns only) to the right, for numbers
+    pub fn right_align(mut self, index: usize) -> Self {
+        self.right_aligned[index] = true;
+        self
+    }
+    
+    pub fn row(&mut self, status: Status, cells: Vec<String>) {
+        self.rows.push((status, cells));
+    }
+    
+    /// The header line followed by one line per row, without trailing spaces
+    pub fn lines(&self) -> Vec<String> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for (_, cells) in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(cells) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        
+        let render = |status: Option<Status>, cells: &[String]| {
+            let mut line = String::new();
+            for (index, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+                if self.right_aligned[index] {
+                    line.push_str(&format!("{:>width$}  ", cell, width = width));
+                } else {
+                    line.push_str(&format!("{:<width$}  ", cell, width = width));
+                }
+                if index == 0 {
+                    match status {
+                        Some(status) => line.push_str(&format!("{}  ", status)),
+                        None => line.push_str(&" ".repeat(6)),
+                    }
+                }
+            }
+            line.trim_end().to_string()
+        };
+        
+        let mut lines = vec![render(None, &self.headers)];
+        for (status, cells) in &self.rows {
+            lines.push(render(Some(*status), cells));
+        }
+        lines
+    }
+    
+    pub fn write_to<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        for line in self.lines() {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    
+    /// Held by tests that depend on the process-wide color setting
+    static COLOR_LOCK: Mutex<()> = Mutex::new(());
+    
+    fn without_ansi(line: &str) -> String {
+        let mut plain = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find('\x1b') {
+            plain.push_str(&rest[..start]);
+            let end = rest[start..].find('m').unwrap();
+            rest = &rest[start + end + 1..];
+        }
+        plain.push_str(rest);
+        plain
+    }
+    
+    fn table() -> Table {
+        let mut table = Table::new(&["Locale", "Strings", "Output"]).right_align(1);
+        table.row(Status::Ok, vec!["en".to_string(), "1234".to_string(), "out/en.json".to_string()]);
+        table.row(Status::Fail, vec!["zh".to_string(), "-".to_string(), "\u{9519}\u{8bef}".to_string()]);
+        table.row(Status::Skip, vec!["fr\u{e9}".to_string(), "7".to_string(), String::new()]);
+        table
+    }
+    
+    #[test]
+    fn status_renders_with_and_without_color() {
+        let _lock = COLOR_LOCK.lock().unwrap();
+        set_color(false);
+        let plain: Vec<String> = [Status::Ok, Status::Skip, Status::Warn, Status::Fail].iter().map(Status::to_string).collect();
+        assert_eq!(plain, ["OK  ", "SKIP", "WARN", "FAIL"]);
+        
+        set_color(true);
+        assert_eq!(Status::Ok.to_string(), "\x1b[32mOK  \x1b[0m");
+        assert_eq!(Status::Skip.to_string(), "\x1b[33mSKIP\x1b[0m");
+        assert_eq!(Status::Warn.to_string(), "\x1b[33mWARN\x1b[0m");
+        assert_eq!(Status::Fail.to_string(), "\x1b[31mFAIL\x1b[0m");
+        set_color(false);
+    }
+    
+    #[test]
+    fn color_follows_the_flag_the_environment_and_the_terminal() {
+        assert!(color_enabled(false, None, true));
+        assert!(color_enabled(false, Some(OsStr::new("")), true));
+        assert!(!color_enabled(false, Some(OsStr::new("1")), true));
+        assert!(!color_enabled(true, None, true));
+        assert!(!color_enabled(false, None, false));
+    }
+    
+    #[test]
+    fn table_aligns_columns_by_character() {
+        let _lock = COLOR_LOCK.lock().unwrap();
+        set_color(false);
+        let lines = table().lines();
+        assert_eq!(
+            lines,
+            [
+                "Locale        Strings  Output",
+                "en      OK       1234  out/en.json",
+                "zh      FAIL        -  \u{9519}\u{8bef}",
+                "fr\u{e9}     SKIP        7",
+            ]
+        );
+        // Multibyte cells are padded by characters, so the columns start together
+        let output_columns: Vec<usize> = lines[..3]
+            .iter()
+            .zip(["Output", "out/en.json", "\u{9519}\u{8bef}"])
+            .map(|(line, cell)| line.chars().count() - cell.chars().count())
+            .collect();
+        assert_eq!(output_columns, [23, 23, 23]);
+        
+        // Color only wraps the status; the visible text is unchanged
+        set_color(true);
+        let colored = table().lines();
+        set_color(false);
+        assert_ne!(colored, lines);
+        assert_eq!(colored.iter().map(|line| without_ansi(line)).collect::<Vec<_>>(), lines);
+        
+        let mut written = Vec::new();
+        table().write_to(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), lines.join("\n") + "\n");
+    }
+}
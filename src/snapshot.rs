@@ -0,0 +1,116 @@
+//! Versioned download folders: `downloaded-data/<id>/`, where `<id>` is taken from
+//! the header hash of the primary manifest the files were downloaded with
+
+use crate::{safe_join, SoulframeError, MANIFEST_HASH_LEN};
+use anyhow::anyhow;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Hex characters of the manifest hash used as a snapshot ID
+pub const SNAPSHOT_ID_LEN: usize = 8;
+
+/// File in the downloads folder holding the ID of the last complete snapshot
+pub const LATEST_POINTER: &str = "latest";
+
+/// Snapshot ID for a primary manifest `_H` file, or `None` if it is too short to
+/// hold a hash
+pub fn snapshot_id(manifest_h: &[u8]) -> Option<String> {
+    let hash = manifest_h.get(..MANIFEST_HASH_LEN)?;
+    let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+    Some(hex[..SNAPSHOT_ID_LEN].to_string())
+}
+
+pub(crate) fn is_snapshot_id(name: &str) -> bool {
+    name.len() == SNAPSHOT_ID_LEN && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Records `id` as the latest snapshot under `downloads`
+pub fn set_latest(downloads: &Path, id: &str) -> Result<(), SoulframeError> {
+    fs::write(downloads.join(LATEST_POINTER), format!("{}\n", id))?;
+    Ok(())
+}
+
+/// Folder of the snapshot named by `reference` under `downloads`: `latest`, a full
+/// ID, or a prefix matching exactly one snapshot
+pub fn resolve_snapshot(downloads: &Path, reference: &str) -> Result<PathBuf, SoulframeError> {
+    let reference = if reference == LATEST_POINTER {
+        let pointer = fs::read_to_string(downloads.join(LATEST_POINTER))
+            .map_err(|_| anyhow!("No latest snapshot recorded in {}", downloads.display()))?;
+        pointer.trim().to_string()
+    } else {
+        reference.to_lowercase()
+    };
+
+    let matches: Vec<SnapshotInfo> = list_snapshots(downloads)?
+        .into_iter()
+        .filter(|snapshot| snapshot.id.starts_with(&reference))
+        .collect();
+    match matches.as_slice() {
+        [snapshot] => Ok(safe_join(downloads, &snapshot.id)?),
+        [] => Err(anyhow!("No snapshot matching '{}' in {}", reference, downloads.display()).into()),
+        _ => Err(anyhow!(
+            "Snapshot '{}' is ambiguous: {}",
+            reference,
+            matches.iter().map(|s| s.id.as_str()).collect::<Vec<_>>().join(", ")
+        )
+        .into()),
+    }
+}
+
+/// A snapshot folder found on disk
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    pub id: String,
+    /// When its primary manifest was stored
+    pub created: Option<SystemTime>,
+    /// Number of `_H` files it holds
+    pub files: usize,
+    /// Whether `latest` points at it
+    pub latest: bool,
+}
+
+/// Snapshots under `downloads`, sorted oldest first
+pub fn list_snapshots(downloads: &Path) -> Result<Vec<SnapshotInfo>, SoulframeError> {
+    let latest = fs::read_to_string(downloads.join(LATEST_POINTER)).ok();
+    let latest = latest.as_deref().map(str::trim);
+
+    let mut snapshots = Vec::new();
+    let Ok(dir) = fs::read_dir(downloads) else {
+        return Ok(snapshots);
+    };
+    for entry in dir {
+        let entry = entry?;
+        let Ok(id) = entry.file_name().into_string() else { continue };
+        if !is_snapshot_id(&id) || !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let created = fs::metadata(entry.path().join("0").join("H.Cache.bin_H"))
+            .and_then(|m| m.modified())
+            .ok();
+        snapshots.push(SnapshotInfo {
+            files: count_h_files(&entry.path()),
+            latest: latest == Some(id.as_str()),
+            id,
+            created,
+        });
+    }
+    snapshots.sort_by_key(|s| (s.created, s.id.clone()));
+    Ok(snapshots)
+}
+
+fn count_h_files(dir: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else { return 0 };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                count_h_files(&path)
+            } else {
+                usize::from(path.to_string_lossy().ends_with("_H"))
+            }
+        })
+        .sum()
+}
@@ -0,0 +1,48 @@
+//! Decoded strings in a SQLite database, for querying across locales: one
+//! `strings(locale, key, value)` table, keyed by locale and key.
+
+use crate::SoulframeError;
+use anyhow::Context;
+use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub struct SqliteWriter {
+    conn: Connection,
+}
+
+impl SqliteWriter {
+    /// Opens or creates the database at `path`, adding the `strings` table if it
+    /// has none
+    pub fn open(path: &Path) -> Result<Self, SoulframeError> {
+        let conn = Connection::open(path).with_context(|| format!("opening {}", path.display()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS strings (
+                locale TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (locale, key)
+            )",
+        )
+        .with_context(|| format!("creating the strings table in {}", path.display()))?;
+        Ok(Self { conn })
+    }
+
+    /// Replaces `locale`'s rows with `entries` in one transaction. Returns the
+    /// number of rows written.
+    pub fn write_locale(&mut self, locale: &str, entries: &BTreeMap<String, String>) -> Result<usize, SoulframeError> {
+        let write = |conn: &mut Connection| -> rusqlite::Result<()> {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM strings WHERE locale = ?1", params![locale])?;
+            {
+                let mut insert = tx.prepare("INSERT INTO strings (locale, key, value) VALUES (?1, ?2, ?3)")?;
+                for (key, value) in entries {
+                    insert.execute(params![locale, key, value])?;
+                }
+            }
+            tx.commit()
+        };
+        write(&mut self.conn).with_context(|| format!("writing {} to the database", locale))?;
+        Ok(entries.len())
+    }
+}
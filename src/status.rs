@@ -0,0 +1,170 @@
+//! Offline inventory of `downloaded-data` and `extracted-data`: per locale, which
+//! `Languages.bin` files are on disk, whether they match the manifest they were
+//! downloaded with, and how the extracted JSON compares to them.
+
+use crate::locales::known_locales;
+use crate::manifest::SoulframeManifest;
+use crate::{locale_suffix, localized_manifest_path, safe_join, ManifestFormat, SoulframeError, DEFAULT_PLATFORM, MANIFEST_HASH_LEN};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Primary manifest `_H`, relative to the downloads folder
+const PRIMARY_MANIFEST_H: &str = "0/H.Cache.bin_H";
+
+/// A file on disk
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Hex header hash, for `_H` files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// The manifest entry for a locale's `Languages.bin`
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestReport {
+    /// Manifest path, e.g. `/B.Cache.Windows_en.bin`, or `/H.Cache.bin` for the
+    /// primary manifest when no localized one lists the file
+    pub manifest: String,
+    /// Hex hash listed for `/Languages.bin`
+    pub hash: String,
+    /// Whether it equals the `_H` header hash; `None` without a `_H` file
+    pub matches: Option<bool>,
+}
+
+/// A locale's extracted JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedReport {
+    pub path: PathBuf,
+    /// Strings in the file, flat or `--nested`; `None` if it isn't valid JSON
+    pub strings: Option<usize>,
+    /// Seconds the JSON is older than the downloaded `_H`, if it is
+    pub older_than_download_secs: Option<u64>,
+}
+
+/// Everything on disk for one locale
+#[derive(Debug, Clone, Serialize)]
+pub struct LocaleStatus {
+    pub locale: String,
+    pub languages_h: Option<FileReport>,
+    pub languages_b: Option<FileReport>,
+    /// `None` when no manifest on disk lists `/Languages.bin`
+    pub manifest: Option<ManifestReport>,
+    pub extracted: Option<ExtractedReport>,
+}
+
+impl LocaleStatus {
+    /// Downloaded, matching its manifest, and extracted since
+    pub fn is_current(&self) -> bool {
+        self.languages_h.is_some()
+            && self.manifest.as_ref().is_none_or(|m| m.matches == Some(true))
+            && self.extracted.as_ref().is_some_and(|e| e.strings.is_some() && e.older_than_download_secs.is_none())
+    }
+}
+
+/// Reports every locale with a download or an extracted JSON for `platform`: the
+/// known locales first, then any other `0_<locale>` folders in `downloads`
+pub fn data_status(downloads: &Path, extracted: &Path, platform: &str) -> Result<Vec<LocaleStatus>, SoulframeError> {
+    let mut locales = known_locales();
+    if let Ok(dir) = fs::read_dir(downloads) {
+        let platform_suffix = format!("_{}", platform);
+        let mut others: Vec<String> = dir
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|name| {
+                let locale = name.strip_prefix("0_")?;
+                let locale = if platform == DEFAULT_PLATFORM { locale } else { locale.strip_suffix(&platform_suffix)? };
+                Some(locale.to_string())
+            })
+            .filter(|locale| !locale.is_empty() && locale.chars().all(|c| c.is_alphanumeric()))
+            .filter(|locale| !locales.contains(locale))
+            .collect();
+        others.sort();
+        locales.append(&mut others);
+    }
+
+    let mut reports = Vec::new();
+    for locale in &locales {
+        let report = locale_status(downloads, extracted, locale, platform)?;
+        if report.languages_h.is_some() || report.languages_b.is_some() || report.extracted.is_some() {
+            reports.push(report);
+        }
+    }
+    Ok(reports)
+}
+
+/// Reports one locale, whether or not anything is on disk for it
+pub fn locale_status(downloads: &Path, extracted: &Path, locale: &str, platform: &str) -> Result<LocaleStatus, SoulframeError> {
+    let suffix = locale_suffix(locale, platform);
+    let h_path = safe_join(downloads, &format!("0{}/Languages.bin_H", suffix))?;
+    let b_path = safe_join(downloads, &format!("0{}/Languages.bin_B", suffix))?;
+
+    let languages_h = fs::read(&h_path).ok().map(|bin| FileReport {
+        hash: bin.get(..MANIFEST_HASH_LEN).map(hex),
+        size: bin.len() as u64,
+        path: h_path.clone(),
+    });
+    let languages_b = fs::metadata(&b_path).ok().map(|metadata| FileReport {
+        path: b_path,
+        size: metadata.len(),
+        hash: None,
+    });
+
+    let manifest = manifest_hash(downloads, platform, locale).map(|(manifest, hash)| ManifestReport {
+        matches: languages_h.as_ref().map(|h| h.hash.as_deref() == Some(hash.as_str())),
+        manifest,
+        hash,
+    });
+
+    // Flat layout first, then --mirror-paths
+    let stem = &suffix[1..];
+    let extracted = [format!("0/Languages/{}.json", stem), format!("0{}/Languages/{}.json", suffix, stem)]
+        .iter()
+        .filter_map(|relative| safe_join(extracted, relative).ok())
+        .find(|path| path.is_file())
+        .map(|path| ExtractedReport {
+            strings: fs::read_to_string(&path)
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .map(|value| count_strings(&value)),
+            older_than_download_secs: older_by(&path, &h_path),
+            path,
+        });
+
+    Ok(LocaleStatus { locale: locale.to_string(), languages_h, languages_b, manifest, extracted })
+}
+
+/// The manifest listing `locale`'s `/Languages.bin` and the hex hash it gives,
+/// checking the localized manifest before the primary one like `download` does
+fn manifest_hash(downloads: &Path, platform: &str, locale: &str) -> Option<(String, String)> {
+    let localized = localized_manifest_path(platform, locale);
+    [(localized.clone(), format!("0{}_H", localized)), ("/H.Cache.bin".to_string(), PRIMARY_MANIFEST_H.to_string())]
+        .into_iter()
+        .find_map(|(manifest, relative)| {
+            let bin = fs::read(safe_join(downloads, &relative).ok()?).ok()?;
+            let hash = SoulframeManifest::from_bytes(bin, ManifestFormat::Current).ok()?.get_hash("/Languages.bin")?;
+            Some((manifest, hex(&hash)))
+        })
+}
+
+/// How many seconds `path` was modified before `reference`, if it was
+fn older_by(path: &Path, reference: &Path) -> Option<u64> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let (modified, reference): (SystemTime, SystemTime) = (modified(path)?, modified(reference)?);
+    reference.duration_since(modified).ok().map(|age| age.as_secs()).filter(|&secs| secs > 0)
+}
+
+/// String leaves of a locale JSON, skipping arrays such as `__order`
+fn count_strings(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::String(_) => 1,
+        serde_json::Value::Object(members) => members.values().map(count_strings).sum(),
+        _ => 0,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,181 @@
+//! Where downloaded and extracted files are read and written. Paths are
+//! `/`-separated and relative to the storage's root, e.g. `0_en/Languages.bin_H`, so
+//! a backend can map them onto object keys as readily as onto files.
+//!
+//! `LocalFs` is the filesystem, used by default. `MemoryStorage` keeps everything in
+//! a map, for tests and for embedding without a disk.
+
+use crate::{safe_join, SoulframeError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub trait Storage: Send + Sync {
+    fn read(&self, path: &str) -> Result<Vec<u8>, SoulframeError>;
+
+    /// Writes `data` to `path`, replacing any file there and creating its parents
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), SoulframeError>;
+
+    fn exists(&self, path: &str) -> bool;
+
+    fn create_dir_all(&self, path: &str) -> Result<(), SoulframeError>;
+
+    /// Removes the file at `path`; a missing file is not an error
+    fn remove(&self, path: &str) -> Result<(), SoulframeError>;
+
+    /// Removes the folder at `path` and everything in it; a missing folder is not
+    /// an error
+    fn remove_dir_all(&self, path: &str) -> Result<(), SoulframeError>;
+
+    /// Filesystem path of `path`, for backends that have one. Callers use it to
+    /// stream large files to disk and to hard-link from the content cache.
+    fn local_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Files under `root`. A relative root is resolved against the working directory
+/// on each call, like `get_download_path`.
+#[derive(Debug, Clone)]
+pub struct LocalFs {
+    root: PathBuf,
+}
+
+impl LocalFs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// `downloaded-data` in the working directory, the default for `DownloadClient`
+    /// and `SoulframeManifest::new`
+    pub fn downloads() -> Self {
+        Self::new("downloaded-data")
+    }
+
+    /// `extracted-data` in the working directory
+    pub fn extracted() -> Self {
+        Self::new("extracted-data")
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn resolve(&self, path: &str) -> Result<PathBuf, SoulframeError> {
+        let root = if self.root.is_relative() {
+            std::env::current_dir()?.join(&self.root)
+        } else {
+            self.root.clone()
+        };
+        Ok(safe_join(&root, path)?)
+    }
+}
+
+impl Storage for LocalFs {
+    fn read(&self, path: &str) -> Result<Vec<u8>, SoulframeError> {
+        Ok(fs::read(self.resolve(path)?)?)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), SoulframeError> {
+        let file = self.resolve(path)?;
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Replaced rather than truncated, so files hard-linked from the content
+        // cache keep their contents
+        let _ = fs::remove_file(&file);
+        fs::write(&file, data)?;
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.resolve(path).is_ok_and(|file| file.exists())
+    }
+
+    fn create_dir_all(&self, path: &str) -> Result<(), SoulframeError> {
+        fs::create_dir_all(self.resolve(path)?)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> Result<(), SoulframeError> {
+        match fs::remove_file(self.resolve(path)?) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn remove_dir_all(&self, path: &str) -> Result<(), SoulframeError> {
+        match fs::remove_dir_all(self.resolve(path)?) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn local_path(&self, path: &str) -> Option<PathBuf> {
+        self.resolve(path).ok()
+    }
+}
+
+/// Files kept in memory, keyed by their normalized path. Folders are implied by
+/// the files in them, so `create_dir_all` does nothing.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paths of every stored file, sorted
+    pub fn paths(&self) -> Vec<String> {
+        self.files.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+/// `path` with empty and `.` segments dropped, rejecting what `safe_join` rejects
+fn normalize(path: &str) -> Result<String, SoulframeError> {
+    let joined = safe_join(Path::new(""), path)?;
+    let segments: Vec<_> = joined.iter().map(|segment| segment.to_string_lossy()).collect();
+    Ok(segments.join("/"))
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, path: &str) -> Result<Vec<u8>, SoulframeError> {
+        let key = normalize(path)?;
+        self.files
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not in memory storage", key)).into())
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), SoulframeError> {
+        self.files.lock().unwrap().insert(normalize(path)?, data.to_vec());
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        normalize(path).is_ok_and(|key| self.files.lock().unwrap().contains_key(&key))
+    }
+
+    fn create_dir_all(&self, path: &str) -> Result<(), SoulframeError> {
+        normalize(path)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> Result<(), SoulframeError> {
+        self.files.lock().unwrap().remove(&normalize(path)?);
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &str) -> Result<(), SoulframeError> {
+        let prefix = format!("{}/", normalize(path)?);
+        self.files.lock().unwrap().retain(|key, _| !key.starts_with(&prefix));
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! Clean-up steps for decoded values, for consumers that don't want the game's
+//! rich-text markup or irregular whitespace. Steps run in the order given, and
+//! `ascii_escape_json` re-encodes the written JSON for consumers that only read ASCII.
+
+use unicode_normalization::UnicodeNormalization;
+
+/// One step of the pipeline set by `UnpackOptions::transforms`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueTransform {
+    /// Remove angle-bracket tags such as `<DT_FIRE>` or `</b>`. A `<` that starts no
+    /// tag is kept: one not followed by a letter, `_` or `/`, as in `1 < 2`, or not
+    /// closed by a `>` on its line.
+    StripTags,
+    /// Replace each run of whitespace with one space, or one line break when the run
+    /// has one, and trim both ends. No-break spaces are kept, since French
+    /// typography depends on them.
+    NormalizeWhitespace,
+    /// Unicode Normalization Form C, so visually identical strings compare equal
+    Nfc,
+}
+
+impl ValueTransform {
+    /// Name recorded in extraction metadata, the same as the `extract` flag
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueTransform::StripTags => "strip-tags",
+            ValueTransform::NormalizeWhitespace => "normalize-whitespace",
+            ValueTransform::Nfc => "nfc",
+        }
+    }
+
+    pub fn apply(self, value: &str) -> String {
+        match self {
+            ValueTransform::StripTags => strip_tags(value),
+            ValueTransform::NormalizeWhitespace => normalize_whitespace(value),
+            ValueTransform::Nfc => value.nfc().collect(),
+        }
+    }
+}
+
+/// `value` passed through each of `transforms` in order
+pub fn apply_transforms(transforms: &[ValueTransform], value: String) -> String {
+    transforms.iter().fold(value, |value, transform| transform.apply(&value))
+}
+
+/// Comma-separated names of `transforms`, for the metadata of an extraction
+pub fn describe_transforms(transforms: &[ValueTransform]) -> String {
+    transforms.iter().map(|transform| transform.name()).collect::<Vec<_>>().join(",")
+}
+
+fn strip_tags(value: &str) -> String {
+    let mut stripped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        stripped.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let end = tag
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| matches!(c, '>' | '<' | '\n'))
+            .filter(|&(_, c)| c == '>')
+            .map(|(end, _)| end);
+        match end {
+            Some(end) if tag[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '/') => {
+                rest = &tag[end + 1..];
+            }
+            _ => {
+                stripped.push('<');
+                rest = &tag[1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+fn normalize_whitespace(value: &str) -> String {
+    let mut normalized = String::with_capacity(value.len());
+    let mut pending: Option<char> = None;
+    for c in value.chars() {
+        if c.is_whitespace() && !matches!(c, '\u{a0}' | '\u{2007}' | '\u{202f}') {
+            if c == '\n' {
+                pending = Some('\n');
+            } else if pending.is_none() {
+                pending = Some(' ');
+            }
+            continue;
+        }
+        if let Some(separator) = pending.take() {
+            if !normalized.is_empty() {
+                normalized.push(separator);
+            }
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+/// `json` with every non-ASCII character written as a `\uXXXX` escape, using a
+/// surrogate pair above U+FFFF. serde_json only writes non-ASCII inside strings, so
+/// the result parses to the same value.
+pub fn ascii_escape_json(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    let mut units = [0u16; 2];
+    for c in json.chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut units) {
+                escaped.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_tags_keeps_angle_brackets_that_start_no_tag() {
+        let strip = |value| ValueTransform::StripTags.apply(value);
+        assert_eq!(strip("Deals <DT_FIRE>Heat</b> damage"), "Deals Heat damage");
+        assert_eq!(strip("<b>Bold</b>"), "Bold");
+        assert_eq!(strip("1 < 2 and 3 > 2"), "1 < 2 and 3 > 2");
+        assert_eq!(strip("a <b and <c>d"), "a <b and d");
+        assert_eq!(strip("<b\n>x"), "<b\n>x");
+        assert_eq!(strip("<<b>>"), "<>");
+        assert_eq!(strip("trailing <"), "trailing <");
+    }
+
+    #[test]
+    fn normalize_whitespace_collapses_runs_and_trims() {
+        let normalize = |value| ValueTransform::NormalizeWhitespace.apply(value);
+        assert_eq!(normalize("  Two \t spaces  "), "Two spaces");
+        assert_eq!(normalize("Line one \n\n  Line two\r\n"), "Line one\nLine two");
+        assert_eq!(normalize("a \n b"), "a\nb");
+        assert_eq!(normalize("Prix\u{a0}: 5\u{202f}%"), "Prix\u{a0}: 5\u{202f}%");
+        assert_eq!(normalize(" \n\t "), "");
+    }
+
+    #[test]
+    fn nfc_composes_decomposed_characters() {
+        assert_eq!(ValueTransform::Nfc.apply("Cafe\u{301}"), "Caf\u{e9}");
+        assert_eq!(ValueTransform::Nfc.apply("\u{1100}\u{1161}"), "\u{ac00}");
+        assert_eq!(ValueTransform::Nfc.apply("Caf\u{e9}"), "Caf\u{e9}");
+    }
+
+    #[test]
+    fn transforms_run_in_the_order_given() {
+        let pipeline = [ValueTransform::StripTags, ValueTransform::NormalizeWhitespace, ValueTransform::Nfc];
+        assert_eq!(apply_transforms(&pipeline, "  <b>Cafe\u{301}</b>  <i>ouvert</i> ".to_string()), "Caf\u{e9} ouvert");
+        assert_eq!(describe_transforms(&pipeline), "strip-tags,normalize-whitespace,nfc");
+        let reversed = [ValueTransform::Nfc, ValueTransform::StripTags];
+        assert_eq!(describe_transforms(&reversed), "nfc,strip-tags");
+        assert_eq!(describe_transforms(&[]), "");
+    }
+
+    #[test]
+    fn ascii_escape_json_writes_surrogate_pairs_above_the_bmp() {
+        let json = serde_json::to_string(&["Caf\u{e9}", "\u{65e5}\u{672c}", "\u{1f525} hot", "plain"]).unwrap();
+        let escaped = ascii_escape_json(&json);
+        assert!(escaped.is_ascii());
+        assert_eq!(escaped, r#"["Caf\u00e9","\u65e5\u672c","\ud83d\udd25 hot","plain"]"#);
+        assert_eq!(serde_json::from_str::<Vec<String>>(&escaped).unwrap(), serde_json::from_str::<Vec<String>>(&json).unwrap());
+    }
+}
@@ -0,0 +1,196 @@
+//! Decoded strings as an Excel workbook for translators: a `Strings` sheet with each
+//! key, its English source and a column per locale, and a `Metadata` sheet with each
+//! locale's `ExtractionStats`.
+
+use crate::extract::ExtractionStats;
+use crate::SoulframeError;
+use anyhow::Context;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use tracing::warn;
+
+/// Most characters Excel holds in one cell; longer keys and values are cut to it
+pub const MAX_CELL_CHARS: usize = 32_767;
+
+/// Widest column, in characters; longer values wrap
+const MAX_COLUMN_WIDTH: usize = 80;
+
+/// Writes the workbook to `path`: one row per key of `source` and `locales`, in byte
+/// order, with the English `source` column left out when it is `None`. Returns the
+/// number of keys.
+pub fn write_workbook(
+    path: &Path,
+    source: Option<&BTreeMap<String, String>>,
+    locales: &[(&str, &BTreeMap<String, String>)],
+    stats: &[&ExtractionStats],
+) -> Result<usize, SoulframeError> {
+    let mut columns: Vec<(String, &BTreeMap<String, String>)> = Vec::new();
+    if let Some(source) = source {
+        columns.push(("en (source)".to_string(), source));
+    }
+    columns.extend(locales.iter().map(|(locale, entries)| (locale.to_string(), *entries)));
+    let keys: BTreeSet<&String> = columns.iter().flat_map(|(_, entries)| entries.keys()).collect();
+
+    let mut workbook = Workbook::new();
+    write_strings(workbook.add_worksheet(), &keys, &columns)
+        .with_context(|| format!("writing the strings of {}", path.display()))?;
+    write_metadata(workbook.add_worksheet(), stats)
+        .with_context(|| format!("writing the metadata of {}", path.display()))?;
+    workbook.save(path).with_context(|| format!("saving {}", path.display()))?;
+    Ok(keys.len())
+}
+
+fn write_strings(sheet: &mut Worksheet, keys: &BTreeSet<&String>, columns: &[(String, &BTreeMap<String, String>)]) -> Result<(), XlsxError> {
+    let header = Format::new().set_bold();
+    let wrapped = Format::new().set_text_wrap();
+    sheet.set_name("Strings")?;
+    sheet.set_freeze_panes(1, 0)?;
+
+    let mut widths = vec![0; columns.len() + 1];
+    let headers = std::iter::once("Key").chain(columns.iter().map(|(name, _)| name.as_str()));
+    for (col, name) in headers.enumerate() {
+        sheet.write_string_with_format(0, col as u16, name, &header)?;
+        widths[col] = name.chars().count();
+    }
+
+    for (index, key) in keys.iter().enumerate() {
+        let row = index as u32 + 1;
+        let cells = std::iter::once(Some(key.as_str()))
+            .chain(columns.iter().map(|(_, entries)| entries.get(*key).map(String::as_str)));
+        for (col, cell) in cells.enumerate() {
+            let Some(cell) = cell else { continue };
+            let cell = fit_cell(cell, key, col == 0);
+            widths[col] = widths[col].max(cell.lines().map(|line| line.chars().count()).max().unwrap_or(0));
+            if cell.contains('\n') {
+                sheet.write_string_with_format(row, col as u16, cell, &wrapped)?;
+            } else {
+                sheet.write_string(row, col as u16, cell)?;
+            }
+        }
+    }
+
+    for (col, width) in widths.into_iter().enumerate() {
+        sheet.set_column_width(col as u16, (width.min(MAX_COLUMN_WIDTH) + 2) as f64)?;
+    }
+    Ok(())
+}
+
+fn write_metadata(sheet: &mut Worksheet, stats: &[&ExtractionStats]) -> Result<(), XlsxError> {
+    let header = Format::new().set_bold();
+    sheet.set_name("Metadata")?;
+    sheet.set_freeze_panes(1, 0)?;
+
+    let headers = [
+        "Locale", "Strings", "Decoded bytes", "Compressed labels", "Raw labels", "Dictionary hash", "Source hash",
+        "Source MD5", "Options", "Tool version",
+    ];
+    for (col, name) in headers.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *name, &header)?;
+    }
+    for (index, stats) in stats.iter().enumerate() {
+        let row = index as u32 + 1;
+        sheet.write_string(row, 0, &stats.locale)?;
+        sheet.write_number(row, 1, stats.strings as f64)?;
+        sheet.write_number(row, 2, stats.decoded_bytes as f64)?;
+        sheet.write_number(row, 3, stats.compressed_labels as f64)?;
+        sheet.write_number(row, 4, stats.raw_labels as f64)?;
+        sheet.write_string(row, 5, &stats.dict_hash)?;
+        sheet.write_string(row, 6, &stats.source_hash)?;
+        sheet.write_string(row, 7, &stats.source_md5)?;
+        sheet.write_string(row, 8, &stats.options)?;
+        sheet.write_string(row, 9, &stats.tool_version)?;
+    }
+    sheet.autofit();
+    Ok(())
+}
+
+/// `cell` cut to `MAX_CELL_CHARS`, with a warning naming `key` when it was
+fn fit_cell<'a>(cell: &'a str, key: &str, is_key: bool) -> &'a str {
+    let Some((end, _)) = cell.char_indices().nth(MAX_CELL_CHARS) else {
+        return cell;
+    };
+    let preview: String = key.chars().take(80).collect();
+    if is_key {
+        warn!("Key {}... is longer than Excel's {}-character cell limit; cut short in the workbook", preview, MAX_CELL_CHARS);
+    } else {
+        warn!("Value of {} is longer than Excel's {}-character cell limit; cut short in the workbook", preview, MAX_CELL_CHARS);
+    }
+    &cell[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{open_workbook, Data, Range, Reader, Xlsx};
+
+    fn stats(locale: &str, strings: usize) -> ExtractionStats {
+        ExtractionStats {
+            locale: locale.to_string(),
+            strings,
+            decoded_bytes: 42,
+            compressed_labels: 1,
+            raw_labels: strings - 1,
+            dict_size: 8,
+            dict_hash: "d1c7".to_string(),
+            source_hash: "5e11".to_string(),
+            source_md5: "abcd".to_string(),
+            options: "nested=false".to_string(),
+            tool_version: "0.1.0".to_string(),
+            duration_ms: 3,
+        }
+    }
+
+    fn entries(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn sheet(path: &Path, name: &str) -> Range<Data> {
+        let mut workbook: Xlsx<_> = open_workbook(path).unwrap();
+        workbook.worksheet_range(name).unwrap()
+    }
+
+    /// Text of the cells of `row`
+    fn row(range: &Range<Data>, row: usize) -> Vec<String> {
+        range.rows().nth(row).unwrap().iter().map(|cell| cell.to_string()).collect()
+    }
+
+    #[test]
+    fn workbook_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("strings.xlsx");
+        let en = entries(&[("/Menu/Play", "Play"), ("/Menu/Quit", "Quit\nnow")]);
+        let fr = entries(&[("/Menu/Play", "Jouer"), ("/Menu/Extra", "Seulement fr")]);
+        let ja = entries(&[("/Menu/Play", "\u{30d7}\u{30ec}\u{30a4}")]);
+        let (fr_stats, ja_stats) = (stats("fr", 2), stats("ja", 1));
+
+        let keys = write_workbook(&path, Some(&en), &[("fr", &fr), ("ja", &ja)], &[&fr_stats, &ja_stats]).unwrap();
+        assert_eq!(keys, 3);
+
+        let strings = sheet(&path, "Strings");
+        assert_eq!(strings.height(), 4);
+        assert_eq!(row(&strings, 0), ["Key", "en (source)", "fr", "ja"]);
+        assert_eq!(row(&strings, 1), ["/Menu/Extra", "", "Seulement fr", ""]);
+        assert_eq!(row(&strings, 2), ["/Menu/Play", "Play", "Jouer", "\u{30d7}\u{30ec}\u{30a4}"]);
+        assert_eq!(row(&strings, 3), ["/Menu/Quit", "Quit\nnow", "", ""]);
+
+        let metadata = sheet(&path, "Metadata");
+        assert_eq!(metadata.height(), 3);
+        assert_eq!(row(&metadata, 0)[..3], ["Locale", "Strings", "Decoded bytes"]);
+        assert_eq!(row(&metadata, 1), ["fr", "2", "42", "1", "1", "d1c7", "5e11", "abcd", "nested=false", "0.1.0"]);
+        assert_eq!(row(&metadata, 2)[..2], ["ja", "1"]);
+    }
+
+    #[test]
+    fn workbook_without_a_source_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("strings.xlsx");
+        let fr = entries(&[("/Menu/Play", "Jouer")]);
+
+        assert_eq!(write_workbook(&path, None, &[("fr", &fr)], &[]).unwrap(), 1);
+        let strings = sheet(&path, "Strings");
+        assert_eq!(row(&strings, 0), ["Key", "fr"]);
+        assert_eq!(row(&strings, 1), ["/Menu/Play", "Jouer"]);
+        assert_eq!(sheet(&path, "Metadata").height(), 1);
+    }
+}
@@ -0,0 +1,58 @@
+// Replays the inputs in tests/corpus through the parsers the fuzz targets cover.
+// Every file is malformed, so each must come back as an Err; a panic, like the
+// out-of-bounds label slice the fuzz targets were written to catch, fails the test.
+
+use anyhow::Result;
+use soulframe_language_downloader::extract::{languages_read_header, languages_unpack, BundledZstd, UnpackOptions};
+use soulframe_language_downloader::{shcc_inspect, shcc_unpack, shcc_unpack_to, BlockDecompressor};
+use std::fs;
+use std::path::Path;
+
+/// Stands in for Oodle like `fuzzing::PassthroughDecompressor`, which is only
+/// built with the `fuzzing` feature
+struct PassthroughDecompressor;
+
+impl BlockDecompressor for PassthroughDecompressor {
+    fn decompress_into(&self, compressed: &[u8], output: &mut [u8]) -> Result<()> {
+        let len = compressed.len().min(output.len());
+        output[..len].copy_from_slice(&compressed[..len]);
+        output[len..].fill(0);
+        Ok(())
+    }
+}
+
+/// Name and contents of every file in `tests/corpus/<dir>`, sorted by name
+fn corpus(dir: &str) -> Vec<(String, Vec<u8>)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus").join(dir);
+    let mut files: Vec<(String, Vec<u8>)> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            (path.file_name().unwrap().to_string_lossy().to_string(), fs::read(&path).unwrap())
+        })
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no corpus files in {}", dir.display());
+    files
+}
+
+#[test]
+fn shcc_corpus_is_rejected() {
+    for (name, data) in corpus("shcc") {
+        assert!(shcc_unpack(&data, &PassthroughDecompressor).is_err(), "{} was accepted", name);
+        let streamed = shcc_unpack_to(&data, &PassthroughDecompressor, &mut std::io::sink(), &mut std::io::sink());
+        assert!(streamed.is_err(), "{} was accepted by shcc_unpack_to", name);
+        // The inspector reports problems instead of failing; it only must not panic
+        shcc_inspect(&data);
+    }
+}
+
+#[test]
+fn languages_corpus_is_rejected() {
+    for (name, data) in corpus("languages") {
+        let result = languages_read_header(&data).and_then(|(_, dict)| {
+            languages_unpack(&data, &BundledZstd::new(&dict)?, &UnpackOptions::default())
+        });
+        assert!(result.is_err(), "{} was accepted", name);
+    }
+}